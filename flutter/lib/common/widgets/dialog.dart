@@ -1262,6 +1262,126 @@ void showRestartRemoteDevice(PeerInfo pi, String id, SessionID sessionId,
   if (res == true) bind.sessionRestartRemoteDevice(sessionId: sessionId);
 }
 
+void showVideoScaleDialog(
+    SessionID sessionId, OverlayDialogManager dialogManager) async {
+  final option =
+      await bind.sessionGetOption(sessionId: sessionId, arg: 'video-scale');
+  var scale = (int.tryParse(option ?? '') ?? 100).clamp(10, 100).toDouble();
+  var auto = bind.sessionGetToggleOptionSync(
+      sessionId: sessionId, arg: 'auto-video-scale');
+  dialogManager.show((setState, close, context) {
+    submit() {
+      bind.sessionSetVideoScale(
+          sessionId: sessionId, scale: scale.round(), auto: auto);
+      close();
+    }
+
+    return CustomAlertDialog(
+      title: Text(translate('Video scale')),
+      content: Column(
+        mainAxisSize: MainAxisSize.min,
+        crossAxisAlignment: CrossAxisAlignment.start,
+        children: [
+          Text(translate('Scale of the display size (%)')),
+          Row(
+            children: [
+              Expanded(
+                child: Slider(
+                  value: scale,
+                  min: 10,
+                  max: 100,
+                  divisions: 9,
+                  onChanged: (v) => setState(() => scale = v),
+                ),
+              ),
+              Text('${scale.round()}%'),
+            ],
+          ),
+          CheckboxListTile(
+            contentPadding: const EdgeInsets.all(0),
+            dense: true,
+            controlAffinity: ListTileControlAffinity.leading,
+            title: Text(translate('Downscale more on high delay')),
+            value: auto,
+            onChanged: (v) {
+              if (v == null) return;
+              setState(() => auto = v);
+            },
+          ),
+        ],
+      ),
+      actions: [
+        dialogButton('Cancel', onPressed: close, isOutline: true),
+        dialogButton('OK', onPressed: submit),
+      ],
+      onSubmit: submit,
+      onCancel: close,
+    );
+  });
+}
+
+void showAudioQualityDialog(SessionID sessionId,
+    OverlayDialogManager dialogManager, bool showApplication) async {
+  getOption(String arg) async =>
+      await bind.sessionGetOption(sessionId: sessionId, arg: arg) ?? '';
+  final bitrateController =
+      TextEditingController(text: await getOption('audio-bitrate'));
+  final applicationController =
+      TextEditingController(text: await getOption('audio-application'));
+  var channels = int.tryParse(await getOption('audio-channels')) ?? 0;
+  dialogManager.show((setState, close, context) {
+    submit() {
+      bind.sessionSetAudioQuality(
+          sessionId: sessionId,
+          bitrate: int.tryParse(bitrateController.text.trim()) ?? 0,
+          channels: channels,
+          application: applicationController.text.trim());
+      close();
+    }
+
+    channelsRadio(int value, String name) => RadioListTile<int>(
+          contentPadding: EdgeInsets.zero,
+          dense: true,
+          title: Text(translate(name)),
+          value: value,
+          groupValue: channels,
+          onChanged: (v) {
+            if (v != null) setState(() => channels = v);
+          },
+        );
+
+    return CustomAlertDialog(
+      title: Text(translate('Audio quality')),
+      content: Column(
+        mainAxisSize: MainAxisSize.min,
+        crossAxisAlignment: CrossAxisAlignment.start,
+        children: [
+          DialogTextField(
+            title: translate('Bitrate (kbps), 0 for the default'),
+            controller: bitrateController,
+            keyboardType: TextInputType.number,
+            inputFormatters: [FilteringTextInputFormatter.digitsOnly],
+          ),
+          channelsRadio(0, 'Default'),
+          channelsRadio(1, 'Mono'),
+          channelsRadio(2, 'Stereo'),
+          if (showApplication)
+            DialogTextField(
+              title: translate('Capture the audio of this application only'),
+              controller: applicationController,
+            ),
+        ],
+      ),
+      actions: [
+        dialogButton('Cancel', onPressed: close, isOutline: true),
+        dialogButton('OK', onPressed: submit),
+      ],
+      onSubmit: submit,
+      onCancel: close,
+    );
+  });
+}
+
 showSetOSPassword(
   SessionID sessionId,
   bool login,
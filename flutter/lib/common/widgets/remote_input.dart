@@ -72,7 +72,12 @@ class _RawTouchGestureDetectorRegionState
   double _mouseScrollIntegral = 0; // mouse scroll speed controller
   double _scale = 1;
 
-  PointerDeviceKind? lastDeviceKind;
+  PointerDeviceKind? _lastDeviceKind;
+
+  // The gestures are not emulated if the touches are forwarded as multi-touch.
+  PointerDeviceKind? get lastDeviceKind => _lastDeviceKind;
+  set lastDeviceKind(PointerDeviceKind? kind) => _lastDeviceKind =
+      kind == PointerDeviceKind.touch && inputModel.isMultiTouch ? null : kind;
 
   FFI get ffi => widget.ffi;
   FfiModel get ffiModel => widget.ffiModel;
@@ -404,6 +409,7 @@ class RawPointerMouseRegion extends StatelessWidget {
         inputModel.onPointUpImage(evt);
       },
       onPointerMove: inputModel.onPointMoveImage,
+      onPointerCancel: inputModel.onPointCancelImage,
       onPointerSignal: inputModel.onPointerSignalImage,
       onPointerPanZoomStart: inputModel.onPointerPanZoomStart,
       onPointerPanZoomUpdate: inputModel.onPointerPanZoomUpdate,
@@ -1,6 +1,7 @@
 import 'dart:convert';
 import 'dart:io';
 
+import 'package:file_picker/file_picker.dart';
 import 'package:flutter/material.dart';
 import 'package:flutter/services.dart';
 import 'package:flutter_hbb/common.dart';
@@ -193,6 +194,87 @@ List<TTextMenu> toolbarControls(BuildContext context, String id, FFI ffi) {
           child: Text(translate('Insert Lock')),
           onPressed: () => bind.sessionLockScreen(sessionId: sessionId)),
     );
+    // typeClipboard
+    v.add(
+      TTextMenu(
+          child: Text(translate('Type clipboard')),
+          onPressed: () async {
+            final data = await Clipboard.getData(Clipboard.kTextPlain);
+            final text = data?.text;
+            if (text != null && text.isNotEmpty) {
+              bind.sessionTypeText(sessionId: sessionId, text: text);
+            }
+          }),
+    );
+    // cancelTypeText
+    if (bind.sessionIsTypingText(sessionId: sessionId)) {
+      v.add(
+        TTextMenu(
+            child: Text(translate('Cancel typing')),
+            onPressed: () => bind.sessionCancelTypeText(sessionId: sessionId)),
+      );
+    }
+    // macro
+    if (isDesktop) {
+      if (bind.sessionIsMacroRecording(sessionId: sessionId)) {
+        v.add(
+          TTextMenu(
+              child: Text(translate('Stop macro recording')),
+              onPressed: () async {
+                // keep recording if no file is chosen
+                final path = await FilePicker.platform.saveFile(
+                    fileName: 'macro.json',
+                    type: FileType.custom,
+                    allowedExtensions: ['json']);
+                if (path != null) {
+                  await bind.sessionStopMacroRecording(
+                      sessionId: sessionId, path: path);
+                }
+              }),
+        );
+      } else if (bind.sessionIsMacroPlaying(sessionId: sessionId)) {
+        v.add(
+          TTextMenu(
+              child: Text(translate('Stop macro')),
+              onPressed: () => bind.sessionStopMacro(sessionId: sessionId)),
+        );
+      } else {
+        v.add(
+          TTextMenu(
+              child: Text(translate('Record macro')),
+              onPressed: () =>
+                  bind.sessionStartMacroRecording(sessionId: sessionId)),
+        );
+        v.add(
+          TTextMenu(
+              child: Text(translate('Play macro')),
+              onPressed: () async {
+                final result = await FilePicker.platform.pickFiles(
+                    type: FileType.custom, allowedExtensions: ['json', 'txt']);
+                final path = result?.files.single.path;
+                if (path != null) {
+                  bind.sessionPlayMacro(
+                      sessionId: sessionId, path: path, speed: 1.0);
+                }
+              }),
+        );
+      }
+    }
+  }
+  // videoScale
+  v.add(
+    TTextMenu(
+        child: Text(translate('Video scale')),
+        onPressed: () => showVideoScaleDialog(sessionId, ffi.dialogManager)),
+  );
+  // audioQuality
+  if (perms['audio'] != false) {
+    v.add(
+      TTextMenu(
+          child: Text(translate('Audio quality')),
+          onPressed: () => showAudioQualityDialog(sessionId, ffi.dialogManager,
+              pi.platform == kPeerPlatformLinux)),
+    );
   }
   // blockUserInput
   if (ffi.ffiModel.keyboard &&
@@ -25,6 +25,8 @@ const String kPlatformAdditionsVirtualDisplays = "virtual_displays";
 const String kPlatformAdditionsHasFileClipboard = "has_file_clipboard";
 const String kPlatformAdditionsSupportedPrivacyModeImpl =
     "supported_privacy_mode_impl";
+const String kPlatformAdditionsMultiTouch = "multi_touch";
+const String kPlatformAdditionsPen = "pen";
 
 const String kPeerPlatformWindows = "Windows";
 const String kPeerPlatformLinux = "Linux";
@@ -81,6 +83,8 @@ const int kWindowMainId = 0;
 
 const String kPointerEventKindTouch = "touch";
 const String kPointerEventKindMouse = "mouse";
+const String kPointerEventKindMultiTouch = "multi_touch";
+const String kPointerEventKindPen = "pen";
 
 const String kKeyShowDisplaysAsIndividualWindows =
     'displays_as_individual_windows';
@@ -669,6 +669,20 @@ class _PrivilegeBoardState extends State<_PrivilegeBoard> {
                       });
                     },
                     translate('Enable blocking user input'),
+                  ),
+                // only linux supports gamepad
+                if (Platform.isLinux)
+                  buildPermissionIcon(
+                    client.gamepad,
+                    Icons.sports_esports,
+                    (enabled) {
+                      bind.cmSwitchPermission(
+                          connId: client.id, name: "gamepad", enabled: enabled);
+                      setState(() {
+                        client.gamepad = enabled;
+                      });
+                    },
+                    translate('Enable gamepad'),
                   )
               ],
             ),
@@ -183,6 +183,13 @@ class InputModel {
 
   bool _pointerMovedAfterEnter = false;
 
+  // multi-touch, pointer -> the last position on the remote
+  final Map<int, Point> _touchPoints = {};
+
+  // pen
+  bool _penInRange = false;
+  Point _penPos = Point(0, 0);
+
   // mouse
   final isPhysicalMouse = false.obs;
   int _lastButtons = 0;
@@ -194,6 +201,13 @@ class InputModel {
   String get id => parent.target?.id ?? '';
   String? get peerPlatform => parent.target?.ffiModel.pi.platform;
 
+  /// The touches are forwarded as they are instead of the gestures, if the peer supports it.
+  bool get isMultiTouch =>
+      isDesktop && (parent.target?.ffiModel.pi.isMultiTouch ?? false);
+
+  /// The stylus events are forwarded as pen events, if the peer supports it.
+  bool get isPen => isDesktop && (parent.target?.ffiModel.pi.isPen ?? false);
+
   InputModel(this.parent) {
     sessionId = parent.target!.sessionId;
 
@@ -467,6 +481,7 @@ class InputModel {
 
   void onPointHoverImage(PointerHoverEvent e) {
     _stopFling = true;
+    if (_handlePen(e, false, true)) return;
     if (e.kind != ui.PointerDeviceKind.mouse) return;
     if (!isPhysicalMouse.value) {
       isPhysicalMouse.value = true;
@@ -507,10 +522,8 @@ class InputModel {
     var x = delta.dx.toInt();
     var y = delta.dy.toInt();
     if (peerPlatform == kPeerPlatformLinux) {
-      _trackpadScrollUnsent += (delta * _trackpadSpeed);
-      x = _trackpadScrollUnsent.dx.truncate();
-      y = _trackpadScrollUnsent.dy.truncate();
-      _trackpadScrollUnsent -= Offset(x.toDouble(), y.toDouble());
+      _sendHiResScroll(delta);
+      return;
     } else {
       if (x == 0 && y == 0) {
         final thr = 0.1;
@@ -533,6 +546,19 @@ class InputModel {
     }
   }
 
+  // Linux peers scroll by 1/120 of a notch, the older versions take the whole notches.
+  void _sendHiResScroll(Offset delta) {
+    _trackpadScrollUnsent += (delta * _trackpadSpeed * 120);
+    final x = _trackpadScrollUnsent.dx.truncate();
+    final y = _trackpadScrollUnsent.dy.truncate();
+    _trackpadScrollUnsent -= Offset(x.toDouble(), y.toDouble());
+    if (x != 0 || y != 0) {
+      bind.sessionSendMouse(
+          sessionId: sessionId,
+          msg: '{"type": "hi_res_wheel", "x": "$x", "y": "$y"}');
+    }
+  }
+
   void _scheduleFling(double x, double y, int delay) {
     if ((x == 0 && y == 0) || _stopFling) {
       _fling = false;
@@ -552,11 +578,6 @@ class InputModel {
       // Try set delta (x,y) and delay.
       var dx = x.toInt();
       var dy = y.toInt();
-      if (parent.target?.ffiModel.pi.platform == kPeerPlatformLinux) {
-        dx = (x * _trackpadSpeed).toInt();
-        dy = (y * _trackpadSpeed).toInt();
-      }
-
       var delay = _flingBaseDelay;
 
       if (dx == 0 && dy == 0) {
@@ -564,6 +585,12 @@ class InputModel {
         return;
       }
 
+      if (parent.target?.ffiModel.pi.platform == kPeerPlatformLinux) {
+        _sendHiResScroll(Offset(x, y));
+        _scheduleFling(x, y, delay);
+        return;
+      }
+
       bind.sessionSendMouse(
           sessionId: sessionId,
           msg: '{"type": "trackpad", "x": "$dx", "y": "$dy"}');
@@ -612,6 +639,8 @@ class InputModel {
   void onPointDownImage(PointerDownEvent e) {
     debugPrint("onPointDownImage ${e.kind}");
     _stopFling = true;
+    if (_handlePen(e, true, false)) return;
+    if (_handleMultiTouch(e, true)) return;
     if (e.kind != ui.PointerDeviceKind.mouse) {
       if (isPhysicalMouse.value) {
         isPhysicalMouse.value = false;
@@ -623,6 +652,9 @@ class InputModel {
   }
 
   void onPointUpImage(PointerUpEvent e) {
+    // out of range after lifted, the hover events bring it back
+    if (_handlePen(e, false, false)) return;
+    if (_handleMultiTouch(e, false)) return;
     if (e.kind != ui.PointerDeviceKind.mouse) return;
     if (isPhysicalMouse.value) {
       handleMouse(_getMouseEvent(e, _kMouseEventUp), e.position);
@@ -630,12 +662,105 @@ class InputModel {
   }
 
   void onPointMoveImage(PointerMoveEvent e) {
+    if (_handlePen(e, true, false)) return;
+    if (_handleMultiTouch(e, true)) return;
     if (e.kind != ui.PointerDeviceKind.mouse) return;
     if (isPhysicalMouse.value) {
       handleMouse(_getMouseEvent(e, _kMouseEventMove), e.position);
     }
   }
 
+  void onPointCancelImage(PointerCancelEvent e) {
+    if (_handlePen(e, false, false)) return;
+    _handleMultiTouch(e, false);
+  }
+
+  // Returns true if the event is a stylus forwarded as pen.
+  bool _handlePen(PointerEvent e, bool down, bool hover) {
+    if (!isPen ||
+        (e.kind != ui.PointerDeviceKind.stylus &&
+            e.kind != ui.PointerDeviceKind.invertedStylus)) {
+      return false;
+    }
+    if (!keyboardPerm ||
+        _checkPeerControlProtected(e.position.dx, e.position.dy)) {
+      return true;
+    }
+    final pos = handlePointerDevicePos(kPointerEventKindPen, e.position.dx,
+        e.position.dy, false, down ? 'down' : 'up');
+    if (pos == null) {
+      if (!_penInRange) return true;
+      // leave the range at the edge
+      down = false;
+      hover = false;
+    }
+    _penInRange = down || hover;
+    final pressureRange = e.pressureMax - e.pressureMin;
+    final pressure = down && pressureRange > 0
+        ? ((e.pressure - e.pressureMin) / pressureRange * 1000).round()
+        : 0;
+    // the tilt and the orientation to the tilts along the axes, in degrees
+    final tanTilt = tan(e.tilt);
+    final tiltX = (atan(tanTilt * sin(e.orientation)) * 180 / pi).round();
+    final tiltY = (atan(-tanTilt * cos(e.orientation)) * 180 / pi).round();
+    final evt = {
+      'k': kPointerEventKindPen,
+      'v': {
+        'x': pos?.x ?? _penPos.x,
+        'y': pos?.y ?? _penPos.y,
+        'pressure': pressure,
+        'tilt_x': tiltX,
+        'tilt_y': tiltY,
+        'eraser': e.kind == ui.PointerDeviceKind.invertedStylus,
+        'barrel': (e.buttons & kPrimaryStylusButton) != 0,
+        'down': down,
+        'hover': hover,
+      },
+    };
+    if (pos != null) _penPos = pos;
+    bind.sessionSendPointer(
+        sessionId: sessionId, msg: json.encode(modify(evt)));
+    return true;
+  }
+
+  // Returns true if the event is a touch forwarded as multi-touch.
+  bool _handleMultiTouch(PointerEvent e, bool down) {
+    if (e.kind != ui.PointerDeviceKind.touch || !isMultiTouch) return false;
+    if (!keyboardPerm ||
+        _checkPeerControlProtected(e.position.dx, e.position.dy)) {
+      return true;
+    }
+    var pos = handlePointerDevicePos(kPointerEventKindTouch, e.position.dx,
+        e.position.dy, false, down ? 'down' : 'up');
+    if (down) {
+      if (pos == null) return true;
+      _touchPoints[e.pointer] = pos;
+    } else {
+      pos = _touchPoints.remove(e.pointer);
+      // not pressed on the remote
+      if (pos == null) return true;
+    }
+    final pressureRange = e.pressureMax - e.pressureMin;
+    final pressure = pressureRange > 0
+        ? ((e.pressure - e.pressureMin) / pressureRange * 1000).round()
+        : 0;
+    final evt = {
+      'k': kPointerEventKindMultiTouch,
+      'v': [
+        {
+          'id': e.pointer,
+          'x': pos.x,
+          'y': pos.y,
+          'pressure': pressure,
+          'down': down,
+        }
+      ],
+    };
+    bind.sessionSendPointer(
+        sessionId: sessionId, msg: json.encode(modify(evt)));
+    return true;
+  }
+
   void onPointerSignalImage(PointerSignalEvent e) {
     if (e is PointerScrollEvent) {
       var dx = e.scrollDelta.dx.toInt();
@@ -2453,6 +2453,9 @@ class PeerInfo with ChangeNotifier {
 
   bool get isWayland => platformAdditions[kPlatformAdditionsIsWayland] == true;
   bool get isHeadless => platformAdditions[kPlatformAdditionsHeadless] == true;
+  bool get isMultiTouch =>
+      platformAdditions[kPlatformAdditionsMultiTouch] == true;
+  bool get isPen => platformAdditions[kPlatformAdditionsPen] == true;
   bool get isInstalled =>
       platform != kPeerPlatformWindows ||
       platformAdditions[kPlatformAdditionsIsInstalled] == true;
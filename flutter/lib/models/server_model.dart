@@ -695,6 +695,7 @@ class Client {
   bool restart = false;
   bool recording = false;
   bool blockInput = false;
+  bool gamepad = false;
   bool disconnected = false;
   bool fromSwitch = false;
   bool inVoiceCall = false;
@@ -719,6 +720,7 @@ class Client {
     restart = json['restart'];
     recording = json['recording'];
     blockInput = json['block_input'];
+    gamepad = json['gamepad'] ?? false;
     disconnected = json['disconnected'];
     fromSwitch = json['from_switch'];
     inVoiceCall = json['in_voice_call'];
@@ -740,6 +742,7 @@ class Client {
     data['restart'] = restart;
     data['recording'] = recording;
     data['block_input'] = blockInput;
+    data['gamepad'] = gamepad;
     data['disconnected'] = disconnected;
     data['from_switch'] = fromSwitch;
     return data;
@@ -380,6 +380,13 @@ pub fn get_online_state() -> i64 {
     *ONLINE.lock().unwrap().values().max().unwrap_or(&0)
 }
 
+/// The registration latency of every rendezvous server, in ms.
+/// 0 means connecting, -1 means failed.
+#[inline]
+pub fn get_online_states() -> HashMap<String, i64> {
+    ONLINE.lock().unwrap().clone()
+}
+
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
 fn patch(path: PathBuf) -> PathBuf {
     if let Some(_tmp) = path.to_str() {
@@ -1,49 +1,83 @@
 use docopt::Docopt;
 use hbb_common::{
+    bail,
     env_logger::{init_from_env, Env, DEFAULT_FILTER_ENV},
-    log,
+    protobuf::Message as _,
+    ResultType,
 };
 use scrap::{
-    aom::{AomDecoder, AomEncoder, AomEncoderConfig},
-    codec::{EncoderApi, EncoderCfg, Quality as Q},
-    Capturer, Display, TraitCapturer, VpxDecoder, VpxDecoderConfig, VpxEncoder, VpxEncoderConfig,
-    VpxVideoCodecId::{self, *},
-    STRIDE_ALIGN,
+    aom::AomEncoderConfig,
+    codec::{Decoder, Encoder, EncoderCfg, Quality},
+    convert_raw_to_yuv, CodecFormat, EncodeInput, ImageFormat, ImageRgb, Pixfmt, VpxEncoderConfig,
+    VpxVideoCodecId::{VP8, VP9},
 };
 use std::{
-    io::Write,
+    fs::File,
+    io::{BufReader, Read, Write},
     time::{Duration, Instant},
 };
 
-// cargo run --package scrap --example benchmark --release --features hwcodec
+// cargo run --package scrap --example benchmark --release --features hwcodec -- --output=result.json
 
 const USAGE: &'static str = "
 Codec benchmark.
 
+Every compiled encoder is run at each quality and chroma on the same frames,
+and the decoded frames are compared with the source frames.
+The encoders which take textures, i.e. gpucodec, are not covered.
+
 Usage:
-  benchmark [--count=COUNT] [--quality=QUALITY] [--i444]
+  benchmark [--count=COUNT] [--size=SIZE] [--fps=FPS] [--input=FILE] [--output=FILE] [--tag=TAG]
   benchmark (-h | --help)
 
 Options:
   -h --help             Show this screen.
-  --count=COUNT         Capture frame count [default: 100].
-  --quality=QUALITY     Video quality [default: Balanced].
-                        Valid values: Best, Balanced, Low.
-  --i444                I444.
+  --count=COUNT         Frame count [default: 100].
+  --size=SIZE           Frame size [default: 1920x1080].
+  --fps=FPS             Frame rate, used for the timestamps and the bitrate [default: 30].
+  --input=FILE          Packed BGRA frames of the size, e.g. recorded with
+                        `ffmpeg -f x11grab -i :0 -pix_fmt bgra -f rawvideo FILE`.
+                        Synthetic frames are used if not set.
+  --output=FILE         Write the results to the file as json.
+  --tag=TAG             The tag of the results, e.g. the release version [default: ].
 ";
 
-#[derive(Debug, serde::Deserialize, Clone, Copy)]
+#[derive(Debug, serde::Deserialize)]
 struct Args {
     flag_count: usize,
-    flag_quality: Quality,
-    flag_i444: bool,
+    flag_size: String,
+    flag_fps: u32,
+    flag_input: Option<String>,
+    flag_output: Option<String>,
+    flag_tag: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct Report {
+    tag: String,
+    source: String,
+    width: usize,
+    height: usize,
+    fps: u32,
+    frames: usize,
+    results: Vec<Record>,
 }
 
-#[derive(Debug, serde::Deserialize, Clone, Copy)]
-enum Quality {
-    Best,
-    Balanced,
-    Low,
+#[derive(Debug, serde::Serialize)]
+struct Record {
+    codec: String,
+    quality: String,
+    chroma: String,
+    // frames which produce no output, e.g. skipped by the encoder
+    dropped: usize,
+    // average per frame, including the yuv conversion
+    encode_ms: f64,
+    // average per frame, including the rgb conversion
+    decode_ms: f64,
+    kbps: f64,
+    // average of the frames on the luma, in dB
+    psnr: f64,
+    ssim: f64,
 }
 
 fn main() {
@@ -51,290 +85,387 @@ fn main() {
     let args: Args = Docopt::new(USAGE)
         .and_then(|d| d.deserialize())
         .unwrap_or_else(|e| e.exit());
-    let quality = args.flag_quality;
-    let yuv_count = args.flag_count;
-    let mut index = 0;
-    let mut displays = Display::all().unwrap();
-    for i in 0..displays.len() {
-        if displays[i].is_primary() {
-            index = i;
-            break;
+    let (width, height) = match args
+        .flag_size
+        .split_once('x')
+        .and_then(|(w, h)| Some((w.parse::<usize>().ok()?, h.parse::<usize>().ok()?)))
+    {
+        // even, as required by some encoders
+        Some((w, h)) if w >= 2 && h >= 2 => (w & !1, h & !1),
+        _ => {
+            eprintln!("invalid size: {}", args.flag_size);
+            std::process::exit(1);
+        }
+    };
+    let mut source = match Source::new(args.flag_input.as_deref(), width, height) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("{e:?}");
+            std::process::exit(1);
+        }
+    };
+    let count = source
+        .count()
+        .map_or(args.flag_count, |n| n.min(args.flag_count));
+    let fps = std::cmp::max(args.flag_fps, 1);
+    println!("benchmark {width}x{height}, {count} frames, {fps} fps");
+
+    let mut results = vec![];
+    for Codec { name, i444, config } in encoders(width, height) {
+        for quality in [Quality::Best, Quality::Balanced, Quality::Low] {
+            for chroma in ["I420", "I444"] {
+                if chroma == "I444" && !i444 {
+                    println!("{name} {quality:?} {chroma}: not supported");
+                    continue;
+                }
+                match run(&mut source, config(quality), count, fps, chroma == "I444") {
+                    Ok(result) => {
+                        println!(
+                            "{name} {quality:?} {chroma}: encode {:.2}ms, decode {:.2}ms, {:.0}kbps, psnr {:.2}dB, ssim {:.4}, dropped {}",
+                            result.encode_ms,
+                            result.decode_ms,
+                            result.kbps,
+                            result.psnr,
+                            result.ssim,
+                            result.dropped
+                        );
+                        results.push(Record {
+                            codec: name.clone(),
+                            quality: format!("{quality:?}"),
+                            chroma: chroma.to_owned(),
+                            ..result
+                        });
+                    }
+                    Err(e) => println!("{name} {quality:?} {chroma}: {e:?}"),
+                }
+            }
         }
     }
-    let d = displays.remove(index);
-    let mut c = Capturer::new(d).unwrap();
-    let width = c.width();
-    let height = c.height();
 
-    println!(
-        "benchmark {}x{} quality:{:?}, i444:{:?}",
-        width, height, quality, args.flag_i444
-    );
-    let quality = match quality {
-        Quality::Best => Q::Best,
-        Quality::Balanced => Q::Balanced,
-        Quality::Low => Q::Low,
-    };
-    [VP8, VP9].map(|codec| {
-        test_vpx(
-            &mut c,
-            codec,
+    if let Some(output) = args.flag_output {
+        let report = Report {
+            tag: args.flag_tag,
+            source: args.flag_input.unwrap_or("synthetic".to_owned()),
             width,
             height,
-            quality,
-            yuv_count,
-            if codec == VP8 { false } else { args.flag_i444 },
-        )
+            fps,
+            frames: count,
+            results,
+        };
+        match File::create(&output)
+            .map_err(|e| e.to_string())
+            .and_then(|f| serde_json::to_writer_pretty(f, &report).map_err(|e| e.to_string()))
+        {
+            Ok(_) => println!("results written to {output}"),
+            Err(e) => eprintln!("failed to write {output}: {e}"),
+        }
+    }
+}
+
+struct Codec {
+    name: String,
+    // whether I444 is supported
+    i444: bool,
+    config: Box<dyn Fn(Quality) -> EncoderCfg>,
+}
+
+// Every compiled encoder.
+fn encoders(width: usize, height: usize) -> Vec<Codec> {
+    let mut v = vec![];
+    for codec in [VP8, VP9] {
+        v.push(Codec {
+            name: format!("{codec:?}"),
+            i444: codec == VP9,
+            config: Box::new(move |quality| {
+                EncoderCfg::VPX(VpxEncoderConfig {
+                    width: width as _,
+                    height: height as _,
+                    quality,
+                    codec,
+                    keyframe_interval: None,
+                })
+            }),
+        });
+    }
+    v.push(Codec {
+        name: "AV1".to_owned(),
+        i444: true,
+        config: Box::new(move |quality| {
+            EncoderCfg::AOM(AomEncoderConfig {
+                width: width as _,
+                height: height as _,
+                quality,
+                keyframe_interval: None,
+            })
+        }),
+    });
+    #[cfg(feature = "openh264")]
+    v.push(Codec {
+        name: "openh264".to_owned(),
+        i444: false,
+        config: Box::new(move |quality| {
+            EncoderCfg::OpenH264(scrap::openh264::OpenH264EncoderConfig {
+                width,
+                height,
+                quality,
+                keyframe_interval: None,
+            })
+        }),
     });
-    test_av1(&mut c, width, height, quality, yuv_count, args.flag_i444);
     #[cfg(feature = "hwcodec")]
     {
-        hw::test(&mut c, width, height, quality, yuv_count);
+        use scrap::hwcodec::{check_available_hwcodec, HwEncoder, HwEncoderConfig};
+        check_available_hwcodec();
+        let best = HwEncoder::best();
+        for info in [best.h264, best.h265].into_iter().flatten() {
+            let name = info.name.clone();
+            v.push(Codec {
+                name: info.name,
+                i444: false,
+                config: Box::new(move |quality| {
+                    EncoderCfg::HW(HwEncoderConfig {
+                        name: name.clone(),
+                        width,
+                        height,
+                        quality,
+                        keyframe_interval: None,
+                    })
+                }),
+            });
+        }
     }
+    v
 }
 
-fn test_vpx(
-    c: &mut Capturer,
-    codec_id: VpxVideoCodecId,
-    width: usize,
-    height: usize,
-    quality: Q,
-    yuv_count: usize,
+// Encode and decode the frames, and compare the decoded frames with the source frames.
+fn run(
+    source: &mut Source,
+    config: EncoderCfg,
+    count: usize,
+    fps: u32,
     i444: bool,
-) {
-    let config = EncoderCfg::VPX(VpxEncoderConfig {
-        width: width as _,
-        height: height as _,
-        quality,
-        codec: codec_id,
-        keyframe_interval: None,
-    });
-    let mut encoder = VpxEncoder::new(config, i444).unwrap();
-    let mut vpxs = vec![];
-    let start = Instant::now();
-    let mut size = 0;
-    let mut yuv = Vec::new();
-    let mut mid_data = Vec::new();
-    let mut counter = 0;
-    let mut time_sum = Duration::ZERO;
-    loop {
-        match c.frame(std::time::Duration::from_millis(30)) {
-            Ok(frame) => {
-                let tmp_timer = Instant::now();
-                let frame = frame.to(encoder.yuvfmt(), &mut yuv, &mut mid_data).unwrap();
-                let yuv = frame.yuv().unwrap();
-                for ref frame in encoder
-                    .encode(start.elapsed().as_millis() as _, &yuv, STRIDE_ALIGN)
-                    .unwrap()
-                {
-                    size += frame.data.len();
-                    vpxs.push(frame.data.to_vec());
-                    counter += 1;
-                    print!("\r{codec_id:?} {}/{}", counter, yuv_count);
-                    std::io::stdout().flush().ok();
-                }
-                for ref frame in encoder.flush().unwrap() {
-                    size += frame.data.len();
-                    vpxs.push(frame.data.to_vec());
-                    counter += 1;
-                    print!("\r{codec_id:?} {}/{}", counter, yuv_count);
-                    std::io::stdout().flush().ok();
-                }
-                time_sum += tmp_timer.elapsed();
-            }
-            Err(e) => {
-                log::error!("{e:?}");
-            }
-        }
-        if counter >= yuv_count {
-            println!();
+) -> ResultType<Record> {
+    let mut encoder = Encoder::new(config, i444)?;
+    let yuvfmt = encoder.yuvfmt();
+    let mut decoder: Option<Decoder> = None;
+    let mut rgb = ImageRgb::new(ImageFormat::ARGB, 1);
+    let mut texture = std::ptr::null_mut();
+    let mut pixelbuffer = true;
+    let mut chroma = None;
+    let (width, height) = (source.width, source.height);
+    let mut bgra = vec![];
+    let mut yuv = vec![];
+    let mut mid_data = vec![];
+    let (mut encode_time, mut decode_time) = (Duration::ZERO, Duration::ZERO);
+    let (mut size, mut dropped, mut compared) = (0, 0, 0);
+    let (mut psnr, mut ssim) = (0., 0.);
+    source.reset()?;
+    for i in 0..count {
+        if !source.next(i, &mut bgra)? {
             break;
         }
+        let ms = i as i64 * 1000 / fps as i64;
+        let start = Instant::now();
+        convert_raw_to_yuv(
+            &bgra,
+            &[width * 4],
+            Pixfmt::BGRA,
+            width,
+            height,
+            yuvfmt.clone(),
+            &mut yuv,
+            &mut mid_data,
+        )?;
+        let vf = encoder.encode_to_message(EncodeInput::YUV(&yuv), ms);
+        encode_time += start.elapsed();
+        let Ok(vf) = vf else {
+            dropped += 1;
+            continue;
+        };
+        size += vf.compute_size() as usize;
+        let Some(frame) = vf.union.as_ref() else {
+            dropped += 1;
+            continue;
+        };
+        let decoder = decoder.get_or_insert_with(|| Decoder::new(CodecFormat::from(&vf), None));
+        let start = Instant::now();
+        let decoded = decoder.handle_video_frame(
+            frame,
+            &mut rgb,
+            &mut texture,
+            &mut pixelbuffer,
+            &mut chroma,
+        )?;
+        decode_time += start.elapsed();
+        if !decoded {
+            dropped += 1;
+            continue;
+        }
+        if (rgb.w, rgb.h) != (width, height) {
+            bail!("decoded {}x{} != {}x{}", rgb.w, rgb.h, width, height);
+        }
+        let stride = (width * 4 + rgb.stride() - 1) & !(rgb.stride() - 1);
+        let (a, b) = (
+            luma(&bgra, width, height, width * 4),
+            luma(&rgb.raw, width, height, stride),
+        );
+        psnr += frame_psnr(&a, &b);
+        ssim += frame_ssim(&a, &b, width, height);
+        compared += 1;
+        print!("\r{}/{}", i + 1, count);
+        std::io::stdout().flush().ok();
     }
-
-    assert_eq!(vpxs.len(), yuv_count);
-    println!(
-        "{:?} encode: {:?}, {} byte",
-        codec_id,
-        time_sum / yuv_count as _,
-        size / yuv_count
-    );
-
-    let mut decoder = VpxDecoder::new(VpxDecoderConfig { codec: codec_id }).unwrap();
-    let start = Instant::now();
-    for vpx in vpxs {
-        let _ = decoder.decode(&vpx);
-        let _ = decoder.flush();
-    }
-    println!(
-        "{:?} decode: {:?}",
-        codec_id,
-        start.elapsed() / yuv_count as _
-    );
+    print!("\r");
+    let frames = std::cmp::max(compared + dropped, 1);
+    let seconds = frames as f64 / fps as f64;
+    let compared_f = std::cmp::max(compared, 1) as f64;
+    Ok(Record {
+        codec: Default::default(),
+        quality: Default::default(),
+        chroma: Default::default(),
+        dropped,
+        encode_ms: encode_time.as_secs_f64() * 1000. / frames as f64,
+        decode_ms: decode_time.as_secs_f64() * 1000. / compared_f,
+        kbps: size as f64 * 8. / 1000. / seconds,
+        psnr: psnr / compared_f,
+        ssim: ssim / compared_f,
+    })
 }
 
-fn test_av1(
-    c: &mut Capturer,
+// The frames to encode, read from a file of packed BGRA frames, or drawn.
+struct Source {
     width: usize,
     height: usize,
-    quality: Q,
-    yuv_count: usize,
-    i444: bool,
-) {
-    let config = EncoderCfg::AOM(AomEncoderConfig {
-        width: width as _,
-        height: height as _,
-        quality,
-        keyframe_interval: None,
-    });
-    let mut encoder = AomEncoder::new(config, i444).unwrap();
-    let start = Instant::now();
-    let mut size = 0;
-    let mut av1s: Vec<Vec<u8>> = vec![];
-    let mut yuv = Vec::new();
-    let mut mid_data = Vec::new();
-    let mut counter = 0;
-    let mut time_sum = Duration::ZERO;
-    loop {
-        match c.frame(std::time::Duration::from_millis(30)) {
-            Ok(frame) => {
-                let tmp_timer = Instant::now();
-                let frame = frame.to(encoder.yuvfmt(), &mut yuv, &mut mid_data).unwrap();
-                let yuv = frame.yuv().unwrap();
-                for ref frame in encoder
-                    .encode(start.elapsed().as_millis() as _, &yuv, STRIDE_ALIGN)
-                    .unwrap()
-                {
-                    size += frame.data.len();
-                    av1s.push(frame.data.to_vec());
-                    counter += 1;
-                    print!("\rAV1 {}/{}", counter, yuv_count);
-                    std::io::stdout().flush().ok();
-                }
-                time_sum += tmp_timer.elapsed();
-            }
-            Err(e) => {
-                log::error!("{e:?}");
-            }
-        }
-        if counter >= yuv_count {
-            println!();
-            break;
-        }
-    }
-    assert_eq!(av1s.len(), yuv_count);
-    println!(
-        "AV1 encode: {:?}, {} byte",
-        time_sum / yuv_count as _,
-        size / yuv_count
-    );
-    let mut decoder = AomDecoder::new().unwrap();
-    let start = Instant::now();
-    for av1 in av1s {
-        let _ = decoder.decode(&av1);
-        let _ = decoder.flush();
-    }
-    println!("AV1 decode: {:?}", start.elapsed() / yuv_count as _);
+    input: Option<(String, BufReader<File>)>,
 }
 
-#[cfg(feature = "hwcodec")]
-mod hw {
-    use hwcodec::ffmpeg::CodecInfo;
-    use scrap::{
-        hwcodec::{HwDecoder, HwEncoder, HwEncoderConfig},
-        CodecFormat,
-    };
+impl Source {
+    fn new(input: Option<&str>, width: usize, height: usize) -> ResultType<Self> {
+        let input = match input {
+            Some(path) => Some((path.to_owned(), BufReader::new(File::open(path)?))),
+            None => None,
+        };
+        Ok(Self {
+            width,
+            height,
+            input,
+        })
+    }
 
-    use super::*;
+    // The count of the frames in the file, `None` for the synthetic frames.
+    fn count(&self) -> Option<usize> {
+        let (_, reader) = self.input.as_ref()?;
+        let len = reader.get_ref().metadata().ok()?.len() as usize;
+        Some(len / (self.width * self.height * 4))
+    }
 
-    pub fn test(c: &mut Capturer, width: usize, height: usize, quality: Q, yuv_count: usize) {
-        let best = HwEncoder::best();
-        let mut h264s = Vec::new();
-        let mut h265s = Vec::new();
-        if let Some(info) = best.h264 {
-            test_encoder(width, height, quality, info, c, yuv_count, &mut h264s);
+    fn reset(&mut self) -> ResultType<()> {
+        if let Some((path, reader)) = self.input.as_mut() {
+            *reader = BufReader::new(File::open(path)?);
+        }
+        Ok(())
+    }
+
+    // Fill the next frame, false if there's no more frame.
+    fn next(&mut self, index: usize, bgra: &mut Vec<u8>) -> ResultType<bool> {
+        bgra.resize(self.width * self.height * 4, 0);
+        match self.input.as_mut() {
+            Some((_, reader)) => match reader.read_exact(bgra) {
+                Ok(_) => Ok(true),
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+                Err(e) => Err(e.into()),
+            },
+            None => {
+                draw(index, self.width, self.height, bgra);
+                Ok(true)
+            }
         }
-        if let Some(info) = best.h265 {
-            test_encoder(width, height, quality, info, c, yuv_count, &mut h265s);
+    }
+}
+
+// A desktop like frame: a static gradient with fine text like stripes, and a window moving to the right.
+fn draw(index: usize, width: usize, height: usize, bgra: &mut [u8]) {
+    let window = (width / 4, height / 4);
+    let window_x = (index * 8) % std::cmp::max(width - window.0, 1);
+    let window_y = height / 3;
+    for (y, row) in bgra.chunks_exact_mut(width * 4).enumerate() {
+        for (x, px) in row.chunks_exact_mut(4).enumerate() {
+            let in_window = x >= window_x
+                && x < window_x + window.0
+                && y >= window_y
+                && y < window_y + window.1;
+            let v = if in_window {
+                let (wx, wy) = (x - window_x, y - window_y);
+                if (wy / 2) % 8 < 6 && (wx * 7 + wy * 3) % 11 < 5 {
+                    [30, 30, 30]
+                } else {
+                    [240, 240, 240]
+                }
+            } else if y % 16 < 10 && x % 96 < 80 && (x / 3 + y) % 5 < 2 {
+                [0, 0, 0]
+            } else {
+                [
+                    (x * 255 / width) as u8,
+                    (y * 255 / height) as u8,
+                    ((x + y) * 127 / (width + height)) as u8,
+                ]
+            };
+            px.copy_from_slice(&[v[0], v[1], v[2], 255]);
         }
-        test_decoder(CodecFormat::H264, &h264s);
-        test_decoder(CodecFormat::H265, &h265s);
     }
+}
 
-    fn test_encoder(
-        width: usize,
-        height: usize,
-        quality: Q,
-        info: CodecInfo,
-        c: &mut Capturer,
-        yuv_count: usize,
-        h26xs: &mut Vec<Vec<u8>>,
-    ) {
-        let mut encoder = HwEncoder::new(
-            EncoderCfg::HW(HwEncoderConfig {
-                name: info.name.clone(),
-                width,
-                height,
-                quality,
-                keyframe_interval: None,
-            }),
-            false,
-        )
-        .unwrap();
-        let mut size = 0;
+// The BT.601 luma of BGRA pixels.
+fn luma(bgra: &[u8], width: usize, height: usize, stride: usize) -> Vec<f64> {
+    let mut v = Vec::with_capacity(width * height);
+    for row in bgra.chunks(stride).take(height) {
+        for px in row[..width * 4].chunks_exact(4) {
+            v.push(0.114 * px[0] as f64 + 0.587 * px[1] as f64 + 0.299 * px[2] as f64);
+        }
+    }
+    v
+}
 
-        let mut yuv = Vec::new();
-        let mut mid_data = Vec::new();
-        let mut counter = 0;
-        let mut time_sum = Duration::ZERO;
-        loop {
-            match c.frame(std::time::Duration::from_millis(30)) {
-                Ok(frame) => {
-                    let tmp_timer = Instant::now();
-                    let frame = frame.to(encoder.yuvfmt(), &mut yuv, &mut mid_data).unwrap();
-                    let yuv = frame.yuv().unwrap();
-                    for ref frame in encoder.encode(&yuv).unwrap() {
-                        size += frame.data.len();
+// Capped at 100dB for the identical frames.
+fn frame_psnr(a: &[f64], b: &[f64]) -> f64 {
+    let mse = a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum::<f64>() / a.len() as f64;
+    if mse <= 0. {
+        100.
+    } else {
+        (10. * (255. * 255. / mse).log10()).min(100.)
+    }
+}
 
-                        h26xs.push(frame.data.to_vec());
-                        counter += 1;
-                        print!("\r{:?} {}/{}", info.name, counter, yuv_count);
-                        std::io::stdout().flush().ok();
-                    }
-                    time_sum += tmp_timer.elapsed();
-                }
-                Err(e) => {
-                    log::error!("{e:?}");
+// The mean SSIM of the 8x8 blocks.
+fn frame_ssim(a: &[f64], b: &[f64], width: usize, height: usize) -> f64 {
+    const BLOCK: usize = 8;
+    const C1: f64 = (0.01 * 255.) * (0.01 * 255.);
+    const C2: f64 = (0.03 * 255.) * (0.03 * 255.);
+    let n = (BLOCK * BLOCK) as f64;
+    let (mut sum, mut blocks) = (0., 0);
+    for by in (0..height.saturating_sub(BLOCK - 1)).step_by(BLOCK) {
+        for bx in (0..width.saturating_sub(BLOCK - 1)).step_by(BLOCK) {
+            let (mut sa, mut sb, mut saa, mut sbb, mut sab) = (0., 0., 0., 0., 0.);
+            for y in by..by + BLOCK {
+                for x in bx..bx + BLOCK {
+                    let (pa, pb) = (a[y * width + x], b[y * width + x]);
+                    sa += pa;
+                    sb += pb;
+                    saa += pa * pa;
+                    sbb += pb * pb;
+                    sab += pa * pb;
                 }
             }
-            if counter >= yuv_count {
-                println!();
-                break;
-            }
+            let (ma, mb) = (sa / n, sb / n);
+            let (va, vb, cov) = (saa / n - ma * ma, sbb / n - mb * mb, sab / n - ma * mb);
+            sum += ((2. * ma * mb + C1) * (2. * cov + C2))
+                / ((ma * ma + mb * mb + C1) * (va + vb + C2));
+            blocks += 1;
         }
-        println!(
-            "{}: {:?}, {} byte",
-            info.name,
-            time_sum / yuv_count as u32,
-            size / yuv_count,
-        );
     }
-
-    fn test_decoder(format: CodecFormat, h26xs: &Vec<Vec<u8>>) {
-        let mut decoder = HwDecoder::new(format).unwrap();
-        let start = Instant::now();
-        let mut cnt = 0;
-        for h26x in h26xs {
-            let _ = decoder.decode(h26x).unwrap();
-            cnt += 1;
-        }
-        let device = format!("{:?}", decoder.info.hwdevice).to_lowercase();
-        let device = device.split("_").last().unwrap();
-        println!(
-            "{} {}: {:?}",
-            decoder.info.name,
-            device,
-            start.elapsed() / cnt
-        );
+    if blocks == 0 {
+        1.
+    } else {
+        sum / blocks as f64
     }
 }
@@ -11,9 +11,12 @@ use crate::gpucodec::*;
 use crate::hwcodec::*;
 #[cfg(feature = "mediacodec")]
 use crate::mediacodec::{MediaCodecDecoder, H264_DECODER_SUPPORT, H265_DECODER_SUPPORT};
+#[cfg(feature = "openh264")]
+use crate::openh264::{OpenH264Decoder, OpenH264Encoder, OpenH264EncoderConfig};
 use crate::{
     aom::{self, AomDecoder, AomEncoder, AomEncoderConfig},
     common::GoogleImage,
+    lossless,
     vpxcodec::{self, VpxDecoder, VpxDecoderConfig, VpxEncoder, VpxEncoderConfig, VpxVideoCodecId},
     CodecFormat, CodecName, EncodeInput, EncodeYuvFormat, ImageRgb,
 };
@@ -50,6 +53,8 @@ pub enum EncoderCfg {
     HW(HwEncoderConfig),
     #[cfg(feature = "gpucodec")]
     GPU(GpuEncoderConfig),
+    #[cfg(feature = "openh264")]
+    OpenH264(OpenH264EncoderConfig),
 }
 
 pub trait EncoderApi {
@@ -105,6 +110,8 @@ pub struct Decoder {
     h264_media_codec: MediaCodecDecoder,
     #[cfg(feature = "mediacodec")]
     h265_media_codec: MediaCodecDecoder,
+    #[cfg(feature = "openh264")]
+    h264_sw: Option<OpenH264Decoder>,
     format: CodecFormat,
     valid: bool,
     #[cfg(feature = "hwcodec")]
@@ -154,6 +161,10 @@ impl Encoder {
                     Err(e)
                 }
             },
+            #[cfg(feature = "openh264")]
+            EncoderCfg::OpenH264(_) => Ok(Encoder {
+                codec: Box::new(OpenH264Encoder::new(config, i444)?),
+            }),
         }
     }
 
@@ -216,8 +227,15 @@ impl Encoder {
                 h265hw_encoding = best.h265.map_or(None, |c| Some(c.name));
             }
         }
-        let h264_useable =
-            _all_support_h264_decoding && (h264gpu_encoding || h264hw_encoding.is_some());
+        // software encoding, the last choice of h264
+        #[allow(unused_mut)]
+        let mut h264sw_encoding: Option<CodecName> = None;
+        #[cfg(feature = "openh264")]
+        if _all_support_h264_decoding {
+            h264sw_encoding = Some(CodecName::H264SW);
+        }
+        let h264_useable = _all_support_h264_decoding
+            && (h264gpu_encoding || h264hw_encoding.is_some() || h264sw_encoding.is_some());
         let h265_useable =
             _all_support_h265_decoding && (h265gpu_encoding || h265hw_encoding.is_some());
         let mut name = ENCODE_CODEC_NAME.lock().unwrap();
@@ -258,6 +276,8 @@ impl Encoder {
                     CodecName::H264GPU
                 } else if let Some(v) = h264hw_encoding {
                     CodecName::H264HW(v)
+                } else if let Some(v) = h264sw_encoding {
+                    v
                 } else {
                     auto_codec
                 }
@@ -315,6 +335,10 @@ impl Encoder {
             encoding.h264 |= GpuEncoder::available(CodecName::H264GPU).len() > 0;
             encoding.h265 |= GpuEncoder::available(CodecName::H265GPU).len() > 0;
         }
+        #[cfg(feature = "openh264")]
+        {
+            encoding.h264 = true;
+        }
         encoding
     }
 
@@ -345,6 +369,8 @@ impl Encoder {
                     return;
                 }
             },
+            #[cfg(feature = "openh264")]
+            EncoderCfg::OpenH264(_) => CodecName::H264SW,
         };
         let current = ENCODE_CODEC_NAME.lock().unwrap().clone();
         if current != name {
@@ -368,9 +394,17 @@ impl Encoder {
             EncoderCfg::HW(_) => false,
             #[cfg(feature = "gpucodec")]
             EncoderCfg::GPU(_) => false,
+            #[cfg(feature = "openh264")]
+            EncoderCfg::OpenH264(_) => false,
         };
         prefer_i444 && i444_useable && !decodings.is_empty()
     }
+
+    /// Whether all peers can decode the lossless frames.
+    pub fn lossless_supported() -> bool {
+        let decodings = PEER_DECODINGS.lock().unwrap();
+        decodings.len() > 0 && decodings.iter().all(|(_, s)| s.ability_lossless > 0)
+    }
 }
 
 impl Decoder {
@@ -387,6 +421,7 @@ impl Decoder {
             ability_vp8: 1,
             ability_vp9: 1,
             ability_av1: 1,
+            ability_lossless: 1,
             i444: Some(CodecAbility {
                 vp9: true,
                 av1: true,
@@ -431,6 +466,10 @@ impl Decoder {
                     0
                 };
         }
+        #[cfg(feature = "openh264")]
+        {
+            decoding.ability_h264 = 1;
+        }
         for unsupported in mark_unsupported {
             match unsupported {
                 CodecFormat::VP8 => decoding.ability_vp8 = 0,
@@ -453,6 +492,8 @@ impl Decoder {
         let (mut h264_vram, mut h265_vram) = (None, None);
         #[cfg(feature = "mediacodec")]
         let (mut h264_media_codec, mut h265_media_codec) = (None, None);
+        #[cfg(feature = "openh264")]
+        let mut h264_sw = None;
         let mut valid = false;
 
         match format {
@@ -506,6 +547,14 @@ impl Decoder {
                     }
                     valid = h264_media_codec.is_some();
                 }
+                #[cfg(feature = "openh264")]
+                if !valid {
+                    match OpenH264Decoder::new() {
+                        Ok(v) => h264_sw = Some(v),
+                        Err(e) => log::error!("create H264 software decoder failed: {}", e),
+                    }
+                    valid = h264_sw.is_some();
+                }
             }
             CodecFormat::H265 => {
                 #[cfg(feature = "gpucodec")]
@@ -558,6 +607,8 @@ impl Decoder {
             h264_media_codec,
             #[cfg(feature = "mediacodec")]
             h265_media_codec,
+            #[cfg(feature = "openh264")]
+            h264_sw,
             format,
             valid,
             #[cfg(feature = "hwcodec")]
@@ -604,7 +655,7 @@ impl Decoder {
                     bail!("av1 decoder not available");
                 }
             }
-            #[cfg(any(feature = "hwcodec", feature = "gpucodec"))]
+            #[cfg(any(feature = "hwcodec", feature = "gpucodec", feature = "openh264"))]
             video_frame::Union::H264s(h264s) => {
                 *chroma = Some(Chroma::I420);
                 #[cfg(feature = "gpucodec")]
@@ -616,6 +667,10 @@ impl Decoder {
                 if let Some(decoder) = &mut self.h264_ram {
                     return Decoder::handle_hw_video_frame(decoder, h264s, rgb, &mut self.i420);
                 }
+                #[cfg(feature = "openh264")]
+                if let Some(decoder) = &mut self.h264_sw {
+                    return decoder.decode(h264s, rgb);
+                }
                 Err(anyhow!("don't support h264!"))
             }
             #[cfg(any(feature = "hwcodec", feature = "gpucodec"))]
@@ -650,6 +705,11 @@ impl Decoder {
                     Err(anyhow!("don't support h265!"))
                 }
             }
+            video_frame::Union::Lossless(frame) => {
+                *chroma = Some(Chroma::I444);
+                *_pixelbuffer = true;
+                lossless::decode(frame, rgb)
+            }
             _ => Err(anyhow!("unsupported video frame type!")),
         }
     }
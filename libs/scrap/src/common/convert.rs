@@ -200,11 +200,138 @@ pub fn convert_to_yuv(
     dst: &mut Vec<u8>,
     mid_data: &mut Vec<u8>,
 ) -> ResultType<()> {
+    convert_raw_to_yuv(
+        captured.data(),
+        &captured.stride(),
+        captured.pixfmt(),
+        captured.width(),
+        captured.height(),
+        dst_fmt,
+        dst,
+        mid_data,
+    )
+}
+
+/// Crop the captured image to `crop` (x, y, width, height) and resize it to `size` before converting it to yuv,
+/// for the shared area and the downscaled video.
+#[cfg(not(target_os = "ios"))]
+pub fn convert_to_yuv_scaled(
+    captured: &PixelBuffer,
+    crop: Option<(usize, usize, usize, usize)>,
+    size: (usize, usize),
+    dst_fmt: EncodeYuvFormat,
+    dst: &mut Vec<u8>,
+    mid_data: &mut Vec<u8>,
+    scaled: &mut Vec<u8>,
+) -> ResultType<()> {
+    let (width, height) = size;
+    if crop.is_none() && (captured.width(), captured.height()) == size {
+        return convert_to_yuv(captured, dst_fmt, dst, mid_data);
+    }
+    let pixfmt = captured.pixfmt();
+    if pixfmt != crate::Pixfmt::BGRA && pixfmt != crate::Pixfmt::RGBA {
+        bail!("crop or scale not support, {pixfmt:?}");
+    }
+    let (x, y, w, h) = crop.unwrap_or((0, 0, captured.width(), captured.height()));
+    if w == 0 || h == 0 || x + w > captured.width() || y + h > captured.height() {
+        bail!(
+            "wrong crop rect, {:?} in {}x{}",
+            (x, y, w, h),
+            captured.width(),
+            captured.height()
+        );
+    }
     let src = captured.data();
-    let src_stride = captured.stride();
-    let src_pixfmt = captured.pixfmt();
-    let src_width = captured.width();
-    let src_height = captured.height();
+    let src_stride = captured.stride()[0];
+    if src.len() < src_stride * captured.height() {
+        bail!(
+            "wrong src len, {} < {} * {}",
+            src.len(),
+            src_stride,
+            captured.height()
+        );
+    }
+    let src = &src[y * src_stride + x * 4..];
+    // The byte order doesn't matter for cropping and scaling.
+    let stride = width * 4;
+    scaled.resize(stride * height, 0);
+    if (w, h) == size {
+        for row in 0..h {
+            let offset = row * src_stride;
+            scaled[row * stride..(row + 1) * stride]
+                .copy_from_slice(&src[offset..offset + stride]);
+        }
+    } else {
+        call_yuv!(ARGBScale(
+            src.as_ptr(),
+            src_stride as _,
+            w as _,
+            h as _,
+            scaled.as_mut_ptr(),
+            stride as _,
+            width as _,
+            height as _,
+            FilterMode::kFilterBox,
+        ));
+    }
+    convert_raw_to_yuv(
+        scaled,
+        &[stride],
+        pixfmt,
+        width,
+        height,
+        dst_fmt,
+        dst,
+        mid_data,
+    )
+}
+
+/// Resize a decoded rgb image, e.g. restore a downscaled video frame to the display size.
+pub fn scale_rgb(
+    rgb: &mut crate::ImageRgb,
+    width: usize,
+    height: usize,
+    tmp: &mut Vec<u8>,
+) -> ResultType<()> {
+    if let crate::ImageFormat::Raw = rgb.fmt() {
+        bail!("scale not support, raw rgb");
+    }
+    let align = |w: usize| (w * 4 + rgb.stride() - 1) & !(rgb.stride() - 1);
+    let src_stride = align(rgb.w);
+    let dst_stride = align(width);
+    if rgb.raw.len() < src_stride * rgb.h {
+        bail!("wrong rgb len, {} < {} * {}", rgb.raw.len(), src_stride, rgb.h);
+    }
+    std::mem::swap(&mut rgb.raw, tmp);
+    rgb.raw.resize(dst_stride * height, 0);
+    call_yuv!(ARGBScale(
+        tmp.as_ptr(),
+        src_stride as _,
+        rgb.w as _,
+        rgb.h as _,
+        rgb.raw.as_mut_ptr(),
+        dst_stride as _,
+        width as _,
+        height as _,
+        FilterMode::kFilterBilinear,
+    ));
+    rgb.w = width;
+    rgb.h = height;
+    Ok(())
+}
+
+/// Convert the packed pixels which are not captured, e.g. recorded or synthetic frames.
+#[cfg(not(target_os = "ios"))]
+pub fn convert_raw_to_yuv(
+    src: &[u8],
+    src_stride: &[usize],
+    src_pixfmt: crate::Pixfmt,
+    src_width: usize,
+    src_height: usize,
+    dst_fmt: EncodeYuvFormat,
+    dst: &mut Vec<u8>,
+    mid_data: &mut Vec<u8>,
+) -> ResultType<()> {
     if src_width > dst_fmt.w || src_height > dst_fmt.h {
         bail!(
             "src rect > dst rect: ({src_width}, {src_height}) > ({},{})",
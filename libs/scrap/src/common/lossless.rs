@@ -0,0 +1,350 @@
+// Lossless frames for the static screen content, e.g. text, which is blurred by the lossy codecs.
+//
+// The frame is split into tiles, and only the changed tiles are sent, as zstd compressed BGRA pixels.
+
+#[cfg(not(target_os = "ios"))]
+use crate::{DirtyRect, PixelBuffer, Pixfmt, TraitPixelBuffer};
+use crate::{ImageFormat, ImageRgb};
+#[cfg(not(target_os = "ios"))]
+use hbb_common::message_proto::VideoFrame;
+use hbb_common::{
+    bail,
+    compress::{compress, decompress},
+    message_proto::{LosslessFrame, LosslessTile},
+    ResultType,
+};
+
+const TILE_SIZE: usize = 64;
+// The max width and height of a frame, larger than any display.
+const MAX_SIZE: usize = 16384;
+
+#[cfg(not(target_os = "ios"))]
+#[derive(Default)]
+pub struct LosslessEncoder {
+    width: usize,
+    height: usize,
+    pixfmt: Option<Pixfmt>,
+    // the last frame in the captured pixel format, rows are packed
+    last: Vec<u8>,
+    // the tiles changed by the last update
+    changed: Vec<LosslessTile>,
+}
+
+#[cfg(not(target_os = "ios"))]
+impl LosslessEncoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compare the frame with the last one, and return the ratio of the changed area, from 0 to 1.
+    ///
+    /// `crop` is the (x, y, width, height) of the captured image to use, the whole image if `None`.
+    /// Only the tiles overlapping `dirty_rects` are compared if they are known, e.g. from XDamage.
+    pub fn update(
+        &mut self,
+        captured: &PixelBuffer,
+        crop: Option<(usize, usize, usize, usize)>,
+        dirty_rects: Option<&[DirtyRect]>,
+    ) -> ResultType<f32> {
+        let pixfmt = captured.pixfmt();
+        if pixfmt != Pixfmt::BGRA && pixfmt != Pixfmt::RGBA {
+            bail!("lossless not support, {pixfmt:?}");
+        }
+        let (x, y, width, height) = crop.unwrap_or((0, 0, captured.width(), captured.height()));
+        if width == 0
+            || height == 0
+            || x + width > captured.width()
+            || y + height > captured.height()
+        {
+            bail!(
+                "wrong crop rect, {:?} in {}x{}",
+                (x, y, width, height),
+                captured.width(),
+                captured.height()
+            );
+        }
+        let src = captured.data();
+        let src_stride = captured.stride()[0];
+        if src.len() < src_stride * captured.height() {
+            bail!(
+                "wrong src len, {} < {} * {}",
+                src.len(),
+                src_stride,
+                captured.height()
+            );
+        }
+        let reset = (self.width, self.height) != (width, height) || self.pixfmt != Some(pixfmt);
+        if reset {
+            self.width = width;
+            self.height = height;
+            self.pixfmt = Some(pixfmt);
+            self.last = vec![0; width * height * 4];
+        }
+        self.changed.clear();
+        let stride = width * 4;
+        let mut changed_area = 0;
+        for tile in tiles(width, height) {
+            let (tx, ty, tw, th) = (
+                tile.x as usize,
+                tile.y as usize,
+                tile.width as usize,
+                tile.height as usize,
+            );
+            if !reset
+                && !dirty_rects.map_or(true, |rects| {
+                    rects.iter().any(|r| overlaps(r, (x + tx, y + ty, tw, th)))
+                })
+            {
+                continue;
+            }
+            let mut changed = reset;
+            for row in ty..ty + th {
+                let s = (y + row) * src_stride + (x + tx) * 4;
+                let d = row * stride + tx * 4;
+                let src_row = &src[s..s + tw * 4];
+                let last_row = &mut self.last[d..d + tw * 4];
+                // The rows above are equal if the change is found here.
+                if changed || src_row != last_row {
+                    changed = true;
+                    last_row.copy_from_slice(src_row);
+                }
+            }
+            if changed {
+                changed_area += tw * th;
+                self.changed.push(tile);
+            }
+        }
+        Ok(changed_area as f32 / (width * height) as f32)
+    }
+
+    /// Encode the tiles changed by the last update, or all the tiles if `key`.
+    ///
+    /// `None` if nothing changed.
+    pub fn encode(&self, key: bool) -> ResultType<Option<VideoFrame>> {
+        if self.last.is_empty() || (!key && self.changed.is_empty()) {
+            return Ok(None);
+        }
+        let tiles: Vec<LosslessTile> = if key {
+            tiles(self.width, self.height).collect()
+        } else {
+            self.changed.clone()
+        };
+        let stride = self.width * 4;
+        let mut raw = Vec::with_capacity(
+            tiles
+                .iter()
+                .map(|t| (t.width * t.height * 4) as usize)
+                .sum(),
+        );
+        for t in tiles.iter() {
+            let (tx, ty, tw, th) = (
+                t.x as usize,
+                t.y as usize,
+                t.width as usize,
+                t.height as usize,
+            );
+            for row in ty..ty + th {
+                let d = row * stride + tx * 4;
+                raw.extend_from_slice(&self.last[d..d + tw * 4]);
+            }
+        }
+        if self.pixfmt == Some(Pixfmt::RGBA) {
+            raw.chunks_exact_mut(4).for_each(|px| px.swap(0, 2));
+        }
+        let data = compress(&raw);
+        if data.is_empty() {
+            bail!("failed to compress lossless frame");
+        }
+        let mut vf = VideoFrame::new();
+        vf.set_lossless(LosslessFrame {
+            width: self.width as _,
+            height: self.height as _,
+            key,
+            tiles: tiles.into(),
+            data: data.into(),
+            ..Default::default()
+        });
+        Ok(Some(vf))
+    }
+}
+
+#[cfg(not(target_os = "ios"))]
+fn overlaps(r: &DirtyRect, (x, y, width, height): (usize, usize, usize, usize)) -> bool {
+    r.x < x + width && x < r.x + r.width && r.y < y + height && y < r.y + r.height
+}
+
+/// Draw the tiles of a lossless frame into `rgb`, which must hold the last frame unless it's a key frame.
+///
+/// The frame comes from the peer, it's checked before anything is allocated.
+pub fn decode(frame: &LosslessFrame, rgb: &mut ImageRgb) -> ResultType<bool> {
+    let swap = match rgb.fmt() {
+        ImageFormat::ARGB => false,
+        ImageFormat::ABGR => true,
+        ImageFormat::Raw => bail!("lossless not support raw rgb"),
+    };
+    let (width, height) = (frame.width as usize, frame.height as usize);
+    if width == 0 || height == 0 || width > MAX_SIZE || height > MAX_SIZE {
+        bail!("wrong lossless frame size, {}x{}", width, height);
+    }
+    let align = rgb.stride().max(1);
+    let stride = (width * 4 + align - 1) & !(align - 1);
+    let Some(len) = stride.checked_mul(height) else {
+        bail!("wrong lossless frame size, {}x{}", width, height);
+    };
+    let mut area = 0;
+    for t in frame.tiles.iter() {
+        let (x, y, w, h) = (
+            t.x as usize,
+            t.y as usize,
+            t.width as usize,
+            t.height as usize,
+        );
+        if x.checked_add(w).map_or(true, |r| r > width)
+            || y.checked_add(h).map_or(true, |b| b > height)
+        {
+            bail!(
+                "wrong lossless tile, {:?} in {}x{}",
+                (x, y, w, h),
+                width,
+                height
+            );
+        }
+        area += w * h;
+        if area > width * height {
+            bail!("lossless tiles over the frame");
+        }
+    }
+    if frame.key && area != width * height {
+        bail!("lossless key frame not covered by the tiles");
+    }
+    let data = decompress(&frame.data);
+    if data.len() != area * 4 {
+        bail!("wrong lossless data len, {} != {}", data.len(), area * 4);
+    }
+    if frame.key {
+        rgb.w = width;
+        rgb.h = height;
+        rgb.raw.resize(len, 0);
+    } else if (rgb.w, rgb.h) != (width, height) || rgb.raw.len() < len {
+        bail!("lossless frame without key frame");
+    }
+    let mut offset = 0;
+    for t in frame.tiles.iter() {
+        let (x, y, w, h) = (
+            t.x as usize,
+            t.y as usize,
+            t.width as usize,
+            t.height as usize,
+        );
+        for row in y..y + h {
+            let d = row * stride + x * 4;
+            let dst = &mut rgb.raw[d..d + w * 4];
+            dst.copy_from_slice(&data[offset..offset + w * 4]);
+            if swap {
+                dst.chunks_exact_mut(4).for_each(|px| px.swap(0, 2));
+            }
+            offset += w * 4;
+        }
+    }
+    Ok(true)
+}
+
+fn tiles(width: usize, height: usize) -> impl Iterator<Item = LosslessTile> {
+    (0..height).step_by(TILE_SIZE).flat_map(move |y| {
+        (0..width).step_by(TILE_SIZE).map(move |x| LosslessTile {
+            x: x as _,
+            y: y as _,
+            width: std::cmp::min(TILE_SIZE, width - x) as _,
+            height: std::cmp::min(TILE_SIZE, height - y) as _,
+            ..Default::default()
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(
+        width: u32,
+        height: u32,
+        key: bool,
+        tiles: Vec<LosslessTile>,
+        raw: &[u8],
+    ) -> LosslessFrame {
+        LosslessFrame {
+            width,
+            height,
+            key,
+            tiles: tiles.into(),
+            data: compress(raw).into(),
+            ..Default::default()
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_round_trip() {
+        let (width, height) = (100, 70);
+        let mut captured: Vec<u8> = (0..width * height * 4).map(|i| (i % 251) as u8).collect();
+        let mut encoder = LosslessEncoder::new();
+        let mut rgb = ImageRgb::new(ImageFormat::ARGB, 1);
+        for i in 0..2 {
+            let pb = PixelBuffer::new(&captured, Pixfmt::BGRA, width, height);
+            encoder.update(&pb, None, None).unwrap();
+            let vf = encoder.encode(i == 0).unwrap().unwrap();
+            let Some(hbb_common::message_proto::video_frame::Union::Lossless(lf)) = vf.union else {
+                panic!("not a lossless frame");
+            };
+            assert!(decode(&lf, &mut rgb).unwrap());
+            assert_eq!((rgb.w, rgb.h), (width, height));
+            assert_eq!(rgb.raw, captured);
+            // only the first tile changes
+            captured[0] = captured[0].wrapping_add(1);
+        }
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut rgb = ImageRgb::new(ImageFormat::ARGB, 1);
+        let tiles: Vec<_> = tiles(100, 70).collect();
+        let raw = vec![0u8; 100 * 70 * 4];
+        assert!(decode(&frame(100, 70, true, tiles.clone(), &raw), &mut rgb).is_ok());
+        assert!(decode(&frame(100, 70, true, tiles.clone(), &raw[1..]), &mut rgb).is_err());
+        // a key frame must cover the whole frame
+        assert!(decode(&frame(100, 70, true, tiles[1..].to_vec(), &raw), &mut rgb).is_err());
+        // a delta frame must follow a key frame of the same size
+        let mut rgb = ImageRgb::new(ImageFormat::ARGB, 1);
+        assert!(decode(
+            &frame(100, 70, false, tiles[..1].to_vec(), &raw[..64 * 64 * 4]),
+            &mut rgb
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_oversized() {
+        let mut rgb = ImageRgb::new(ImageFormat::ARGB, 1);
+        assert!(decode(&frame(u32::MAX, u32::MAX, true, vec![], &[]), &mut rgb).is_err());
+        assert!(decode(&frame(MAX_SIZE as u32 + 1, 1, true, vec![], &[]), &mut rgb).is_err());
+        let tile = LosslessTile {
+            x: u32::MAX,
+            y: 0,
+            width: 2,
+            height: 1,
+            ..Default::default()
+        };
+        assert!(decode(&frame(100, 70, false, vec![tile], &[0; 8]), &mut rgb).is_err());
+        let tile = LosslessTile {
+            x: 0,
+            y: 0,
+            width: 100,
+            height: 70,
+            ..Default::default()
+        };
+        // the tiles can't claim more than the frame
+        let tiles = vec![tile.clone(), tile];
+        assert!(decode(&frame(100, 70, true, tiles, &[]), &mut rgb).is_err());
+        assert!(rgb.raw.is_empty());
+    }
+}
@@ -41,8 +41,11 @@ pub mod convert;
 pub mod gpucodec;
 #[cfg(feature = "hwcodec")]
 pub mod hwcodec;
+pub mod lossless;
 #[cfg(feature = "mediacodec")]
 pub mod mediacodec;
+#[cfg(feature = "openh264")]
+pub mod openh264;
 pub mod vpxcodec;
 pub use self::convert::*;
 pub const STRIDE_ALIGN: usize = 64; // commonly used in libvpx vpx_img_alloc caller
@@ -50,6 +53,9 @@ pub const HW_STRIDE_ALIGN: usize = 0; // recommended by av_frame_get_buffer
 
 pub mod aom;
 pub mod record;
+pub mod record_encrypt;
+#[cfg(any(feature = "hwcodec", feature = "openh264"))]
+mod record_mp4;
 mod vpx;
 
 #[repr(usize)]
@@ -145,6 +151,20 @@ pub trait TraitPixelBuffer {
     fn stride(&self) -> Vec<usize>;
 
     fn pixfmt(&self) -> Pixfmt;
+
+    /// The changed regions since the previous frame, `None` if unknown.
+    fn dirty_rects(&self) -> Option<&[DirtyRect]> {
+        None
+    }
+}
+
+/// A changed region of a frame, in pixels relative to the frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DirtyRect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
 }
 
 #[cfg(not(any(target_os = "ios")))]
@@ -162,6 +182,14 @@ impl Frame<'_> {
         }
     }
 
+    /// The changed regions since the previous frame, `None` if unknown, i.e. the whole frame may be changed.
+    pub fn dirty_rects(&self) -> Option<&[DirtyRect]> {
+        match self {
+            Frame::PixelBuffer(pixelbuffer) => pixelbuffer.dirty_rects(),
+            Frame::Texture(_) => None,
+        }
+    }
+
     pub fn to<'a>(
         &'a self,
         yuvfmt: EncodeYuvFormat,
@@ -176,6 +204,26 @@ impl Frame<'_> {
             Frame::Texture(texture) => Ok(EncodeInput::Texture(*texture)),
         }
     }
+
+    /// Same as [`Frame::to`], but the image is cropped to `crop` (x, y, width, height) and resized to `size` first.
+    /// Texture frames are neither cropped nor resized.
+    pub fn to_scaled<'a>(
+        &'a self,
+        yuvfmt: EncodeYuvFormat,
+        crop: Option<(usize, usize, usize, usize)>,
+        size: (usize, usize),
+        yuv: &'a mut Vec<u8>,
+        mid_data: &mut Vec<u8>,
+        scaled: &mut Vec<u8>,
+    ) -> ResultType<EncodeInput> {
+        match self {
+            Frame::PixelBuffer(pixelbuffer) => {
+                convert_to_yuv_scaled(&pixelbuffer, crop, size, yuvfmt, yuv, mid_data, scaled)?;
+                Ok(EncodeInput::YUV(yuv))
+            }
+            Frame::Texture(texture) => Ok(EncodeInput::Texture(*texture)),
+        }
+    }
 }
 
 pub enum EncodeInput<'a> {
@@ -249,6 +297,9 @@ pub enum CodecName {
     H265HW(String),
     H264GPU,
     H265GPU,
+    // openh264
+    #[cfg(feature = "openh264")]
+    H264SW,
 }
 
 #[derive(PartialEq, Debug, Clone, Copy)]
@@ -281,6 +332,8 @@ impl From<&CodecName> for CodecFormat {
             CodecName::VP9 => Self::VP9,
             CodecName::AV1 => Self::AV1,
             CodecName::H264HW(_) | CodecName::H264GPU => Self::H264,
+            #[cfg(feature = "openh264")]
+            CodecName::H264SW => Self::H264,
             CodecName::H265HW(_) | CodecName::H265GPU => Self::H265,
         }
     }
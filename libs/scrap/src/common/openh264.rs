@@ -0,0 +1,279 @@
+// Software H.264 codec with openh264, used when no hardware encoder or decoder is available.
+// https://github.com/cisco/openh264
+
+use crate::{
+    codec::{base_bitrate, EncoderApi, EncoderCfg, Quality},
+    common::GoogleImage,
+    EncodeInput, EncodeYuvFormat, ImageRgb, Pixfmt, STRIDE_ALIGN,
+};
+use hbb_common::{
+    anyhow::{anyhow, Context},
+    bytes::Bytes,
+    log,
+    message_proto::{Chroma, EncodedVideoFrame, EncodedVideoFrames, VideoFrame},
+    ResultType,
+};
+use openh264::{
+    decoder::Decoder,
+    encoder::{Encoder, EncoderConfig, FrameType},
+    formats::YUVSource,
+};
+
+const FRAME_RATE: f32 = 30.;
+
+#[derive(Debug, Clone)]
+pub struct OpenH264EncoderConfig {
+    pub width: usize,
+    pub height: usize,
+    pub quality: Quality,
+    pub keyframe_interval: Option<usize>,
+}
+
+pub struct OpenH264Encoder {
+    encoder: Encoder,
+    config: OpenH264EncoderConfig,
+    bitrate: u32,
+    yuvfmt: EncodeYuvFormat,
+    frames: usize,
+}
+
+impl EncoderApi for OpenH264Encoder {
+    fn new(cfg: EncoderCfg, _i444: bool) -> ResultType<Self>
+    where
+        Self: Sized,
+    {
+        match cfg {
+            EncoderCfg::OpenH264(config) => {
+                let bitrate = Self::calc_bitrate(config.width, config.height, config.quality);
+                let encoder = Self::create(config.width, config.height, bitrate)?;
+                Ok(Self {
+                    encoder,
+                    yuvfmt: Self::get_yuvfmt(config.width, config.height),
+                    config,
+                    bitrate,
+                    frames: 0,
+                })
+            }
+            _ => Err(anyhow!("encoder type mismatch")),
+        }
+    }
+
+    fn encode_to_message(&mut self, input: EncodeInput, ms: i64) -> ResultType<VideoFrame> {
+        let source = Source {
+            data: input.yuv()?,
+            fmt: &self.yuvfmt,
+        };
+        if let Some(interval) = self.config.keyframe_interval {
+            if self.frames > 0 && self.frames % interval == 0 {
+                self.encoder.force_intra_frame();
+            }
+        }
+        self.frames += 1;
+        let stream = self
+            .encoder
+            .encode(&source)
+            .with_context(|| "Failed to encode")?;
+        let key = match stream.frame_type() {
+            FrameType::IDR | FrameType::I => true,
+            FrameType::Skip | FrameType::Invalid => return Err(anyhow!("no valid frame")),
+            _ => false,
+        };
+        let data = stream.to_vec();
+        if data.is_empty() {
+            return Err(anyhow!("no valid frame"));
+        }
+        let mut vf = VideoFrame::new();
+        vf.set_h264s(EncodedVideoFrames {
+            frames: vec![EncodedVideoFrame {
+                data: Bytes::from(data),
+                key,
+                pts: ms,
+                ..Default::default()
+            }]
+            .into(),
+            ..Default::default()
+        });
+        Ok(vf)
+    }
+
+    fn yuvfmt(&self) -> EncodeYuvFormat {
+        self.yuvfmt.clone()
+    }
+
+    #[cfg(feature = "gpucodec")]
+    fn input_texture(&self) -> bool {
+        false
+    }
+
+    // openh264 can't change the bitrate of a running encoder with the safe api,
+    // so the encoder is recreated, the next frame is a key frame.
+    fn set_quality(&mut self, quality: Quality) -> ResultType<()> {
+        let bitrate = Self::calc_bitrate(self.config.width, self.config.height, quality);
+        if bitrate != self.bitrate {
+            self.encoder = Self::create(self.config.width, self.config.height, bitrate)?;
+            self.bitrate = bitrate;
+            self.config.quality = quality;
+            self.frames = 0;
+        }
+        Ok(())
+    }
+
+    fn bitrate(&self) -> u32 {
+        self.bitrate
+    }
+
+    fn support_abr(&self) -> bool {
+        false
+    }
+}
+
+impl OpenH264Encoder {
+    fn create(width: usize, height: usize, bitrate: u32) -> ResultType<Encoder> {
+        let config = EncoderConfig::new(width as _, height as _)
+            .set_bitrate_bps(bitrate * 1000)
+            .max_frame_rate(FRAME_RATE)
+            .enable_skip_frame(false);
+        Encoder::with_config(config).map_err(|e| anyhow!("Failed to create openh264 encoder: {e:?}"))
+    }
+
+    // kbps, same as hwcodec
+    fn calc_bitrate(width: usize, height: usize, quality: Quality) -> u32 {
+        let b = match quality {
+            Quality::Best => 150,
+            Quality::Balanced => 100,
+            Quality::Low => 50,
+            Quality::Custom(b) => b,
+        };
+        base_bitrate(width as _, height as _) * b / 100
+    }
+
+    fn get_yuvfmt(width: usize, height: usize) -> EncodeYuvFormat {
+        let align = |x: usize| (x + STRIDE_ALIGN - 1) / STRIDE_ALIGN * STRIDE_ALIGN;
+        let stride_y = align(width);
+        let stride_uv = align((width + 1) / 2);
+        let u = stride_y * height;
+        let v = u + stride_uv * ((height + 1) / 2);
+        EncodeYuvFormat {
+            pixfmt: Pixfmt::I420,
+            w: width,
+            h: height,
+            stride: vec![stride_y, stride_uv, stride_uv],
+            u,
+            v,
+        }
+    }
+}
+
+// The I420 frame converted by `convert_to_yuv`.
+struct Source<'a> {
+    data: &'a [u8],
+    fmt: &'a EncodeYuvFormat,
+}
+
+impl YUVSource for Source<'_> {
+    fn width(&self) -> i32 {
+        self.fmt.w as _
+    }
+
+    fn height(&self) -> i32 {
+        self.fmt.h as _
+    }
+
+    fn y(&self) -> &[u8] {
+        &self.data[..self.fmt.u]
+    }
+
+    fn u(&self) -> &[u8] {
+        &self.data[self.fmt.u..self.fmt.v]
+    }
+
+    fn v(&self) -> &[u8] {
+        let len = self.fmt.stride[2] * ((self.fmt.h + 1) / 2);
+        &self.data[self.fmt.v..self.fmt.v + len]
+    }
+
+    fn y_stride(&self) -> i32 {
+        self.fmt.stride[0] as _
+    }
+
+    fn u_stride(&self) -> i32 {
+        self.fmt.stride[1] as _
+    }
+
+    fn v_stride(&self) -> i32 {
+        self.fmt.stride[2] as _
+    }
+}
+
+pub struct OpenH264Decoder {
+    decoder: Decoder,
+}
+
+impl OpenH264Decoder {
+    pub fn new() -> ResultType<Self> {
+        let decoder =
+            Decoder::new().map_err(|e| anyhow!("Failed to create openh264 decoder: {e:?}"))?;
+        Ok(Self { decoder })
+    }
+
+    // rgb [in/out] fmt and stride must be set in ImageRgb
+    pub fn decode(&mut self, frames: &EncodedVideoFrames, rgb: &mut ImageRgb) -> ResultType<bool> {
+        let mut ret = false;
+        for h264 in frames.frames.iter() {
+            match self.decoder.decode(&h264.data) {
+                Ok(Some(yuv)) => {
+                    let (width, height) = yuv.dimension_rgb();
+                    let (stride_y, stride_u, stride_v) = yuv.strides_yuv();
+                    Image {
+                        width,
+                        height,
+                        stride: vec![stride_y as _, stride_u as _, stride_v as _],
+                        planes: vec![
+                            yuv.y_with_stride().as_ptr() as _,
+                            yuv.u_with_stride().as_ptr() as _,
+                            yuv.v_with_stride().as_ptr() as _,
+                        ],
+                    }
+                    .to(rgb);
+                    ret = true;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    log::error!("Failed to decode h264: {e:?}");
+                    return Err(anyhow!("Failed to decode h264: {e:?}"));
+                }
+            }
+        }
+        Ok(ret)
+    }
+}
+
+// A decoded I420 image, borrowed from the decoder until the next call of decode.
+struct Image {
+    width: usize,
+    height: usize,
+    stride: Vec<i32>,
+    planes: Vec<*mut u8>,
+}
+
+impl GoogleImage for Image {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn stride(&self) -> Vec<i32> {
+        self.stride.clone()
+    }
+
+    fn planes(&self) -> Vec<*mut u8> {
+        self.planes.clone()
+    }
+
+    fn chroma(&self) -> Chroma {
+        Chroma::I420
+    }
+}
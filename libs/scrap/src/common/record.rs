@@ -1,26 +1,37 @@
-use crate::CodecFormat;
-#[cfg(feature = "hwcodec")]
-use hbb_common::anyhow::anyhow;
+#[cfg(any(feature = "hwcodec", feature = "openh264"))]
+use crate::record_mp4::{self, Mp4Muxer};
+use crate::{
+    record_encrypt::{self, EncryptedWriter},
+    CodecFormat,
+};
 use hbb_common::{
     bail, chrono,
     config::Config,
     log,
-    message_proto::{message, video_frame, EncodedVideoFrame, Message},
+    message_proto::{message, misc, video_frame, AudioFrame, EncodedVideoFrame, Message},
     ResultType,
 };
-#[cfg(feature = "hwcodec")]
-use hwcodec::mux::{MuxContext, Muxer};
 use std::{
+    collections::HashSet,
     fs::{File, OpenOptions},
     io,
     ops::{Deref, DerefMut},
-    path::PathBuf,
-    sync::mpsc::Sender,
-    time::Instant,
+    path::{Path, PathBuf},
+    sync::{mpsc::Sender, Mutex},
+    time::{Duration, Instant, SystemTime},
 };
-use webm::mux::{self, Segment, Track, VideoTrack, Writer};
+use webm::mux::{self, AudioTrack, Segment, Track, VideoTrack, Writer};
 
 const MIN_SECS: u64 = 1;
+// The limits of the segments and the retention, 0 means unlimited.
+pub const OPTION_SEGMENT_SECONDS: &str = "video-segment-seconds";
+pub const OPTION_SEGMENT_SIZE: &str = "video-segment-size"; // MB
+pub const OPTION_RETENTION_DAYS: &str = "video-retention-days";
+pub const OPTION_RETENTION_SIZE: &str = "video-retention-size"; // MB, of all the recordings in the directory
+const INDEX_EXT: &str = "json";
+// Opus is always decoded at 48000Hz.
+const AUDIO_SAMPLE_RATE: u32 = 48000;
+pub const DEFAULT_AUDIO_CHANNELS: u8 = 2;
 
 #[derive(Debug, Clone)]
 pub struct RecorderContext {
@@ -31,6 +42,8 @@ pub struct RecorderContext {
     pub width: usize,
     pub height: usize,
     pub format: CodecFormat,
+    // The channels of the opus packets, from the audio format of the session.
+    pub audio_channels: u8,
     pub tx: Option<Sender<RecordState>>,
 }
 
@@ -47,8 +60,7 @@ impl RecorderContext {
                 std::fs::create_dir_all(&dir)?;
             }
         }
-        let file = if self.server { "s" } else { "c" }.to_string()
-            + &self.id.clone()
+        let file = self.prefix()
             + &chrono::Local::now().format("_%Y%m%d%H%M%S%3f_").to_string()
             + &self.format.to_string().to_lowercase()
             + if self.format == CodecFormat::VP9
@@ -58,11 +70,20 @@ impl RecorderContext {
                 ".webm"
             } else {
                 ".mp4"
+            }
+            + &if record_encrypt::is_enabled() {
+                format!(".{}", record_encrypt::EXT)
+            } else {
+                "".to_owned()
             };
         self.filename = PathBuf::from(&dir).join(file).to_string_lossy().to_string();
         log::info!("video will save to {}", self.filename);
         Ok(())
     }
+
+    fn prefix(&self) -> String {
+        if self.server { "s" } else { "c" }.to_string() + &self.id
+    }
 }
 
 unsafe impl Send for Recorder {}
@@ -73,6 +94,10 @@ pub trait RecorderApi {
     where
         Self: Sized;
     fn write_video(&mut self, frame: &EncodedVideoFrame) -> bool;
+    /// Write an opus packet, `pts` is in milliseconds on the timeline of the video frames.
+    fn write_audio(&mut self, _data: &[u8], _pts: i64) -> bool {
+        false
+    }
 }
 
 #[derive(Debug)]
@@ -87,6 +112,13 @@ pub struct Recorder {
     pub inner: Box<dyn RecorderApi>,
     ctx: RecorderContext,
     pts: Option<i64>,
+    // The time and the pts of the first video frame, to map the audio frames onto the video timeline.
+    base: Option<(Instant, i64)>,
+    limits: Limits,
+    // The new audio channels, applied with the next segment.
+    audio_channels: Option<u8>,
+    // After `inner`, so that the index is saved after the last segment is finished.
+    session: Session,
 }
 
 impl Deref for Recorder {
@@ -106,47 +138,115 @@ impl DerefMut for Recorder {
 impl Recorder {
     pub fn new(mut ctx: RecorderContext) -> ResultType<Self> {
         ctx.set_filename()?;
-        let recorder = match ctx.format {
-            CodecFormat::VP8 | CodecFormat::VP9 | CodecFormat::AV1 => Recorder {
-                inner: Box::new(WebmRecorder::new(ctx.clone())?),
-                ctx,
-                pts: None,
-            },
-            #[cfg(feature = "hwcodec")]
-            _ => Recorder {
-                inner: Box::new(HwRecorder::new(ctx.clone())?),
-                ctx,
-                pts: None,
-            },
-            #[cfg(not(feature = "hwcodec"))]
-            _ => bail!("unsupported codec type"),
+        let mut recorder = Recorder {
+            inner: Self::create(&ctx)?,
+            session: Session::new(&ctx),
+            ctx,
+            pts: None,
+            base: None,
+            limits: Default::default(),
+            audio_channels: None,
         };
-        recorder.send_state(RecordState::NewFile(recorder.ctx.filename.clone()));
+        recorder.start_segment();
         Ok(recorder)
     }
 
-    fn change(&mut self, mut ctx: RecorderContext) -> ResultType<()> {
-        ctx.set_filename()?;
-        self.inner = match ctx.format {
+    fn create(ctx: &RecorderContext) -> ResultType<Box<dyn RecorderApi>> {
+        Ok(match ctx.format {
             CodecFormat::VP8 | CodecFormat::VP9 | CodecFormat::AV1 => {
                 Box::new(WebmRecorder::new(ctx.clone())?)
             }
-            #[cfg(feature = "hwcodec")]
-            _ => Box::new(HwRecorder::new(ctx.clone())?),
-            #[cfg(not(feature = "hwcodec"))]
+            #[cfg(any(feature = "hwcodec", feature = "openh264"))]
+            CodecFormat::H264 | CodecFormat::H265 => Box::new(Mp4Recorder::new(ctx.clone())?),
             _ => bail!("unsupported codec type"),
-        };
+        })
+    }
+
+    fn change(&mut self, mut ctx: RecorderContext) -> ResultType<()> {
+        if let Some(channels) = self.audio_channels.take() {
+            ctx.audio_channels = channels;
+        }
+        ctx.set_filename()?;
+        // the last segment is finished when dropped
+        self.inner = Self::create(&ctx)?;
         self.ctx = ctx;
         self.pts = None;
-        self.send_state(RecordState::NewFile(self.ctx.filename.clone()));
+        self.base = None;
+        self.start_segment();
         Ok(())
     }
 
+    fn start_segment(&mut self) {
+        self.limits = Limits::load();
+        self.session.push(&self.ctx);
+        self.send_state(RecordState::NewFile(self.ctx.filename.clone()));
+        if self.limits.retention_days > 0 || self.limits.retention_size > 0 {
+            if let Some(dir) = Path::new(&self.ctx.filename).parent() {
+                let (dir, limits) = (dir.to_path_buf(), self.limits);
+                std::thread::spawn(move || apply_retention(&dir, limits));
+            }
+        }
+    }
+
+    // Segments are split on key frames only, so that every segment is playable alone.
+    fn segment_full(&self, pts: i64) -> bool {
+        let Some(start) = self.session.start() else {
+            return false;
+        };
+        (self.limits.segment_secs > 0 && pts - start >= self.limits.segment_secs as i64 * 1000)
+            || (self.limits.segment_size > 0
+                && std::fs::metadata(&self.ctx.filename)
+                    .map_or(false, |m| m.len() >= self.limits.segment_size))
+    }
+
     pub fn write_message(&mut self, msg: &Message) {
-        if let Some(message::Union::VideoFrame(vf)) = &msg.union {
-            if let Some(frame) = &vf.union {
-                self.write_frame(frame).ok();
+        match &msg.union {
+            Some(message::Union::VideoFrame(vf)) => {
+                if let Some(frame) = &vf.union {
+                    self.write_frame(frame).ok();
+                }
+            }
+            Some(message::Union::AudioFrame(af)) => self.write_audio_frame(af),
+            Some(message::Union::Misc(m)) => {
+                if let Some(misc::Union::AudioFormat(f)) = &m.union {
+                    self.set_audio_channels(f.channels as _).ok();
+                }
             }
+            _ => {}
+        }
+    }
+
+    /// The audio track is created with the file, a new file is started if the channels change.
+    ///
+    /// The new file starts on the next key frame, the packets till then are in the old track,
+    /// which is fine as an opus decoder outputs its own channels.
+    pub fn set_audio_channels(&mut self, channels: u8) -> ResultType<()> {
+        if !(1..=2).contains(&channels) {
+            return Ok(());
+        }
+        if channels == self.ctx.audio_channels {
+            self.audio_channels = None;
+            return Ok(());
+        }
+        log::info!(
+            "audio channels {} -> {}, change record filename",
+            self.ctx.audio_channels,
+            channels
+        );
+        self.audio_channels = Some(channels);
+        if self.pts.is_none() {
+            // nothing is written yet
+            return self.change(self.ctx.clone());
+        }
+        Ok(())
+    }
+
+    /// Audio frames have no pts, they are placed on the video timeline by their arrival time.
+    /// Audio frames before the first video frame are dropped.
+    pub fn write_audio_frame(&mut self, frame: &AudioFrame) {
+        if let Some((instant, pts)) = self.base {
+            let pts = pts + instant.elapsed().as_millis() as i64;
+            self.inner.write_audio(&frame.data, pts);
         }
     }
 
@@ -160,7 +260,7 @@ impl Recorder {
                     })?;
                 }
                 for f in vp8s.frames.iter() {
-                    self.check_pts(f.pts)?;
+                    self.check_pts(f.pts, f.key)?;
                     self.write_video(f);
                 }
             }
@@ -172,7 +272,7 @@ impl Recorder {
                     })?;
                 }
                 for f in vp9s.frames.iter() {
-                    self.check_pts(f.pts)?;
+                    self.check_pts(f.pts, f.key)?;
                     self.write_video(f);
                 }
             }
@@ -184,11 +284,11 @@ impl Recorder {
                     })?;
                 }
                 for f in av1s.frames.iter() {
-                    self.check_pts(f.pts)?;
+                    self.check_pts(f.pts, f.key)?;
                     self.write_video(f);
                 }
             }
-            #[cfg(feature = "hwcodec")]
+            #[cfg(any(feature = "hwcodec", feature = "openh264"))]
             video_frame::Union::H264s(h264s) => {
                 if self.ctx.format != CodecFormat::H264 {
                     self.change(RecorderContext {
@@ -197,7 +297,7 @@ impl Recorder {
                     })?;
                 }
                 for f in h264s.frames.iter() {
-                    self.check_pts(f.pts)?;
+                    self.check_pts(f.pts, f.key)?;
                     self.write_video(f);
                 }
             }
@@ -210,7 +310,7 @@ impl Recorder {
                     })?;
                 }
                 for f in h265s.frames.iter() {
-                    self.check_pts(f.pts)?;
+                    self.check_pts(f.pts, f.key)?;
                     self.write_video(f);
                 }
             }
@@ -220,14 +320,26 @@ impl Recorder {
         Ok(())
     }
 
-    fn check_pts(&mut self, pts: i64) -> ResultType<()> {
+    fn check_pts(&mut self, pts: i64, key: bool) -> ResultType<()> {
         // https://stackoverflow.com/questions/76379101/how-to-create-one-playable-webm-file-from-two-different-video-tracks-with-same-c
         let old_pts = self.pts;
         self.pts = Some(pts);
         if old_pts.clone().unwrap_or_default() > pts {
             log::info!("pts {:?} -> {}, change record filename", old_pts, pts);
             self.change(self.ctx.clone())?;
+        } else if key && self.audio_channels.is_some() {
+            self.change(self.ctx.clone())?;
+        } else if key && self.segment_full(pts) {
+            log::info!(
+                "segment {} is full, change record filename",
+                self.ctx.filename
+            );
+            self.change(self.ctx.clone())?;
+        }
+        if self.base.is_none() {
+            self.base = Some((Instant::now(), pts));
         }
+        self.session.update(pts);
         Ok(())
     }
 
@@ -236,9 +348,279 @@ impl Recorder {
     }
 }
 
+const MB: u64 = 1024 * 1024;
+
+lazy_static::lazy_static! {
+    // The segments being written, which are kept by the retention.
+    static ref ACTIVE: Mutex<HashSet<PathBuf>> = Default::default();
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Limits {
+    segment_secs: u64,
+    segment_size: u64,
+    retention_days: u64,
+    retention_size: u64,
+}
+
+impl Limits {
+    fn load() -> Self {
+        let get = |key: &str| Config::get_option(key).trim().parse::<u64>().unwrap_or(0);
+        Self {
+            segment_secs: get(OPTION_SEGMENT_SECONDS),
+            segment_size: get(OPTION_SEGMENT_SIZE) * MB,
+            retention_days: get(OPTION_RETENTION_DAYS),
+            retention_size: get(OPTION_RETENTION_SIZE) * MB,
+        }
+    }
+}
+
+struct SegmentInfo {
+    filename: String,
+    format: CodecFormat,
+    // the first and the last pts, on the timeline of the session
+    start: Option<i64>,
+    end: Option<i64>,
+}
+
+// The segments of a recording session, listed in the index `<s|c><id>_<start time>.json` next to them.
+// The removed segments, e.g. too short ones, are not listed.
+struct Session {
+    index: PathBuf,
+    id: String,
+    server: bool,
+    segments: Vec<SegmentInfo>,
+}
+
+impl Session {
+    fn new(ctx: &RecorderContext) -> Self {
+        let name = ctx.prefix()
+            + &chrono::Local::now().format("_%Y%m%d%H%M%S%3f.").to_string()
+            + INDEX_EXT;
+        let dir = Path::new(&ctx.filename).parent().map(|p| p.to_path_buf());
+        Self {
+            index: dir.unwrap_or_default().join(name),
+            id: ctx.id.clone(),
+            server: ctx.server,
+            segments: vec![],
+        }
+    }
+
+    fn push(&mut self, ctx: &RecorderContext) {
+        let mut active = ACTIVE.lock().unwrap();
+        if let Some(last) = self.segments.last() {
+            active.remove(Path::new(&last.filename));
+        }
+        active.insert(PathBuf::from(&ctx.filename));
+        drop(active);
+        self.segments.push(SegmentInfo {
+            filename: ctx.filename.clone(),
+            format: ctx.format,
+            start: None,
+            end: None,
+        });
+        self.save();
+    }
+
+    fn update(&mut self, pts: i64) {
+        if let Some(segment) = self.segments.last_mut() {
+            segment.start.get_or_insert(pts);
+            segment.end = Some(pts);
+        }
+    }
+
+    fn start(&self) -> Option<i64> {
+        self.segments.last().and_then(|s| s.start)
+    }
+
+    fn save(&self) {
+        let segments: Vec<_> = self
+            .segments
+            .iter()
+            .filter_map(|s| {
+                let size = std::fs::metadata(&s.filename).ok()?.len();
+                let file = Path::new(&s.filename)
+                    .file_name()?
+                    .to_string_lossy()
+                    .to_string();
+                Some(serde_json::json!({
+                    "file": file,
+                    "format": s.format.to_string().to_lowercase(),
+                    "start": s.start,
+                    "end": s.end,
+                    "size": size,
+                }))
+            })
+            .collect();
+        if segments.is_empty() {
+            std::fs::remove_file(&self.index).ok();
+            return;
+        }
+        let index = serde_json::json!({
+            "id": self.id,
+            "server": self.server,
+            "segments": segments,
+        });
+        if let Err(e) = std::fs::write(&self.index, index.to_string()) {
+            log::error!("Failed to write record index {:?}: {}", self.index, e);
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        if let Some(last) = self.segments.last() {
+            ACTIVE.lock().unwrap().remove(Path::new(&last.filename));
+        }
+        self.save();
+    }
+}
+
+// The name of a segment made by `set_filename`: `<s|c><id>_<time>_<codec>.<webm|mp4>[.<encrypted ext>]`.
+// Other files in the directory, e.g. the recordings of the user, are never touched by the retention.
+fn is_recording(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+    let encrypted = format!(".{}", record_encrypt::EXT);
+    let name = name.strip_suffix(&encrypted).unwrap_or(name);
+    let (name, codecs): (_, &[&str]) = if let Some(name) = name.strip_suffix(".webm") {
+        (name, &["vp8", "vp9", "av1"])
+    } else if let Some(name) = name.strip_suffix(".mp4") {
+        (name, &["h264", "h265"])
+    } else {
+        return false;
+    };
+    match name.rsplit_once('_') {
+        Some((name, codec)) if codecs.contains(&codec) => is_session_name(name),
+        _ => false,
+    }
+}
+
+// The name of an index made by `Session::new`: `<s|c><id>_<time>.json`.
+fn is_index(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .and_then(|n| n.strip_suffix(&format!(".{}", INDEX_EXT)))
+        .map_or(false, is_session_name)
+}
+
+// `<s|c><id>_<time>`, the time is `%Y%m%d%H%M%S%3f`.
+fn is_session_name(name: &str) -> bool {
+    match name.rsplit_once('_') {
+        Some((prefix, time)) => {
+            prefix.len() > 1
+                && prefix.starts_with(['s', 'c'])
+                && time.len() == 17
+                && time.bytes().all(|b| b.is_ascii_digit())
+        }
+        None => false,
+    }
+}
+
+// Remove the oldest recordings in `dir` until the rest are within the limits, and drop them from the indexes.
+fn apply_retention(dir: &Path, limits: Limits) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut files: Vec<(PathBuf, SystemTime, u64)> = entries
+        .flatten()
+        .filter_map(|e| {
+            let path = e.path();
+            if !is_recording(&path) {
+                return None;
+            }
+            let meta = e.metadata().ok()?;
+            Some((path, meta.modified().ok()?, meta.len()))
+        })
+        .collect();
+    files.sort_by_key(|f| f.1);
+    let max_age = Duration::from_secs(limits.retention_days * 24 * 3600);
+    let now = SystemTime::now();
+    let mut total: u64 = files.iter().map(|f| f.2).sum();
+    let mut removed = false;
+    for (path, modified, len) in files {
+        let expired =
+            limits.retention_days > 0 && now.duration_since(modified).unwrap_or_default() > max_age;
+        let oversize = limits.retention_size > 0 && total > limits.retention_size;
+        if !expired && !oversize {
+            break;
+        }
+        if ACTIVE.lock().unwrap().contains(&path) {
+            continue;
+        }
+        match std::fs::remove_file(&path) {
+            Ok(_) => {
+                log::info!("recording {:?} removed by the retention", path);
+                total -= len;
+                removed = true;
+            }
+            Err(e) => log::error!("Failed to remove recording {:?}: {}", path, e),
+        }
+    }
+    if !removed {
+        return;
+    }
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for path in entries.flatten().map(|e| e.path()).filter(|p| is_index(p)) {
+        let Some(mut index) = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|s| serde_json::from_str::<serde_json::Value>(&s).ok())
+        else {
+            continue;
+        };
+        let Some(segments) = index.get_mut("segments").and_then(|v| v.as_array_mut()) else {
+            continue;
+        };
+        let len = segments.len();
+        segments.retain(|s| {
+            s.get("file")
+                .and_then(|f| f.as_str())
+                .map_or(false, |f| dir.join(f).exists())
+        });
+        if segments.is_empty() {
+            std::fs::remove_file(&path).ok();
+        } else if segments.len() != len {
+            std::fs::write(&path, index.to_string()).ok();
+        }
+    }
+}
+
+trait WriteSeek: io::Write + io::Seek {}
+
+impl<T: io::Write + io::Seek> WriteSeek for T {}
+
+// The output of the muxers, encrypted if the filename has the extension of the encrypted recordings.
+fn create_file(filename: &str) -> ResultType<Box<dyn WriteSeek>> {
+    let out = match {
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(filename)
+    } {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => File::create(filename)?,
+        Err(e) => return Err(e.into()),
+    };
+    if filename.ends_with(&format!(".{}", record_encrypt::EXT)) {
+        let recipients = record_encrypt::recipients()?;
+        Ok(Box::new(EncryptedWriter::new(
+            io::BufWriter::new(out),
+            &recipients,
+        )?))
+    } else {
+        Ok(Box::new(out))
+    }
+}
+
 struct WebmRecorder {
     vt: VideoTrack,
-    webm: Option<Segment<Writer<File>>>,
+    at: AudioTrack,
+    // ns
+    audio_timestamp: u64,
+    webm: Option<Segment<Writer<Box<dyn WriteSeek>>>>,
     ctx: RecorderContext,
     key: bool,
     written: bool,
@@ -247,16 +629,7 @@ struct WebmRecorder {
 
 impl RecorderApi for WebmRecorder {
     fn new(ctx: RecorderContext) -> ResultType<Self> {
-        let out = match {
-            OpenOptions::new()
-                .write(true)
-                .create_new(true)
-                .open(&ctx.filename)
-        } {
-            Ok(file) => file,
-            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => File::create(&ctx.filename)?,
-            Err(e) => return Err(e.into()),
-        };
+        let out = create_file(&ctx.filename)?;
         let mut webm = match mux::Segment::new(mux::Writer::new(out)) {
             Some(v) => v,
             None => bail!("Failed to create webm mux"),
@@ -280,8 +653,19 @@ impl RecorderApi for WebmRecorder {
                 bail!("Failed to set codec private");
             }
         }
+        let at = webm.add_audio_track(
+            AUDIO_SAMPLE_RATE as _,
+            ctx.audio_channels as _,
+            None,
+            mux::AudioCodecId::Opus,
+        );
+        if !webm.set_codec_private(at.track_number(), &opus_head(ctx.audio_channels)) {
+            bail!("Failed to set audio codec private");
+        }
         Ok(WebmRecorder {
             vt,
+            at,
+            audio_timestamp: 0,
             webm: Some(webm),
             ctx,
             key: false,
@@ -306,6 +690,31 @@ impl RecorderApi for WebmRecorder {
             false
         }
     }
+
+    fn write_audio(&mut self, data: &[u8], pts: i64) -> bool {
+        // the muxer requires audio frames after the first key frame, in order
+        if !self.key || pts < 0 {
+            return false;
+        }
+        let timestamp = std::cmp::max(pts as u64 * 1_000_000, self.audio_timestamp);
+        let ok = self.at.add_frame(data, timestamp, true);
+        if ok {
+            self.audio_timestamp = timestamp;
+        }
+        ok
+    }
+}
+
+// https://www.rfc-editor.org/rfc/rfc7845#section-5.1
+fn opus_head(channels: u8) -> Vec<u8> {
+    let mut head = b"OpusHead".to_vec();
+    head.push(1); // version
+    head.push(channels);
+    head.extend_from_slice(&0u16.to_le_bytes()); // pre-skip
+    head.extend_from_slice(&AUDIO_SAMPLE_RATE.to_le_bytes());
+    head.extend_from_slice(&0i16.to_le_bytes()); // output gain
+    head.push(0); // channel mapping family
+    head
 }
 
 impl Drop for WebmRecorder {
@@ -320,55 +729,96 @@ impl Drop for WebmRecorder {
     }
 }
 
-#[cfg(feature = "hwcodec")]
-struct HwRecorder {
-    muxer: Muxer,
+// Muxes the h264 and h265 frames with the opus packets into mp4.
+#[cfg(any(feature = "hwcodec", feature = "openh264"))]
+struct Mp4Recorder {
+    // Taken by the muxer, which is created on the first key frame with the parameter sets.
+    out: Option<io::BufWriter<Box<dyn WriteSeek>>>,
+    muxer: Option<Mp4Muxer<io::BufWriter<Box<dyn WriteSeek>>>>,
     ctx: RecorderContext,
     written: bool,
-    key: bool,
     start: Instant,
 }
 
-#[cfg(feature = "hwcodec")]
-impl RecorderApi for HwRecorder {
+#[cfg(any(feature = "hwcodec", feature = "openh264"))]
+impl RecorderApi for Mp4Recorder {
     fn new(ctx: RecorderContext) -> ResultType<Self> {
-        let muxer = Muxer::new(MuxContext {
-            filename: ctx.filename.clone(),
-            width: ctx.width,
-            height: ctx.height,
-            is265: ctx.format == CodecFormat::H265,
-            framerate: crate::hwcodec::DEFAULT_TIME_BASE[1] as _,
-        })
-        .map_err(|_| anyhow!("Failed to create hardware muxer"))?;
-        Ok(HwRecorder {
-            muxer,
+        if ctx.format != CodecFormat::H264 && ctx.format != CodecFormat::H265 {
+            bail!("unsupported codec type");
+        }
+        Ok(Mp4Recorder {
+            out: Some(io::BufWriter::new(create_file(&ctx.filename)?)),
+            muxer: None,
             ctx,
             written: false,
-            key: false,
             start: Instant::now(),
         })
     }
 
     fn write_video(&mut self, frame: &EncodedVideoFrame) -> bool {
-        if frame.key {
-            self.key = true;
+        if self.muxer.is_none() {
+            if !frame.key {
+                return false;
+            }
+            let hevc = self.ctx.format == CodecFormat::H265;
+            let config = match record_mp4::codec_config(hevc, &frame.data) {
+                Ok(config) => config,
+                Err(e) => {
+                    log::error!("Failed to get the mp4 codec config: {}", e);
+                    return false;
+                }
+            };
+            let Some(out) = self.out.take() else {
+                return false;
+            };
+            match Mp4Muxer::new(
+                out,
+                hevc,
+                self.ctx.width,
+                self.ctx.height,
+                config,
+                self.ctx.audio_channels,
+                frame.pts,
+            ) {
+                Ok(muxer) => self.muxer = Some(muxer),
+                Err(e) => {
+                    log::error!("Failed to create mp4 muxer: {}", e);
+                    return false;
+                }
+            }
         }
-        if self.key {
-            let ok = self.muxer.write_video(&frame.data, frame.key).is_ok();
-            if ok {
+        let Some(muxer) = self.muxer.as_mut() else {
+            return false;
+        };
+        match muxer.write_video(&frame.data, frame.pts, frame.key) {
+            Ok(_) => {
                 self.written = true;
+                true
+            }
+            Err(e) => {
+                log::error!("Failed to write mp4 video: {}", e);
+                false
             }
-            ok
-        } else {
-            false
         }
     }
+
+    fn write_audio(&mut self, data: &[u8], pts: i64) -> bool {
+        self.muxer
+            .as_mut()
+            .map_or(false, |m| m.write_audio(data, pts).is_ok())
+    }
 }
 
-#[cfg(feature = "hwcodec")]
-impl Drop for HwRecorder {
+#[cfg(any(feature = "hwcodec", feature = "openh264"))]
+impl Drop for Mp4Recorder {
     fn drop(&mut self) {
-        self.muxer.write_tail().ok();
+        if let Some(muxer) = self.muxer.take() {
+            if let Err(e) = muxer.finish() {
+                log::error!("Failed to finish mp4 {}: {}", self.ctx.filename, e);
+            }
+        }
+        // close the file before it may be removed
+        self.out.take();
         let mut state = RecordState::WriteTail;
         if !self.written || self.start.elapsed().as_secs() < MIN_SECS {
             std::fs::remove_file(&self.ctx.filename).ok();
@@ -377,3 +827,103 @@ impl Drop for HwRecorder {
         self.ctx.tx.as_ref().map(|tx| tx.send(state));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_recording() {
+        for name in [
+            "s123456789_20240102030405678_vp9.webm",
+            "c123456789_20240102030405678_h264.mp4",
+            "sabc_def_20240102030405678_av1.webm",
+        ] {
+            assert!(is_recording(Path::new(name)), "{name}");
+        }
+        let encrypted = format!("s1_20240102030405678_vp8.webm.{}", record_encrypt::EXT);
+        assert!(is_recording(Path::new(&encrypted)));
+        for name in [
+            "screen.webm",
+            "s.webm",
+            "sample.mp4",
+            "s1_20240102030405678.webm",
+            "s1_2024010203040567_vp9.webm",
+            "s1_20240102030405678_h264.webm",
+            "x1_20240102030405678_vp9.webm",
+            "s1_20240102030405678_vp9.mkv",
+            "s1_20240102030405678.json",
+        ] {
+            assert!(!is_recording(Path::new(name)), "{name}");
+        }
+        assert!(is_index(Path::new("s1_20240102030405678.json")));
+        assert!(!is_index(Path::new("settings.json")));
+    }
+
+    #[test]
+    fn test_retention_keeps_other_files() {
+        let dir = std::env::temp_dir().join(format!("rustdesk_retention_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let recording = "s1_20240102030405678_vp9.webm";
+        let foreign = ["screen.webm", "sample.mp4", "s1.webm"];
+        for name in foreign.iter().chain([&recording]) {
+            std::fs::write(dir.join(name), [0u8; 16]).unwrap();
+        }
+        let index = "s1_20240102030405678.json";
+        let segments = serde_json::json!({ "segments": [{ "file": recording }] });
+        std::fs::write(dir.join(index), segments.to_string()).unwrap();
+        let limits = Limits {
+            retention_size: 1,
+            ..Default::default()
+        };
+        apply_retention(&dir, limits);
+        assert!(!dir.join(recording).exists());
+        assert!(!dir.join(index).exists());
+        for name in foreign {
+            assert!(dir.join(name).exists(), "{name}");
+        }
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_audio_channels_on_key_frame() {
+        let dir = std::env::temp_dir().join(format!("rustdesk_channels_{}", std::process::id()));
+        let mut recorder = Recorder::new(RecorderContext {
+            server: false,
+            id: "1".to_owned(),
+            default_dir: dir.to_string_lossy().to_string(),
+            filename: "".to_owned(),
+            width: 64,
+            height: 48,
+            format: CodecFormat::VP9,
+            audio_channels: 2,
+            tx: None,
+        })
+        .unwrap();
+        let frame = |pts, key| {
+            video_frame::Union::Vp9s(hbb_common::message_proto::EncodedVideoFrames {
+                frames: vec![EncodedVideoFrame {
+                    data: vec![0u8; 8].into(),
+                    key,
+                    pts,
+                    ..Default::default()
+                }]
+                .into(),
+                ..Default::default()
+            })
+        };
+        recorder.write_frame(&frame(0, true)).unwrap();
+        let first = recorder.ctx.filename.clone();
+        recorder.set_audio_channels(1).unwrap();
+        // a segment starts with a key frame
+        recorder.write_frame(&frame(33, false)).unwrap();
+        assert_eq!(recorder.ctx.filename, first);
+        assert_eq!(recorder.ctx.audio_channels, 2);
+        std::thread::sleep(Duration::from_millis(2));
+        recorder.write_frame(&frame(66, true)).unwrap();
+        assert_ne!(recorder.ctx.filename, first);
+        assert_eq!(recorder.ctx.audio_channels, 1);
+        drop(recorder);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,374 @@
+// Encryption of the recordings at rest, enabled by the option `video-encryption-keys`,
+// a list of base64 encoded curve25519 public keys separated by commas.
+//
+// The muxers seek back to fill the headers, so the file is a log of the writes:
+//
+//   magic | recipient count (u16) | (public key, sealed stream key) * count | stream header
+//   | (ciphertext length (u32), ciphertext of (offset (u64), data)) * n
+//
+// Each write is a message of the xchacha20poly1305 secret stream, and the last message is tagged final.
+// The log is replayed to decrypt, so a truncated file, e.g. after a crash, is decrypted up to the last complete write.
+
+use hbb_common::{
+    bail,
+    config::Config,
+    log,
+    sodiumoxide::{
+        base64,
+        crypto::{
+            box_::{self, PublicKey, SecretKey},
+            sealedbox,
+            secretstream::{self, Header, Key, Pull, Push, Stream, Tag},
+        },
+    },
+    ResultType,
+};
+use std::{
+    convert::TryInto,
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+pub const OPTION_ENCRYPTION_KEYS: &str = "video-encryption-keys";
+pub const EXT: &str = "enc";
+const MAGIC: &[u8; 8] = b"RDREC\x00\x00\x01";
+// The pending data is written as a message when it reaches the size.
+const MESSAGE_SIZE: usize = 64 * 1024;
+const MAX_CIPHERTEXT: usize = 16 * 1024 * 1024;
+
+#[inline]
+pub fn is_enabled() -> bool {
+    !Config::get_option(OPTION_ENCRYPTION_KEYS).trim().is_empty()
+}
+
+/// The public keys to encrypt to, an error if any of them is invalid.
+pub fn recipients() -> ResultType<Vec<PublicKey>> {
+    let mut keys = vec![];
+    for s in Config::get_option(OPTION_ENCRYPTION_KEYS)
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+    {
+        match base64::decode(s, base64::Variant::Original)
+            .ok()
+            .and_then(|k| PublicKey::from_slice(&k))
+        {
+            Some(key) => keys.push(key),
+            None => bail!("invalid recording encryption key: {}", s),
+        }
+    }
+    if keys.is_empty() {
+        bail!("no recording encryption key");
+    }
+    Ok(keys)
+}
+
+/// A new key pair in base64, (public key, secret key).
+pub fn gen_keypair() -> (String, String) {
+    let (pk, sk) = box_::gen_keypair();
+    (
+        base64::encode(pk, base64::Variant::Original),
+        base64::encode(sk, base64::Variant::Original),
+    )
+}
+
+/// Save a secret key to a new file, which is readable by the owner only.
+pub fn save_secret_key(path: &Path, sk: &str) -> ResultType<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    file.write_all(sk.as_bytes())?;
+    file.write_all(b"\n")?;
+    Ok(())
+}
+
+/// Read a secret key from a file, or from the stdin if the path is `-`,
+/// so that the key never shows in the command line.
+pub fn read_secret_key(path: &str) -> ResultType<SecretKey> {
+    let mut s = String::new();
+    if path == "-" {
+        io::stdin().read_to_string(&mut s)?;
+    } else {
+        File::open(path)?.read_to_string(&mut s)?;
+    }
+    parse_secret_key(&s)
+}
+
+pub fn parse_secret_key(s: &str) -> ResultType<SecretKey> {
+    match base64::decode(s.trim(), base64::Variant::Original)
+        .ok()
+        .and_then(|k| SecretKey::from_slice(&k))
+    {
+        Some(key) => Ok(key),
+        None => bail!("invalid secret key"),
+    }
+}
+
+pub struct EncryptedWriter<W: Write> {
+    out: W,
+    stream: Stream<Push>,
+    // the position and the length of the plain data
+    pos: u64,
+    len: u64,
+    // the continuous data written at `pending_offset`, not encrypted yet
+    pending: Vec<u8>,
+    pending_offset: u64,
+    finished: bool,
+}
+
+impl<W: Write> EncryptedWriter<W> {
+    pub fn new(mut out: W, recipients: &[PublicKey]) -> ResultType<Self> {
+        if recipients.is_empty() || recipients.len() > u16::MAX as usize {
+            bail!("wrong recipient count: {}", recipients.len());
+        }
+        let key = secretstream::gen_key();
+        let Ok((stream, header)) = Stream::init_push(&key) else {
+            bail!("failed to init the encryption stream");
+        };
+        out.write_all(MAGIC)?;
+        out.write_all(&(recipients.len() as u16).to_le_bytes())?;
+        for pk in recipients {
+            out.write_all(pk.as_ref())?;
+            out.write_all(&sealedbox::seal(key.as_ref(), pk))?;
+        }
+        out.write_all(header.as_ref())?;
+        Ok(Self {
+            out,
+            stream,
+            pos: 0,
+            len: 0,
+            pending: Vec::with_capacity(MESSAGE_SIZE),
+            pending_offset: 0,
+            finished: false,
+        })
+    }
+
+    fn push(&mut self, data: &[u8], tag: Tag) -> io::Result<()> {
+        let ciphertext = self
+            .stream
+            .push(data, None, tag)
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "failed to encrypt"))?;
+        self.out
+            .write_all(&(ciphertext.len() as u32).to_le_bytes())?;
+        self.out.write_all(&ciphertext)
+    }
+
+    fn write_pending(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let mut message = Vec::with_capacity(8 + self.pending.len());
+        message.extend_from_slice(&self.pending_offset.to_le_bytes());
+        message.extend_from_slice(&self.pending);
+        self.pending.clear();
+        self.push(&message, Tag::Message)
+    }
+
+    /// Write the pending data and the final message, the file is complete then.
+    pub fn finish(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.write_pending()?;
+        self.push(&[], Tag::Final)?;
+        self.finished = true;
+        self.out.flush()
+    }
+}
+
+impl<W: Write> Write for EncryptedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.finished {
+            return Err(io::Error::new(io::ErrorKind::Other, "finished"));
+        }
+        if self.pending_offset + self.pending.len() as u64 != self.pos {
+            self.write_pending()?;
+        }
+        if self.pending.is_empty() {
+            self.pending_offset = self.pos;
+        }
+        self.pending.extend_from_slice(buf);
+        self.pos += buf.len() as u64;
+        self.len = std::cmp::max(self.len, self.pos);
+        if self.pending.len() >= MESSAGE_SIZE {
+            self.write_pending()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_pending()?;
+        self.out.flush()
+    }
+}
+
+// Only the position of the plain data is changed, the written data are never read back.
+impl<W: Write> Seek for EncryptedWriter<W> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(n) => n as i128,
+            SeekFrom::Current(n) => self.pos as i128 + n as i128,
+            SeekFrom::End(n) => self.len as i128 + n as i128,
+        };
+        if pos < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek before the start",
+            ));
+        }
+        self.pos = pos as u64;
+        Ok(self.pos)
+    }
+}
+
+impl<W: Write> Drop for EncryptedWriter<W> {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            log::error!("Failed to finish the encrypted recording: {}", e);
+        }
+    }
+}
+
+/// Decrypt a recording to `output`, return false if the recording is incomplete, e.g. the recorder was killed.
+pub fn decrypt(input: &Path, sk: &SecretKey, output: &Path) -> ResultType<bool> {
+    let mut reader = io::BufReader::new(File::open(input)?);
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        bail!("not an encrypted recording");
+    }
+    let mut count = [0u8; 2];
+    reader.read_exact(&mut count)?;
+    let pk = sk.public_key();
+    let mut key = None;
+    for _ in 0..u16::from_le_bytes(count) {
+        let mut recipient = [0u8; box_::PUBLICKEYBYTES];
+        let mut sealed = [0u8; secretstream::KEYBYTES + sealedbox::SEALBYTES];
+        reader.read_exact(&mut recipient)?;
+        reader.read_exact(&mut sealed)?;
+        if recipient == pk.0 {
+            key = sealedbox::open(&sealed, &pk, sk)
+                .ok()
+                .and_then(|k| Key::from_slice(&k));
+        }
+    }
+    let Some(key) = key else {
+        bail!("the recording is not encrypted to the key");
+    };
+    let mut header = [0u8; secretstream::HEADERBYTES];
+    reader.read_exact(&mut header)?;
+    let Some(header) = Header::from_slice(&header) else {
+        bail!("wrong stream header");
+    };
+    let Ok(mut stream) = Stream::<Pull>::init_pull(&header, &key) else {
+        bail!("failed to init the decryption stream");
+    };
+    let mut out = File::create(output)?;
+    let mut ciphertext = vec![];
+    loop {
+        let mut len = [0u8; 4];
+        if reader.read_exact(&mut len).is_err() {
+            return Ok(false);
+        }
+        let len = u32::from_le_bytes(len) as usize;
+        if len > MAX_CIPHERTEXT {
+            bail!("wrong message length: {}", len);
+        }
+        ciphertext.resize(len, 0);
+        if reader.read_exact(&mut ciphertext).is_err() {
+            return Ok(false);
+        }
+        let Ok((message, tag)) = stream.pull(&ciphertext, None) else {
+            bail!("the recording is corrupted");
+        };
+        if tag == Tag::Final {
+            out.flush()?;
+            return Ok(true);
+        }
+        if message.len() < 8 {
+            bail!("wrong message length: {}", message.len());
+        }
+        let offset = u64::from_le_bytes(message[..8].try_into()?);
+        out.seek(SeekFrom::Start(offset))?;
+        out.write_all(&message[8..])?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("rustdesk_{}_{}", std::process::id(), name))
+    }
+
+    // Write like a muxer: the data, then seek back to fill the header, and append at the end.
+    fn write_encrypted(pk: &PublicKey, plain: &mut Vec<u8>) -> Vec<u8> {
+        let mut encrypted = vec![];
+        let mut writer = EncryptedWriter::new(&mut encrypted, &[*pk]).unwrap();
+        let body: Vec<u8> = (0..MESSAGE_SIZE * 2 + 100).map(|i| i as u8).collect();
+        writer.write_all(&[0; 16]).unwrap();
+        writer.write_all(&body).unwrap();
+        writer.seek(SeekFrom::Start(4)).unwrap();
+        writer.write_all(b"head").unwrap();
+        writer.seek(SeekFrom::End(0)).unwrap();
+        writer.write_all(b"tail").unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+        plain.extend_from_slice(&[0; 16]);
+        plain.extend_from_slice(&body);
+        plain[4..8].copy_from_slice(b"head");
+        plain.extend_from_slice(b"tail");
+        encrypted
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let (pk, sk) = gen_keypair();
+        let pk =
+            PublicKey::from_slice(&base64::decode(pk, base64::Variant::Original).unwrap()).unwrap();
+        let sk = parse_secret_key(&sk).unwrap();
+        let mut plain = vec![];
+        let encrypted = write_encrypted(&pk, &mut plain);
+        let (input, output) = (temp_path("rt.enc"), temp_path("rt.webm"));
+
+        std::fs::write(&input, &encrypted).unwrap();
+        assert!(decrypt(&input, &sk, &output).unwrap());
+        assert_eq!(std::fs::read(&output).unwrap(), plain);
+
+        // A truncated file is decrypted up to the last complete write,
+        // cut in the message of "tail", which is followed by the final message of 21 bytes.
+        std::fs::write(&input, &encrypted[..encrypted.len() - 30]).unwrap();
+        assert!(!decrypt(&input, &sk, &output).unwrap());
+        assert_eq!(std::fs::read(&output).unwrap(), plain[..plain.len() - 4]);
+
+        let (_, other) = box_::gen_keypair();
+        assert!(decrypt(&input, &other, &output).is_err());
+        std::fs::remove_file(&input).ok();
+        std::fs::remove_file(&output).ok();
+    }
+
+    #[test]
+    fn test_secret_key_file() {
+        let (_, sk) = gen_keypair();
+        let path = temp_path("sk");
+        std::fs::remove_file(&path).ok();
+        save_secret_key(&path, &sk).unwrap();
+        assert!(save_secret_key(&path, &sk).is_err());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        let read = read_secret_key(&path.to_string_lossy()).unwrap();
+        assert_eq!(read, parse_secret_key(&sk).unwrap());
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,833 @@
+// A minimal mp4 muxer for the h264 and h265 recordings, with an opus audio track.
+//
+//   ftyp | mdat (64 bits size, filled at the end) | moov
+//
+// The samples are written to mdat as they come, each sample is a chunk, and the sample tables are
+// written to moov when the recording is finished. The audio track is placed on the timeline of the
+// video frames by an edit list.
+//
+// https://developer.apple.com/documentation/quicktime-file-format
+// https://opus-codec.org/docs/opus_in_isobmff.html
+
+use hbb_common::{bail, ResultType};
+use std::io::{Seek, SeekFrom, Write};
+
+const MOVIE_TIMESCALE: u32 = 1000; // ms, same as the pts
+const AUDIO_TIMESCALE: u32 = 48000;
+const VIDEO_TRACK_ID: u32 = 1;
+const AUDIO_TRACK_ID: u32 = 2;
+const LAST_VIDEO_DURATION: u64 = 33; // ms
+const MATRIX: [u32; 9] = [0x00010000, 0, 0, 0, 0x00010000, 0, 0, 0, 0x40000000];
+const LANGUAGE_UND: u16 = 0x55C4;
+
+const H264_SPS: u8 = 7;
+const H264_PPS: u8 = 8;
+const H264_AUD: u8 = 9;
+const H265_VPS: u8 = 32;
+const H265_SPS: u8 = 33;
+const H265_PPS: u8 = 34;
+const H265_AUD: u8 = 35;
+
+struct Sample {
+    offset: u64,
+    size: u32,
+    // in the timescale of the track, from the start of the movie
+    time: u64,
+    sync: bool,
+}
+
+#[derive(Default)]
+struct Track {
+    samples: Vec<Sample>,
+    last_duration: u64,
+}
+
+impl Track {
+    fn start(&self) -> u64 {
+        self.samples.first().map_or(0, |s| s.time)
+    }
+
+    fn end(&self) -> u64 {
+        self.samples
+            .last()
+            .map_or(0, |s| s.time + self.last_duration)
+    }
+}
+
+pub struct Mp4Muxer<W: Write + Seek> {
+    out: W,
+    hevc: bool,
+    width: usize,
+    height: usize,
+    // the avcC or hvcC box
+    codec_config: Vec<u8>,
+    audio_channels: u8,
+    start_pts: i64,
+    mdat_start: u64,
+    offset: u64,
+    video: Track,
+    audio: Track,
+}
+
+/// The avcC or hvcC box of the parameter sets in a key frame in annex b.
+pub fn codec_config(hevc: bool, key_frame: &[u8]) -> ResultType<Vec<u8>> {
+    if hevc {
+        hvcc(key_frame)
+    } else {
+        avcc(key_frame)
+    }
+}
+
+impl<W: Write + Seek> Mp4Muxer<W> {
+    /// `codec_config` is from the first key frame, see [`codec_config`].
+    pub fn new(
+        mut out: W,
+        hevc: bool,
+        width: usize,
+        height: usize,
+        codec_config: Vec<u8>,
+        audio_channels: u8,
+        start_pts: i64,
+    ) -> ResultType<Self> {
+        let mut head = ftyp(hevc);
+        let mdat_start = head.len() as u64;
+        // the 64 bits size is filled at the end
+        head.extend_from_slice(&1u32.to_be_bytes());
+        head.extend_from_slice(b"mdat");
+        head.extend_from_slice(&0u64.to_be_bytes());
+        out.write_all(&head)?;
+        Ok(Self {
+            out,
+            hevc,
+            width,
+            height,
+            codec_config,
+            audio_channels: audio_channels.clamp(1, 2),
+            start_pts,
+            mdat_start,
+            offset: head.len() as u64,
+            video: Track {
+                last_duration: LAST_VIDEO_DURATION,
+                ..Default::default()
+            },
+            audio: Default::default(),
+        })
+    }
+
+    /// `data` is a frame in annex b, `pts` in milliseconds.
+    pub fn write_video(&mut self, data: &[u8], pts: i64, key: bool) -> ResultType<()> {
+        let data = to_length_prefixed(data, self.hevc);
+        let time = std::cmp::max(pts - self.start_pts, 0) as u64;
+        // the times of the samples must be increasing
+        let time = match self.video.samples.last() {
+            Some(last) if time <= last.time => last.time + 1,
+            _ => time,
+        };
+        self.write_sample(&data, time, key, false)
+    }
+
+    /// `data` is an opus packet, `pts` in milliseconds on the timeline of the video frames.
+    pub fn write_audio(&mut self, data: &[u8], pts: i64) -> ResultType<()> {
+        if pts < self.start_pts || data.is_empty() {
+            return Ok(());
+        }
+        let time = (pts - self.start_pts) as u64 * (AUDIO_TIMESCALE / MOVIE_TIMESCALE) as u64;
+        // the packets are placed by their arrival, but never overlap
+        let time = std::cmp::max(time, self.audio.end());
+        self.write_sample(data, time, true, true)?;
+        self.audio.last_duration = opus_packet_samples(data) as _;
+        Ok(())
+    }
+
+    fn write_sample(&mut self, data: &[u8], time: u64, sync: bool, audio: bool) -> ResultType<()> {
+        self.out.write_all(data)?;
+        let sample = Sample {
+            offset: self.offset,
+            size: data.len() as _,
+            time,
+            sync,
+        };
+        self.offset += data.len() as u64;
+        if audio {
+            self.audio.samples.push(sample);
+        } else {
+            self.video.samples.push(sample);
+        }
+        Ok(())
+    }
+
+    /// Fill the size of mdat and write the sample tables.
+    pub fn finish(mut self) -> ResultType<W> {
+        if self.video.samples.is_empty() {
+            bail!("no video frame");
+        }
+        self.out.seek(SeekFrom::Start(self.mdat_start + 8))?;
+        self.out
+            .write_all(&(self.offset - self.mdat_start).to_be_bytes())?;
+        self.out.seek(SeekFrom::Start(self.offset))?;
+        let moov = self.moov();
+        self.out.write_all(&moov)?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn moov(&self) -> Vec<u8> {
+        let video_duration = self.video.end();
+        let audio_duration = self.audio.end() / (AUDIO_TIMESCALE / MOVIE_TIMESCALE) as u64;
+        let duration = std::cmp::max(video_duration, audio_duration);
+        let mut content = mvhd(duration);
+        content.extend(self.video_trak());
+        if !self.audio.samples.is_empty() {
+            content.extend(self.audio_trak());
+        }
+        mp4_box(b"moov", &content)
+    }
+
+    fn video_trak(&self) -> Vec<u8> {
+        let duration = self.video.end();
+        let mut entry = visual_sample_entry(self.width as _, self.height as _);
+        entry.extend_from_slice(&self.codec_config);
+        let stsd = sample_description(if self.hevc { b"hvc1" } else { b"avc1" }, &entry);
+        let mut stbl = stsd;
+        stbl.extend(sample_tables(&self.video, true));
+        let mut vmhd = 0u16.to_be_bytes().to_vec(); // graphics mode
+        vmhd.extend_from_slice(&[0; 6]); // op color
+        let mut minf = full_box(b"vmhd", 0, 1, &vmhd);
+        minf.extend(dinf());
+        minf.extend(mp4_box(b"stbl", &stbl));
+        let mut mdia = mdhd(MOVIE_TIMESCALE, duration);
+        mdia.extend(hdlr(b"vide", "VideoHandler"));
+        mdia.extend(mp4_box(b"minf", &minf));
+        let mut trak = tkhd(
+            VIDEO_TRACK_ID,
+            duration,
+            0,
+            self.width as _,
+            self.height as _,
+        );
+        trak.extend(edts(self.video.start(), duration));
+        trak.extend(mp4_box(b"mdia", &mdia));
+        mp4_box(b"trak", &trak)
+    }
+
+    fn audio_trak(&self) -> Vec<u8> {
+        let scale = (AUDIO_TIMESCALE / MOVIE_TIMESCALE) as u64;
+        let start = self.audio.start();
+        let media_duration = self.audio.end() - start;
+        let mut entry = audio_sample_entry(self.audio_channels);
+        entry.extend(dops(self.audio_channels));
+        let mut stbl = sample_description(b"Opus", &entry);
+        stbl.extend(sample_tables(&self.audio, false));
+        let mut smhd = 0u16.to_be_bytes().to_vec(); // balance
+        smhd.extend_from_slice(&[0; 2]);
+        let mut minf = full_box(b"smhd", 0, 0, &smhd);
+        minf.extend(dinf());
+        minf.extend(mp4_box(b"stbl", &stbl));
+        let mut mdia = mdhd(AUDIO_TIMESCALE, media_duration);
+        mdia.extend(hdlr(b"soun", "SoundHandler"));
+        mdia.extend(mp4_box(b"minf", &minf));
+        let duration = self.audio.end() / scale;
+        let mut trak = tkhd(AUDIO_TRACK_ID, duration, 0x0100, 0, 0);
+        trak.extend(edts(start / scale, media_duration / scale));
+        trak.extend(mp4_box(b"mdia", &mdia));
+        mp4_box(b"trak", &trak)
+    }
+}
+
+fn mp4_box(kind: &[u8; 4], content: &[u8]) -> Vec<u8> {
+    let mut b = Vec::with_capacity(content.len() + 8);
+    b.extend_from_slice(&((content.len() + 8) as u32).to_be_bytes());
+    b.extend_from_slice(kind);
+    b.extend_from_slice(content);
+    b
+}
+
+fn full_box(kind: &[u8; 4], version: u8, flags: u32, content: &[u8]) -> Vec<u8> {
+    let mut c = vec![version];
+    c.extend_from_slice(&flags.to_be_bytes()[1..]);
+    c.extend_from_slice(content);
+    mp4_box(kind, &c)
+}
+
+fn ftyp(hevc: bool) -> Vec<u8> {
+    let mut c = b"isom".to_vec();
+    c.extend_from_slice(&512u32.to_be_bytes());
+    c.extend_from_slice(b"isomiso2mp41");
+    c.extend_from_slice(if hevc { b"hvc1" } else { b"avc1" });
+    mp4_box(b"ftyp", &c)
+}
+
+fn mvhd(duration: u64) -> Vec<u8> {
+    let mut c = vec![];
+    c.extend_from_slice(&0u64.to_be_bytes()); // creation and modification time
+    c.extend_from_slice(&MOVIE_TIMESCALE.to_be_bytes());
+    c.extend_from_slice(&(duration as u32).to_be_bytes());
+    c.extend_from_slice(&0x00010000u32.to_be_bytes()); // rate
+    c.extend_from_slice(&0x0100u16.to_be_bytes()); // volume
+    c.extend_from_slice(&[0; 10]);
+    MATRIX
+        .iter()
+        .for_each(|m| c.extend_from_slice(&m.to_be_bytes()));
+    c.extend_from_slice(&[0; 24]);
+    c.extend_from_slice(&(AUDIO_TRACK_ID + 1).to_be_bytes()); // next track id
+    full_box(b"mvhd", 0, 0, &c)
+}
+
+fn tkhd(id: u32, duration: u64, volume: u16, width: u32, height: u32) -> Vec<u8> {
+    let mut c = vec![];
+    c.extend_from_slice(&0u64.to_be_bytes()); // creation and modification time
+    c.extend_from_slice(&id.to_be_bytes());
+    c.extend_from_slice(&[0; 4]);
+    c.extend_from_slice(&(duration as u32).to_be_bytes());
+    c.extend_from_slice(&[0; 8]);
+    c.extend_from_slice(&[0; 4]); // layer and alternate group
+    c.extend_from_slice(&volume.to_be_bytes());
+    c.extend_from_slice(&[0; 2]);
+    MATRIX
+        .iter()
+        .for_each(|m| c.extend_from_slice(&m.to_be_bytes()));
+    c.extend_from_slice(&(width << 16).to_be_bytes());
+    c.extend_from_slice(&(height << 16).to_be_bytes());
+    // enabled and in movie
+    full_box(b"tkhd", 0, 3, &c)
+}
+
+// An empty edit before the first sample if the track starts later than the movie.
+// The durations are in the timescale of the movie.
+fn edts(start: u64, duration: u64) -> Vec<u8> {
+    let mut entries: Vec<(u32, i32)> = vec![];
+    if start > 0 {
+        entries.push((start as _, -1));
+    }
+    entries.push((duration as _, 0));
+    let mut c = (entries.len() as u32).to_be_bytes().to_vec();
+    for (segment_duration, media_time) in entries {
+        c.extend_from_slice(&segment_duration.to_be_bytes());
+        c.extend_from_slice(&media_time.to_be_bytes());
+        c.extend_from_slice(&0x00010000u32.to_be_bytes()); // rate
+    }
+    mp4_box(b"edts", &full_box(b"elst", 0, 0, &c))
+}
+
+fn mdhd(timescale: u32, duration: u64) -> Vec<u8> {
+    let mut c = vec![];
+    c.extend_from_slice(&0u64.to_be_bytes()); // creation and modification time
+    c.extend_from_slice(&timescale.to_be_bytes());
+    c.extend_from_slice(&(duration as u32).to_be_bytes());
+    c.extend_from_slice(&LANGUAGE_UND.to_be_bytes());
+    c.extend_from_slice(&[0; 2]);
+    full_box(b"mdhd", 0, 0, &c)
+}
+
+fn hdlr(handler: &[u8; 4], name: &str) -> Vec<u8> {
+    let mut c = vec![0; 4];
+    c.extend_from_slice(handler);
+    c.extend_from_slice(&[0; 12]);
+    c.extend_from_slice(name.as_bytes());
+    c.push(0);
+    full_box(b"hdlr", 0, 0, &c)
+}
+
+fn dinf() -> Vec<u8> {
+    let mut dref = 1u32.to_be_bytes().to_vec();
+    // the data is in the same file
+    dref.extend(full_box(b"url ", 0, 1, &[]));
+    mp4_box(b"dinf", &full_box(b"dref", 0, 0, &dref))
+}
+
+fn sample_description(kind: &[u8; 4], entry: &[u8]) -> Vec<u8> {
+    let mut c = 1u32.to_be_bytes().to_vec();
+    c.extend(mp4_box(kind, entry));
+    full_box(b"stsd", 0, 0, &c)
+}
+
+fn visual_sample_entry(width: u16, height: u16) -> Vec<u8> {
+    let mut c = vec![0; 6];
+    c.extend_from_slice(&1u16.to_be_bytes()); // data reference index
+    c.extend_from_slice(&[0; 16]);
+    c.extend_from_slice(&width.to_be_bytes());
+    c.extend_from_slice(&height.to_be_bytes());
+    c.extend_from_slice(&0x00480000u32.to_be_bytes()); // 72 dpi
+    c.extend_from_slice(&0x00480000u32.to_be_bytes());
+    c.extend_from_slice(&[0; 4]);
+    c.extend_from_slice(&1u16.to_be_bytes()); // frame count
+    c.extend_from_slice(&[0; 32]); // compressor name
+    c.extend_from_slice(&0x0018u16.to_be_bytes()); // depth
+    c.extend_from_slice(&(-1i16).to_be_bytes());
+    c
+}
+
+fn audio_sample_entry(channels: u8) -> Vec<u8> {
+    let mut c = vec![0; 6];
+    c.extend_from_slice(&1u16.to_be_bytes()); // data reference index
+    c.extend_from_slice(&[0; 8]);
+    c.extend_from_slice(&(channels as u16).to_be_bytes());
+    c.extend_from_slice(&16u16.to_be_bytes()); // sample size
+    c.extend_from_slice(&[0; 4]);
+    c.extend_from_slice(&(AUDIO_TIMESCALE << 16).to_be_bytes());
+    c
+}
+
+// https://opus-codec.org/docs/opus_in_isobmff.html#4.3.2
+fn dops(channels: u8) -> Vec<u8> {
+    let mut c = vec![0, channels]; // version, output channel count
+    c.extend_from_slice(&0u16.to_be_bytes()); // pre-skip
+    c.extend_from_slice(&AUDIO_TIMESCALE.to_be_bytes()); // input sample rate
+    c.extend_from_slice(&0i16.to_be_bytes()); // output gain
+    c.push(0); // channel mapping family
+    mp4_box(b"dOps", &c)
+}
+
+// stts, stss of the video, stsc, stsz and co64, every sample is a chunk.
+fn sample_tables(track: &Track, video: bool) -> Vec<u8> {
+    let samples = &track.samples;
+    let mut deltas: Vec<(u32, u32)> = vec![];
+    for (i, s) in samples.iter().enumerate() {
+        let delta = match samples.get(i + 1) {
+            Some(next) => next.time - s.time,
+            None => track.last_duration,
+        } as u32;
+        match deltas.last_mut() {
+            Some((count, d)) if *d == delta => *count += 1,
+            _ => deltas.push((1, delta)),
+        }
+    }
+    let mut stts = (deltas.len() as u32).to_be_bytes().to_vec();
+    for (count, delta) in deltas {
+        stts.extend_from_slice(&count.to_be_bytes());
+        stts.extend_from_slice(&delta.to_be_bytes());
+    }
+    let mut tables = full_box(b"stts", 0, 0, &stts);
+    if video {
+        let syncs: Vec<u32> = samples
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.sync)
+            .map(|(i, _)| i as u32 + 1)
+            .collect();
+        let mut stss = (syncs.len() as u32).to_be_bytes().to_vec();
+        syncs
+            .iter()
+            .for_each(|n| stss.extend_from_slice(&n.to_be_bytes()));
+        tables.extend(full_box(b"stss", 0, 0, &stss));
+    }
+    let mut stsc = 1u32.to_be_bytes().to_vec();
+    stsc.extend_from_slice(&1u32.to_be_bytes()); // first chunk
+    stsc.extend_from_slice(&1u32.to_be_bytes()); // samples per chunk
+    stsc.extend_from_slice(&1u32.to_be_bytes()); // sample description index
+    tables.extend(full_box(b"stsc", 0, 0, &stsc));
+    let mut stsz = 0u32.to_be_bytes().to_vec();
+    stsz.extend_from_slice(&(samples.len() as u32).to_be_bytes());
+    samples
+        .iter()
+        .for_each(|s| stsz.extend_from_slice(&s.size.to_be_bytes()));
+    tables.extend(full_box(b"stsz", 0, 0, &stsz));
+    let mut co64 = (samples.len() as u32).to_be_bytes().to_vec();
+    samples
+        .iter()
+        .for_each(|s| co64.extend_from_slice(&s.offset.to_be_bytes()));
+    tables.extend(full_box(b"co64", 0, 0, &co64));
+    tables
+}
+
+fn nal_type(nal: &[u8], hevc: bool) -> u8 {
+    if hevc {
+        (nal[0] >> 1) & 0x3f
+    } else {
+        nal[0] & 0x1f
+    }
+}
+
+// https://www.iso.org/standard/83529.html, 5.3.3.1
+fn avcc(key_frame: &[u8]) -> ResultType<Vec<u8>> {
+    let nals = nal_units(key_frame);
+    let sps = nals.iter().find(|n| nal_type(n, false) == H264_SPS);
+    let pps = nals.iter().find(|n| nal_type(n, false) == H264_PPS);
+    let (Some(sps), Some(pps)) = (sps, pps) else {
+        bail!("no sps or pps in the key frame");
+    };
+    if sps.len() < 4 {
+        bail!("invalid sps");
+    }
+    // version, profile, compatibility, level, 4 bytes nal length
+    let mut c = vec![1, sps[1], sps[2], sps[3], 0xff];
+    c.push(0xe1); // one sps
+    c.extend_from_slice(&(sps.len() as u16).to_be_bytes());
+    c.extend_from_slice(sps);
+    c.push(1); // one pps
+    c.extend_from_slice(&(pps.len() as u16).to_be_bytes());
+    c.extend_from_slice(pps);
+    // the high profiles have the chroma format and the bit depths
+    if matches!(sps[1], 100 | 110 | 122 | 244) {
+        let rbsp = remove_emulation_prevention(&sps[4..]);
+        let mut r = BitReader::new(&rbsp);
+        r.ue()?; // sps id
+        let chroma_format = r.ue()?;
+        if chroma_format == 3 {
+            r.skip(1)?; // separate colour plane
+        }
+        let (bit_depth_luma, bit_depth_chroma) = (r.ue()?, r.ue()?);
+        if chroma_format > 3 || bit_depth_luma > 7 || bit_depth_chroma > 7 {
+            bail!("invalid sps");
+        }
+        c.push(0xfc | chroma_format as u8);
+        c.push(0xf8 | bit_depth_luma as u8);
+        c.push(0xf8 | bit_depth_chroma as u8);
+        c.push(0); // no sps extension
+    }
+    Ok(mp4_box(b"avcC", &c))
+}
+
+// https://www.iso.org/standard/83529.html, 8.3.3.1
+// The fields are from the sps, https://www.itu.int/rec/T-REC-H.265, 7.3.2.2
+fn hvcc(key_frame: &[u8]) -> ResultType<Vec<u8>> {
+    let nals = nal_units(key_frame);
+    let find = |t| nals.iter().find(|n| nal_type(n, true) == t);
+    let (Some(vps), Some(sps), Some(pps)) = (find(H265_VPS), find(H265_SPS), find(H265_PPS)) else {
+        bail!("no vps, sps or pps in the key frame");
+    };
+    let rbsp = remove_emulation_prevention(&sps[2..]);
+    let mut r = BitReader::new(&rbsp);
+    r.skip(4)?; // vps id
+    let max_sub_layers = r.bits(3)? + 1;
+    let temporal_id_nested = r.bits(1)?;
+    // profile space, tier, profile, compatibility flags, constraint flags, level
+    r.skip(96)?;
+    let profile_tier_level = &rbsp[1..13];
+    let sub_layers = (1..max_sub_layers)
+        .map(|_| Ok((r.bits(1)?, r.bits(1)?)))
+        .collect::<ResultType<Vec<_>>>()?;
+    if max_sub_layers > 1 {
+        r.skip(2 * (9 - max_sub_layers as usize))?;
+    }
+    for (profile_present, level_present) in sub_layers {
+        r.skip(88 * profile_present as usize + 8 * level_present as usize)?;
+    }
+    r.ue()?; // sps id
+    let chroma_format = r.ue()?;
+    if chroma_format == 3 {
+        r.skip(1)?; // separate colour plane
+    }
+    r.ue()?; // width
+    r.ue()?; // height
+    if r.bits(1)? == 1 {
+        // conformance window
+        for _ in 0..4 {
+            r.ue()?;
+        }
+    }
+    let (bit_depth_luma, bit_depth_chroma) = (r.ue()?, r.ue()?);
+    if chroma_format > 3 || bit_depth_luma > 7 || bit_depth_chroma > 7 {
+        bail!("invalid sps");
+    }
+    let mut c = vec![1];
+    c.extend_from_slice(profile_tier_level);
+    c.extend_from_slice(&0xf000u16.to_be_bytes()); // min spatial segmentation
+    c.push(0xfc); // parallelism type
+    c.push(0xfc | chroma_format as u8);
+    c.push(0xf8 | bit_depth_luma as u8);
+    c.push(0xf8 | bit_depth_chroma as u8);
+    c.extend_from_slice(&0u16.to_be_bytes()); // average frame rate
+                                              // constant frame rate 0, temporal layers, temporal id nested, 4 bytes nal length
+    c.push((max_sub_layers << 3 | temporal_id_nested << 2 | 3) as u8);
+    c.push(3);
+    for (t, nal) in [(H265_VPS, vps), (H265_SPS, sps), (H265_PPS, pps)] {
+        c.push(0x80 | t); // array completeness
+        c.extend_from_slice(&1u16.to_be_bytes());
+        c.extend_from_slice(&(nal.len() as u16).to_be_bytes());
+        c.extend_from_slice(nal);
+    }
+    Ok(mp4_box(b"hvcC", &c))
+}
+
+// Reads the fields of a parameter set without the emulation prevention bytes.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn skip(&mut self, n: usize) -> ResultType<()> {
+        if self.pos + n > self.data.len() * 8 {
+            bail!("invalid parameter set");
+        }
+        self.pos += n;
+        Ok(())
+    }
+
+    fn bits(&mut self, n: usize) -> ResultType<u32> {
+        let mut v = 0;
+        for _ in 0..n {
+            let Some(byte) = self.data.get(self.pos / 8) else {
+                bail!("invalid parameter set");
+            };
+            v = v << 1 | ((byte >> (7 - self.pos % 8)) & 1) as u32;
+            self.pos += 1;
+        }
+        Ok(v)
+    }
+
+    // exp-golomb
+    fn ue(&mut self) -> ResultType<u32> {
+        let mut zeros = 0;
+        while self.bits(1)? == 0 {
+            zeros += 1;
+            if zeros > 31 {
+                bail!("invalid parameter set");
+            }
+        }
+        Ok((1u64 << zeros) as u32 - 1 + self.bits(zeros)?)
+    }
+}
+
+fn remove_emulation_prevention(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut zeros = 0;
+    for &b in data {
+        if zeros >= 2 && b == 3 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if b == 0 { zeros + 1 } else { 0 };
+        out.push(b);
+    }
+    out
+}
+
+// Split the annex b byte stream by the start codes.
+fn nal_units(data: &[u8]) -> Vec<&[u8]> {
+    let mut nals = vec![];
+    let mut start = None;
+    let mut i = 0;
+    while i + 3 <= data.len() {
+        if data[i] == 0 && data[i + 1] == 0 && data[i + 2] == 1 {
+            if let Some(s) = start {
+                // the leading zero of a 4 bytes start code
+                let end = if i > s && data[i - 1] == 0 { i - 1 } else { i };
+                nals.push(&data[s..end]);
+            }
+            i += 3;
+            start = Some(i);
+        } else {
+            i += 1;
+        }
+    }
+    if let Some(s) = start {
+        nals.push(&data[s..]);
+    }
+    nals.into_iter().filter(|n| !n.is_empty()).collect()
+}
+
+// The parameter sets are in the sample description of mp4, the access unit delimiters are not needed.
+fn to_length_prefixed(data: &[u8], hevc: bool) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    for nal in nal_units(data) {
+        let t = nal_type(nal, hevc);
+        let skip = if hevc {
+            matches!(t, H265_VPS | H265_SPS | H265_PPS | H265_AUD)
+        } else {
+            matches!(t, H264_SPS | H264_PPS | H264_AUD)
+        };
+        if skip {
+            continue;
+        }
+        out.extend_from_slice(&(nal.len() as u32).to_be_bytes());
+        out.extend_from_slice(nal);
+    }
+    out
+}
+
+// The samples of an opus packet at 48000Hz, by the toc byte.
+// https://www.rfc-editor.org/rfc/rfc6716#section-3.1
+fn opus_packet_samples(data: &[u8]) -> u32 {
+    let Some(toc) = data.first() else {
+        return 0;
+    };
+    let config = toc >> 3;
+    let frame_samples = match config {
+        0..=11 => [480, 960, 1920, 2880][config as usize % 4],
+        12..=15 => [480, 960][config as usize % 2],
+        _ => [120, 240, 480, 960][config as usize % 4],
+    };
+    let frames = match toc & 0x3 {
+        0 => 1,
+        1 | 2 => 2,
+        _ => data.get(1).map_or(0, |c| (c & 0x3f) as u32),
+    };
+    frame_samples * frames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    #[test]
+    fn test_opus_packet_samples() {
+        // celt fullband 20ms, one frame
+        assert_eq!(opus_packet_samples(&[31 << 3]), 960);
+        // silk 10ms, two frames
+        assert_eq!(opus_packet_samples(&[1]), 960);
+        // celt 2.5ms, 3 frames in code 3
+        assert_eq!(opus_packet_samples(&[(16 << 3) | 3, 3]), 360);
+        assert_eq!(opus_packet_samples(&[]), 0);
+    }
+
+    #[test]
+    fn test_mux() {
+        let sps = [0x67, 0x42, 0xc0, 0x1f, 0xda];
+        let pps = [0x68, 0xce, 0x3c, 0x80];
+        let idr = [0x65, 0x88, 0x84];
+        let mut key = vec![];
+        for nal in [&sps[..], &pps[..], &idr[..]] {
+            key.extend_from_slice(&[0, 0, 0, 1]);
+            key.extend_from_slice(nal);
+        }
+        let out = std::io::Cursor::new(vec![]);
+        let config = codec_config(false, &key).unwrap();
+        let mut muxer = Mp4Muxer::new(out, false, 64, 48, config, 1, 100).unwrap();
+        muxer.write_video(&key, 100, true).unwrap();
+        muxer.write_audio(&[31 << 3, 1, 2], 110).unwrap();
+        muxer
+            .write_video(&[0, 0, 1, 0x41, 0x9a], 133, false)
+            .unwrap();
+        // before the first video frame
+        muxer.write_audio(&[31 << 3, 1, 2], 90).unwrap();
+        let data = muxer.finish().unwrap().into_inner();
+        assert_eq!(&data[4..8], b"ftyp");
+        let mdat = ftyp(false).len();
+        assert_eq!(&data[mdat + 4..mdat + 8], b"mdat");
+        let mdat_size = u64::from_be_bytes(data[mdat + 8..mdat + 16].try_into().unwrap());
+        // the sps and pps are not in the samples
+        assert_eq!(mdat_size, 16 + (4 + 3) + 3 + (4 + 2));
+        let moov = &data[mdat + mdat_size as usize..];
+        assert_eq!(&moov[4..8], b"moov");
+        assert_eq!(
+            u32::from_be_bytes(moov[..4].try_into().unwrap()) as usize,
+            moov.len()
+        );
+        let find = |kind: &[u8]| moov.windows(4).filter(|w| *w == kind).count();
+        assert_eq!(find(b"trak"), 2);
+        assert_eq!(find(b"avcC"), 1);
+        assert_eq!(find(b"dOps"), 1);
+        assert_eq!(find(b"stss"), 1);
+    }
+
+    // The content of the only box of the kind in `data`, the sizes of the boxes must add up.
+    fn child<'a>(data: &'a [u8], kind: &[u8]) -> &'a [u8] {
+        let mut found = vec![];
+        let mut i = 0;
+        while i < data.len() {
+            let mut size = u32::from_be_bytes(data[i..i + 4].try_into().unwrap()) as usize;
+            let mut header = 8;
+            if size == 1 {
+                size = u64::from_be_bytes(data[i + 8..i + 16].try_into().unwrap()) as usize;
+                header = 16;
+            }
+            assert!(size >= header && i + size <= data.len());
+            if &data[i + 4..i + 8] == kind {
+                found.push(&data[i + header..i + size]);
+            }
+            i += size;
+        }
+        assert_eq!(found.len(), 1, "{:?}", std::str::from_utf8(kind));
+        found[0]
+    }
+
+    struct BitWriter(Vec<bool>);
+
+    impl BitWriter {
+        fn bits(&mut self, n: usize, v: u64) {
+            (0..n)
+                .rev()
+                .for_each(|i| self.0.push(v.checked_shr(i as u32).unwrap_or(0) & 1 == 1));
+        }
+
+        fn ue(&mut self, v: u64) {
+            let n = 64 - (v + 1).leading_zeros() as usize;
+            self.bits(n - 1, 0);
+            self.bits(n, v + 1);
+        }
+
+        // with the stop bit and the emulation prevention bytes
+        fn rbsp(mut self) -> Vec<u8> {
+            self.0.push(true);
+            self.0.resize(self.0.len().div_ceil(8) * 8, false);
+            let mut out = vec![];
+            for byte in self.0.chunks(8) {
+                let b = byte.iter().fold(0u8, |b, bit| b << 1 | *bit as u8);
+                if out.len() >= 2 && out[out.len() - 2..] == [0, 0] && b <= 3 {
+                    out.push(3);
+                }
+                out.push(b);
+            }
+            out
+        }
+    }
+
+    #[test]
+    fn test_mux_hevc() {
+        // main 4:4:4 10, two sub layers
+        let mut w = BitWriter(vec![]);
+        w.bits(4, 0); // vps id
+        w.bits(3, 1); // max sub layers - 1
+        w.bits(1, 1); // temporal id nested
+        w.bits(8, 4); // profile space, tier, profile
+        w.bits(32, 1 << 27); // compatibility flags
+        w.bits(48, 0x9000_0000_0000); // constraint flags
+        w.bits(8, 93); // level
+        w.bits(2, 0b11); // sub layer profile and level present
+        w.bits(14, 0); // reserved
+        w.bits(88, 0); // sub layer profile
+        w.bits(8, 90); // sub layer level
+        w.ue(0); // sps id
+        w.ue(3); // chroma format 4:4:4
+        w.bits(1, 0); // separate colour plane
+        w.ue(64);
+        w.ue(48);
+        w.bits(1, 1); // conformance window
+        (0..4).for_each(|_| w.ue(1));
+        w.ue(2); // bit depth luma 10
+        w.ue(2); // bit depth chroma 10
+        let mut sps = vec![H265_SPS << 1, 1];
+        sps.extend(w.rbsp());
+        let vps = [H265_VPS << 1, 1, 0x0c];
+        let pps = [H265_PPS << 1, 1, 0xc1];
+        let idr = [19 << 1, 1, 0xaf];
+        let mut key = vec![];
+        for nal in [&vps[..], &sps[..], &pps[..], &idr[..]] {
+            key.extend_from_slice(&[0, 0, 0, 1]);
+            key.extend_from_slice(nal);
+        }
+        let config = codec_config(true, &key).unwrap();
+        let out = std::io::Cursor::new(vec![]);
+        let mut muxer = Mp4Muxer::new(out, true, 64, 48, config, 2, 0).unwrap();
+        muxer.write_video(&key, 0, true).unwrap();
+        muxer
+            .write_video(&[0, 0, 1, 2, 1, 0xd0], 33, false)
+            .unwrap();
+        let data = muxer.finish().unwrap().into_inner();
+        assert_eq!(&child(&data, b"ftyp")[8..], b"isomiso2mp41hvc1");
+        assert_eq!(child(&data, b"mdat").len(), (4 + 3) + (4 + 3));
+        let stbl = [&b"moov"[..], b"trak", b"mdia", b"minf", b"stbl"]
+            .iter()
+            .fold(&data[..], |d, kind| child(d, kind));
+        // version, flags and the entry count
+        let hvc1 = child(&child(stbl, b"stsd")[8..], b"hvc1");
+        assert_eq!(u16::from_be_bytes(hvc1[24..26].try_into().unwrap()), 64);
+        let c = child(&hvc1[78..], b"hvcC");
+        assert_eq!(c[0], 1);
+        assert_eq!(&c[1..13], &[4, 8, 0, 0, 0, 0x90, 0, 0, 0, 0, 0, 93]);
+        assert_eq!(&c[15..19], &[0xfc, 0xfc | 3, 0xf8 | 2, 0xf8 | 2]);
+        // two temporal layers, nested, 4 bytes nal length
+        assert_eq!(c[21], 2 << 3 | 1 << 2 | 3);
+        assert_eq!(c[22], 3);
+        assert_eq!(&c[23..26], &[0x80 | H265_VPS, 0, 1]);
+        // stss lists the key frame only
+        assert_eq!(&child(stbl, b"stss")[4..], &[0, 0, 0, 1, 0, 0, 0, 1]);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{common::TraitCapturer, x11, Frame, Pixfmt, TraitPixelBuffer};
+use crate::{common::TraitCapturer, x11, DirtyRect, Frame, Pixfmt, TraitPixelBuffer};
 use std::{io, time::Duration};
 
 pub struct Capturer(x11::Capturer);
@@ -23,12 +23,10 @@ impl TraitCapturer for Capturer {
     fn frame<'a>(&'a mut self, _timeout: Duration) -> io::Result<Frame<'a>> {
         let width = self.width();
         let height = self.height();
-        Ok(Frame::PixelBuffer(PixelBuffer::new(
-            self.0.frame()?,
-            Pixfmt::BGRA,
-            width,
-            height,
-        )))
+        let (data, dirty_rects) = self.0.frame()?;
+        Ok(Frame::PixelBuffer(
+            PixelBuffer::new(data, Pixfmt::BGRA, width, height).with_dirty_rects(dirty_rects),
+        ))
     }
 }
 
@@ -38,6 +36,7 @@ pub struct PixelBuffer<'a> {
     width: usize,
     height: usize,
     stride: Vec<usize>,
+    dirty_rects: Option<Vec<DirtyRect>>,
 }
 
 impl<'a> PixelBuffer<'a> {
@@ -51,8 +50,14 @@ impl<'a> PixelBuffer<'a> {
             width,
             height,
             stride,
+            dirty_rects: None,
         }
     }
+
+    pub fn with_dirty_rects(mut self, dirty_rects: Option<Vec<DirtyRect>>) -> Self {
+        self.dirty_rects = dirty_rects;
+        self
+    }
 }
 
 impl<'a> TraitPixelBuffer for PixelBuffer<'a> {
@@ -75,6 +80,10 @@ impl<'a> TraitPixelBuffer for PixelBuffer<'a> {
     fn pixfmt(&self) -> crate::Pixfmt {
         self.pixfmt
     }
+
+    fn dirty_rects(&self) -> Option<&[DirtyRect]> {
+        self.dirty_rects.as_deref()
+    }
 }
 
 pub struct Display(x11::Display);
@@ -1,5 +1,7 @@
+use super::damage::Damage;
 use super::ffi::*;
 use super::Display;
+use crate::DirtyRect;
 use hbb_common::libc;
 use std::{io, ptr, slice};
 
@@ -11,6 +13,9 @@ pub struct Capturer {
 
     size: usize,
     saved_raw_data: Vec<u8>, // for faster compare and copy
+    // Only capture if the screen is changed, `None` if the DAMAGE extension is not available.
+    damage: Option<Damage>,
+    captured: bool,
 }
 
 impl Capturer {
@@ -57,6 +62,7 @@ impl Capturer {
             );
         }
 
+        let damage = Damage::new(server, display.root());
         let c = Capturer {
             display,
             shmid,
@@ -64,6 +70,8 @@ impl Capturer {
             buffer,
             size,
             saved_raw_data: Vec::new(),
+            damage,
+            captured: false,
         };
         Ok(c)
     }
@@ -93,16 +101,31 @@ impl Capturer {
         }
     }
 
-    pub fn frame<'b>(&'b mut self) -> std::io::Result<&'b [u8]> {
+    /// Returns the frame and the changed rectangles, the rectangles are `None` if unknown.
+    pub fn frame<'b>(&'b mut self) -> std::io::Result<(&'b [u8], Option<Vec<DirtyRect>>)> {
+        let mut dirty_rects = None;
+        if let Some(damage) = self.damage.as_mut() {
+            let rects = damage.changed_rects(&self.display.rect());
+            if self.captured {
+                if rects.is_empty() {
+                    return Err(io::ErrorKind::WouldBlock.into());
+                }
+                dirty_rects = Some(rects);
+            }
+        }
         self.get_image();
         let result = unsafe { slice::from_raw_parts(self.buffer, self.size) };
-        crate::would_block_if_equal(&mut self.saved_raw_data, result)?;
-        Ok(result)
+        if self.damage.is_none() {
+            crate::would_block_if_equal(&mut self.saved_raw_data, result)?;
+        }
+        self.captured = true;
+        Ok((result, dirty_rects))
     }
 }
 
 impl Drop for Capturer {
     fn drop(&mut self) {
+        self.damage.take();
         unsafe {
             // Detach segment from XCB.
             xcb_shm_detach(self.display.server().raw(), self.xcbid);
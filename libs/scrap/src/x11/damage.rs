@@ -0,0 +1,171 @@
+// https://www.x.org/releases/current/doc/damageproto/damageproto.txt
+//
+// libxcb-damage is loaded at runtime, so that it is not a build dependency,
+// and the capturer falls back to comparing the whole frame if it or the DAMAGE extension is not available.
+
+use super::ffi::*;
+use super::Rect;
+use crate::DirtyRect;
+use hbb_common::{
+    dlopen::symbor::Library,
+    libc::{self, c_void},
+    log,
+};
+use std::ptr;
+
+const LIB_NAME: &str = "libxcb-damage.so.0";
+const XCB_DAMAGE_REPORT_LEVEL_DELTA_RECTANGLES: u8 = 1;
+const XCB_DAMAGE_NOTIFY: u8 = 0;
+const XCB_NONE: u32 = 0;
+
+type QueryVersion = unsafe extern "C" fn(
+    c: *mut xcb_connection_t,
+    client_major_version: u32,
+    client_minor_version: u32,
+) -> xcb_damage_query_version_cookie_t;
+type QueryVersionReply = unsafe extern "C" fn(
+    c: *mut xcb_connection_t,
+    cookie: xcb_damage_query_version_cookie_t,
+    e: *mut *mut xcb_generic_error_t,
+) -> *mut xcb_damage_query_version_reply_t;
+type Create = unsafe extern "C" fn(
+    c: *mut xcb_connection_t,
+    damage: xcb_damage_damage_t,
+    drawable: xcb_drawable_t,
+    level: u8,
+) -> xcb_void_cookie_t;
+type Destroy =
+    unsafe extern "C" fn(c: *mut xcb_connection_t, damage: xcb_damage_damage_t) -> xcb_void_cookie_t;
+type Subtract = unsafe extern "C" fn(
+    c: *mut xcb_connection_t,
+    damage: xcb_damage_damage_t,
+    repair: u32,
+    parts: u32,
+) -> xcb_void_cookie_t;
+
+/// Tracks the changed regions of the root window.
+pub struct Damage {
+    conn: *mut xcb_connection_t,
+    damage: xcb_damage_damage_t,
+    notify_event: u8,
+    destroy: Destroy,
+    subtract: Subtract,
+    _lib: Library,
+}
+
+impl Damage {
+    pub fn new(conn: *mut xcb_connection_t, root: xcb_window_t) -> Option<Self> {
+        let lib = match Library::open(LIB_NAME) {
+            Ok(lib) => lib,
+            Err(e) => {
+                log::info!("Failed to load {}, {}", LIB_NAME, e);
+                return None;
+            }
+        };
+        unsafe {
+            let query_version = *lib.symbol::<QueryVersion>("xcb_damage_query_version").ok()?;
+            let query_version_reply = *lib
+                .symbol::<QueryVersionReply>("xcb_damage_query_version_reply")
+                .ok()?;
+            let create = *lib.symbol::<Create>("xcb_damage_create").ok()?;
+            let destroy = *lib.symbol::<Destroy>("xcb_damage_destroy").ok()?;
+            let subtract = *lib.symbol::<Subtract>("xcb_damage_subtract").ok()?;
+            // the address of the extension id
+            let ext = *lib.symbol::<*mut xcb_extension_t>("xcb_damage_id").ok()?;
+
+            let ext_reply = xcb_get_extension_data(conn, ext);
+            if ext_reply.is_null() || (*ext_reply).present == 0 {
+                log::info!("DAMAGE extension is not available");
+                return None;
+            }
+            let notify_event = (*ext_reply).first_event + XCB_DAMAGE_NOTIFY;
+
+            // The version must be negotiated before any other request.
+            let reply = query_version_reply(conn, query_version(conn, 1, 1), ptr::null_mut());
+            if reply.is_null() {
+                log::info!("Failed to query DAMAGE version");
+                return None;
+            }
+            libc::free(reply as *mut c_void);
+
+            let damage = xcb_generate_id(conn);
+            create(
+                conn,
+                damage,
+                root,
+                XCB_DAMAGE_REPORT_LEVEL_DELTA_RECTANGLES,
+            );
+            xcb_flush(conn);
+            Some(Self {
+                conn,
+                damage,
+                notify_event,
+                destroy,
+                subtract,
+                _lib: lib,
+            })
+        }
+    }
+
+    /// The changed rectangles since the last call, clipped to `rect` and relative to its origin.
+    pub fn changed_rects(&mut self, rect: &Rect) -> Vec<DirtyRect> {
+        let mut rects = Vec::new();
+        let mut notified = false;
+        unsafe {
+            loop {
+                let event = xcb_poll_for_event(self.conn);
+                if event.is_null() {
+                    break;
+                }
+                // the highest bit is set if the event is from a SendEvent request
+                if (*event).response_type & 0x7f == self.notify_event {
+                    let notify = event as *const xcb_damage_notify_event_t;
+                    if (*notify).damage == self.damage {
+                        notified = true;
+                        if let Some(r) = clip(&(*notify).area, rect) {
+                            rects.push(r);
+                        }
+                    }
+                }
+                libc::free(event as *mut c_void);
+            }
+            if notified {
+                // Empty the damage region, the region only reports the new damages when it grows.
+                (self.subtract)(self.conn, self.damage, XCB_NONE, XCB_NONE);
+                xcb_flush(self.conn);
+            }
+        }
+        rects
+    }
+}
+
+impl Drop for Damage {
+    fn drop(&mut self) {
+        unsafe {
+            (self.destroy)(self.conn, self.damage);
+            xcb_flush(self.conn);
+        }
+    }
+}
+
+fn clip(area: &xcb_rectangle_t, rect: &Rect) -> Option<DirtyRect> {
+    let left = std::cmp::max(area.x as i32, rect.x as i32);
+    let top = std::cmp::max(area.y as i32, rect.y as i32);
+    let right = std::cmp::min(
+        area.x as i32 + area.width as i32,
+        rect.x as i32 + rect.w as i32,
+    );
+    let bottom = std::cmp::min(
+        area.y as i32 + area.height as i32,
+        rect.y as i32 + rect.h as i32,
+    );
+    if right <= left || bottom <= top {
+        return None;
+    }
+    Some(DirtyRect {
+        x: (left - rect.x as i32) as _,
+        y: (top - rect.y as i32) as _,
+        width: (right - left) as _,
+        height: (bottom - top) as _,
+    })
+}
@@ -88,6 +88,84 @@ extern "C" {
         cookie: xcb_shm_query_version_cookie_t,
         e: *mut *mut xcb_generic_error_t,
     ) -> *const xcb_shm_query_version_reply_t;
+
+    pub fn xcb_get_extension_data(
+        c: *mut xcb_connection_t,
+        ext: *mut xcb_extension_t,
+    ) -> *const xcb_query_extension_reply_t;
+
+    pub fn xcb_poll_for_event(c: *mut xcb_connection_t) -> *mut xcb_generic_event_t;
+
+    pub fn xcb_flush(c: *mut xcb_connection_t) -> i32;
+
+    pub fn xcb_intern_atom(
+        c: *mut xcb_connection_t,
+        only_if_exists: u8,
+        name_len: u16,
+        name: *const i8,
+    ) -> xcb_intern_atom_cookie_t;
+
+    pub fn xcb_intern_atom_reply(
+        c: *mut xcb_connection_t,
+        cookie: xcb_intern_atom_cookie_t,
+        e: *mut *mut xcb_generic_error_t,
+    ) -> *mut xcb_intern_atom_reply_t;
+
+    pub fn xcb_get_property(
+        c: *mut xcb_connection_t,
+        delete: u8,
+        window: xcb_window_t,
+        property: xcb_atom_t,
+        type_: xcb_atom_t,
+        long_offset: u32,
+        long_length: u32,
+    ) -> xcb_get_property_cookie_t;
+
+    pub fn xcb_get_property_reply(
+        c: *mut xcb_connection_t,
+        cookie: xcb_get_property_cookie_t,
+        e: *mut *mut xcb_generic_error_t,
+    ) -> *mut xcb_get_property_reply_t;
+
+    pub fn xcb_get_property_value(r: *const xcb_get_property_reply_t) -> *mut c_void;
+
+    pub fn xcb_get_property_value_length(r: *const xcb_get_property_reply_t) -> i32;
+
+    pub fn xcb_get_window_attributes(
+        c: *mut xcb_connection_t,
+        window: xcb_window_t,
+    ) -> xcb_get_window_attributes_cookie_t;
+
+    pub fn xcb_get_window_attributes_reply(
+        c: *mut xcb_connection_t,
+        cookie: xcb_get_window_attributes_cookie_t,
+        e: *mut *mut xcb_generic_error_t,
+    ) -> *mut xcb_get_window_attributes_reply_t;
+
+    pub fn xcb_get_geometry(
+        c: *mut xcb_connection_t,
+        drawable: xcb_drawable_t,
+    ) -> xcb_get_geometry_cookie_t;
+
+    pub fn xcb_get_geometry_reply(
+        c: *mut xcb_connection_t,
+        cookie: xcb_get_geometry_cookie_t,
+        e: *mut *mut xcb_generic_error_t,
+    ) -> *mut xcb_get_geometry_reply_t;
+
+    pub fn xcb_translate_coordinates(
+        c: *mut xcb_connection_t,
+        src_window: xcb_window_t,
+        dst_window: xcb_window_t,
+        src_x: i16,
+        src_y: i16,
+    ) -> xcb_translate_coordinates_cookie_t;
+
+    pub fn xcb_translate_coordinates_reply(
+        c: *mut xcb_connection_t,
+        cookie: xcb_translate_coordinates_cookie_t,
+        e: *mut *mut xcb_generic_error_t,
+    ) -> *mut xcb_translate_coordinates_reply_t;
 }
 
 pub const XCB_IMAGE_FORMAT_Z_PIXMAP: u8 = 2;
@@ -104,6 +182,8 @@ pub type xcb_drawable_t = u32;
 pub type xcb_get_atom_name_cookie_t = u32;
 pub type xcb_get_atom_name_reply_t = u32;
 pub type xcb_get_atom_name_request_t = xcb_get_atom_name_reply_t;
+pub type xcb_extension_t = c_void;
+pub type xcb_damage_damage_t = u32;
 
 #[repr(C)]
 pub struct xcb_setup_t {
@@ -248,3 +328,162 @@ pub struct xcb_shm_query_version_reply_t {
     pub pixmap_format: u8,
     pub pad0: [u8; 15],
 }
+
+#[repr(C)]
+pub struct xcb_query_extension_reply_t {
+    pub response_type: u8,
+    pub pad0: u8,
+    pub sequence: u16,
+    pub length: u32,
+    pub present: u8,
+    pub major_opcode: u8,
+    pub first_event: u8,
+    pub first_error: u8,
+}
+
+#[repr(C)]
+pub struct xcb_generic_event_t {
+    pub response_type: u8,
+    pub pad0: u8,
+    pub sequence: u16,
+    pub pad: [u32; 7],
+    pub full_sequence: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct xcb_rectangle_t {
+    pub x: i16,
+    pub y: i16,
+    pub width: u16,
+    pub height: u16,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct xcb_damage_query_version_cookie_t {
+    pub sequence: u32,
+}
+
+#[repr(C)]
+pub struct xcb_damage_query_version_reply_t {
+    pub response_type: u8,
+    pub pad0: u8,
+    pub sequence: u16,
+    pub length: u32,
+    pub major_version: u32,
+    pub minor_version: u32,
+    pub pad1: [u8; 16],
+}
+
+#[repr(C)]
+pub struct xcb_damage_notify_event_t {
+    pub response_type: u8,
+    pub level: u8,
+    pub sequence: u16,
+    pub drawable: xcb_drawable_t,
+    pub damage: xcb_damage_damage_t,
+    pub timestamp: xcb_timestamp_t,
+    pub area: xcb_rectangle_t,
+    pub geometry: xcb_rectangle_t,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct xcb_intern_atom_cookie_t {
+    pub sequence: u32,
+}
+
+#[repr(C)]
+pub struct xcb_intern_atom_reply_t {
+    pub response_type: u8,
+    pub pad0: u8,
+    pub sequence: u16,
+    pub length: u32,
+    pub atom: xcb_atom_t,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct xcb_get_property_cookie_t {
+    pub sequence: u32,
+}
+
+#[repr(C)]
+pub struct xcb_get_property_reply_t {
+    pub response_type: u8,
+    pub format: u8,
+    pub sequence: u16,
+    pub length: u32,
+    pub type_: xcb_atom_t,
+    pub bytes_after: u32,
+    pub value_len: u32,
+    pub pad0: [u8; 12],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct xcb_get_window_attributes_cookie_t {
+    pub sequence: u32,
+}
+
+#[repr(C)]
+pub struct xcb_get_window_attributes_reply_t {
+    pub response_type: u8,
+    pub backing_store: u8,
+    pub sequence: u16,
+    pub length: u32,
+    pub visual: xcb_visualid_t,
+    pub _class: u16,
+    pub bit_gravity: u8,
+    pub win_gravity: u8,
+    pub backing_planes: u32,
+    pub backing_pixel: u32,
+    pub save_under: u8,
+    pub map_is_installed: u8,
+    pub map_state: u8,
+    pub override_redirect: u8,
+    pub colormap: xcb_colormap_t,
+    pub all_event_masks: u32,
+    pub your_event_mask: u32,
+    pub do_not_propagate_mask: u16,
+    pub pad0: [u8; 2],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct xcb_get_geometry_cookie_t {
+    pub sequence: u32,
+}
+
+#[repr(C)]
+pub struct xcb_get_geometry_reply_t {
+    pub response_type: u8,
+    pub depth: u8,
+    pub sequence: u16,
+    pub length: u32,
+    pub root: xcb_window_t,
+    pub x: i16,
+    pub y: i16,
+    pub width: u16,
+    pub height: u16,
+    pub border_width: u16,
+    pub pad0: [u8; 2],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct xcb_translate_coordinates_cookie_t {
+    pub sequence: u32,
+}
+
+#[repr(C)]
+pub struct xcb_translate_coordinates_reply_t {
+    pub response_type: u8,
+    pub same_screen: u8,
+    pub sequence: u16,
+    pub length: u32,
+    pub child: xcb_window_t,
+    pub dst_x: i16,
+    pub dst_y: i16,
+}
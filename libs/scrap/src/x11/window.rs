@@ -0,0 +1,173 @@
+// Find a top level window by its title, to share a single window instead of the whole displays.
+// https://specifications.freedesktop.org/wm-spec/latest/
+
+use super::ffi::*;
+use super::Server;
+use hbb_common::libc::{self, c_void};
+use std::ptr;
+
+const XCB_ATOM_WM_NAME: xcb_atom_t = 39;
+const XCB_GET_PROPERTY_TYPE_ANY: xcb_atom_t = 0;
+const XCB_MAP_STATE_VIEWABLE: u8 = 2;
+// The max length of a property value to read, in 4 bytes.
+const MAX_PROPERTY_LENGTH: u32 = 4096;
+
+/// The geometry of a window in the root window coordinates, excluding the frame of the window manager.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Find the first viewable top level window whose title contains `title`, case insensitively.
+///
+/// `None` if there is no such window, or it is minimized.
+pub fn find_window_rect(title: &str) -> Option<WindowRect> {
+    let title = title.to_lowercase();
+    let server = Server::connect(ptr::null()).ok()?;
+    let conn = server.raw();
+    unsafe {
+        let roots = xcb_setup_roots_iterator(server.setup());
+        if roots.rem == 0 || roots.data.is_null() {
+            return None;
+        }
+        let root = (*roots.data).root;
+        let client_list = intern_atom(conn, "_NET_CLIENT_LIST")?;
+        let net_wm_name = intern_atom(conn, "_NET_WM_NAME");
+        let windows = get_property(conn, root, client_list)?;
+        for w in windows.chunks_exact(4) {
+            let window = u32::from_ne_bytes([w[0], w[1], w[2], w[3]]);
+            let Some(name) = window_title(conn, window, net_wm_name) else {
+                continue;
+            };
+            if !name.to_lowercase().contains(&title) {
+                continue;
+            }
+            if !is_viewable(conn, window) {
+                return None;
+            }
+            return geometry(conn, window, root);
+        }
+    }
+    None
+}
+
+/// The title and the geometry of the focused top level window, by `_NET_ACTIVE_WINDOW`.
+pub fn active_window() -> Option<(String, WindowRect)> {
+    let server = Server::connect(ptr::null()).ok()?;
+    let conn = server.raw();
+    unsafe {
+        let roots = xcb_setup_roots_iterator(server.setup());
+        if roots.rem == 0 || roots.data.is_null() {
+            return None;
+        }
+        let root = (*roots.data).root;
+        let active_window = intern_atom(conn, "_NET_ACTIVE_WINDOW")?;
+        let w = get_property(conn, root, active_window)?;
+        if w.len() < 4 {
+            return None;
+        }
+        let window = u32::from_ne_bytes([w[0], w[1], w[2], w[3]]);
+        if window == 0 {
+            return None;
+        }
+        let net_wm_name = intern_atom(conn, "_NET_WM_NAME");
+        let title = window_title(conn, window, net_wm_name).unwrap_or_default();
+        Some((title, geometry(conn, window, root)?))
+    }
+}
+
+unsafe fn window_title(
+    conn: *mut xcb_connection_t,
+    window: xcb_window_t,
+    net_wm_name: Option<xcb_atom_t>,
+) -> Option<String> {
+    let name = net_wm_name
+        .and_then(|atom| get_property(conn, window, atom))
+        .filter(|name| !name.is_empty())
+        .or_else(|| get_property(conn, window, XCB_ATOM_WM_NAME))?;
+    Some(String::from_utf8_lossy(&name).to_string())
+}
+
+unsafe fn intern_atom(conn: *mut xcb_connection_t, name: &str) -> Option<xcb_atom_t> {
+    let cookie = xcb_intern_atom(conn, 1, name.len() as _, name.as_ptr() as _);
+    let reply = xcb_intern_atom_reply(conn, cookie, ptr::null_mut());
+    if reply.is_null() {
+        return None;
+    }
+    let atom = (*reply).atom;
+    libc::free(reply as *mut c_void);
+    // XCB_ATOM_NONE if the atom doesn't exist
+    (atom != 0).then_some(atom)
+}
+
+unsafe fn get_property(
+    conn: *mut xcb_connection_t,
+    window: xcb_window_t,
+    property: xcb_atom_t,
+) -> Option<Vec<u8>> {
+    let cookie = xcb_get_property(
+        conn,
+        0,
+        window,
+        property,
+        XCB_GET_PROPERTY_TYPE_ANY,
+        0,
+        MAX_PROPERTY_LENGTH,
+    );
+    let reply = xcb_get_property_reply(conn, cookie, ptr::null_mut());
+    if reply.is_null() {
+        return None;
+    }
+    let len = xcb_get_property_value_length(reply);
+    let value = xcb_get_property_value(reply) as *const u8;
+    let v = if len > 0 && !value.is_null() {
+        Some(std::slice::from_raw_parts(value, len as _).to_vec())
+    } else {
+        None
+    };
+    libc::free(reply as *mut c_void);
+    v
+}
+
+unsafe fn is_viewable(conn: *mut xcb_connection_t, window: xcb_window_t) -> bool {
+    let cookie = xcb_get_window_attributes(conn, window);
+    let reply = xcb_get_window_attributes_reply(conn, cookie, ptr::null_mut());
+    if reply.is_null() {
+        return false;
+    }
+    let viewable = (*reply).map_state == XCB_MAP_STATE_VIEWABLE;
+    libc::free(reply as *mut c_void);
+    viewable
+}
+
+unsafe fn geometry(
+    conn: *mut xcb_connection_t,
+    window: xcb_window_t,
+    root: xcb_window_t,
+) -> Option<WindowRect> {
+    let cookie = xcb_get_geometry(conn, window);
+    let reply = xcb_get_geometry_reply(conn, cookie, ptr::null_mut());
+    if reply.is_null() {
+        return None;
+    }
+    let (width, height) = ((*reply).width as u32, (*reply).height as u32);
+    libc::free(reply as *mut c_void);
+
+    // The position in the geometry is relative to the parent, which is the frame of the window manager.
+    let cookie = xcb_translate_coordinates(conn, window, root, 0, 0);
+    let reply = xcb_translate_coordinates_reply(conn, cookie, ptr::null_mut());
+    if reply.is_null() {
+        return None;
+    }
+    let (x, y) = ((*reply).dst_x as i32, (*reply).dst_y as i32);
+    libc::free(reply as *mut c_void);
+    Some(WindowRect {
+        x,
+        y,
+        width,
+        height,
+    })
+}
@@ -0,0 +1,263 @@
+//! Local audit log of the controlled side, and the retry queue of the http audits.
+//!
+//! If the option `enable-local-audit` is `Y`, the events of the incoming sessions (logins, permission
+//! changes, file operations, clipboard transfers, port forwards and disconnections) are appended as
+//! json lines to `audit.jsonl` in the log directory. The file is rotated when it exceeds
+//! `local-audit-max-size` MB, the last [`KEEP_FILES`] rotated files are kept.
+//!
+//! If the option `local-audit-hash-chain` is `Y`, every record carries the hash of the previous
+//! record (`prev`) and its own hash (`hash`), so that a removed or modified record can be detected
+//! with [`verify`]. The chain continues across the rotated files.
+//!
+//! The http audits are retried if the audit server is unreachable, see [`post`].
+
+use std::{
+    collections::VecDeque,
+    fs::{self, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use hbb_common::{bail, config::Config, get_time, log, tokio, ResultType};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+
+const OPTION_ENABLE: &str = "enable-local-audit";
+const OPTION_HASH_CHAIN: &str = "local-audit-hash-chain";
+const OPTION_MAX_SIZE: &str = "local-audit-max-size";
+const FILE_NAME: &str = "audit";
+const FILE_EXT: &str = "jsonl";
+const DEFAULT_MAX_SIZE_MB: u64 = 10;
+pub const KEEP_FILES: usize = 5;
+const MAX_PENDING: usize = 1_000;
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+lazy_static::lazy_static! {
+    // The hash of the last record, `None` if not loaded from the file yet.
+    static ref LAST_HASH: Mutex<Option<String>> = Default::default();
+    static ref PENDING: Mutex<VecDeque<(String, Value)>> = Default::default();
+}
+static RETRY_RUNNING: AtomicBool = AtomicBool::new(false);
+
+#[inline]
+pub fn is_enabled() -> bool {
+    Config::get_option(OPTION_ENABLE) == "Y"
+}
+
+fn path(n: usize) -> PathBuf {
+    let name = if n == 0 {
+        format!("{}.{}", FILE_NAME, FILE_EXT)
+    } else {
+        format!("{}.{}.{}", FILE_NAME, n, FILE_EXT)
+    };
+    Config::log_path().join(name)
+}
+
+fn max_size() -> u64 {
+    let mb = Config::get_option(OPTION_MAX_SIZE)
+        .parse()
+        .unwrap_or(DEFAULT_MAX_SIZE_MB);
+    std::cmp::max(mb, 1) * 1024 * 1024
+}
+
+/// Append an event to the local audit log, if enabled.
+///
+/// `fields` should be a json object, it is merged into the record.
+pub fn log(event: &str, fields: Value) {
+    if !is_enabled() {
+        return;
+    }
+    if let Err(err) = write(event, fields) {
+        log::error!("Failed to write audit log: {}", err);
+    }
+}
+
+fn write(event: &str, fields: Value) -> ResultType<()> {
+    let mut record = Map::new();
+    record.insert("time".to_owned(), json!(get_time()));
+    record.insert("event".to_owned(), json!(event));
+    if let Value::Object(fields) = fields {
+        record.extend(fields);
+    }
+    let mut last_hash = LAST_HASH.lock().unwrap();
+    let chain = Config::get_option(OPTION_HASH_CHAIN) == "Y";
+    if chain {
+        if last_hash.is_none() {
+            *last_hash = Some(load_last_hash().unwrap_or_default());
+        }
+        let prev = last_hash.clone().unwrap_or_default();
+        record.insert("prev".to_owned(), json!(prev));
+        let hash = hash_record(&record);
+        record.insert("hash".to_owned(), json!(hash));
+        *last_hash = Some(hash);
+    } else {
+        // the chain is restarted from the file if enabled again
+        *last_hash = None;
+    }
+    let mut line = Value::Object(record).to_string();
+    line.push('\n');
+    rotate()?;
+    let mut f = OpenOptions::new().create(true).append(true).open(path(0))?;
+    f.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+fn hash_record(record: &Map<String, Value>) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(Value::Object(record.clone()).to_string());
+    hex::encode(hasher.finalize())
+}
+
+fn rotate() -> ResultType<()> {
+    let current = path(0);
+    match fs::metadata(&current) {
+        Ok(m) if m.len() >= max_size() => {}
+        _ => return Ok(()),
+    }
+    fs::remove_file(path(KEEP_FILES)).ok();
+    for n in (1..KEEP_FILES).rev() {
+        let from = path(n);
+        if from.exists() {
+            fs::rename(from, path(n + 1))?;
+        }
+    }
+    fs::rename(current, path(1))?;
+    Ok(())
+}
+
+fn load_last_hash() -> Option<String> {
+    for n in 0..=KEEP_FILES {
+        let Ok(f) = fs::File::open(path(n)) else {
+            continue;
+        };
+        let last = BufReader::new(f)
+            .lines()
+            .filter_map(|l| l.ok())
+            .filter(|l| !l.trim().is_empty())
+            .last();
+        if let Some(line) = last {
+            let v: Value = serde_json::from_str(&line).ok()?;
+            return v["hash"].as_str().map(|s| s.to_owned());
+        }
+    }
+    None
+}
+
+/// Verify the hash chain of an audit log file, returns the number of the records.
+///
+/// The first record is trusted, as its previous record may be in a removed rotated file.
+pub fn verify(path: &Path) -> ResultType<usize> {
+    let f = fs::File::open(path)?;
+    let mut prev: Option<String> = None;
+    let mut count = 0;
+    for (i, line) in BufReader::new(f).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Value::Object(mut record) = serde_json::from_str(&line)? else {
+            bail!("line {}: not an object", i + 1);
+        };
+        let Some(Value::String(hash)) = record.remove("hash") else {
+            bail!("line {}: no hash", i + 1);
+        };
+        if hash_record(&record) != hash {
+            bail!("line {}: hash mismatch", i + 1);
+        }
+        if let Some(prev) = prev {
+            if record.get("prev").and_then(|v| v.as_str()) != Some(&prev) {
+                bail!("line {}: chain broken", i + 1);
+            }
+        }
+        prev = Some(hash);
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Post an audit to the audit server. It is queued and retried later if failed.
+pub fn post(url: String, v: Value) {
+    tokio::spawn(async move {
+        if let Err(err) = crate::post_request(url.clone(), v.to_string(), "").await {
+            log::warn!("Failed to post audit, will retry: {}", err);
+            push_pending(url, v);
+        }
+    });
+}
+
+fn push_pending(url: String, v: Value) {
+    let mut pending = PENDING.lock().unwrap();
+    if pending.len() >= MAX_PENDING {
+        log::warn!("Too many pending audits, the oldest one is dropped");
+        pending.pop_front();
+    }
+    pending.push_back((url, v));
+    drop(pending);
+    if !RETRY_RUNNING.swap(true, Ordering::SeqCst) {
+        tokio::spawn(retry_pending());
+    }
+}
+
+async fn retry_pending() {
+    loop {
+        tokio::time::sleep(RETRY_INTERVAL).await;
+        loop {
+            let Some((url, v)) = PENDING.lock().unwrap().pop_front() else {
+                break;
+            };
+            if crate::post_request(url.clone(), v.to_string(), "")
+                .await
+                .is_err()
+            {
+                // keep the order, try again later
+                PENDING.lock().unwrap().push_front((url, v));
+                break;
+            }
+        }
+        if PENDING.lock().unwrap().is_empty() {
+            RETRY_RUNNING.store(false, Ordering::SeqCst);
+            // a new audit may be queued between the check and the store
+            if PENDING.lock().unwrap().is_empty() || RETRY_RUNNING.swap(true, Ordering::SeqCst) {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_chain() {
+        let dir = std::env::temp_dir().join(format!("rustdesk-audit-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("audit.jsonl");
+        let mut prev = "".to_owned();
+        let mut lines = vec![];
+        for i in 0..3 {
+            let mut record = Map::new();
+            record.insert("event".to_owned(), json!("login"));
+            record.insert("conn_id".to_owned(), json!(i));
+            record.insert("prev".to_owned(), json!(prev));
+            let hash = hash_record(&record);
+            record.insert("hash".to_owned(), json!(hash));
+            prev = hash;
+            lines.push(Value::Object(record).to_string());
+        }
+        fs::write(&file, lines.join("\n")).unwrap();
+        assert_eq!(verify(&file).unwrap(), 3);
+        lines[1] = lines[1].replace("\"conn_id\":1", "\"conn_id\":5");
+        fs::write(&file, lines.join("\n")).unwrap();
+        assert!(verify(&file).is_err());
+        lines.remove(1);
+        fs::write(&file, lines.join("\n")).unwrap();
+        assert!(verify(&file).is_err());
+        fs::remove_dir_all(&dir).ok();
+    }
+}
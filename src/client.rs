@@ -72,7 +72,10 @@ use crate::{check_clipboard, CLIPBOARD_INTERVAL};
 pub use super::lang::*;
 
 pub mod file_trait;
+#[cfg(target_os = "linux")]
+pub mod gamepad;
 pub mod helper;
+pub mod input_macro;
 pub mod io_loop;
 
 pub const MILLI1: Duration = Duration::from_millis(1);
@@ -136,7 +139,7 @@ lazy_static::lazy_static! {
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
 lazy_static::lazy_static! {
     static ref ENIGO: Arc<Mutex<enigo::Enigo>> = Arc::new(Mutex::new(enigo::Enigo::new()));
-    static ref OLD_CLIPBOARD_TEXT: Arc<Mutex<String>> = Default::default();
+    static ref OLD_CLIPBOARD_CONTENT: Arc<Mutex<crate::ClipboardContent>> = Default::default();
     static ref TEXT_CLIPBOARD_STATE: Arc<Mutex<TextClipboardState>> = Arc::new(Mutex::new(TextClipboardState::new()));
 }
 
@@ -144,8 +147,8 @@ const PUBLIC_SERVER: &str = "public";
 
 #[inline]
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
-pub fn get_old_clipboard_text() -> &'static Arc<Mutex<String>> {
-    &OLD_CLIPBOARD_TEXT
+pub fn get_old_clipboard_content() -> &'static Arc<Mutex<crate::ClipboardContent>> {
+    &OLD_CLIPBOARD_CONTENT
 }
 
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
@@ -269,6 +272,12 @@ impl Client {
             ));
         }
 
+        if let Some((relay_server, pairing_token)) = crate::common::get_fixed_relay() {
+            interface.update_direct(Some(false));
+            return Self::connect_fixed_relay(peer, relay_server, &pairing_token, key, conn_type)
+                .await;
+        }
+
         let other_server = interface.get_lch().read().unwrap().other_server.clone();
         let (peer, other_server, key, token) = if let Some((a, b, c)) = other_server.as_ref() {
             (a.as_ref(), b.as_ref(), c.as_ref(), "")
@@ -559,12 +568,22 @@ impl Client {
                 return Ok(option_pk);
             }
         };
+        Self::exchange_symmetric_key(peer_id, &sign_pk, conn).await?;
+        Ok(option_pk)
+    }
+
+    /// Exchange the symmetric key with the server, whose signed id is verified by `sign_pk`.
+    async fn exchange_symmetric_key(
+        peer_id: &str,
+        sign_pk: &sign::PublicKey,
+        conn: &mut Stream,
+    ) -> ResultType<()> {
         match timeout(READ_TIMEOUT, conn.next()).await? {
             Some(res) => {
                 let bytes = res?;
                 if let Ok(msg_in) = Message::parse_from_bytes(&bytes) {
                     if let Some(message::Union::SignedId(si)) = msg_in.union {
-                        if let Ok((id, their_pk_b)) = decode_id_pk(&si.id, &sign_pk) {
+                        if let Ok((id, their_pk_b)) = decode_id_pk(&si.id, sign_pk) {
                             if id == peer_id {
                                 let (asymmetric_value, symmetric_value, key) =
                                     create_symmetric_key_msg(their_pk_b);
@@ -600,7 +619,31 @@ impl Client {
                 bail!("Reset by the peer");
             }
         }
-        Ok(option_pk)
+        Ok(())
+    }
+
+    /// Connect to the peer at the fixed relay server, without any rendezvous server.
+    ///
+    /// The server's signed id is verified with the key derived from the pairing token,
+    /// so only peers sharing the same token can complete the key exchange.
+    async fn connect_fixed_relay(
+        peer: &str,
+        relay_server: String,
+        pairing_token: &str,
+        key: &str,
+        conn_type: ConnType,
+    ) -> ResultType<(Stream, bool, Option<Vec<u8>>)> {
+        log::info!("connect {} via fixed relay server {}", peer, relay_server);
+        let uuid = crate::common::get_fixed_relay_uuid(pairing_token, peer);
+        let mut conn = Self::create_relay(peer, uuid, relay_server, key, conn_type, true).await?;
+        // wake up the standing connection of the server
+        conn.send(&Message::new()).await?;
+        let (sign_pk, _) = crate::common::get_fixed_relay_key_pair(pairing_token);
+        Self::exchange_symmetric_key(peer, &sign_pk, &mut conn).await?;
+        if !conn.is_secured() {
+            bail!("Handshake failed: pairing token mismatch");
+        }
+        Ok((conn, false, Some(sign_pk.0.to_vec())))
     }
 
     /// Request a relay connection to the server.
@@ -728,6 +771,18 @@ impl Client {
         std::thread::spawn(move || {
             let mut is_sent = false;
             let mut ctx = None;
+            #[cfg(target_os = "linux")]
+            let mut watcher = crate::platform::linux_clipboard::ClipboardWatcher::new();
+            let send = |msg: Message| {
+                #[cfg(feature = "flutter")]
+                crate::flutter::send_text_clipboard_msg(msg);
+                #[cfg(not(feature = "flutter"))]
+                if let Some(ctx) = &_ctx {
+                    if ctx.cfg.is_text_clipboard_required() {
+                        let _ = ctx.tx.send(Data::Message(msg));
+                    }
+                }
+            };
             loop {
                 if !TEXT_CLIPBOARD_STATE.lock().unwrap().running {
                     break;
@@ -736,14 +791,19 @@ impl Client {
                     continue;
                 }
 
-                if let Some(msg) = check_clipboard(&mut ctx, Some(&OLD_CLIPBOARD_TEXT)) {
-                    #[cfg(feature = "flutter")]
-                    crate::flutter::send_text_clipboard_msg(msg);
-                    #[cfg(not(feature = "flutter"))]
-                    if let Some(ctx) = &_ctx {
-                        if ctx.cfg.is_text_clipboard_required() {
-                            let _ = ctx.tx.send(Data::Message(msg));
-                        }
+                #[cfg(target_os = "linux")]
+                let changed = watcher.clipboard_changed();
+                #[cfg(not(target_os = "linux"))]
+                let changed = true;
+                if changed {
+                    if let Some(msg) = check_clipboard(&mut ctx, Some(&OLD_CLIPBOARD_CONTENT)) {
+                        send(msg);
+                    }
+                }
+                #[cfg(target_os = "linux")]
+                if watcher.primary_changed() && crate::is_primary_selection_enabled() {
+                    if let Some(msg) = crate::check_primary_selection(&mut ctx, false) {
+                        send(msg);
                     }
                 }
 
@@ -752,6 +812,9 @@ impl Client {
                     tx.send(()).ok();
                 }
 
+                #[cfg(target_os = "linux")]
+                watcher.wait(Duration::from_millis(CLIPBOARD_INTERVAL));
+                #[cfg(not(target_os = "linux"))]
                 std::thread::sleep(Duration::from_millis(CLIPBOARD_INTERVAL));
             }
             log::info!("Stop text clipboard loop");
@@ -763,11 +826,11 @@ impl Client {
     #[inline]
     #[cfg(not(any(target_os = "android", target_os = "ios")))]
     fn get_current_text_clipboard_msg() -> Option<Message> {
-        let txt = &*OLD_CLIPBOARD_TEXT.lock().unwrap();
-        if txt.is_empty() {
+        let content = &*OLD_CLIPBOARD_CONTENT.lock().unwrap();
+        if content.is_empty() {
             None
         } else {
-            Some(crate::create_clipboard_msg(txt.clone()))
+            Some(crate::create_clipboard_content_msg(content))
         }
     }
 }
@@ -1027,8 +1090,11 @@ pub struct VideoHandler {
     pub texture: *mut c_void,
     recorder: Arc<Mutex<Option<Recorder>>>,
     record: bool,
+    // The recording is stopped on the error, which is shown to the user.
+    pub record_error: Option<String>,
     _display: usize, // useful for debug
     fail_counter: usize,
+    scale_tmp: Vec<u8>,
 }
 
 impl VideoHandler {
@@ -1045,8 +1111,21 @@ impl VideoHandler {
             texture: std::ptr::null_mut(),
             recorder: Default::default(),
             record: false,
+            record_error: None,
             _display,
             fail_counter: 0,
+            scale_tmp: Vec::new(),
+        }
+    }
+
+    /// Restore the video downscaled by the peer to the display size,
+    /// so that the coordinates of the mouse and the cursor are not changed.
+    pub fn restore_size(&mut self, (width, height): (usize, usize)) {
+        if self.rgb.w == 0 || (self.rgb.w >= width && self.rgb.h >= height) {
+            return;
+        }
+        if let Err(e) = scrap::scale_rgb(&mut self.rgb, width, height, &mut self.scale_tmp) {
+            log::error!("Failed to restore the video size: {e}");
         }
     }
 
@@ -1059,7 +1138,9 @@ impl VideoHandler {
         chroma: &mut Option<Chroma>,
     ) -> ResultType<bool> {
         let format = CodecFormat::from(&vf);
-        if format != self.decoder.format() {
+        // The lossless frames are drawn over the last decoded image, they don't change the decoder.
+        let lossless = matches!(vf.union, Some(video_frame::Union::Lossless(_)));
+        if !lossless && format != self.decoder.format() {
             self.reset(Some(format));
         }
         match &vf.union {
@@ -1079,11 +1160,15 @@ impl VideoHandler {
                     }
                 }
                 if self.record {
-                    self.recorder
+                    let recorded = self
+                        .recorder
                         .lock()
                         .unwrap()
                         .as_mut()
                         .map(|r| r.write_frame(frame));
+                    if let Some(Err(e)) = recorded {
+                        self.stop_record(e);
+                    }
                 }
                 res
             }
@@ -1102,11 +1187,12 @@ impl VideoHandler {
         self.fail_counter = 0;
     }
 
-    /// Start or stop screen record.
-    pub fn record_screen(&mut self, start: bool, w: i32, h: i32, id: String) {
+    /// Start or stop screen record, `audio_channels` is from the audio format of the session.
+    pub fn record_screen(&mut self, start: bool, w: i32, h: i32, id: String, audio_channels: u8) {
         self.record = false;
+        self.recorder = Default::default();
         if start {
-            self.recorder = Recorder::new(RecorderContext {
+            match Recorder::new(RecorderContext {
                 server: false,
                 id,
                 default_dir: crate::ui_interface::default_video_save_directory(),
@@ -1114,14 +1200,45 @@ impl VideoHandler {
                 width: w as _,
                 height: h as _,
                 format: scrap::CodecFormat::VP9,
+                audio_channels,
                 tx: None,
-            })
-            .map_or(Default::default(), |r| Arc::new(Mutex::new(Some(r))));
-        } else {
-            self.recorder = Default::default();
+            }) {
+                Ok(r) => {
+                    self.recorder = Arc::new(Mutex::new(Some(r)));
+                    self.record = true;
+                }
+                Err(e) => self.stop_record(e),
+            }
         }
+    }
+
+    fn stop_record(&mut self, e: hbb_common::anyhow::Error) {
+        log::error!("Failed to record the screen: {e}");
+        self.record = false;
+        self.recorder = Default::default();
+        self.record_error = Some(e.to_string());
+    }
 
-        self.record = start;
+    /// The audio format of the session is changed.
+    pub fn record_audio_format(&mut self, channels: u8) {
+        if self.record {
+            self.recorder
+                .lock()
+                .unwrap()
+                .as_mut()
+                .map(|r| r.set_audio_channels(channels).ok());
+        }
+    }
+
+    /// Write an audio frame to the screen record.
+    pub fn record_audio(&mut self, frame: &AudioFrame) {
+        if self.record {
+            self.recorder
+                .lock()
+                .unwrap()
+                .as_mut()
+                .map(|r| r.write_audio_frame(frame));
+        }
     }
 }
 
@@ -1579,6 +1696,14 @@ impl LoginConfigHandler {
             msg.disable_clipboard = BoolOption::Yes.into();
             n += 1;
         }
+        // Always sent, so that the peer knows the downscaled video can be restored here.
+        msg.video_scale = self.video_scale();
+        msg.auto_video_scale = self.auto_video_scale().into();
+        n += 1;
+        if let Some(quality) = self.audio_quality() {
+            msg.audio_quality = hbb_common::protobuf::MessageField::some(quality);
+            n += 1;
+        }
         msg.supported_decoding =
             hbb_common::protobuf::MessageField::some(Decoder::supported_decodings(
                 Some(&self.id),
@@ -1763,6 +1888,137 @@ impl LoginConfigHandler {
         msg_out
     }
 
+    /// The scale of the video requested from the peer, in percent of the display size.
+    pub fn video_scale(&self) -> i32 {
+        self.get_option("video-scale")
+            .parse::<i32>()
+            .unwrap_or(100)
+            .clamp(10, 100)
+    }
+
+    // Opt-in, the video service of the peer is restarted when the scale changes.
+    fn auto_video_scale(&self) -> BoolOption {
+        if self.get_toggle_option("auto-video-scale") {
+            BoolOption::Yes
+        } else {
+            BoolOption::No
+        }
+    }
+
+    /// Create a [`Message`] for setting the scale of the video.
+    ///
+    /// # Arguments
+    ///
+    /// * `scale` - The percent of the display size, 10 to 100.
+    /// * `auto` - Allow the peer to downscale the video further if the network delay is high.
+    pub fn set_video_scale(&mut self, scale: i32, auto: bool) -> Message {
+        let mut config = self.load_config();
+        config
+            .options
+            .insert("video-scale".to_owned(), scale.clamp(10, 100).to_string());
+        if auto {
+            config
+                .options
+                .insert("auto-video-scale".to_owned(), "Y".to_owned());
+        } else {
+            config.options.remove("auto-video-scale");
+        }
+        self.save_config(config);
+        self.video_scale_msg(false)
+    }
+
+    /// Create a [`Message`] for the scale of the video, the full size if `unscaled`.
+    ///
+    /// The video decoded to a texture is requested unscaled without changing the options,
+    /// as it can't be restored to the display size, see [`VideoHandler::restore_size`].
+    pub fn video_scale_msg(&self, unscaled: bool) -> Message {
+        let (video_scale, auto_video_scale) = if unscaled {
+            (100, BoolOption::No)
+        } else {
+            (self.video_scale(), self.auto_video_scale())
+        };
+        let mut misc = Misc::new();
+        misc.set_option(OptionMessage {
+            video_scale,
+            auto_video_scale: auto_video_scale.into(),
+            ..Default::default()
+        });
+        let mut msg_out = Message::new();
+        msg_out.set_misc(misc);
+        msg_out
+    }
+
+    /// The audio quality requested from the peer, `None` if the defaults of the peer are used.
+    pub fn audio_quality(&self) -> Option<AudioQuality> {
+        let bitrate = self.get_option("audio-bitrate").parse::<u32>().unwrap_or(0);
+        let channels = self
+            .get_option("audio-channels")
+            .parse::<u32>()
+            .unwrap_or(0);
+        let application = self.get_option("audio-application");
+        if bitrate == 0 && channels == 0 && application.is_empty() {
+            return None;
+        }
+        Some(AudioQuality {
+            bitrate,
+            channels,
+            application,
+            ..Default::default()
+        })
+    }
+
+    /// Create a [`Message`] for setting the audio quality.
+    ///
+    /// # Arguments
+    ///
+    /// * `bitrate` - The bitrate in kbps, 0 for the default of the peer.
+    /// * `channels` - 1 for mono, 2 for stereo, 0 for the channels of the device.
+    /// * `application` - Capture the audio of this application only, Linux peers only.
+    pub fn set_audio_quality(
+        &mut self,
+        bitrate: i32,
+        channels: i32,
+        application: String,
+    ) -> Message {
+        let mut config = self.load_config();
+        let application = application.trim().to_owned();
+        for (k, v) in [
+            ("audio-bitrate", bitrate.max(0).to_string()),
+            ("audio-channels", channels.clamp(0, 2).to_string()),
+        ] {
+            if v == "0" {
+                config.options.remove(k);
+            } else {
+                config.options.insert(k.to_owned(), v);
+            }
+        }
+        if application.is_empty() {
+            config.options.remove("audio-application");
+        } else {
+            config
+                .options
+                .insert("audio-application".to_owned(), application);
+        }
+        self.save_config(config);
+        let mut misc = Misc::new();
+        misc.set_option(OptionMessage {
+            // send the defaults explicitly, so the peer can reset the quality
+            audio_quality: hbb_common::protobuf::MessageField::some(
+                self.audio_quality().unwrap_or_default(),
+            ),
+            ..Default::default()
+        });
+        let mut msg_out = Message::new();
+        msg_out.set_misc(misc);
+        msg_out
+    }
+
+    /// The size of a display of the peer, the downscaled video is restored to it.
+    pub fn display_size(&self, display: usize) -> Option<(usize, usize)> {
+        let d = self.peer_info.as_ref()?.displays.get(display)?;
+        Some((d.width as _, d.height as _))
+    }
+
     pub fn get_option(&self, k: &str) -> String {
         if let Some(v) = self.config.options.get(k) {
             v.clone()
@@ -2043,7 +2299,12 @@ where
     std::thread::spawn(move || {
         #[cfg(windows)]
         sync_cpu_usage();
+        let stats_key =
+            crate::session_stats::controlling_key(session.lc.read().unwrap().session_id);
         let mut handler_controller_map = Vec::new();
+        let mut audio_channels = scrap::record::DEFAULT_AUDIO_CHANNELS;
+        // The video is requested unscaled while it's decoded to a texture.
+        let mut unscaled_for_texture = false;
         // let mut count = Vec::new();
         // let mut duration = std::time::Duration::ZERO;
         // let mut skip_beginning = Vec::new();
@@ -2093,6 +2354,28 @@ where
                                 &mut tmp_chroma,
                             ) {
                                 Ok(true) => {
+                                    if pixelbuffer {
+                                        let size = session.lc.read().unwrap().display_size(display);
+                                        if let Some(size) = size {
+                                            handler_controller.handler.restore_size(size);
+                                        }
+                                    }
+                                    if pixelbuffer == unscaled_for_texture {
+                                        unscaled_for_texture = !pixelbuffer;
+                                        let msg = {
+                                            let lc = session.lc.read().unwrap();
+                                            (lc.video_scale() < 100)
+                                                .then(|| lc.video_scale_msg(unscaled_for_texture))
+                                        };
+                                        if let Some(msg) = msg {
+                                            log::info!("video decoded to texture: {unscaled_for_texture}, request the video scale again");
+                                            session.send(Data::Message(msg));
+                                        }
+                                    }
+                                    let decode_time = start.elapsed();
+                                    crate::session_stats::update_with(&stats_key, |s| {
+                                        s.on_frame_decoded(decode_time)
+                                    });
                                     video_callback(
                                         display,
                                         &mut handler_controller.handler.rgb,
@@ -2175,24 +2458,52 @@ where
                             handler_controler.handler.reset(None);
                         }
                     }
+                    MediaData::AudioFrame(af) => {
+                        for handler_controller in handler_controller_map.iter_mut() {
+                            handler_controller.handler.record_audio(&af);
+                        }
+                    }
+                    MediaData::AudioFormat(f) => {
+                        audio_channels = f.channels as _;
+                        for handler_controller in handler_controller_map.iter_mut() {
+                            handler_controller
+                                .handler
+                                .record_audio_format(audio_channels);
+                        }
+                    }
                     MediaData::RecordScreen(start, display, w, h, id) => {
                         log::info!("record screen command: start: {start}, display: {display}");
                         if handler_controller_map.len() == 1 {
                             // Compatible with the sciter version(single ui session).
                             // For the sciter version, there're no multi-ui-sessions for one connection.
                             // The display is always 0, video_handler_controllers.len() is always 1. So we use the first video handler.
-                            handler_controller_map[0]
-                                .handler
-                                .record_screen(start, w, h, id);
+                            handler_controller_map[0].handler.record_screen(
+                                start,
+                                w,
+                                h,
+                                id,
+                                audio_channels,
+                            );
                         } else {
                             if let Some(handler_controler) = handler_controller_map.get_mut(display)
                             {
-                                handler_controler.handler.record_screen(start, w, h, id);
+                                handler_controler.handler.record_screen(
+                                    start,
+                                    w,
+                                    h,
+                                    id,
+                                    audio_channels,
+                                );
                             }
                         }
                     }
                     _ => {}
                 }
+                for handler_controller in handler_controller_map.iter_mut() {
+                    if let Some(err) = handler_controller.handler.record_error.take() {
+                        session.msgbox("error", "Recording", &err, "");
+                    }
+                }
             } else {
                 break;
             }
@@ -2364,6 +2675,45 @@ pub fn send_mouse(
     interface.send(Data::Message(msg_out));
 }
 
+/// Send the high resolution wheel deltas, 120 per notch.
+///
+/// The whole notches are also sent for the peers of the older versions, which don't read the deltas.
+#[inline]
+pub fn send_hi_res_scroll(
+    hi_res: (i32, i32),
+    notches: (i32, i32),
+    alt: bool,
+    ctrl: bool,
+    shift: bool,
+    command: bool,
+    interface: &impl Interface,
+) {
+    let mut msg_out = Message::new();
+    let mut mouse_event = MouseEvent {
+        mask: crate::input::MOUSE_TYPE_WHEEL,
+        x: notches.0,
+        y: notches.1,
+        hi_res_x: hi_res.0,
+        hi_res_y: hi_res.1,
+        ..Default::default()
+    };
+    if alt {
+        mouse_event.modifiers.push(ControlKey::Alt.into());
+    }
+    if shift {
+        mouse_event.modifiers.push(ControlKey::Shift.into());
+    }
+    if ctrl {
+        mouse_event.modifiers.push(ControlKey::Control.into());
+    }
+    if command {
+        mouse_event.modifiers.push(ControlKey::Meta.into());
+    }
+    interface.swap_modifier_mouse(&mut mouse_event);
+    msg_out.set_mouse_event(mouse_event);
+    interface.send(Data::Message(msg_out));
+}
+
 #[inline]
 pub fn send_pointer_device_event(
     mut evt: PointerDeviceEvent,
@@ -0,0 +1,275 @@
+//! Forward the local game controllers to the peer, if it has "gamepad" in the platform additions.
+//!
+//! Linux only, the controllers are polled with evdev, so the user needs read access to `/dev/input`.
+//! The capture on Windows and macOS, and playing the rumble of the peer locally, are not implemented,
+//! the other clients can send their controllers with `session_send_gamepad`.
+
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::mpsc::{Receiver, Sender, TryRecvError},
+    time::{Duration, Instant},
+};
+
+use evdev::{AbsoluteAxisType, AttributeSet, Device, Key};
+use hbb_common::{log, message_proto::*, tokio::sync::mpsc::UnboundedSender};
+
+use crate::client::Data;
+
+const MAX_GAMEPADS: usize = 4;
+const POLL_INTERVAL: Duration = Duration::from_millis(16);
+const SCAN_INTERVAL: Duration = Duration::from_secs(2);
+// in the bit order of `GamepadState.buttons`, x and y as xpad
+const BUTTONS: [Key; 11] = [
+    Key::BTN_SOUTH,
+    Key::BTN_EAST,
+    Key::BTN_NORTH,
+    Key::BTN_WEST,
+    Key::BTN_TL,
+    Key::BTN_TR,
+    Key::BTN_SELECT,
+    Key::BTN_START,
+    Key::BTN_MODE,
+    Key::BTN_THUMBL,
+    Key::BTN_THUMBR,
+];
+const DPAD_UP: usize = 11;
+const DPAD: [Key; 4] = [
+    Key::BTN_DPAD_UP,
+    Key::BTN_DPAD_DOWN,
+    Key::BTN_DPAD_LEFT,
+    Key::BTN_DPAD_RIGHT,
+];
+const STICKS: [AbsoluteAxisType; 4] = [
+    AbsoluteAxisType::ABS_X,
+    AbsoluteAxisType::ABS_Y,
+    AbsoluteAxisType::ABS_RX,
+    AbsoluteAxisType::ABS_RY,
+];
+const TRIGGERS: [(AbsoluteAxisType, Key); 2] = [
+    (AbsoluteAxisType::ABS_Z, Key::BTN_TL2),
+    (AbsoluteAxisType::ABS_RZ, Key::BTN_TR2),
+];
+// The virtual gamepads of the incoming connections, not forwarded back.
+const VIRTUAL_NAME_PREFIX: &str = "RustDesk Gamepad";
+
+struct Pad {
+    index: u32,
+    device: Device,
+    state: Option<GamepadState>,
+}
+
+/// Start forwarding the game controllers, dropping or sending to the returned sender stops it.
+pub fn start(sender: UnboundedSender<Data>) -> Sender<()> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || run(sender, rx));
+    tx
+}
+
+fn run(sender: UnboundedSender<Data>, rx: Receiver<()>) {
+    log::info!("Gamepad capture started");
+    let mut pads: HashMap<PathBuf, Pad> = HashMap::new();
+    let mut last_scan: Option<Instant> = None;
+    loop {
+        match rx.try_recv() {
+            Err(TryRecvError::Empty) => {}
+            _ => break,
+        }
+        if last_scan.map_or(true, |t| t.elapsed() >= SCAN_INTERVAL) {
+            last_scan = Some(Instant::now());
+            for evt in scan(&mut pads) {
+                if !send(&sender, evt) {
+                    return;
+                }
+            }
+        }
+        let mut unplugged = vec![];
+        for (path, pad) in pads.iter_mut() {
+            let (Ok(keys), Ok(abs)) = (pad.device.get_key_state(), pad.device.get_abs_state())
+            else {
+                unplugged.push(path.clone());
+                continue;
+            };
+            let state = gamepad_state(&keys, &|axis: AbsoluteAxisType| {
+                let info = &abs[axis.0 as usize];
+                Some((info.value, info.minimum, info.maximum))
+            });
+            if pad.state.as_ref() != Some(&state) {
+                pad.state = Some(state.clone());
+                let mut evt = GamepadEvent {
+                    index: pad.index,
+                    ..Default::default()
+                };
+                evt.set_state(state);
+                if !send(&sender, evt) {
+                    return;
+                }
+            }
+        }
+        for path in unplugged {
+            if let Some(pad) = pads.remove(&path) {
+                log::info!("Gamepad {} unplugged", pad.index);
+                let mut evt = GamepadEvent {
+                    index: pad.index,
+                    ..Default::default()
+                };
+                evt.set_disconnected(true);
+                if !send(&sender, evt) {
+                    return;
+                }
+            }
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    for pad in pads.values() {
+        let mut evt = GamepadEvent {
+            index: pad.index,
+            ..Default::default()
+        };
+        evt.set_disconnected(true);
+        send(&sender, evt);
+    }
+    log::info!("Gamepad capture stopped");
+}
+
+fn send(sender: &UnboundedSender<Data>, evt: GamepadEvent) -> bool {
+    let mut msg_out = Message::new();
+    msg_out.set_gamepad_event(evt);
+    sender.send(Data::Message(msg_out)).is_ok()
+}
+
+// Open the newly plugged controllers, the indexes are reused after unplugging.
+fn scan(pads: &mut HashMap<PathBuf, Pad>) -> Vec<GamepadEvent> {
+    let mut events = vec![];
+    for (path, device) in evdev::enumerate() {
+        if pads.len() >= MAX_GAMEPADS {
+            break;
+        }
+        if pads.contains_key(&path) || !is_gamepad(&device) {
+            continue;
+        }
+        let Some(index) = (0..MAX_GAMEPADS as u32).find(|i| pads.values().all(|p| p.index != *i))
+        else {
+            break;
+        };
+        let name = device.name().unwrap_or_default().to_owned();
+        log::info!("Gamepad {} plugged: {}", index, name);
+        let mut evt = GamepadEvent {
+            index,
+            ..Default::default()
+        };
+        evt.set_connected(GamepadConnected {
+            name,
+            ..Default::default()
+        });
+        events.push(evt);
+        pads.insert(
+            path,
+            Pad {
+                index,
+                device,
+                state: None,
+            },
+        );
+    }
+    events
+}
+
+fn is_gamepad(device: &Device) -> bool {
+    if device
+        .name()
+        .map_or(false, |n| n.starts_with(VIRTUAL_NAME_PREFIX))
+    {
+        return false;
+    }
+    device
+        .supported_keys()
+        .map_or(false, |keys| keys.contains(Key::BTN_SOUTH))
+}
+
+// `abs` returns the value, minimum and maximum of an axis.
+fn gamepad_state(
+    keys: &AttributeSet<Key>,
+    abs: &dyn Fn(AbsoluteAxisType) -> Option<(i32, i32, i32)>,
+) -> GamepadState {
+    let mut buttons = 0u32;
+    for (i, key) in BUTTONS.iter().enumerate() {
+        if keys.contains(*key) {
+            buttons |= 1 << i;
+        }
+    }
+    // the d-pad is a hat or the buttons
+    let hat = |axis| abs(axis).map_or(0, |(v, _, _)| v.signum());
+    let (hat_x, hat_y) = (
+        hat(AbsoluteAxisType::ABS_HAT0X),
+        hat(AbsoluteAxisType::ABS_HAT0Y),
+    );
+    for (i, (key, pressed)) in DPAD
+        .iter()
+        .zip([hat_y < 0, hat_y > 0, hat_x < 0, hat_x > 0])
+        .enumerate()
+    {
+        if pressed || keys.contains(*key) {
+            buttons |= 1 << (DPAD_UP + i);
+        }
+    }
+    let scale = |axis, to_min: i64, to_max: i64| {
+        abs(axis).and_then(|(v, min, max)| {
+            if max <= min {
+                return None;
+            }
+            let (v, min, max) = (v.clamp(min, max) as i64, min as i64, max as i64);
+            Some((to_min + (v - min) * (to_max - to_min) / (max - min)) as i32)
+        })
+    };
+    let mut axes: Vec<i32> = STICKS
+        .iter()
+        .map(|axis| scale(*axis, i16::MIN as _, i16::MAX as _).unwrap_or(0))
+        .collect();
+    for (axis, key) in TRIGGERS {
+        let digital = if keys.contains(key) { u8::MAX as _ } else { 0 };
+        axes.push(scale(axis, 0, u8::MAX as _).unwrap_or(digital));
+    }
+    GamepadState {
+        buttons,
+        axes,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gamepad_state() {
+        let mut keys = AttributeSet::<Key>::new();
+        keys.insert(Key::BTN_SOUTH);
+        keys.insert(Key::BTN_START);
+        keys.insert(Key::BTN_TR2);
+        let state = gamepad_state(&keys, &|axis| match axis {
+            AbsoluteAxisType::ABS_X => Some((0, -32768, 32767)),
+            AbsoluteAxisType::ABS_Y => Some((255, 0, 255)),
+            AbsoluteAxisType::ABS_RX => Some((-40000, -32768, 32767)),
+            AbsoluteAxisType::ABS_Z => Some((512, 0, 1023)),
+            AbsoluteAxisType::ABS_HAT0X => Some((1, -1, 1)),
+            AbsoluteAxisType::ABS_HAT0Y => Some((-1, -1, 1)),
+            _ => None,
+        });
+        let up = 1 << DPAD_UP;
+        let right = 1 << (DPAD_UP + 3);
+        assert_eq!(state.buttons, 1 | 1 << 7 | up | right);
+        // no axis, the trigger from the button
+        assert_eq!(state.axes, vec![0, 32767, -32768, 0, 127, 255]);
+    }
+
+    #[test]
+    fn test_gamepad_dpad_buttons() {
+        let mut keys = AttributeSet::<Key>::new();
+        keys.insert(Key::BTN_DPAD_DOWN);
+        keys.insert(Key::BTN_DPAD_LEFT);
+        let state = gamepad_state(&keys, &|_| None);
+        assert_eq!(state.buttons, 1 << (DPAD_UP + 1) | 1 << (DPAD_UP + 2));
+        assert_eq!(state.axes, vec![0; 6]);
+    }
+}
@@ -0,0 +1,606 @@
+//! Record the key and mouse events sent to the peer, and play them back.
+//!
+//! A macro is saved as json, with the delays between the events and the display they were recorded on.
+//! The absolute mouse positions are mapped from that display to the current one on playback.
+//! A `.txt` macro is the enigo DSL, e.g. `{+CTRL}a{-CTRL}`, which types keys only.
+
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use hbb_common::{
+    bail,
+    message_proto::*,
+    protobuf::{EnumOrUnknown, Message as _},
+    ResultType,
+};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::input::{MOUSE_TYPE_DOWN, MOUSE_TYPE_MOVE, MOUSE_TYPE_UP};
+
+pub const VERSION: u32 = 1;
+// the delay between the keys of a DSL macro
+const DSL_DELAY: u64 = 20;
+const MIN_SPEED: f64 = 0.1;
+const MAX_SPEED: f64 = 10.0;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: i32, height: i32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.width <= 0 || self.height <= 0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Input {
+    // base64 of the protobuf
+    Key(String),
+    Mouse {
+        mask: i32,
+        x: i32,
+        y: i32,
+        #[serde(default)]
+        modifiers: Vec<i32>,
+        #[serde(default)]
+        hi_res_x: i32,
+        #[serde(default)]
+        hi_res_y: i32,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MacroEvent {
+    // ms since the previous event
+    pub delay: u64,
+    #[serde(flatten)]
+    pub input: Input,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Macro {
+    pub version: u32,
+    #[serde(default)]
+    pub display: Rect,
+    pub events: Vec<MacroEvent>,
+}
+
+impl Macro {
+    pub fn load(path: &str) -> ResultType<Self> {
+        let content = std::fs::read_to_string(path)?;
+        let is_dsl = Path::new(path)
+            .extension()
+            .map_or(false, |ext| ext.eq_ignore_ascii_case("txt"));
+        if is_dsl {
+            return Self::from_dsl(&content);
+        }
+        let m: Self = serde_json::from_str(&content)?;
+        if m.version > VERSION {
+            bail!("Unsupported macro version {}", m.version);
+        }
+        Ok(m)
+    }
+
+    pub fn save(&self, path: &str) -> ResultType<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    pub fn from_dsl(dsl: &str) -> ResultType<Self> {
+        let mut collector = KeyCollector::default();
+        if let Err(e) = enigo::dsl::eval(&mut collector, dsl) {
+            bail!("Invalid macro: {:?}", e);
+        }
+        let mut events = vec![];
+        for evt in collector.events {
+            events.push(MacroEvent {
+                delay: if events.is_empty() { 0 } else { DSL_DELAY },
+                input: Input::Key(STANDARD.encode(evt.write_to_bytes()?)),
+            });
+        }
+        Ok(Self {
+            version: VERSION,
+            display: Rect::default(),
+            events,
+        })
+    }
+
+    #[cfg(any(target_os = "android", target_os = "ios"))]
+    pub fn from_dsl(_dsl: &str) -> ResultType<Self> {
+        bail!("Text macros are not supported on this platform");
+    }
+
+    /// The messages to send with their delays, the mouse positions are mapped to `display`.
+    pub fn messages(&self, display: Rect) -> Vec<(u64, Message)> {
+        let mut res = vec![];
+        for evt in self.events.iter() {
+            let mut msg = Message::new();
+            match &evt.input {
+                Input::Key(data) => {
+                    let Some(key_event) = STANDARD
+                        .decode(data)
+                        .ok()
+                        .and_then(|data| KeyEvent::parse_from_bytes(&data).ok())
+                    else {
+                        continue;
+                    };
+                    msg.set_key_event(key_event);
+                }
+                Input::Mouse {
+                    mask,
+                    x,
+                    y,
+                    modifiers,
+                    hi_res_x,
+                    hi_res_y,
+                } => {
+                    let (x, y) = if is_absolute(*mask) {
+                        map_position(*x, *y, self.display, display)
+                    } else {
+                        (*x, *y)
+                    };
+                    msg.set_mouse_event(MouseEvent {
+                        mask: *mask,
+                        x,
+                        y,
+                        modifiers: modifiers
+                            .iter()
+                            .map(|m| EnumOrUnknown::from_i32(*m))
+                            .collect(),
+                        hi_res_x: *hi_res_x,
+                        hi_res_y: *hi_res_y,
+                        ..Default::default()
+                    });
+                }
+            }
+            res.push((evt.delay, msg));
+        }
+        res
+    }
+}
+
+// the wheel and trackpad events carry deltas
+#[inline]
+fn is_absolute(mask: i32) -> bool {
+    let t = mask & 0x7;
+    t == MOUSE_TYPE_MOVE || t == MOUSE_TYPE_DOWN || t == MOUSE_TYPE_UP
+}
+
+fn map_position(x: i32, y: i32, from: Rect, to: Rect) -> (i32, i32) {
+    if from.is_empty() || to.is_empty() || from == to {
+        return (x, y);
+    }
+    let map = |v: i32, from_pos: i32, from_len: i32, to_pos: i32, to_len: i32| {
+        to_pos + ((v - from_pos) as i64 * to_len as i64 / from_len as i64) as i32
+    };
+    (
+        map(x, from.x, from.width, to.x, to.width),
+        map(y, from.y, from.height, to.y, to.height),
+    )
+}
+
+/// The macro state of a session.
+#[derive(Default)]
+pub struct InputMacro {
+    // the current display of the peer
+    display: Rect,
+    recording: Option<(Macro, Instant)>,
+    // set to stop the playback
+    playing: Option<Arc<AtomicBool>>,
+}
+
+impl InputMacro {
+    pub fn set_display(&mut self, display: Rect) {
+        if !display.is_empty() {
+            self.display = display;
+        }
+    }
+
+    pub fn display(&self) -> Rect {
+        self.display
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing.is_some()
+    }
+
+    pub fn start_recording(&mut self) -> bool {
+        if self.is_playing() {
+            return false;
+        }
+        self.recording = Some((
+            Macro {
+                version: VERSION,
+                display: self.display,
+                events: vec![],
+            },
+            Instant::now(),
+        ));
+        true
+    }
+
+    pub fn stop_recording(&mut self) -> Option<Macro> {
+        self.recording.take().map(|(m, _)| m)
+    }
+
+    pub fn record(&mut self, msg: &Message) {
+        let Some((m, last)) = self.recording.as_mut() else {
+            return;
+        };
+        let input = match &msg.union {
+            Some(message::Union::KeyEvent(evt)) => match evt.write_to_bytes() {
+                Ok(data) => Input::Key(STANDARD.encode(data)),
+                Err(_) => return,
+            },
+            Some(message::Union::MouseEvent(evt)) => Input::Mouse {
+                mask: evt.mask,
+                x: evt.x,
+                y: evt.y,
+                modifiers: evt.modifiers.iter().map(|m| m.value()).collect(),
+                hi_res_x: evt.hi_res_x,
+                hi_res_y: evt.hi_res_y,
+            },
+            _ => return,
+        };
+        let now = Instant::now();
+        let delay = if m.events.is_empty() {
+            0
+        } else {
+            now.duration_since(*last).as_millis() as u64
+        };
+        *last = now;
+        m.events.push(MacroEvent { delay, input });
+    }
+
+    /// The flag to stop the playback, None if recording or playing.
+    pub fn start_playing(&mut self) -> Option<Arc<AtomicBool>> {
+        if self.is_recording() || self.is_playing() {
+            return None;
+        }
+        let stop = Arc::new(AtomicBool::new(false));
+        self.playing = Some(stop.clone());
+        Some(stop)
+    }
+
+    pub fn stop_playing(&mut self) {
+        if let Some(stop) = self.playing.take() {
+            stop.store(true, Ordering::SeqCst);
+        }
+    }
+
+    // clear the state when a playback ends, unless another one has started
+    pub fn finish_playing(&mut self, stop: &Arc<AtomicBool>) {
+        if self
+            .playing
+            .as_ref()
+            .map_or(false, |playing| Arc::ptr_eq(playing, stop))
+        {
+            self.playing = None;
+        }
+    }
+}
+
+/// Send the messages with their delays scaled by `speed`, until all are sent or `stop` is set.
+/// The keys and mouse buttons left down are released at the end.
+pub fn play(messages: Vec<(u64, Message)>, speed: f64, stop: &AtomicBool, send: impl Fn(Message)) {
+    let speed = if speed.is_finite() && speed > 0. {
+        speed.clamp(MIN_SPEED, MAX_SPEED)
+    } else {
+        1.
+    };
+    let mut held = Held::default();
+    'outer: for (delay, msg) in messages {
+        let deadline = Instant::now() + Duration::from_secs_f64(delay as f64 / 1000. / speed);
+        loop {
+            if stop.load(Ordering::SeqCst) {
+                break 'outer;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            std::thread::sleep((deadline - now).min(Duration::from_millis(100)));
+        }
+        held.track(&msg);
+        send(msg);
+    }
+    for msg in held.release() {
+        send(msg);
+    }
+}
+
+// The keys and mouse buttons down on the peer during a playback.
+#[derive(Default)]
+struct Held {
+    keys: Vec<KeyEvent>,
+    // the buttons of the mask
+    buttons: i32,
+    // the last absolute position
+    position: (i32, i32),
+}
+
+impl Held {
+    fn track(&mut self, msg: &Message) {
+        match &msg.union {
+            Some(message::Union::KeyEvent(evt)) if !evt.press => {
+                self.keys.retain(|k| k.union != evt.union);
+                if evt.down {
+                    self.keys.push(evt.clone());
+                }
+            }
+            Some(message::Union::MouseEvent(evt)) => {
+                if is_absolute(evt.mask) {
+                    self.position = (evt.x, evt.y);
+                }
+                let buttons = evt.mask >> 3;
+                match evt.mask & 0x7 {
+                    MOUSE_TYPE_DOWN => self.buttons |= buttons,
+                    MOUSE_TYPE_UP => self.buttons &= !buttons,
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // the ups of the keys in the reverse order, then the buttons
+    fn release(self) -> Vec<Message> {
+        let mut res = vec![];
+        for key in self.keys.into_iter().rev() {
+            let mut msg = Message::new();
+            msg.set_key_event(KeyEvent {
+                down: false,
+                mode: key.mode,
+                union: key.union,
+                ..Default::default()
+            });
+            res.push(msg);
+        }
+        for i in 0..(i32::BITS - 3) {
+            let button = self.buttons & (1 << i);
+            if button != 0 {
+                let mut msg = Message::new();
+                msg.set_mouse_event(MouseEvent {
+                    mask: MOUSE_TYPE_UP | (button << 3),
+                    x: self.position.0,
+                    y: self.position.1,
+                    ..Default::default()
+                });
+                res.push(msg);
+            }
+        }
+        res
+    }
+}
+
+// converts the keys typed by the enigo DSL to key events
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+#[derive(Default)]
+struct KeyCollector {
+    modifiers: Vec<ControlKey>,
+    events: Vec<KeyEvent>,
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+impl KeyCollector {
+    fn modifier(key: enigo::Key) -> Option<ControlKey> {
+        match key {
+            enigo::Key::Shift => Some(ControlKey::Shift),
+            enigo::Key::Control => Some(ControlKey::Control),
+            enigo::Key::Meta => Some(ControlKey::Meta),
+            enigo::Key::Alt => Some(ControlKey::Alt),
+            _ => None,
+        }
+    }
+
+    fn push(&mut self, mut evt: KeyEvent) {
+        evt.mode = KeyboardMode::Legacy.into();
+        evt.modifiers = self.modifiers.iter().map(|m| (*m).into()).collect();
+        self.events.push(evt);
+    }
+
+    fn push_modifier(&mut self, key: ControlKey, down: bool) {
+        self.modifiers.retain(|m| *m != key);
+        if down {
+            self.modifiers.push(key);
+        }
+        let mut evt = KeyEvent::new();
+        evt.set_control_key(key);
+        evt.down = down;
+        self.push(evt);
+    }
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+impl enigo::KeyboardControllable for KeyCollector {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_mut_any(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+
+    fn key_sequence(&mut self, sequence: &str) {
+        let mut evt = KeyEvent::new();
+        evt.set_seq(sequence.to_owned());
+        evt.press = true;
+        self.push(evt);
+    }
+
+    fn key_down(&mut self, key: enigo::Key) -> enigo::ResultType {
+        if let Some(key) = Self::modifier(key) {
+            self.push_modifier(key, true);
+        }
+        Ok(())
+    }
+
+    fn key_up(&mut self, key: enigo::Key) {
+        if let Some(key) = Self::modifier(key) {
+            self.push_modifier(key, false);
+        }
+    }
+
+    fn key_click(&mut self, key: enigo::Key) {
+        let enigo::Key::Layout(chr) = key else {
+            return;
+        };
+        let mut evt = KeyEvent::new();
+        match chr {
+            '\n' => evt.set_control_key(ControlKey::Return),
+            '\t' => evt.set_control_key(ControlKey::Tab),
+            '\r' => return,
+            _ => evt.set_chr(chr as _),
+        }
+        evt.press = true;
+        self.push(evt);
+    }
+
+    fn get_key_state(&mut self, key: enigo::Key) -> bool {
+        Self::modifier(key).map_or(false, |key| self.modifiers.contains(&key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map_position() {
+        let from = Rect::new(0, 0, 1920, 1080);
+        let to = Rect::new(1920, 0, 3840, 2160);
+        assert_eq!(map_position(960, 540, from, to), (3840, 1080));
+        assert_eq!(map_position(10, 20, from, Rect::default()), (10, 20));
+        assert!(is_absolute(MOUSE_TYPE_DOWN | (1 << 3)));
+        assert!(!is_absolute(crate::input::MOUSE_TYPE_WHEEL));
+    }
+
+    #[test]
+    fn test_record_and_play() {
+        let mut state = InputMacro::default();
+        state.set_display(Rect::new(0, 0, 100, 100));
+        assert!(state.start_recording());
+        let mut msg = Message::new();
+        msg.set_mouse_event(MouseEvent {
+            mask: MOUSE_TYPE_MOVE,
+            x: 50,
+            y: 25,
+            ..Default::default()
+        });
+        state.record(&msg);
+        let mut msg = Message::new();
+        msg.set_misc(Misc::new());
+        state.record(&msg);
+        let m = state.stop_recording().unwrap();
+        assert_eq!(m.events.len(), 1);
+        let m: Macro = serde_json::from_str(&serde_json::to_string(&m).unwrap()).unwrap();
+        let messages = m.messages(Rect::new(100, 0, 200, 200));
+        assert_eq!(messages[0].1.mouse_event().x, 200);
+        assert_eq!(messages[0].1.mouse_event().y, 50);
+        let stop = state.start_playing().unwrap();
+        assert!(!state.start_recording());
+        let sent = std::sync::Mutex::new(0);
+        play(messages, 2., &stop, |_| *sent.lock().unwrap() += 1);
+        assert_eq!(*sent.lock().unwrap(), 1);
+    }
+
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    #[test]
+    fn test_dsl() {
+        let m = Macro::from_dsl("{+CTRL}a{-CTRL}\n").unwrap();
+        let keys: Vec<KeyEvent> = m
+            .messages(Rect::default())
+            .into_iter()
+            .map(|(_, msg)| msg.key_event().clone())
+            .collect();
+        assert_eq!(keys.len(), 4);
+        assert_eq!(keys[0].control_key(), ControlKey::Control);
+        assert_eq!(keys[1].chr(), 'a' as u32);
+        assert_eq!(keys[1].modifiers, vec![ControlKey::Control.into()]);
+        assert!(!keys[2].down);
+        assert_eq!(keys[3].control_key(), ControlKey::Return);
+        assert!(Macro::from_dsl("{+CTRL").is_err());
+    }
+
+    #[test]
+    fn test_release_on_stop() {
+        let key = |key: ControlKey, down: bool| {
+            let mut evt = KeyEvent::new();
+            evt.set_control_key(key);
+            evt.down = down;
+            let mut msg = Message::new();
+            msg.set_key_event(evt);
+            (0, msg)
+        };
+        let mouse = |mask: i32| {
+            let mut msg = Message::new();
+            msg.set_mouse_event(MouseEvent {
+                mask,
+                x: 10,
+                y: 20,
+                ..Default::default()
+            });
+            (0, msg)
+        };
+        let left = crate::input::MOUSE_BUTTON_LEFT << 3;
+        let right = crate::input::MOUSE_BUTTON_RIGHT << 3;
+        let messages = vec![
+            key(ControlKey::Control, true),
+            key(ControlKey::Shift, true),
+            key(ControlKey::Shift, false),
+            key(ControlKey::Alt, true),
+            mouse(MOUSE_TYPE_DOWN | left),
+            mouse(MOUSE_TYPE_DOWN | right),
+            mouse(MOUSE_TYPE_UP | right),
+            // not sent
+            (60_000, mouse(MOUSE_TYPE_UP | left).1),
+        ];
+        let stop = AtomicBool::new(false);
+        let sent = std::sync::Mutex::new(vec![]);
+        play(messages, 1., &stop, |msg| {
+            if sent.lock().unwrap().len() == 6 {
+                stop.store(true, Ordering::SeqCst);
+            }
+            sent.lock().unwrap().push(msg);
+        });
+        let sent = sent.into_inner().unwrap();
+        assert_eq!(sent.len(), 10);
+        let ups: Vec<_> = sent[7..9]
+            .iter()
+            .map(|msg| (msg.key_event().control_key(), msg.key_event().down))
+            .collect();
+        assert_eq!(
+            ups,
+            vec![(ControlKey::Alt, false), (ControlKey::Control, false)]
+        );
+        let up = sent[9].mouse_event();
+        assert_eq!((up.mask, up.x, up.y), (MOUSE_TYPE_UP | left, 10, 20));
+    }
+}
@@ -43,11 +43,16 @@ use crate::client::{
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
 use crate::common::{self, update_clipboard};
 use crate::common::{get_default_sound_input, set_sound_input};
+use crate::session_stats::{self, SessionStats};
 use crate::ui_session_interface::{InvokeUiSession, Session};
 #[cfg(not(any(target_os = "ios")))]
 use crate::{audio_service, ConnInner, CLIENT_SERVER};
 use crate::{client::Data, client::Interface};
 
+// Report the session statistics to the server process every 5 seconds.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+const STATS_REPORT_INTERVAL: i64 = 5_000;
+
 pub struct Remote<T: InvokeUiSession> {
     handler: Session<T>,
     video_queue_map: Arc<RwLock<HashMap<usize, ArrayQueue<VideoFrame>>>>,
@@ -57,6 +62,9 @@ pub struct Remote<T: InvokeUiSession> {
     sender: mpsc::UnboundedSender<Data>,
     // Stop sending local audio to remote client.
     stop_voice_call_sender: Option<std::sync::mpsc::Sender<()>>,
+    // Stop forwarding the local game controllers.
+    #[cfg(target_os = "linux")]
+    stop_gamepad_sender: Option<std::sync::mpsc::Sender<()>>,
     voice_call_request_timestamp: Option<NonZeroI64>,
     read_jobs: Vec<fs::TransferJob>,
     write_jobs: Vec<fs::TransferJob>,
@@ -74,6 +82,10 @@ pub struct Remote<T: InvokeUiSession> {
     fps_control_map: HashMap<usize, FpsControl>,
     decode_fps_map: Arc<RwLock<HashMap<usize, usize>>>,
     chroma: Arc<RwLock<Option<Chroma>>>,
+    stats: SessionStats,
+    stats_reported: i64,
+    // The audio frames are also sent to the video thread if any display is being recorded.
+    recording_displays: std::collections::HashSet<usize>,
 }
 
 impl<T: InvokeUiSession> Remote<T> {
@@ -108,11 +120,16 @@ impl<T: InvokeUiSession> Remote<T> {
             frame_count_map,
             video_format: CodecFormat::Unknown,
             stop_voice_call_sender: None,
+            #[cfg(target_os = "linux")]
+            stop_gamepad_sender: None,
             voice_call_request_timestamp: None,
             elevation_requested: false,
             fps_control_map: Default::default(),
             decode_fps_map: decode_fps,
             chroma,
+            stats: Default::default(),
+            stats_reported: 0,
+            recording_displays: Default::default(),
         }
     }
 
@@ -146,6 +163,19 @@ impl<T: InvokeUiSession> Remote<T> {
                     self.handler
                         .set_fingerprint(crate::common::pk_to_fingerprint(pk.unwrap_or_default()));
                 }
+                self.stats = SessionStats::new(
+                    session_stats::controlling_key(self.handler.lc.read().unwrap().session_id),
+                    session_stats::SIDE_CONTROLLING,
+                    0,
+                    &self.handler.get_id(),
+                );
+                self.stats.conn_type = match conn_type {
+                    ConnType::FILE_TRANSFER => "file_transfer",
+                    ConnType::PORT_FORWARD => "port_forward",
+                    _ => "remote",
+                }
+                .to_owned();
+                self.stats.direct = Some(direct);
 
                 // just build for now
                 #[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
@@ -238,6 +268,7 @@ impl<T: InvokeUiSession> Remote<T> {
                                 continue;
                             }
                             fps_instant = Instant::now();
+                            self.publish_stats();
                             let mut speed = self.data_count.swap(0, Ordering::Relaxed);
                             speed = speed * 1000 / elapsed as usize;
                             let speed = format!("{:.2}kB/s", speed as f32 / 1024 as f32);
@@ -267,10 +298,15 @@ impl<T: InvokeUiSession> Remote<T> {
                     }
                 }
                 log::debug!("Exit io_loop of id={}", self.handler.get_id());
+                session_stats::remove(&self.stats.key);
                 // Stop client audio server.
                 if let Some(s) = self.stop_voice_call_sender.take() {
                     s.send(()).ok();
                 }
+                #[cfg(target_os = "linux")]
+                if let Some(s) = self.stop_gamepad_sender.take() {
+                    s.send(()).ok();
+                }
             }
             Err(err) => {
                 self.handler.on_establish_connection_error(err.to_string());
@@ -477,6 +513,7 @@ impl<T: InvokeUiSession> Remote<T> {
                 self.check_clipboard_file_context();
             }
             Data::Message(msg) => {
+                self.stats.on_send(&msg);
                 allow_err!(peer.send(&msg).await);
             }
             Data::SendFiles((id, path, to, file_num, include_hidden, is_remote)) => {
@@ -798,6 +835,11 @@ impl<T: InvokeUiSession> Remote<T> {
                 }
             }
             Data::RecordScreen(start, display, w, h, id) => {
+                if start {
+                    self.recording_displays.insert(display);
+                } else {
+                    self.recording_displays.remove(&display);
+                }
                 let _ = self
                     .video_sender
                     .send(MediaData::RecordScreen(start, display, w, h, id));
@@ -960,6 +1002,17 @@ impl<T: InvokeUiSession> Remote<T> {
         }
     }
 
+    fn publish_stats(&mut self) {
+        self.stats.publish();
+        #[cfg(not(any(target_os = "android", target_os = "ios")))]
+        if self.stats.update_time - self.stats_reported > STATS_REPORT_INTERVAL {
+            self.stats_reported = self.stats.update_time;
+            tokio::spawn(async move {
+                allow_err!(session_stats::report().await);
+            });
+        }
+    }
+
     #[inline]
     fn fps_control(&mut self, direct: bool) {
         let custom_fps = self.handler.lc.read().unwrap().custom_fps.clone();
@@ -1042,6 +1095,7 @@ impl<T: InvokeUiSession> Remote<T> {
 
     async fn handle_msg_from_peer(&mut self, data: &[u8], peer: &mut Stream) -> bool {
         if let Ok(msg_in) = Message::parse_from_bytes(&data) {
+            self.stats.on_recv(&msg_in, data.len());
             match msg_in.union {
                 Some(message::Union::VideoFrame(vf)) => {
                     if !self.first_frame {
@@ -1052,7 +1106,10 @@ impl<T: InvokeUiSession> Remote<T> {
                         self.send_toggle_privacy_mode_msg(peer).await;
                     }
                     let incoming_format = CodecFormat::from(&vf);
-                    if self.video_format != incoming_format {
+                    // Keep the codec of the lossy frames while the lossless frames are received.
+                    if self.video_format != incoming_format
+                        && incoming_format != CodecFormat::Unknown
+                    {
                         self.video_format = incoming_format.clone();
                         self.handler.update_quality_status(QualityStatus {
                             codec_format: Some(incoming_format),
@@ -1070,14 +1127,18 @@ impl<T: InvokeUiSession> Remote<T> {
                     }
                     if Self::contains_key_frame(&vf) {
                         if let Some(video_queue) = video_queue_write.get_mut(&display) {
-                            while let Some(_) = video_queue.pop() {}
+                            while let Some(_) = video_queue.pop() {
+                                self.stats.frames_dropped += 1;
+                            }
                         }
                         self.video_sender
                             .send(MediaData::VideoFrame(Box::new(vf)))
                             .ok();
                     } else {
                         if let Some(video_queue) = video_queue_write.get_mut(&display) {
-                            video_queue.force_push(vf);
+                            if video_queue.force_push(vf).is_some() {
+                                self.stats.frames_dropped += 1;
+                            }
                         }
                         self.video_sender.send(MediaData::VideoQueue(display)).ok();
                     }
@@ -1094,6 +1155,11 @@ impl<T: InvokeUiSession> Remote<T> {
                         }
                     }
                     Some(login_response::Union::PeerInfo(pi)) => {
+                        #[cfg(target_os = "linux")]
+                        let gamepad = serde_json::from_str::<HashMap<String, serde_json::Value>>(
+                            &pi.platform_additions,
+                        )
+                        .map_or(false, |v| v.contains_key("gamepad"));
                         self.handler.handle_peer_info(pi);
                         self.check_clipboard_file_context();
                         if !(self.handler.is_file_transfer() || self.handler.is_port_forward()) {
@@ -1133,7 +1199,16 @@ impl<T: InvokeUiSession> Remote<T> {
                             crate::plugin::handle_listen_event(
                                 crate::plugin::EVENT_ON_CONN_CLIENT.to_owned(),
                                 self.handler.get_id(),
-                            )
+                            );
+
+                            #[cfg(target_os = "linux")]
+                            if gamepad
+                                && !self.handler.lc.read().unwrap().view_only.v
+                                && self.stop_gamepad_sender.is_none()
+                            {
+                                self.stop_gamepad_sender =
+                                    Some(crate::client::gamepad::start(self.sender.clone()));
+                            }
                         }
 
                         if self.handler.is_file_transfer() {
@@ -1156,16 +1231,13 @@ impl<T: InvokeUiSession> Remote<T> {
                 Some(message::Union::Clipboard(cb)) => {
                     if !self.handler.lc.read().unwrap().disable_clipboard.v {
                         #[cfg(not(any(target_os = "android", target_os = "ios")))]
-                        update_clipboard(cb, Some(&crate::client::get_old_clipboard_text()));
+                        update_clipboard(cb, Some(&crate::client::get_old_clipboard_content()));
                         #[cfg(any(target_os = "android", target_os = "ios"))]
                         {
-                            let content = if cb.compress {
-                                hbb_common::compress::decompress(&cb.content)
-                            } else {
-                                cb.content.into()
-                            };
-                            if let Ok(content) = String::from_utf8(content) {
-                                self.handler.clipboard(content);
+                            let primary = cb.primary;
+                            let content = common::parse_clipboard(cb);
+                            if !primary && !content.text.is_empty() {
+                                self.handler.clipboard(content.text);
                             }
                         }
                     }
@@ -1320,6 +1392,10 @@ impl<T: InvokeUiSession> Remote<T> {
                 }
                 Some(message::Union::Misc(misc)) => match misc.union {
                     Some(misc::Union::AudioFormat(f)) => {
+                        // for the audio track of the screen record
+                        self.video_sender
+                            .send(MediaData::AudioFormat(f.clone()))
+                            .ok();
                         self.audio_sender.send(MediaData::AudioFormat(f)).ok();
                     }
                     Some(misc::Union::ChatMessage(c)) => {
@@ -1363,6 +1439,9 @@ impl<T: InvokeUiSession> Remote<T> {
                             Ok(Permission::BlockInput) => {
                                 self.handler.set_permission("block_input", p.enabled);
                             }
+                            Ok(Permission::Gamepad) => {
+                                self.handler.set_permission("gamepad", p.enabled);
+                            }
                             _ => {}
                         }
                     }
@@ -1508,10 +1587,19 @@ impl<T: InvokeUiSession> Remote<T> {
                     _ => {}
                 },
                 Some(message::Union::TestDelay(t)) => {
+                    if !t.from_client {
+                        self.stats.rtt = t.last_delay;
+                        self.stats.bitrate = t.target_bitrate;
+                    }
                     self.handler.handle_test_delay(t, peer).await;
                 }
                 Some(message::Union::AudioFrame(frame)) => {
                     if !self.handler.lc.read().unwrap().disable_audio.v {
+                        if !self.recording_displays.is_empty() {
+                            self.video_sender
+                                .send(MediaData::AudioFrame(Box::new(frame.clone())))
+                                .ok();
+                        }
                         self.audio_sender
                             .send(MediaData::AudioFrame(Box::new(frame)))
                             .ok();
@@ -1568,6 +1656,10 @@ impl<T: InvokeUiSession> Remote<T> {
                     self.handler.set_displays(&pi.displays);
                     self.handler.set_platform_additions(&pi.platform_additions);
                 }
+                Some(message::Union::GamepadRumble(r)) => {
+                    self.handler
+                        .on_gamepad_rumble(r.index, r.strong, r.weak, r.duration);
+                }
                 _ => {}
             }
         }
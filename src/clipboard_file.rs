@@ -1,6 +1,17 @@
 use clipboard::ClipboardFile;
 use hbb_common::message_proto::*;
 
+/// Whether the message offers or carries the files of the sender's clipboard,
+/// the others are requests and notifications.
+pub fn is_clip_data(clip: &ClipboardFile) -> bool {
+    matches!(
+        clip,
+        ClipboardFile::FormatList { .. }
+            | ClipboardFile::FormatDataResponse { .. }
+            | ClipboardFile::FileContentsResponse { .. }
+    )
+}
+
 pub fn clip_2_msg(clip: ClipboardFile) -> Message {
     match clip {
         ClipboardFile::NotifyCallback {
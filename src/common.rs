@@ -29,11 +29,20 @@ pub struct ClipboardContext {
     string_setter: x11rb::protocol::xproto::Atom,
     string_getter: x11rb::protocol::xproto::Atom,
     text_uri_list: x11rb::protocol::xproto::Atom,
+    html_setter: x11rb::protocol::xproto::Atom,
+    html_getter: x11rb::protocol::xproto::Atom,
+    rtf_setter: x11rb::protocol::xproto::Atom,
+    rtf_getter: x11rb::protocol::xproto::Atom,
+    png_setter: x11rb::protocol::xproto::Atom,
+    png_getter: x11rb::protocol::xproto::Atom,
 
     clip: x11rb::protocol::xproto::Atom,
     prop: x11rb::protocol::xproto::Atom,
 }
 
+#[cfg(all(target_os = "linux", feature = "unix-file-copy-paste"))]
+const X11_CLIPBOARD_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(120);
+
 #[cfg(all(target_os = "linux", feature = "unix-file-copy-paste"))]
 fn parse_plain_uri_list(v: Vec<u8>) -> Result<String, String> {
     let text = String::from_utf8(v).map_err(|_| "ConversionFailure".to_owned())?;
@@ -67,12 +76,42 @@ impl ClipboardContext {
             .getter
             .get_atom("text/uri-list")
             .map_err(|e| e.to_string())?;
+        let html_setter = clipboard
+            .setter
+            .get_atom(CLIPBOARD_MIME_HTML)
+            .map_err(|e| e.to_string())?;
+        let html_getter = clipboard
+            .getter
+            .get_atom(CLIPBOARD_MIME_HTML)
+            .map_err(|e| e.to_string())?;
+        let rtf_setter = clipboard
+            .setter
+            .get_atom(CLIPBOARD_MIME_RTF)
+            .map_err(|e| e.to_string())?;
+        let rtf_getter = clipboard
+            .getter
+            .get_atom(CLIPBOARD_MIME_RTF)
+            .map_err(|e| e.to_string())?;
+        let png_setter = clipboard
+            .setter
+            .get_atom(CLIPBOARD_MIME_PNG)
+            .map_err(|e| e.to_string())?;
+        let png_getter = clipboard
+            .getter
+            .get_atom(CLIPBOARD_MIME_PNG)
+            .map_err(|e| e.to_string())?;
         let prop = clipboard.getter.atoms.property;
         let clip = clipboard.getter.atoms.clipboard;
         Ok(Self {
             text_uri_list,
             string_setter,
             string_getter,
+            html_setter,
+            html_getter,
+            rtf_setter,
+            rtf_getter,
+            png_setter,
+            png_getter,
             clip,
             prop,
         })
@@ -82,13 +121,12 @@ impl ClipboardContext {
         let clip = self.clip;
         let prop = self.prop;
 
-        const TIMEOUT: std::time::Duration = std::time::Duration::from_millis(120);
-
         let text_content = get_clipboard()?
-            .load(clip, self.string_getter, prop, TIMEOUT)
+            .load(clip, self.string_getter, prop, X11_CLIPBOARD_TIMEOUT)
             .map_err(|e| e.to_string())?;
 
-        let file_urls = get_clipboard()?.load(clip, self.text_uri_list, prop, TIMEOUT);
+        let file_urls =
+            get_clipboard()?.load(clip, self.text_uri_list, prop, X11_CLIPBOARD_TIMEOUT);
 
         if file_urls.is_err() || file_urls.as_ref().unwrap().is_empty() {
             log::trace!("clipboard get text, no file urls");
@@ -116,16 +154,75 @@ impl ClipboardContext {
             .map_err(|e| e.to_string())?;
         Ok(())
     }
+
+    fn load(&self, target: x11rb::protocol::xproto::Atom) -> Result<Vec<u8>, String> {
+        get_clipboard()?
+            .load(self.clip, target, self.prop, X11_CLIPBOARD_TIMEOUT)
+            .map_err(|e| e.to_string())
+    }
+
+    pub fn get_content(&mut self) -> Result<ClipboardContent, String> {
+        Ok(ClipboardContent {
+            text: self.get_text().unwrap_or_default(),
+            html: self
+                .load(self.html_getter)
+                .ok()
+                .and_then(|v| String::from_utf8(v).ok())
+                .unwrap_or_default(),
+            rtf: self.load(self.rtf_getter).unwrap_or_default(),
+            image: self.load(self.png_getter).unwrap_or_default(),
+        })
+    }
+
+    pub fn get_primary_text(&mut self) -> Result<String, String> {
+        let text = get_clipboard()?
+            .load(
+                x11rb::protocol::xproto::AtomEnum::PRIMARY.into(),
+                self.string_getter,
+                self.prop,
+                X11_CLIPBOARD_TIMEOUT,
+            )
+            .map_err(|e| e.to_string())?;
+        String::from_utf8(text).map_err(|e| e.to_string())
+    }
+
+    pub fn set_primary_text(&mut self, text: &str) -> Result<(), String> {
+        get_clipboard()?
+            .store(
+                x11rb::protocol::xproto::AtomEnum::PRIMARY.into(),
+                self.string_setter,
+                text.as_bytes().to_vec(),
+            )
+            .map_err(|e| e.to_string())
+    }
+
+    // all the formats are offered as the targets of the selection
+    pub fn set_content(&mut self, content: &ClipboardContent) -> Result<(), String> {
+        let batch: Vec<_> = [
+            (self.string_setter, content.text.as_bytes()),
+            (self.html_setter, content.html.as_bytes()),
+            (self.rtf_setter, &content.rtf[..]),
+            (self.png_setter, &content.image[..]),
+        ]
+        .into_iter()
+        .filter(|(_, v)| !v.is_empty())
+        .map(|(target, v)| (target, v.to_vec()))
+        .collect();
+        if batch.is_empty() {
+            return Ok(());
+        }
+        get_clipboard()?
+            .store_batch(self.clip, batch)
+            .map_err(|e| e.to_string())
+    }
 }
 
-#[cfg(not(any(target_os = "android", target_os = "ios")))]
-use hbb_common::compress::decompress;
 use hbb_common::{
     allow_err,
     anyhow::{anyhow, Context},
     bail,
     bytes::Bytes,
-    compress::compress as compress_func,
+    compress::{compress as compress_func, decompress},
     config::{self, Config, CONNECT_TIMEOUT, READ_TIMEOUT},
     futures_util::future::poll_fn,
     get_version_number, log,
@@ -151,6 +248,21 @@ pub type NotifyMessageBox = fn(String, String, String, String) -> dyn Future<Out
 
 pub const CLIPBOARD_NAME: &'static str = "clipboard";
 pub const CLIPBOARD_INTERVAL: u64 = 333;
+pub const CLIPBOARD_MIME_TEXT: &str = "text/plain";
+pub const CLIPBOARD_MIME_HTML: &str = "text/html";
+pub const CLIPBOARD_MIME_RTF: &str = "text/rtf";
+pub const CLIPBOARD_MIME_PNG: &str = "image/png";
+pub const OPTION_PRIMARY_SELECTION: &str = "enable-primary-selection";
+// the formats over the limits are not synced
+const MAX_CLIPBOARD_TEXT: usize = 2_000_000;
+const MAX_CLIPBOARD_FORMAT: usize = 8 * 1024 * 1024;
+// The raw RGBA image is not encoded to png if larger, e.g. 4K is about 32 MB.
+#[cfg(not(any(
+    target_os = "android",
+    target_os = "ios",
+    all(target_os = "linux", feature = "unix-file-copy-paste")
+)))]
+const MAX_CLIPBOARD_IMAGE_RAW: usize = 8 * MAX_CLIPBOARD_FORMAT;
 
 #[cfg(all(target_os = "macos", feature = "flutter_texture_render"))]
 // https://developer.apple.com/forums/thread/712709
@@ -181,13 +293,36 @@ pub mod input {
     pub const MOUSE_BUTTON_WHEEL: i32 = 0x04;
     pub const MOUSE_BUTTON_BACK: i32 = 0x08;
     pub const MOUSE_BUTTON_FORWARD: i32 = 0x10;
+
+    // the high resolution wheel units of a notch, the same as WHEEL_DELTA and REL_WHEEL_HI_RES
+    pub const MOUSE_WHEEL_HI_RES_NOTCH: i32 = 120;
+
+    /// Add the high resolution deltas to `remainder`, take the whole notches out of it.
+    pub fn take_wheel_notches(remainder: &mut (i32, i32), x: i32, y: i32) -> (i32, i32) {
+        remainder.0 += x;
+        remainder.1 += y;
+        let notches = (
+            remainder.0 / MOUSE_WHEEL_HI_RES_NOTCH,
+            remainder.1 / MOUSE_WHEEL_HI_RES_NOTCH,
+        );
+        remainder.0 %= MOUSE_WHEEL_HI_RES_NOTCH;
+        remainder.1 %= MOUSE_WHEEL_HI_RES_NOTCH;
+        notches
+    }
 }
 
 lazy_static::lazy_static! {
-    pub static ref CONTENT: Arc<Mutex<String>> = Default::default();
+    pub static ref CONTENT: Arc<Mutex<ClipboardContent>> = Default::default();
     pub static ref SOFTWARE_UPDATE_URL: Arc<Mutex<String>> = Default::default();
 }
 
+#[cfg(target_os = "linux")]
+lazy_static::lazy_static! {
+    // the last primary selection of the host and the client
+    static ref PRIMARY: Arc<Mutex<String>> = Default::default();
+    static ref CLIENT_PRIMARY: Arc<Mutex<String>> = Default::default();
+}
+
 lazy_static::lazy_static! {
     pub static ref DEVICE_ID: Arc<Mutex<String>> = Default::default();
     pub static ref DEVICE_NAME: Arc<Mutex<String>> = Default::default();
@@ -268,42 +403,246 @@ pub fn valid_for_numlock(evt: &KeyEvent) -> bool {
     }
 }
 
+/// The clipboard content synced, the formats not available are empty.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ClipboardContent {
+    pub text: String,
+    pub html: String,
+    pub rtf: Vec<u8>,
+    // png
+    pub image: Vec<u8>,
+}
+
+impl ClipboardContent {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty() && self.html.is_empty() && self.rtf.is_empty() && self.image.is_empty()
+    }
+
+    fn limit(&mut self) {
+        if self.text.len() >= MAX_CLIPBOARD_TEXT {
+            log::info!("{} text is too large: {}", CLIPBOARD_NAME, self.text.len());
+            self.text.clear();
+        }
+        if self.html.len() > MAX_CLIPBOARD_FORMAT {
+            log::info!("{} html is too large: {}", CLIPBOARD_NAME, self.html.len());
+            self.html.clear();
+        }
+        if self.rtf.len() > MAX_CLIPBOARD_FORMAT {
+            log::info!("{} rtf is too large: {}", CLIPBOARD_NAME, self.rtf.len());
+            self.rtf.clear();
+        }
+        if self.image.len() > MAX_CLIPBOARD_FORMAT {
+            log::info!(
+                "{} image is too large: {}",
+                CLIPBOARD_NAME,
+                self.image.len()
+            );
+            self.image.clear();
+        }
+    }
+}
+
+fn compress_clipboard(data: &[u8]) -> (bool, Vec<u8>) {
+    let compressed = compress_func(data);
+    if compressed.len() < data.len() {
+        (true, compressed)
+    } else {
+        (false, data.to_vec())
+    }
+}
+
+#[inline]
 pub fn create_clipboard_msg(content: String) -> Message {
-    let bytes = content.into_bytes();
-    let compressed = compress_func(&bytes);
-    let compress = compressed.len() < bytes.len();
-    let content = if compress { compressed } else { bytes };
+    create_clipboard_content_msg(&ClipboardContent {
+        text: content,
+        ..Default::default()
+    })
+}
+
+#[inline]
+pub fn create_clipboard_content_msg(content: &ClipboardContent) -> Message {
     let mut msg = Message::new();
-    msg.set_clipboard(Clipboard {
+    msg.set_clipboard(create_clipboard(content, false));
+    msg
+}
+
+/// The primary selection is sent as the plain text format only.
+pub fn create_clipboard(content: &ClipboardContent, primary: bool) -> Clipboard {
+    let (compress, text) = compress_clipboard(content.text.as_bytes());
+    if primary {
+        return Clipboard {
+            formats: vec![ClipboardFormat {
+                mime: CLIPBOARD_MIME_TEXT.to_owned(),
+                compress,
+                content: text.into(),
+                ..Default::default()
+            }],
+            primary: true,
+            ..Default::default()
+        };
+    }
+    let mut formats = vec![];
+    for (mime, data) in [
+        (CLIPBOARD_MIME_HTML, content.html.as_bytes()),
+        (CLIPBOARD_MIME_RTF, &content.rtf[..]),
+        (CLIPBOARD_MIME_PNG, &content.image[..]),
+    ] {
+        if data.is_empty() {
+            continue;
+        }
+        // png is compressed already
+        let (compress, data) = if mime == CLIPBOARD_MIME_PNG {
+            (false, data.to_vec())
+        } else {
+            compress_clipboard(data)
+        };
+        formats.push(ClipboardFormat {
+            mime: mime.to_owned(),
+            compress,
+            content: data.into(),
+            ..Default::default()
+        });
+    }
+    Clipboard {
         compress,
-        content: content.into(),
+        content: text.into(),
+        formats,
         ..Default::default()
-    });
-    msg
+    }
+}
+
+/// The formats not known or not valid are ignored.
+pub fn parse_clipboard(clipboard: Clipboard) -> ClipboardContent {
+    let decompress_clipboard = |compress: bool, data: Bytes| -> Vec<u8> {
+        if compress {
+            decompress(&data)
+        } else {
+            data.into()
+        }
+    };
+    let mut content = ClipboardContent {
+        text: String::from_utf8(decompress_clipboard(clipboard.compress, clipboard.content))
+            .unwrap_or_default(),
+        ..Default::default()
+    };
+    for format in clipboard.formats {
+        let data = decompress_clipboard(format.compress, format.content);
+        match format.mime.as_str() {
+            CLIPBOARD_MIME_TEXT if content.text.is_empty() => {
+                content.text = String::from_utf8(data).unwrap_or_default();
+            }
+            CLIPBOARD_MIME_HTML => content.html = String::from_utf8(data).unwrap_or_default(),
+            CLIPBOARD_MIME_RTF => content.rtf = data,
+            CLIPBOARD_MIME_PNG => content.image = data,
+            _ => {}
+        }
+    }
+    content.limit();
+    content
+}
+
+#[inline]
+#[cfg(target_os = "linux")]
+pub fn is_primary_selection_enabled() -> bool {
+    Config::get_option(OPTION_PRIMARY_SELECTION) == "Y"
+}
+
+#[cfg(target_os = "linux")]
+pub fn check_primary_selection(ctx: &mut Option<ClipboardContext>, host: bool) -> Option<Message> {
+    let (side, old) = if host {
+        ("host", &*PRIMARY)
+    } else {
+        ("client", &*CLIENT_PRIMARY)
+    };
+    if ctx.is_none() {
+        *ctx = ClipboardContext::new().ok();
+    }
+    let text = {
+        let ctx = ctx.as_mut()?;
+        let _lock = ARBOARD_MTX.lock().unwrap();
+        ctx.get_primary_text().ok()?
+    };
+    if text.is_empty() || text.len() >= MAX_CLIPBOARD_TEXT {
+        return None;
+    }
+    let mut old = old.lock().unwrap();
+    if text == *old {
+        return None;
+    }
+    log::debug!("primary selection update found on {}", side);
+    let mut msg = Message::new();
+    msg.set_clipboard(create_clipboard(
+        &ClipboardContent {
+            text: text.clone(),
+            ..Default::default()
+        },
+        true,
+    ));
+    *old = text;
+    Some(msg)
+}
+
+#[cfg(target_os = "linux")]
+fn update_primary_selection(text: String, host: bool) {
+    let old = if host { &*PRIMARY } else { &*CLIENT_PRIMARY };
+    match ClipboardContext::new() {
+        Ok(mut ctx) => {
+            let mut old = old.lock().unwrap();
+            let _lock = ARBOARD_MTX.lock().unwrap();
+            allow_err!(ctx.set_primary_text(&text));
+            *old = text;
+        }
+        Err(err) => {
+            log::error!("Failed to create clipboard context: {}", err);
+        }
+    }
+}
+
+#[inline]
+pub fn get_clipboard_size(clipboard: &Clipboard) -> usize {
+    clipboard.content.len()
+        + clipboard
+            .formats
+            .iter()
+            .map(|f| f.content.len())
+            .sum::<usize>()
 }
 
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
 pub fn check_clipboard(
     ctx: &mut Option<ClipboardContext>,
-    old: Option<&Arc<Mutex<String>>>,
+    old: Option<&Arc<Mutex<ClipboardContent>>>,
 ) -> Option<Message> {
-    if ctx.is_none() {
-        *ctx = ClipboardContext::new().ok();
-    }
-    let ctx2 = ctx.as_mut()?;
     let side = if old.is_none() { "host" } else { "client" };
     let old = if let Some(old) = old { old } else { &CONTENT };
-    let content = {
-        let _lock = ARBOARD_MTX.lock().unwrap();
-        ctx2.get_text()
+    #[cfg(all(target_os = "linux", feature = "test_display"))]
+    let test_content = crate::server::test_display::is_enabled().then(|| ClipboardContent {
+        text: crate::server::test_display::get_clipboard(),
+        ..Default::default()
+    });
+    #[cfg(not(all(target_os = "linux", feature = "test_display")))]
+    let test_content: Option<ClipboardContent> = None;
+    let content = match test_content {
+        Some(content) => Some(content),
+        None => {
+            if ctx.is_none() {
+                *ctx = ClipboardContext::new().ok();
+            }
+            let ctx2 = ctx.as_mut()?;
+            let _lock = ARBOARD_MTX.lock().unwrap();
+            ctx2.get_content().ok()
+        }
     };
-    if let Ok(content) = content {
-        if content.len() < 2_000_000 && !content.is_empty() {
-            let changed = content != *old.lock().unwrap();
-            if changed {
+    if let Some(mut content) = content {
+        content.limit();
+        if !content.is_empty() {
+            let mut old = old.lock().unwrap();
+            if content != *old {
                 log::info!("{} update found on {}", CLIPBOARD_NAME, side);
-                *old.lock().unwrap() = content.clone();
-                return Some(create_clipboard_msg(content));
+                let msg = create_clipboard_content_msg(&content);
+                *old = content;
+                return Some(msg);
             }
         }
     }
@@ -359,29 +698,51 @@ pub fn get_default_sound_input() -> Option<String> {
 }
 
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
-pub fn update_clipboard(clipboard: Clipboard, old: Option<&Arc<Mutex<String>>>) {
-    let content = if clipboard.compress {
-        decompress(&clipboard.content)
-    } else {
-        clipboard.content.into()
-    };
-    if let Ok(content) = String::from_utf8(content) {
-        if content.is_empty() {
-            // ctx.set_text may crash if content is empty
-            return;
+pub fn update_clipboard(clipboard: Clipboard, old: Option<&Arc<Mutex<ClipboardContent>>>) {
+    let primary = clipboard.primary;
+    let content = parse_clipboard(clipboard);
+    if primary {
+        #[cfg(target_os = "linux")]
+        if is_primary_selection_enabled() && !content.text.is_empty() {
+            update_primary_selection(content.text, old.is_none());
         }
-        match ClipboardContext::new() {
-            Ok(mut ctx) => {
-                let side = if old.is_none() { "host" } else { "client" };
-                let old = if let Some(old) = old { old } else { &CONTENT };
-                *old.lock().unwrap() = content.clone();
-                let _lock = ARBOARD_MTX.lock().unwrap();
-                allow_err!(ctx.set_text(content));
-                log::debug!("{} updated on {}", CLIPBOARD_NAME, side);
-            }
-            Err(err) => {
-                log::error!("Failed to create clipboard context: {}", err);
-            }
+        return;
+    }
+    if content.is_empty() {
+        // ctx.set_text may crash if content is empty
+        return;
+    }
+    #[cfg(all(target_os = "linux", feature = "test_display"))]
+    if crate::server::test_display::is_enabled() {
+        if !content.text.is_empty() {
+            *old.unwrap_or(&CONTENT).lock().unwrap() = ClipboardContent {
+                text: content.text.clone(),
+                ..Default::default()
+            };
+            crate::server::test_display::set_clipboard(content.text);
+        }
+        return;
+    }
+    match ClipboardContext::new() {
+        Ok(mut ctx) => {
+            let side = if old.is_none() { "host" } else { "client" };
+            let old = if let Some(old) = old { old } else { &CONTENT };
+            // locked until read back, so that check_clipboard doesn't send it back
+            let mut old = old.lock().unwrap();
+            let _lock = ARBOARD_MTX.lock().unwrap();
+            allow_err!(ctx.set_content(&content));
+            // what is read back may differ from what is set, e.g. the html is wrapped or the image is encoded again
+            *old = match ctx.get_content() {
+                Ok(mut read) if !read.is_empty() => {
+                    read.limit();
+                    read
+                }
+                _ => content,
+            };
+            log::debug!("{} updated on {}", CLIPBOARD_NAME, side);
+        }
+        Err(err) => {
+            log::error!("Failed to create clipboard context: {}", err);
         }
     }
 }
@@ -1350,6 +1711,44 @@ pub fn using_public_server() -> bool {
         && crate::get_custom_rendezvous_server(get_option("custom-rendezvous-server")).is_empty()
 }
 
+/// The relay server and the pre-shared pairing token of the fixed relay mode.
+///
+/// If both are configured, peers meet directly at the relay server,
+/// without registering to the rendezvous server or punching hole.
+pub fn get_fixed_relay() -> Option<(String, String)> {
+    let relay_server = Config::get_option("fixed-relay-server").trim().to_owned();
+    let token = Config::get_option("relay-pairing-token");
+    if relay_server.is_empty() || token.is_empty() {
+        None
+    } else {
+        Some((relay_server, token))
+    }
+}
+
+/// The uuid that pairs the two connections to the controlled peer `id` on the fixed relay server.
+pub fn get_fixed_relay_uuid(token: &str, id: &str) -> String {
+    use sha2::{Digest, Sha256};
+    let mut hasher = Sha256::new();
+    hasher.update(b"fixed-relay-uuid");
+    hasher.update(token);
+    hasher.update(id);
+    hex::encode(hasher.finalize())
+}
+
+/// The sign key pair derived from the pairing token.
+///
+/// The controlled side signs its id and public key with it, just as the rendezvous server
+/// would do, so the end-to-end key exchange can not be tampered with by the relay server.
+pub fn get_fixed_relay_key_pair(token: &str) -> (sign::PublicKey, sign::SecretKey) {
+    use sha2::{Digest, Sha256};
+    let mut hasher = Sha256::new();
+    hasher.update(b"fixed-relay-sign");
+    hasher.update(token);
+    let mut seed = [0u8; sign::SEEDBYTES];
+    seed.copy_from_slice(&hasher.finalize());
+    sign::keypair_from_seed(&sign::Seed(seed))
+}
+
 pub struct ThrottledInterval {
     interval: Interval,
     last_tick: Instant,
@@ -1400,7 +1799,15 @@ pub fn rustdesk_interval(i: Interval) -> ThrottledInterval {
     target_os = "ios",
     all(target_os = "linux", feature = "unix-file-copy-paste")
 )))]
-pub struct ClipboardContext(arboard::Clipboard);
+pub struct ClipboardContext {
+    inner: arboard::Clipboard,
+    // the sequence number of the clipboard and the content read then, not to read it again until changed
+    #[cfg(any(target_os = "windows", target_os = "macos"))]
+    last: Option<(u64, ClipboardContent)>,
+    // the hash of the last raw image read and its png, not to encode it every time
+    #[cfg(target_os = "linux")]
+    image: Option<(u64, Vec<u8>)>,
+}
 
 #[cfg(not(any(
     target_os = "android",
@@ -1411,7 +1818,10 @@ impl ClipboardContext {
     #[inline]
     #[cfg(any(target_os = "windows", target_os = "macos"))]
     pub fn new() -> ResultType<ClipboardContext> {
-        Ok(ClipboardContext(arboard::Clipboard::new()?))
+        Ok(ClipboardContext {
+            inner: arboard::Clipboard::new()?,
+            last: None,
+        })
     }
 
     #[cfg(target_os = "linux")]
@@ -1426,7 +1836,9 @@ impl ClipboardContext {
         for i in 1..4 {
             arboard::Clipboard::set_x11_server_conn_timeout(dur * i);
             match arboard::Clipboard::new() {
-                Ok(c) => return Ok(ClipboardContext(c)),
+                Ok(inner) => {
+                    return Ok(ClipboardContext { inner, image: None });
+                }
                 Err(arboard::Error::X11ServerConnTimeout) => continue,
                 Err(err) => return Err(err.into()),
             }
@@ -1436,12 +1848,18 @@ impl ClipboardContext {
 
     #[inline]
     #[cfg(any(target_os = "windows", target_os = "macos"))]
-    pub fn get_text(&mut self) -> ResultType<String> {
-        Ok(self.0.get_text()?)
+    fn get<T>(
+        &mut self,
+        f: impl Fn(&mut arboard::Clipboard) -> Result<T, arboard::Error>,
+    ) -> ResultType<T> {
+        Ok(f(&mut self.inner)?)
     }
 
     #[cfg(target_os = "linux")]
-    pub fn get_text(&mut self) -> ResultType<String> {
+    fn get<T>(
+        &mut self,
+        f: impl Fn(&mut arboard::Clipboard) -> Result<T, arboard::Error>,
+    ) -> ResultType<T> {
         let dur = arboard::Clipboard::get_x11_server_conn_timeout();
         let dur_bak = dur;
         let _restore_timeout_on_ret = SimpleCallOnReturn {
@@ -1451,18 +1869,131 @@ impl ClipboardContext {
 
         for i in 1..4 {
             arboard::Clipboard::set_x11_server_conn_timeout(dur * i);
-            match self.0.get_text() {
-                Ok(s) => return Ok(s),
+            match f(&mut self.inner) {
+                Ok(v) => return Ok(v),
                 Err(arboard::Error::X11ServerConnTimeout) => continue,
                 Err(err) => return Err(err.into()),
             }
         }
-        bail!("Failed to get text, timeout");
+        bail!("Failed to get clipboard, timeout");
+    }
+
+    #[inline]
+    pub fn get_text(&mut self) -> ResultType<String> {
+        self.get(|c| c.get_text())
+    }
+
+    fn get_image(&mut self) -> ResultType<Vec<u8>> {
+        let image = self.get(|c| c.get_image())?;
+        if image.bytes.len() > MAX_CLIPBOARD_IMAGE_RAW {
+            bail!("image is too large: {}x{}", image.width, image.height);
+        }
+        // no sequence number on Linux, the image is read on every check
+        #[cfg(target_os = "linux")]
+        let hash = {
+            use std::hash::{Hash, Hasher};
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            (image.width, image.height).hash(&mut hasher);
+            image.bytes.hash(&mut hasher);
+            hasher.finish()
+        };
+        #[cfg(target_os = "linux")]
+        if let Some((h, png)) = &self.image {
+            if *h == hash {
+                return Ok(png.clone());
+            }
+        }
+        let mut png = Vec::new();
+        repng::encode(&mut png, image.width as _, image.height as _, &image.bytes)?;
+        #[cfg(target_os = "linux")]
+        {
+            self.image = Some((hash, png.clone()));
+        }
+        Ok(png)
+    }
+
+    /// The formats not available are left empty.
+    ///
+    /// On Windows and macOS, the clipboard is read only if its sequence number is changed.
+    /// On Linux, the html and the rtf are read from X11, not on Wayland without Xwayland.
+    pub fn get_content(&mut self) -> ResultType<ClipboardContent> {
+        #[cfg(any(target_os = "windows", target_os = "macos"))]
+        let seq = crate::platform::get_clipboard_sequence();
+        #[cfg(any(target_os = "windows", target_os = "macos"))]
+        if let Some((last, content)) = &self.last {
+            if *last == seq {
+                return Ok(content.clone());
+            }
+        }
+        let text = self.get_text();
+        // not kept if the clipboard can't be read now, e.g. it is opened by another app
+        #[cfg(any(target_os = "windows", target_os = "macos"))]
+        let readable = match &text {
+            Ok(_) => true,
+            Err(e) => matches!(
+                e.downcast_ref::<arboard::Error>(),
+                Some(arboard::Error::ContentNotAvailable)
+            ),
+        };
+        let content = ClipboardContent {
+            text: text.unwrap_or_default(),
+            html: crate::platform::get_clipboard_html().unwrap_or_default(),
+            rtf: crate::platform::get_clipboard_rtf().unwrap_or_default(),
+            image: self.get_image().unwrap_or_default(),
+        };
+        #[cfg(any(target_os = "windows", target_os = "macos"))]
+        if readable {
+            self.last = Some((seq, content.clone()));
+        }
+        Ok(content)
     }
 
     #[inline]
     pub fn set_text<'a, T: Into<Cow<'a, str>>>(&mut self, text: T) -> ResultType<()> {
-        self.0.set_text(text)?;
+        self.inner.set_text(text)?;
+        Ok(())
+    }
+
+    #[inline]
+    #[cfg(target_os = "linux")]
+    pub fn get_primary_text(&mut self) -> ResultType<String> {
+        use arboard::{GetExtLinux, LinuxClipboardKind};
+        self.get(|c| c.get().clipboard(LinuxClipboardKind::Primary).text())
+    }
+
+    #[inline]
+    #[cfg(target_os = "linux")]
+    pub fn set_primary_text(&mut self, text: &str) -> ResultType<()> {
+        use arboard::{LinuxClipboardKind, SetExtLinux};
+        self.inner
+            .set()
+            .clipboard(LinuxClipboardKind::Primary)
+            .text(text)?;
+        Ok(())
+    }
+
+    // each set replaces the clipboard, so the richest format is set with its alternative
+    pub fn set_content(&mut self, content: &ClipboardContent) -> ResultType<()> {
+        if !content.image.is_empty() {
+            let image = image::load_from_memory(&content.image)?.to_rgba8();
+            let (width, height) = image.dimensions();
+            self.inner.set_image(arboard::ImageData {
+                width: width as _,
+                height: height as _,
+                bytes: image.into_raw().into(),
+            })?;
+            return Ok(());
+        }
+        if !content.html.is_empty() {
+            let text = (!content.text.is_empty()).then_some(content.text.as_str());
+            self.inner.set_html(content.html.as_str(), text)?;
+        } else if !content.text.is_empty() {
+            self.set_text(content.text.as_str())?;
+        }
+        #[cfg(any(target_os = "windows", target_os = "macos"))]
+        if !content.rtf.is_empty() {
+            crate::platform::set_clipboard_rtf(&content.rtf)?;
+        }
         Ok(())
     }
 }
@@ -1494,6 +2025,26 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_fixed_relay_key_pair() {
+        let (pk, sk) = get_fixed_relay_key_pair("token");
+        let (pk2, _) = get_fixed_relay_key_pair("token");
+        let (pk3, _) = get_fixed_relay_key_pair("other token");
+        assert_eq!(pk, pk2);
+        assert_ne!(pk, pk3);
+        let signed = sign::sign(b"id", &sk);
+        assert_eq!(sign::verify(&signed, &pk2).unwrap(), b"id");
+        assert!(sign::verify(&signed, &pk3).is_err());
+        assert_eq!(
+            get_fixed_relay_uuid("token", "123456789"),
+            get_fixed_relay_uuid("token", "123456789")
+        );
+        assert_ne!(
+            get_fixed_relay_uuid("token", "123456789"),
+            get_fixed_relay_uuid("token", "987654321")
+        );
+    }
+
     // ThrottledInterval tick at the same time as tokio interval, if no sleeps
     #[allow(non_snake_case)]
     #[tokio::test]
@@ -1629,4 +2180,52 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_clipboard_formats() {
+        let content = ClipboardContent {
+            text: "text".repeat(100),
+            html: "<b>text</b>".repeat(100),
+            rtf: b"{\\rtf1 text}".to_vec(),
+            image: vec![0x89, b'P', b'N', b'G'],
+        };
+        let msg = create_clipboard_content_msg(&content);
+        let Some(message::Union::Clipboard(cb)) = msg.union else {
+            panic!("not a clipboard message");
+        };
+        // the text is still in content for the old peers
+        assert!(cb.compress);
+        assert_eq!(decompress(&cb.content), content.text.as_bytes());
+        assert_eq!(parse_clipboard(cb), content);
+
+        let mut cb = Clipboard::new();
+        cb.formats.push(ClipboardFormat {
+            mime: CLIPBOARD_MIME_TEXT.to_owned(),
+            content: b"text".to_vec().into(),
+            ..Default::default()
+        });
+        cb.formats.push(ClipboardFormat {
+            mime: "application/x-unknown".to_owned(),
+            content: b"unknown".to_vec().into(),
+            ..Default::default()
+        });
+        cb.formats.push(ClipboardFormat {
+            mime: CLIPBOARD_MIME_PNG.to_owned(),
+            content: vec![0; MAX_CLIPBOARD_FORMAT + 1].into(),
+            ..Default::default()
+        });
+        let content = parse_clipboard(cb);
+        assert_eq!(content.text, "text");
+        assert!(content.image.is_empty());
+    }
+
+    #[test]
+    fn test_take_wheel_notches() {
+        let mut remainder = (0, 0);
+        assert_eq!(input::take_wheel_notches(&mut remainder, 100, -100), (0, 0));
+        assert_eq!(input::take_wheel_notches(&mut remainder, 30, -150), (1, -2));
+        assert_eq!(remainder, (10, -10));
+        assert_eq!(input::take_wheel_notches(&mut remainder, -20, 10), (0, 0));
+        assert_eq!(remainder, (-10, 0));
+    }
 }
@@ -318,6 +318,70 @@ pub fn core_main() -> Option<Vec<String>> {
                 println!("Installation and administrative privileges required!");
             }
             return None;
+        } else if args[0] == "--session-stats" {
+            match crate::session_stats::query() {
+                Ok(stats) => {
+                    for s in stats {
+                        println!("{}", serde_json::to_string(&s).unwrap_or_default());
+                    }
+                }
+                Err(err) => println!("{}", err),
+            }
+            return None;
+        } else if args[0] == "--verify-audit-log" {
+            // --verify-audit-log <audit log file>
+            if args.len() == 2 {
+                match crate::audit::verify(std::path::Path::new(&args[1])) {
+                    Ok(n) => println!("{} records verified", n),
+                    Err(err) => println!("{}", err),
+                }
+            } else {
+                println!("Usage: --verify-audit-log <audit log file>");
+            }
+            return None;
+        } else if args[0] == "--gen-recording-key" {
+            // --gen-recording-key <new file of the secret key>
+            if args.len() == 2 {
+                let (pk, sk) = scrap::record_encrypt::gen_keypair();
+                match scrap::record_encrypt::save_secret_key(std::path::Path::new(&args[1]), &sk) {
+                    Ok(_) => {
+                        println!("secret key saved to {}", args[1]);
+                        println!("public key: {}", pk);
+                    }
+                    Err(err) => println!("{}", err),
+                }
+            } else {
+                println!("Usage: --gen-recording-key <new file of the secret key>");
+            }
+            return None;
+        } else if args[0] == "--decrypt-recording" {
+            // --decrypt-recording <file> <file of the secret key, or - for stdin> [output]
+            if args.len() == 3 || args.len() == 4 {
+                let input = std::path::Path::new(&args[1]);
+                let output = args
+                    .get(3)
+                    .map(std::path::PathBuf::from)
+                    .unwrap_or(input.with_extension(""));
+                if output == input {
+                    println!("The output is the same as the input");
+                    return None;
+                }
+                match scrap::record_encrypt::read_secret_key(&args[2])
+                    .and_then(|sk| scrap::record_encrypt::decrypt(input, &sk, &output))
+                {
+                    Ok(true) => println!("decrypted to {}", output.display()),
+                    Ok(false) => println!(
+                        "decrypted to {}, the recording is incomplete",
+                        output.display()
+                    ),
+                    Err(err) => println!("{}", err),
+                }
+            } else {
+                println!(
+                    "Usage: --decrypt-recording <file> <file of the secret key, or - for stdin> [output]"
+                );
+            }
+            return None;
         } else if args[0] == "--set-id" {
             if args.len() == 2 {
                 if crate::platform::is_installed() && is_root() {
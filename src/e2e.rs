@@ -0,0 +1,391 @@
+//! End-to-end session tests, a server with the virtual test display and a client over loopback.
+//!
+//! They need no display or audio device, but change the app name and the env of the process,
+//! the home is a temp dir removed at the end. So they are built with the feature `test_display` only,
+//! and run alone, e.g. on headless Linux in CI:
+//! `cargo test --features test_display --lib e2e -- --test-threads=1`
+
+use crate::{
+    client::{self, Client, Data, Interface, LoginConfigHandler, VideoHandler},
+    server::test_display::{self, TestInput},
+};
+use hbb_common::{
+    allow_err, bail,
+    compress::decompress,
+    config::{Config, APP_NAME},
+    futures::StreamExt,
+    log,
+    message_proto::*,
+    protobuf::Message as _,
+    rendezvous_proto::ConnType,
+    tokio::{self, net::TcpListener, sync::mpsc},
+    ResultType, Stream,
+};
+use scrap::CodecFormat;
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+const WIDTH: usize = 640;
+const HEIGHT: usize = 480;
+const PASSWORD: &str = "e2e-Passw0rd";
+const TIMEOUT: Duration = Duration::from_secs(30);
+const MIN_FRAMES: usize = 10;
+const CLIENT_CLIPBOARD: &str = "clipboard from the client";
+const HOST_CLIPBOARD: &str = "clipboard from the host";
+
+#[derive(Clone)]
+struct TestSession {
+    lc: Arc<RwLock<LoginConfigHandler>>,
+    sender: mpsc::UnboundedSender<Data>,
+}
+
+impl TestSession {
+    fn new(id: &str, conn_type: ConnType) -> Self {
+        let (sender, _) = mpsc::unbounded_channel();
+        let session = Self {
+            lc: Default::default(),
+            sender,
+        };
+        session
+            .lc
+            .write()
+            .unwrap()
+            .initialize(id.to_owned(), conn_type, None, false, None);
+        session
+    }
+}
+
+impl Interface for TestSession {
+    fn send(&self, data: Data) {
+        self.sender.send(data).ok();
+    }
+
+    fn msgbox(&self, msgtype: &str, title: &str, text: &str, _link: &str) {
+        log::info!("{}: {}: {}", msgtype, title, text);
+    }
+
+    fn handle_login_error(&self, err: &str) -> bool {
+        log::error!("login error: {}", err);
+        false
+    }
+
+    fn handle_peer_info(&self, pi: PeerInfo) {
+        self.lc.write().unwrap().handle_peer_info(&pi);
+    }
+
+    fn set_multiple_windows_session(&self, _sessions: Vec<WindowsSession>) {}
+
+    async fn handle_hash(&self, pass: &str, hash: Hash, peer: &mut Stream) {
+        client::handle_hash(self.lc.clone(), pass, hash, self, peer).await;
+    }
+
+    async fn handle_login_from_ui(
+        &self,
+        os_username: String,
+        os_password: String,
+        password: String,
+        remember: bool,
+        peer: &mut Stream,
+    ) {
+        client::handle_login_from_ui(
+            self.lc.clone(),
+            os_username,
+            os_password,
+            password,
+            remember,
+            peer,
+        )
+        .await;
+    }
+
+    async fn handle_test_delay(&self, t: TestDelay, peer: &mut Stream) {
+        client::handle_test_delay(t, peer).await;
+    }
+
+    fn get_lch(&self) -> Arc<RwLock<LoginConfigHandler>> {
+        self.lc.clone()
+    }
+}
+
+// The home of the tests, with the config and the logs, and the ipc dir, removed when dropped.
+struct TestHome(PathBuf);
+
+impl Drop for TestHome {
+    fn drop(&mut self) {
+        allow_err!(std::fs::remove_dir_all(&self.0));
+        if let Some(ipc_dir) = Path::new(&Config::ipc_path("")).parent() {
+            std::fs::remove_dir_all(ipc_dir).ok();
+        }
+    }
+}
+
+// Must be called before the config is loaded, so that the config of the user is untouched.
+fn setup() -> TestHome {
+    let home = std::env::temp_dir().join(format!("rustdesk-e2e-{}", std::process::id()));
+    allow_err!(std::fs::create_dir_all(&home));
+    std::env::set_var("HOME", &home);
+    std::env::set_var("XDG_CONFIG_HOME", home.join(".config"));
+    std::env::set_var(test_display::ENV_TEST_DISPLAY, format!("{WIDTH}x{HEIGHT}"));
+    *APP_NAME.write().unwrap() = "RustDeskE2E".to_owned();
+    Config::set_permanent_password(PASSWORD);
+    Config::set_option(
+        "verification-method".to_owned(),
+        "use-permanent-password".to_owned(),
+    );
+    Config::set_option("approve-mode".to_owned(), "password".to_owned());
+    TestHome(home)
+}
+
+// The connection manager, which has nothing to approve in the password mode.
+async fn start_cm() -> ResultType<()> {
+    let mut incoming = crate::ipc::new_listener("_cm").await?;
+    tokio::spawn(async move {
+        while let Some(Ok(stream)) = incoming.next().await {
+            let mut stream = crate::ipc::Connection::new(stream);
+            tokio::spawn(async move { while let Ok(Some(_)) = stream.next().await {} });
+        }
+    });
+    Ok(())
+}
+
+async fn start_server() -> ResultType<String> {
+    let server = crate::server::new();
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        while let Ok((stream, peer_addr)) = listener.accept().await {
+            let server = server.clone();
+            tokio::spawn(async move {
+                allow_err!(
+                    crate::server::create_tcp_connection(
+                        server,
+                        Stream::from(stream, addr),
+                        peer_addr,
+                        false,
+                        false,
+                    )
+                    .await
+                );
+            });
+        }
+    });
+    Ok(addr.to_string())
+}
+
+async fn next_message(stream: &mut Stream) -> ResultType<Message> {
+    match stream.next_timeout(TIMEOUT.as_millis() as _).await {
+        Some(Ok(bytes)) => Ok(Message::parse_from_bytes(&bytes)?),
+        Some(Err(err)) => bail!("connection closed: {}", err),
+        None => bail!("no message in {:?}", TIMEOUT),
+    }
+}
+
+async fn connect(peer: &str, conn_type: ConnType) -> ResultType<(TestSession, Stream)> {
+    let session = TestSession::new(peer, conn_type);
+    let (mut stream, _, _) = Client::start(peer, "", "", conn_type, session.clone()).await?;
+    loop {
+        let msg = next_message(&mut stream).await?;
+        match msg.union {
+            Some(message::Union::Hash(hash)) => {
+                session.handle_hash(PASSWORD, hash, &mut stream).await;
+            }
+            Some(message::Union::TestDelay(t)) => {
+                session.handle_test_delay(t, &mut stream).await;
+            }
+            Some(message::Union::LoginResponse(lr)) => match lr.union {
+                Some(login_response::Union::PeerInfo(pi)) => {
+                    session.handle_peer_info(pi);
+                    return Ok((session, stream));
+                }
+                Some(login_response::Union::Error(err)) => bail!("login failed: {}", err),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+}
+
+fn clipboard_text(cb: &Clipboard) -> String {
+    let content = if cb.compress {
+        decompress(&cb.content)
+    } else {
+        cb.content.to_vec()
+    };
+    String::from_utf8_lossy(&content).to_string()
+}
+
+// Frames, audio, clipboard of both directions and input in a remote desktop session.
+async fn check_remote(peer: &str) -> ResultType<()> {
+    let (_session, mut stream) = connect(peer, ConnType::DEFAULT_CONN).await?;
+    let mut video: Option<VideoHandler> = None;
+    let mut audio: Option<magnum_opus::Decoder> = None;
+    let mut audio_buf = vec![0f32; 48000 * 2];
+    let mut frames = 0;
+    let mut audio_ok = false;
+    let mut input_sent = false;
+    let mut input = Vec::new();
+    let mut input_ok = false;
+    let mut host_clipboard_set = false;
+    let mut host_clipboard_ok = false;
+    let start = Instant::now();
+    while !(frames >= MIN_FRAMES && audio_ok && input_ok && host_clipboard_ok) {
+        if start.elapsed() > TIMEOUT {
+            bail!(
+                "timeout, frames: {frames}, audio: {audio_ok}, input: {input_ok}, host clipboard: {host_clipboard_ok}"
+            );
+        }
+        let msg = next_message(&mut stream).await?;
+        match msg.union {
+            Some(message::Union::VideoFrame(vf)) => {
+                let handler =
+                    video.get_or_insert_with(|| VideoHandler::new(CodecFormat::from(&vf), 0));
+                let mut pixelbuffer = true;
+                let mut chroma = None;
+                if let Ok(true) = handler.handle_frame(vf, &mut pixelbuffer, &mut chroma) {
+                    if pixelbuffer && (handler.rgb.w, handler.rgb.h) != (WIDTH, HEIGHT) {
+                        bail!(
+                            "wrong frame size {:?}",
+                            (handler.rgb.w, handler.rgb.h)
+                        );
+                    }
+                    frames += 1;
+                }
+            }
+            Some(message::Union::Misc(misc)) => {
+                if let Some(misc::Union::AudioFormat(f)) = misc.union {
+                    let channels = if f.channels > 1 {
+                        magnum_opus::Channels::Stereo
+                    } else {
+                        magnum_opus::Channels::Mono
+                    };
+                    audio = Some(magnum_opus::Decoder::new(f.sample_rate, channels)?);
+                }
+            }
+            Some(message::Union::AudioFrame(frame)) => {
+                // The null sink, the sine wave is checked instead of played.
+                if let Some(decoder) = audio.as_mut() {
+                    let n = decoder.decode_float(&frame.data, &mut audio_buf, false)?;
+                    audio_ok |= audio_buf[..n].iter().any(|x| x.abs() > 0.1);
+                }
+            }
+            Some(message::Union::Clipboard(cb)) => {
+                host_clipboard_ok |= host_clipboard_set && clipboard_text(&cb) == HOST_CLIPBOARD;
+            }
+            _ => {}
+        }
+        if frames >= MIN_FRAMES && !input_sent {
+            input_sent = true;
+            stream
+                .send(&crate::create_clipboard_msg(CLIENT_CLIPBOARD.to_owned()))
+                .await?;
+            let mut msg = Message::new();
+            msg.set_mouse_event(MouseEvent {
+                mask: 0,
+                x: 100,
+                y: 50,
+                ..Default::default()
+            });
+            stream.send(&msg).await?;
+            let mut msg = Message::new();
+            msg.set_key_event(KeyEvent {
+                down: true,
+                mode: KeyboardMode::Map.into(),
+                union: Some(key_event::Union::Chr(30)),
+                ..Default::default()
+            });
+            stream.send(&msg).await?;
+        }
+        if input_sent && !input_ok {
+            input.extend(test_display::take_input());
+            let mouse = input
+                .iter()
+                .any(|i| matches!(i, TestInput::Mouse(m) if (m.x, m.y) == (100, 50)));
+            let key = input
+                .iter()
+                .any(|i| matches!(i, TestInput::Key(k) if k.chr() == 30 && k.down));
+            input_ok = mouse && key;
+        }
+        if input_sent && !host_clipboard_set && test_display::get_clipboard() == CLIENT_CLIPBOARD
+        {
+            host_clipboard_set = true;
+            test_display::set_clipboard(HOST_CLIPBOARD.to_owned());
+        }
+    }
+    Ok(())
+}
+
+// Download a file from the host in a file transfer session.
+async fn check_file_transfer(peer: &str) -> ResultType<()> {
+    const ID: i32 = 1;
+    let dir = std::env::temp_dir().join(format!("rustdesk-e2e-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join("e2e.bin");
+    let content: Vec<u8> = (0..300_000u32).map(|i| (i * 7 % 251) as u8).collect();
+    std::fs::write(&path, &content)?;
+
+    let (_session, mut stream) = connect(peer, ConnType::FILE_TRANSFER).await?;
+    let mut fa = FileAction::new();
+    fa.set_send(FileTransferSendRequest {
+        id: ID,
+        path: path.to_string_lossy().to_string(),
+        ..Default::default()
+    });
+    let mut msg = Message::new();
+    msg.set_file_action(fa);
+    stream.send(&msg).await?;
+    let mut received = Vec::new();
+    loop {
+        let msg = next_message(&mut stream).await?;
+        let Some(message::Union::FileResponse(fr)) = msg.union else {
+            continue;
+        };
+        match fr.union {
+            Some(file_response::Union::Digest(d)) if d.id == ID => {
+                // Nothing to resume, receive the whole file.
+                let mut fa = FileAction::new();
+                fa.set_send_confirm(FileTransferSendConfirmRequest {
+                    id: ID,
+                    file_num: d.file_num,
+                    union: Some(file_transfer_send_confirm_request::Union::OffsetBlk(0)),
+                    ..Default::default()
+                });
+                let mut msg = Message::new();
+                msg.set_file_action(fa);
+                stream.send(&msg).await?;
+            }
+            Some(file_response::Union::Block(b)) if b.id == ID => {
+                if b.compressed {
+                    received.extend(decompress(&b.data));
+                } else {
+                    received.extend_from_slice(&b.data);
+                }
+            }
+            Some(file_response::Union::Error(e)) if e.id == ID => {
+                bail!("file transfer failed: {}", e.error)
+            }
+            Some(file_response::Union::Done(d)) if d.id == ID => break,
+            _ => {}
+        }
+    }
+    std::fs::remove_dir_all(&dir).ok();
+    if received != content {
+        bail!(
+            "wrong file content, {} bytes received, {} expected",
+            received.len(),
+            content.len()
+        );
+    }
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn e2e_session() {
+    let _home = setup();
+    start_cm().await.unwrap();
+    let peer = start_server().await.unwrap();
+    check_remote(&peer).await.unwrap();
+    check_file_transfer(&peer).await.unwrap();
+}
@@ -934,6 +934,19 @@ impl InvokeUiSession for FlutterHandler {
         );
     }
 
+    fn on_gamepad_rumble(&self, index: u32, strong: u32, weak: u32, duration: u32) {
+        self.push_event(
+            "gamepad_rumble",
+            &[
+                ("index", &index.to_string()),
+                ("strong", &strong.to_string()),
+                ("weak", &weak.to_string()),
+                ("duration", &duration.to_string()),
+            ],
+            &[],
+        );
+    }
+
     #[cfg(any(target_os = "android", target_os = "ios"))]
     fn clipboard(&self, content: String) {
         self.push_event("clipboard", &[("content", &content)], &[]);
@@ -1575,6 +1588,66 @@ fn session_send_touch_event(
     }
 }
 
+// v: [{"id": 0, "x": 100, "y": 100, "pressure": 0, "down": true}]
+#[inline]
+fn session_send_multi_touch(
+    session_id: SessionID,
+    v: &serde_json::Value,
+    alt: bool,
+    ctrl: bool,
+    shift: bool,
+    command: bool,
+) {
+    let Some(points) = v.as_array() else {
+        return;
+    };
+    let points = points
+        .iter()
+        .filter_map(|p| {
+            Some(TouchPoint {
+                id: p.get("id")?.as_i64()? as _,
+                x: p.get("x")?.as_i64()? as _,
+                y: p.get("y")?.as_i64()? as _,
+                pressure: p.get("pressure").and_then(|p| p.as_u64()).unwrap_or(0) as _,
+                down: p.get("down")?.as_bool()?,
+                ..Default::default()
+            })
+        })
+        .collect();
+    if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        session.send_multi_touch(points, alt, ctrl, shift, command);
+    }
+}
+
+// v: {"x": 100, "y": 100, "pressure": 500, "tilt_x": 0, "tilt_y": 0, "eraser": false, "barrel": false, "down": true, "hover": false}
+#[inline]
+fn session_send_pen(
+    session_id: SessionID,
+    v: &serde_json::Value,
+    alt: bool,
+    ctrl: bool,
+    shift: bool,
+    command: bool,
+) {
+    let int = |k: &str| v.get(k).and_then(|v| v.as_i64()).unwrap_or(0);
+    let flag = |k: &str| v.get(k).and_then(|v| v.as_bool()).unwrap_or(false);
+    let pen = PenEvent {
+        x: int("x") as _,
+        y: int("y") as _,
+        pressure: int("pressure").max(0) as _,
+        tilt_x: int("tilt_x") as _,
+        tilt_y: int("tilt_y") as _,
+        eraser: flag("eraser"),
+        barrel: flag("barrel"),
+        down: flag("down"),
+        hover: flag("hover"),
+        ..Default::default()
+    };
+    if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        session.send_pen(pen, alt, ctrl, shift, command);
+    }
+}
+
 pub fn session_send_pointer(session_id: SessionID, msg: String) {
     if let Ok(m) = serde_json::from_str::<HashMap<String, serde_json::Value>>(&msg) {
         let alt = m.get("alt").is_some();
@@ -1584,6 +1657,10 @@ pub fn session_send_pointer(session_id: SessionID, msg: String) {
         match (m.get("k"), m.get("v")) {
             (Some(k), Some(v)) => match k.as_str() {
                 Some("touch") => session_send_touch_event(session_id, v, alt, ctrl, shift, command),
+                Some("multi_touch") => {
+                    session_send_multi_touch(session_id, v, alt, ctrl, shift, command)
+                }
+                Some("pen") => session_send_pen(session_id, v, alt, ctrl, shift, command),
                 _ => {}
             },
             _ => {}
@@ -1591,6 +1668,39 @@ pub fn session_send_pointer(session_id: SessionID, msg: String) {
     }
 }
 
+// {"index": 0, "connected": "name"}, {"index": 0, "disconnected": true}
+// or {"index": 0, "buttons": 1, "axes": [0, 0, 0, 0, 0, 0]}
+pub fn session_send_gamepad(session_id: SessionID, msg: String) {
+    let Ok(v) = serde_json::from_str::<serde_json::Value>(&msg) else {
+        return;
+    };
+    let mut evt = GamepadEvent {
+        index: v.get("index").and_then(|i| i.as_u64()).unwrap_or(0) as _,
+        ..Default::default()
+    };
+    if let Some(name) = v.get("connected").and_then(|n| n.as_str()) {
+        evt.set_connected(GamepadConnected {
+            name: name.to_owned(),
+            ..Default::default()
+        });
+    } else if v.get("disconnected").is_some() {
+        evt.set_disconnected(true);
+    } else {
+        evt.set_state(GamepadState {
+            buttons: v.get("buttons").and_then(|b| b.as_u64()).unwrap_or(0) as _,
+            axes: v
+                .get("axes")
+                .and_then(|a| a.as_array())
+                .map(|a| a.iter().map(|x| x.as_i64().unwrap_or(0) as _).collect())
+                .unwrap_or_default(),
+            ..Default::default()
+        });
+    }
+    if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        session.send_gamepad_event(evt);
+    }
+}
+
 #[inline]
 pub fn session_on_waiting_for_image_dialog_show(session_id: SessionID) {
     for s in sessions::get_sessions() {
@@ -438,12 +438,93 @@ pub fn session_set_custom_fps(session_id: SessionID, fps: i32) {
     }
 }
 
+pub fn session_set_video_scale(session_id: SessionID, scale: i32, auto: bool) {
+    if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        session.set_video_scale(scale, auto);
+    }
+}
+
+pub fn session_set_audio_quality(
+    session_id: SessionID,
+    bitrate: i32,
+    channels: i32,
+    application: String,
+) {
+    if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        session.set_audio_quality(bitrate, channels, application);
+    }
+}
+
 pub fn session_lock_screen(session_id: SessionID) {
     if let Some(session) = sessions::get_session_by_session_id(&session_id) {
         session.lock_screen();
     }
 }
 
+pub fn session_type_text(session_id: SessionID, text: String) {
+    if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        session.type_text(text);
+    }
+}
+
+pub fn session_is_typing_text(session_id: SessionID) -> SyncReturn<bool> {
+    if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        SyncReturn(session.is_typing_text())
+    } else {
+        SyncReturn(false)
+    }
+}
+
+pub fn session_cancel_type_text(session_id: SessionID) {
+    if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        session.cancel_type_text();
+    }
+}
+
+pub fn session_is_macro_recording(session_id: SessionID) -> SyncReturn<bool> {
+    if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        SyncReturn(session.is_macro_recording())
+    } else {
+        SyncReturn(false)
+    }
+}
+
+pub fn session_is_macro_playing(session_id: SessionID) -> SyncReturn<bool> {
+    if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        SyncReturn(session.is_macro_playing())
+    } else {
+        SyncReturn(false)
+    }
+}
+
+pub fn session_start_macro_recording(session_id: SessionID) -> SyncReturn<bool> {
+    if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        SyncReturn(session.start_macro_recording())
+    } else {
+        SyncReturn(false)
+    }
+}
+
+pub fn session_stop_macro_recording(session_id: SessionID, path: String) -> bool {
+    if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        session.stop_macro_recording(path)
+    } else {
+        false
+    }
+}
+
+pub fn session_play_macro(session_id: SessionID, path: String, speed: f64) {
+    if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        session.play_macro(path, speed);
+    }
+}
+
+pub fn session_stop_macro(session_id: SessionID) {
+    if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        session.stop_macro();
+    }
+}
+
 pub fn session_ctrl_alt_del(session_id: SessionID) {
     if let Some(session) = sessions::get_session_by_session_id(&session_id) {
         session.ctrl_alt_del();
@@ -1368,6 +1449,13 @@ pub fn session_send_pointer(session_id: SessionID, msg: String) {
     super::flutter::session_send_pointer(session_id, msg);
 }
 
+// The bundled client only captures the game controllers on Linux, see `client::gamepad`,
+// this is for the clients reading them on the other platforms, e.g. with a plugin.
+// The rumble of the peer comes back as the "gamepad_rumble" event.
+pub fn session_send_gamepad(session_id: SessionID, msg: String) {
+    super::flutter::session_send_gamepad(session_id, msg);
+}
+
 pub fn session_send_mouse(session_id: SessionID, msg: String) {
     if let Ok(m) = serde_json::from_str::<HashMap<String, String>>(&msg) {
         let alt = m.get("alt").is_some();
@@ -1382,6 +1470,12 @@ pub fn session_send_mouse(session_id: SessionID, msg: String) {
             .get("y")
             .map(|x| x.parse::<i32>().unwrap_or(0))
             .unwrap_or(0);
+        if m.get("type").map(|t| t.as_str()) == Some("hi_res_wheel") {
+            if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+                session.send_hi_res_scroll(x, y, alt, ctrl, shift, command);
+            }
+            return;
+        }
         let mut mask = 0;
         if let Some(_type) = m.get("type") {
             mask = match _type.as_str() {
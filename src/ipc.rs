@@ -128,6 +128,52 @@ pub enum DataMouse {
     Refresh,
 }
 
+#[cfg(target_os = "linux")]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "t", content = "c")]
+pub enum DataTouch {
+    // ((minx, maxx), (miny, maxy))
+    Resolution((i32, i32), (i32, i32)),
+    // (id, x, y, pressure, down)
+    Frame(Vec<(i32, i32, i32, u32, bool)>),
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DataPenState {
+    pub x: i32,
+    pub y: i32,
+    pub pressure: u32,
+    pub tilt_x: i32,
+    pub tilt_y: i32,
+    pub eraser: bool,
+    pub barrel: bool,
+    pub down: bool,
+    pub hover: bool,
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "t", content = "c")]
+pub enum DataPen {
+    // ((minx, maxx), (miny, maxy))
+    Resolution((i32, i32), (i32, i32)),
+    State(DataPenState),
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "t", content = "c")]
+pub enum DataGamepad {
+    // index, name
+    Connected(u32, String),
+    Disconnected(u32),
+    // index, buttons, axes
+    State(u32, u32, Vec<i32>),
+    // index, strong, weak, duration in ms, from the service
+    Rumble(u32, u16, u16, u32),
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(tag = "t", content = "c")]
 pub enum DataControl {
@@ -171,6 +217,7 @@ pub enum Data {
         restart: bool,
         recording: bool,
         block_input: bool,
+        gamepad: bool,
         from_switch: bool,
     },
     ChatMessage {
@@ -209,6 +256,15 @@ pub enum Data {
     KeyboardResponse(DataKeyboardResponse),
     #[cfg(not(any(target_os = "android", target_os = "ios")))]
     Mouse(DataMouse),
+    #[cfg(target_os = "linux")]
+    Touch(DataTouch),
+    #[cfg(target_os = "linux")]
+    Pen(DataPen),
+    // the high resolution wheel deltas (x, y)
+    #[cfg(target_os = "linux")]
+    HiResScroll(i32, i32),
+    #[cfg(target_os = "linux")]
+    Gamepad(DataGamepad),
     Control(DataControl),
     Theme(String),
     Language(String),
@@ -231,6 +287,7 @@ pub enum Data {
     #[cfg(windows)]
     ControlledSessionCount(usize),
     CmErr(String),
+    SessionStats(Option<Vec<crate::session_stats::SessionStats>>),
 }
 
 #[tokio::main(flavor = "current_thread")]
@@ -491,6 +548,16 @@ async fn handle(data: Data, stream: &mut Connection) {
         #[cfg(all(feature = "flutter", feature = "plugin_framework"))]
         #[cfg(not(any(target_os = "android", target_os = "ios")))]
         Data::Plugin(plugin) => crate::plugin::ipc::handle_plugin(plugin, stream).await,
+        Data::SessionStats(None) => {
+            allow_err!(
+                stream
+                    .send(&Data::SessionStats(Some(crate::session_stats::all())))
+                    .await
+            );
+        }
+        Data::SessionStats(Some(stats)) => {
+            crate::session_stats::merge(stats);
+        }
         #[cfg(windows)]
         Data::ControlledSessionCount(_) => {
             allow_err!(
@@ -524,12 +591,37 @@ pub async fn start_pa() {
                         Ok(stream) => {
                             let mut stream = Connection::new(stream);
                             let mut device: String = "".to_owned();
-                            if let Some(Ok(Some(Data::Config((_, Some(x)))))) =
-                                stream.next_timeout2(1000).await
-                            {
-                                device = x;
+                            let mut application: String = "".to_owned();
+                            for _ in 0..2 {
+                                match stream.next_timeout2(1000).await {
+                                    Some(Ok(Some(Data::Config((name, Some(x)))))) => {
+                                        if name == "audio-application" {
+                                            application = x;
+                                        } else {
+                                            device = x;
+                                        }
+                                    }
+                                    _ => break,
+                                }
                             }
-                            if !device.is_empty() {
+                            let mut capture = None;
+                            if !application.is_empty() {
+                                // never fall back to the monitor, which would leak the other audio
+                                match crate::platform::linux::PaAppCapture::new(&application) {
+                                    Ok(c) => {
+                                        device = c.source();
+                                        capture = Some(c);
+                                    }
+                                    Err(err) => {
+                                        log::error!(
+                                            "Failed to capture audio of {}: {}",
+                                            application,
+                                            err
+                                        );
+                                        continue;
+                                    }
+                                }
+                            } else if !device.is_empty() {
                                 device = crate::platform::linux::get_pa_source_name(&device);
                             }
                             if device.is_empty() {
@@ -546,6 +638,7 @@ pub async fn start_pa() {
                             log::info!("pa monitor: {:?}", device);
                             // systemctl --user status pulseaudio.service
                             let mut buf: Vec<u8> = vec![0; AUDIO_DATA_SIZE_U8];
+                            let mut reads: u64 = 0;
                             match psimple::Simple::new(
                                 None,                             // Use the default server
                                 &crate::get_app_name(),           // Our application’s name
@@ -557,6 +650,13 @@ pub async fn start_pa() {
                                 None, // Use default buffering attributes
                             ) {
                                 Ok(s) => loop {
+                                    if let Some(capture) = capture.as_mut() {
+                                        // 10ms per read, look for new streams of the application every second
+                                        reads += 1;
+                                        if reads % 100 == 0 {
+                                            capture.refresh();
+                                        }
+                                    }
                                     if let Ok(_) = s.read(&mut buf) {
                                         let out =
                                             if buf.iter().filter(|x| **x != 0).next().is_none() {
@@ -586,5 +586,21 @@ pub static ref T: std::collections::HashMap<&'static str, &'static str> =
         ("2FA code must be 6 digits.", "双重认证代码必须是 6 位数字。"),
         ("Multiple Windows sessions found", "发现多个 Windows 会话"),
         ("Please select the session you want to connect to", "请选择您要连接的会话"),
+        ("Video scale", "视频缩放"),
+        ("Scale of the display size (%)", "相对于显示器尺寸的比例 (%)"),
+        ("Downscale more on high delay", "高延迟时进一步缩小"),
+        ("Enable gamepad", "启用游戏手柄"),
+        ("Type clipboard", "键入剪贴板内容"),
+        ("Audio quality", "音频质量"),
+        ("Bitrate (kbps), 0 for the default", "码率 (kbps)，0 为默认值"),
+        ("Capture the audio of this application only", "仅采集该应用的音频"),
+        ("Channels", "声道"),
+        ("Mono", "单声道"),
+        ("Stereo", "立体声"),
+        ("Record macro", "录制宏"),
+        ("Stop macro recording", "停止录制宏"),
+        ("Play macro", "播放宏"),
+        ("Stop macro", "停止播放宏"),
+        ("Cancel typing", "取消键入"),
     ].iter().cloned().collect();
 }
@@ -586,5 +586,21 @@ pub static ref T: std::collections::HashMap<&'static str, &'static str> =
         ("2FA code must be 6 digits.", ""),
         ("Multiple Windows sessions found", ""),
         ("Please select the session you want to connect to", ""),
+        ("Video scale", ""),
+        ("Scale of the display size (%)", ""),
+        ("Downscale more on high delay", ""),
+        ("Enable gamepad", ""),
+        ("Type clipboard", ""),
+        ("Audio quality", ""),
+        ("Bitrate (kbps), 0 for the default", ""),
+        ("Capture the audio of this application only", ""),
+        ("Channels", ""),
+        ("Mono", ""),
+        ("Stereo", ""),
+        ("Record macro", ""),
+        ("Stop macro recording", ""),
+        ("Play macro", ""),
+        ("Stop macro", ""),
+        ("Cancel typing", ""),
     ].iter().cloned().collect();
 }
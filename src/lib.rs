@@ -59,6 +59,17 @@ mod ui_session_interface;
 
 mod hbbs_http;
 
+pub mod session_stats;
+
+#[cfg(not(any(target_os = "ios")))]
+pub mod audit;
+
+#[cfg(not(any(target_os = "ios")))]
+pub mod clipboard_policy;
+
+#[cfg(all(test, target_os = "linux", feature = "test_display"))]
+mod e2e;
+
 #[cfg(any(target_os = "windows", target_os = "linux", target_os = "macos"))]
 pub mod clipboard_file;
 
@@ -200,6 +200,18 @@ fn start_uinput_service() {
     std::thread::spawn(|| {
         service::start_service_mouse();
     });
+    std::thread::spawn(|| {
+        service::start_service_touch();
+    });
+    std::thread::spawn(|| {
+        service::start_service_pen();
+    });
+    std::thread::spawn(|| {
+        service::start_service_gamepad();
+    });
+    std::thread::spawn(|| {
+        service::start_service_scroll();
+    });
 }
 
 #[inline]
@@ -655,6 +667,162 @@ pub fn get_default_pa_source() -> Option<(String, String)> {
     None
 }
 
+// the null sink the captured application plays to
+const PA_APP_SINK: &str = "rustdesk_app_capture";
+// the latency of the loopback to the default sink, too low underruns and crackles
+const PA_APP_LOOPBACK_LATENCY_MS: u32 = 30;
+
+/// Capture the audio of one application by moving its sink inputs to a null sink,
+/// a loopback to the default sink keeps it audible locally.
+/// The sink inputs are moved back and the modules unloaded on drop.
+pub struct PaAppCapture {
+    application: String,
+    modules: Vec<String>,
+    // sink input -> the sink it was moved from
+    moved: std::collections::HashMap<u32, u32>,
+}
+
+impl PaAppCapture {
+    pub fn new(application: &str) -> ResultType<Self> {
+        use pulsectl::controllers::*;
+        // left by a previous capture, e.g. if the process was killed
+        unload_pa_app_modules();
+        let default_sink = match SinkController::create() {
+            Ok(mut handler) => handler
+                .get_default_device()
+                .ok()
+                .and_then(|dev| dev.name)
+                .unwrap_or_default(),
+            Err(err) => bail!("Failed to create sink controller: {:?}", err),
+        };
+        let mut capture = Self {
+            application: application.to_lowercase(),
+            modules: Vec::new(),
+            moved: Default::default(),
+        };
+        capture.modules.push(load_pa_module(&[
+            "module-null-sink",
+            &format!("sink_name={}", PA_APP_SINK),
+            "sink_properties=device.description=RustDesk",
+        ])?);
+        if !default_sink.is_empty() && default_sink != PA_APP_SINK {
+            capture.modules.push(load_pa_module(&[
+                "module-loopback",
+                &format!("source={}", capture.source()),
+                &format!("sink={}", default_sink),
+                &format!("latency_msec={}", PA_APP_LOOPBACK_LATENCY_MS),
+            ])?);
+        }
+        capture.refresh();
+        Ok(capture)
+    }
+
+    pub fn source(&self) -> String {
+        format!("{}.monitor", PA_APP_SINK)
+    }
+
+    // move the application's new sink inputs to the null sink
+    pub fn refresh(&mut self) {
+        use pulsectl::controllers::*;
+        let mut handler = match SinkController::create() {
+            Ok(handler) => handler,
+            Err(err) => {
+                log::error!("Failed to create sink controller: {:?}", err);
+                return;
+            }
+        };
+        let Ok(sink) = handler.get_device_by_name(PA_APP_SINK) else {
+            return;
+        };
+        let Ok(apps) = handler.list_applications() else {
+            return;
+        };
+        for app in apps {
+            if app.sink == sink.index || !self.is_match(&app) {
+                continue;
+            }
+            match handler.move_app_by_index(app.index, sink.index) {
+                Ok(_) => {
+                    log::info!("Capture audio of sink input {}", app.index);
+                    self.moved.insert(app.index, app.sink);
+                }
+                Err(err) => {
+                    log::error!("Failed to move sink input {}: {:?}", app.index, err);
+                }
+            }
+        }
+    }
+
+    fn is_match(&self, app: &pulsectl::controllers::types::ApplicationInfo) -> bool {
+        ["application.name", "application.process.binary"]
+            .iter()
+            .filter_map(|key| app.proplist.get_str(key))
+            .any(|value| value.to_lowercase() == self.application)
+    }
+}
+
+impl Drop for PaAppCapture {
+    fn drop(&mut self) {
+        use pulsectl::controllers::*;
+        if let Ok(mut handler) = SinkController::create() {
+            for (app, sink) in self.moved.drain() {
+                // the sink input may have been closed already
+                handler.move_app_by_index(app, sink).ok();
+            }
+        }
+        for module in self.modules.drain(..).rev() {
+            Command::new("pactl")
+                .args(["unload-module", &module])
+                .status()
+                .ok();
+        }
+    }
+}
+
+// The null sink and the loopbacks from its monitor.
+fn unload_pa_app_modules() {
+    let Ok(output) = Command::new("pactl")
+        .args(["list", "short", "modules"])
+        .output()
+    else {
+        return;
+    };
+    let sink = format!("sink_name={}", PA_APP_SINK);
+    let source = format!("source={}.monitor", PA_APP_SINK);
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let mut fields = line.split('\t');
+        let (Some(module), Some(_name), Some(args)) = (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        if args
+            .split_whitespace()
+            .any(|arg| arg == sink || arg == source)
+        {
+            log::info!("Unload the stale pa module {}: {}", module, args);
+            Command::new("pactl")
+                .args(["unload-module", module])
+                .status()
+                .ok();
+        }
+    }
+}
+
+fn load_pa_module(args: &[&str]) -> ResultType<String> {
+    let output = Command::new("pactl")
+        .arg("load-module")
+        .args(args)
+        .output()?;
+    if !output.status.success() {
+        bail!(
+            "Failed to load {}: {}",
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
 pub fn lock_screen() {
     Command::new("xdg-screensaver").arg("lock").spawn().ok();
 }
@@ -1390,3 +1558,125 @@ pub fn is_selinux_enforcing() -> bool {
         },
     }
 }
+
+// Reads the targets of the X11 clipboard that arboard doesn't, with its own window.
+struct ClipboardReader {
+    conn: x11rb::rust_connection::RustConnection,
+    window: u32,
+    clipboard: u32,
+    property: u32,
+    incr: u32,
+}
+
+lazy_static::lazy_static! {
+    static ref CLIPBOARD_READER: std::sync::Mutex<Option<ClipboardReader>> = Default::default();
+}
+
+const CLIPBOARD_READ_TIMEOUT: Duration = Duration::from_millis(120);
+
+impl ClipboardReader {
+    fn new() -> ResultType<Self> {
+        use x11rb::{connection::Connection, protocol::xproto::*};
+        let (conn, screen) = x11rb::connect(None)?;
+        let root = conn.setup().roots[screen].root;
+        let window = conn.generate_id()?;
+        conn.create_window(
+            x11rb::COPY_DEPTH_FROM_PARENT,
+            window,
+            root,
+            0,
+            0,
+            1,
+            1,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            x11rb::COPY_FROM_PARENT,
+            &CreateWindowAux::new(),
+        )?
+        .check()?;
+        let atom = |name: &str| -> ResultType<u32> {
+            Ok(conn.intern_atom(false, name.as_bytes())?.reply()?.atom)
+        };
+        let clipboard = atom("CLIPBOARD")?;
+        let property = atom("RUSTDESK_CLIPBOARD")?;
+        let incr = atom("INCR")?;
+        Ok(Self {
+            conn,
+            window,
+            clipboard,
+            property,
+            incr,
+        })
+    }
+
+    // Empty if the target is not offered, the incremental transfers are not supported.
+    fn read(&self, target: &str) -> ResultType<Vec<u8>> {
+        use x11rb::{
+            connection::Connection,
+            protocol::{xproto::*, Event},
+        };
+        let target = self
+            .conn
+            .intern_atom(false, target.as_bytes())?
+            .reply()?
+            .atom;
+        self.conn.convert_selection(
+            self.window,
+            self.clipboard,
+            target,
+            self.property,
+            x11rb::CURRENT_TIME,
+        )?;
+        self.conn.flush()?;
+        let deadline = Instant::now() + CLIPBOARD_READ_TIMEOUT;
+        loop {
+            match self.conn.poll_for_event()? {
+                Some(Event::SelectionNotify(e)) if e.requestor == self.window => {
+                    if e.property == x11rb::NONE {
+                        return Ok(vec![]);
+                    }
+                    let reply = self
+                        .conn
+                        .get_property(true, self.window, self.property, AtomEnum::ANY, 0, u32::MAX)?
+                        .reply()?;
+                    if reply.type_ == self.incr {
+                        bail!("clipboard target is too large");
+                    }
+                    return Ok(reply.value);
+                }
+                Some(_) => {}
+                None => {
+                    if Instant::now() >= deadline {
+                        bail!("clipboard read timeout");
+                    }
+                    std::thread::sleep(Duration::from_millis(5));
+                }
+            }
+        }
+    }
+}
+
+fn get_clipboard_target(target: &str) -> ResultType<Vec<u8>> {
+    let mut lock = CLIPBOARD_READER.lock().unwrap();
+    if lock.is_none() {
+        *lock = Some(ClipboardReader::new()?);
+    }
+    let res = lock.as_ref().map_or(Ok(vec![]), |r| r.read(target));
+    if res.is_err() {
+        // reconnect on the next read, not to take a late reply as the next one
+        *lock = None;
+    }
+    res
+}
+
+/// The html of the X11 clipboard, empty if there is no html.
+pub fn get_clipboard_html() -> ResultType<String> {
+    Ok(String::from_utf8(get_clipboard_target(
+        crate::common::CLIPBOARD_MIME_HTML,
+    )?)?)
+}
+
+#[inline]
+pub fn get_clipboard_rtf() -> ResultType<Vec<u8>> {
+    get_clipboard_target(crate::common::CLIPBOARD_MIME_RTF)
+}
@@ -0,0 +1,282 @@
+// The clipboard changes are notified, instead of reading the clipboard every time.
+// On Wayland, by the data control protocol `zwlr_data_control`, if the compositor supports it, e.g. wlroots and KDE.
+// Otherwise by XFixes, the compositors sync the selections to XWayland, e.g. GNOME, or it falls back to polling.
+// `ext_data_control` is not supported yet, it is not in the wayland-protocols we use.
+
+use hbb_common::{libc, log, ResultType};
+use std::{os::unix::io::AsRawFd, time::Duration};
+use x11rb::{
+    connection::Connection,
+    protocol::{
+        xfixes::{self, ConnectionExt as _},
+        xproto::{Atom, AtomEnum, ConnectionExt as _},
+        Event,
+    },
+    rust_connection::RustConnection,
+};
+
+pub struct ClipboardWatcher {
+    wayland: Option<wlr::Watcher>,
+    conn: Option<(RustConnection, Atom)>,
+    clipboard: bool,
+    primary: bool,
+}
+
+impl ClipboardWatcher {
+    pub fn new() -> Self {
+        let wayland = if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            match wlr::Watcher::new() {
+                Ok(watcher) => Some(watcher),
+                Err(err) => {
+                    log::info!("Failed to watch the wayland clipboard, try XFixes: {}", err);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let conn = if wayland.is_none() {
+            match Self::connect() {
+                Ok(conn) => Some(conn),
+                Err(err) => {
+                    log::info!("Failed to watch the clipboard, poll it instead: {}", err);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        // check the selections at first
+        Self {
+            wayland,
+            conn,
+            clipboard: true,
+            primary: true,
+        }
+    }
+
+    #[inline]
+    fn watching(&self) -> bool {
+        self.wayland.is_some() || self.conn.is_some()
+    }
+
+    fn connect() -> ResultType<(RustConnection, Atom)> {
+        let (conn, screen) = x11rb::connect(None)?;
+        conn.xfixes_query_version(5, 0)?.reply()?;
+        let root = conn.setup().roots[screen].root;
+        let clipboard = conn.intern_atom(false, b"CLIPBOARD")?.reply()?.atom;
+        let mask = xfixes::SelectionEventMask::SET_SELECTION_OWNER
+            | xfixes::SelectionEventMask::SELECTION_WINDOW_DESTROY
+            | xfixes::SelectionEventMask::SELECTION_CLIENT_CLOSE;
+        for selection in [clipboard, AtomEnum::PRIMARY.into()] {
+            conn.xfixes_select_selection_input(root, selection, mask)?;
+        }
+        conn.flush()?;
+        Ok((conn, clipboard))
+    }
+
+    /// Wait until a selection owner changes or the timeout, sleep for the timeout if not watching.
+    pub fn wait(&mut self, timeout: Duration) {
+        if let Some(wayland) = self.wayland.as_mut() {
+            let pending = self.clipboard || self.primary;
+            match wayland.wait(if pending { Duration::ZERO } else { timeout }) {
+                Ok((clipboard, primary)) => {
+                    self.clipboard |= clipboard;
+                    self.primary |= primary;
+                }
+                Err(err) => {
+                    log::error!(
+                        "Failed to watch the wayland clipboard, poll it instead: {}",
+                        err
+                    );
+                    self.wayland = None;
+                    self.clipboard = true;
+                    self.primary = true;
+                }
+            }
+            return;
+        }
+        let Some((conn, _)) = &self.conn else {
+            std::thread::sleep(timeout);
+            return;
+        };
+        if !self.clipboard && !self.primary {
+            let mut fd = libc::pollfd {
+                fd: conn.stream().as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            unsafe {
+                libc::poll(&mut fd, 1, timeout.as_millis() as _);
+            }
+        }
+        if let Err(err) = self.read_events() {
+            log::error!("Failed to read clipboard events, poll it instead: {}", err);
+            self.conn = None;
+            self.clipboard = true;
+            self.primary = true;
+        }
+    }
+
+    fn read_events(&mut self) -> ResultType<()> {
+        let Some((conn, clipboard)) = &self.conn else {
+            return Ok(());
+        };
+        while let Some(event) = conn.poll_for_event()? {
+            if let Event::XfixesSelectionNotify(e) = event {
+                if e.selection == *clipboard {
+                    self.clipboard = true;
+                } else if e.selection == AtomEnum::PRIMARY.into() {
+                    self.primary = true;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// If the clipboard may have changed since the last call.
+    pub fn clipboard_changed(&mut self) -> bool {
+        let polling = !self.watching();
+        std::mem::replace(&mut self.clipboard, polling)
+    }
+
+    /// If the primary selection may have changed since the last call.
+    pub fn primary_changed(&mut self) -> bool {
+        let polling = !self.watching();
+        std::mem::replace(&mut self.primary, polling)
+    }
+}
+
+mod wlr {
+    use super::*;
+    use hbb_common::bail;
+    use wayland_client::{
+        delegate_noop, event_created_child,
+        globals::{registry_queue_init, GlobalListContents},
+        protocol::{
+            wl_registry::{self, WlRegistry},
+            wl_seat::WlSeat,
+        },
+        Connection, Dispatch, EventQueue, QueueHandle, WaylandError,
+    };
+    use wayland_protocols_wlr::data_control::v1::client::{
+        zwlr_data_control_device_v1::{self, ZwlrDataControlDeviceV1},
+        zwlr_data_control_manager_v1::ZwlrDataControlManagerV1,
+        zwlr_data_control_offer_v1::ZwlrDataControlOfferV1,
+    };
+
+    #[derive(Default)]
+    struct State {
+        clipboard: bool,
+        primary: bool,
+        finished: bool,
+    }
+
+    pub struct Watcher {
+        queue: EventQueue<State>,
+        state: State,
+        _device: ZwlrDataControlDeviceV1,
+    }
+
+    impl Watcher {
+        pub fn new() -> ResultType<Self> {
+            let conn = Connection::connect_to_env()?;
+            let (globals, mut queue) = registry_queue_init::<State>(&conn)?;
+            let qh = queue.handle();
+            // version 2 for the primary selection
+            let manager: ZwlrDataControlManagerV1 = globals.bind(&qh, 1..=2, ())?;
+            let seat: WlSeat = globals.bind(&qh, 1..=1, ())?;
+            let device = manager.get_data_device(&seat, &qh, ());
+            let mut state = State::default();
+            queue.roundtrip(&mut state)?;
+            Ok(Self {
+                queue,
+                state,
+                _device: device,
+            })
+        }
+
+        /// Wait for the events until the timeout, return if (the clipboard, the primary selection) changed.
+        pub fn wait(&mut self, timeout: Duration) -> ResultType<(bool, bool)> {
+            self.queue.dispatch_pending(&mut self.state)?;
+            if !self.state.clipboard && !self.state.primary {
+                self.queue.flush()?;
+                if let Some(guard) = self.queue.prepare_read() {
+                    let mut fd = libc::pollfd {
+                        fd: guard.connection_fd().as_raw_fd(),
+                        events: libc::POLLIN,
+                        revents: 0,
+                    };
+                    unsafe {
+                        libc::poll(&mut fd, 1, timeout.as_millis() as _);
+                    }
+                    if fd.revents & libc::POLLIN != 0 {
+                        match guard.read() {
+                            Ok(_) => {}
+                            Err(WaylandError::Io(e))
+                                if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                            Err(e) => return Err(e.into()),
+                        }
+                    }
+                }
+                self.queue.dispatch_pending(&mut self.state)?;
+            }
+            if self.state.finished {
+                bail!("the data control device is finished");
+            }
+            Ok((
+                std::mem::take(&mut self.state.clipboard),
+                std::mem::take(&mut self.state.primary),
+            ))
+        }
+    }
+
+    impl Dispatch<WlRegistry, GlobalListContents> for State {
+        fn event(
+            _: &mut Self,
+            _: &WlRegistry,
+            _: wl_registry::Event,
+            _: &GlobalListContents,
+            _: &Connection,
+            _: &QueueHandle<Self>,
+        ) {
+        }
+    }
+
+    delegate_noop!(State: ignore WlSeat);
+    delegate_noop!(State: ZwlrDataControlManagerV1);
+    delegate_noop!(State: ignore ZwlrDataControlOfferV1);
+
+    impl Dispatch<ZwlrDataControlDeviceV1, ()> for State {
+        fn event(
+            state: &mut Self,
+            _: &ZwlrDataControlDeviceV1,
+            event: zwlr_data_control_device_v1::Event,
+            _: &(),
+            _: &Connection,
+            _: &QueueHandle<Self>,
+        ) {
+            // The offers are not read here, the content is read by the clipboard context.
+            match event {
+                zwlr_data_control_device_v1::Event::Selection { id } => {
+                    if let Some(offer) = id {
+                        offer.destroy();
+                    }
+                    state.clipboard = true;
+                }
+                zwlr_data_control_device_v1::Event::PrimarySelection { id } => {
+                    if let Some(offer) = id {
+                        offer.destroy();
+                    }
+                    state.primary = true;
+                }
+                zwlr_data_control_device_v1::Event::Finished => state.finished = true,
+                _ => {}
+            }
+        }
+
+        event_created_child!(State, ZwlrDataControlDeviceV1, [
+            zwlr_data_control_device_v1::EVT_DATA_OFFER_OPCODE => (ZwlrDataControlOfferV1, ()),
+        ]);
+    }
+}
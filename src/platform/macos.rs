@@ -752,3 +752,54 @@ impl WakeLock {
             .ok_or(anyhow!("no AwakeHandle"))?
     }
 }
+
+fn get_pasteboard_data(uti: &str) -> Vec<u8> {
+    unsafe {
+        let pasteboard: id = msg_send![class!(NSPasteboard), generalPasteboard];
+        let data: id = msg_send![pasteboard, dataForType: NSString::alloc(nil).init_str(uti)];
+        if data == nil {
+            return vec![];
+        }
+        let len: usize = msg_send![data, length];
+        let bytes: *const u8 = msg_send![data, bytes];
+        if bytes.is_null() || len == 0 {
+            return vec![];
+        }
+        std::slice::from_raw_parts(bytes, len).to_vec()
+    }
+}
+
+/// Changed whenever the content of the pasteboard changes.
+pub fn get_clipboard_sequence() -> u64 {
+    unsafe {
+        let pasteboard: id = msg_send![class!(NSPasteboard), generalPasteboard];
+        let count: cocoa::foundation::NSInteger = msg_send![pasteboard, changeCount];
+        count as _
+    }
+}
+
+/// The html of the pasteboard, empty if there is no html.
+pub fn get_clipboard_html() -> ResultType<String> {
+    Ok(String::from_utf8(get_pasteboard_data("public.html"))?)
+}
+
+#[inline]
+pub fn get_clipboard_rtf() -> ResultType<Vec<u8>> {
+    Ok(get_pasteboard_data("public.rtf"))
+}
+
+/// Add rtf to the pasteboard already set, e.g. with the plain text.
+pub fn set_clipboard_rtf(rtf: &[u8]) -> ResultType<()> {
+    unsafe {
+        let pasteboard: id = msg_send![class!(NSPasteboard), generalPasteboard];
+        let uti = NSString::alloc(nil).init_str("public.rtf");
+        let types: id = msg_send![class!(NSArray), arrayWithObject: uti];
+        let _: isize = msg_send![pasteboard, addTypes: types owner: nil];
+        let data: id = msg_send![class!(NSData), dataWithBytes: rtf.as_ptr() as *const c_void length: rtf.len()];
+        let ok: BOOL = msg_send![pasteboard, setData: data forType: uti];
+        if ok == NO {
+            bail!("Failed to set rtf to the pasteboard");
+        }
+    }
+    Ok(())
+}
@@ -2247,6 +2247,26 @@ pub fn get_unicode_from_vk(vk: u32) -> Option<u16> {
     }
 }
 
+/// The virtual key of the char in the layout of the foreground window, and if shift is needed.
+/// None if the char is not in the layout or needs other modifiers, e.g. AltGr.
+pub fn get_vk_from_char(chr: char) -> Option<(u32, bool)> {
+    let chr = u16::try_from(chr as u32).ok()?;
+    let res = unsafe {
+        let current_window_thread_id = GetWindowThreadProcessId(GetForegroundWindow(), null_mut());
+        let layout = GetKeyboardLayout(current_window_thread_id);
+        VkKeyScanExW(chr, layout)
+    };
+    if res == -1 {
+        return None;
+    }
+    let vk = (res & 0xFF) as u32;
+    match (res >> 8) & 0xFF {
+        0 => Some((vk, false)),
+        1 => Some((vk, true)),
+        _ => None,
+    }
+}
+
 pub fn is_process_consent_running() -> ResultType<bool> {
     let output = std::process::Command::new("cmd")
         .args(&["/C", "tasklist | findstr consent.exe"])
@@ -2615,3 +2635,134 @@ impl Drop for WallPaperRemover {
         allow_err!(Self::set_wallpaper(Some(self.old_path.clone())));
     }
 }
+
+// arboard doesn't support html reading and rtf, the clipboard is opened here for them.
+struct OpenedClipboard;
+
+impl OpenedClipboard {
+    fn new() -> ResultType<Self> {
+        for _ in 0..5 {
+            if unsafe { OpenClipboard(null_mut()) } != FALSE {
+                return Ok(Self);
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        bail!("Failed to open clipboard: {}", io::Error::last_os_error());
+    }
+}
+
+impl Drop for OpenedClipboard {
+    fn drop(&mut self) {
+        unsafe {
+            CloseClipboard();
+        }
+    }
+}
+
+fn register_clipboard_format(name: &str) -> ResultType<UINT> {
+    let format = unsafe { RegisterClipboardFormatW(wide_string(name).as_ptr()) };
+    if format == 0 {
+        bail!(
+            "Failed to register clipboard format {}: {}",
+            name,
+            io::Error::last_os_error()
+        );
+    }
+    Ok(format)
+}
+
+fn get_clipboard_data(name: &str) -> ResultType<Vec<u8>> {
+    let format = register_clipboard_format(name)?;
+    let _clipboard = OpenedClipboard::new()?;
+    unsafe {
+        if IsClipboardFormatAvailable(format) == FALSE {
+            return Ok(vec![]);
+        }
+        let handle = GetClipboardData(format);
+        if handle.is_null() {
+            bail!(
+                "Failed to get clipboard data: {}",
+                io::Error::last_os_error()
+            );
+        }
+        let ptr = GlobalLock(handle) as *const u8;
+        if ptr.is_null() {
+            bail!(
+                "Failed to lock clipboard data: {}",
+                io::Error::last_os_error()
+            );
+        }
+        let data = std::slice::from_raw_parts(ptr, GlobalSize(handle)).to_vec();
+        GlobalUnlock(handle);
+        Ok(data)
+    }
+}
+
+/// Changed whenever the content of the clipboard changes.
+#[inline]
+pub fn get_clipboard_sequence() -> u64 {
+    unsafe { GetClipboardSequenceNumber() as _ }
+}
+
+/// The html fragment of the clipboard, empty if there is no html.
+pub fn get_clipboard_html() -> ResultType<String> {
+    // https://learn.microsoft.com/en-us/windows/win32/dataxchg/html-clipboard-format
+    let data = get_clipboard_data("HTML Format")?;
+    let header = String::from_utf8_lossy(&data);
+    let offset = |key: &str| {
+        header
+            .lines()
+            .find_map(|l| l.strip_prefix(key))
+            .and_then(|v| v.trim().parse::<usize>().ok())
+    };
+    match (offset("StartFragment:"), offset("EndFragment:")) {
+        (Some(start), Some(end)) if start <= end && end <= data.len() => {
+            Ok(String::from_utf8(data[start..end].to_vec())?)
+        }
+        _ => Ok("".to_owned()),
+    }
+}
+
+pub fn get_clipboard_rtf() -> ResultType<Vec<u8>> {
+    let mut data = get_clipboard_data("Rich Text Format")?;
+    // the global memory may be larger than the null terminated rtf
+    if let Some(pos) = data.iter().position(|&c| c == 0) {
+        data.truncate(pos);
+    }
+    Ok(data)
+}
+
+/// Add rtf to the clipboard already set, e.g. with the plain text.
+pub fn set_clipboard_rtf(rtf: &[u8]) -> ResultType<()> {
+    let format = register_clipboard_format("Rich Text Format")?;
+    let _clipboard = OpenedClipboard::new()?;
+    unsafe {
+        let handle = GlobalAlloc(GMEM_MOVEABLE, rtf.len() + 1);
+        if handle.is_null() {
+            bail!(
+                "Failed to alloc clipboard data: {}",
+                io::Error::last_os_error()
+            );
+        }
+        let ptr = GlobalLock(handle) as *mut u8;
+        if ptr.is_null() {
+            GlobalFree(handle);
+            bail!(
+                "Failed to lock clipboard data: {}",
+                io::Error::last_os_error()
+            );
+        }
+        std::ptr::copy_nonoverlapping(rtf.as_ptr(), ptr, rtf.len());
+        *ptr.add(rtf.len()) = 0;
+        GlobalUnlock(handle);
+        // the memory is owned by the system once set
+        if SetClipboardData(format, handle).is_null() {
+            GlobalFree(handle);
+            bail!(
+                "Failed to set clipboard data: {}",
+                io::Error::last_os_error()
+            );
+        }
+    }
+    Ok(())
+}
@@ -12,7 +12,7 @@ use uuid::Uuid;
 use hbb_common::{
     allow_err,
     anyhow::{self, bail},
-    config::{Config, CONNECT_TIMEOUT, READ_TIMEOUT, REG_INTERVAL, RENDEZVOUS_PORT},
+    config::{Config, CONNECT_TIMEOUT, READ_TIMEOUT, REG_INTERVAL, RELAY_PORT, RENDEZVOUS_PORT},
     futures::future::join_all,
     log,
     protobuf::Message as _,
@@ -38,6 +38,8 @@ type Message = RendezvousMessage;
 
 const TIMER_OUT: Duration = Duration::from_secs(1);
 const DEFAULT_KEEP_ALIVE: i32 = 60_000;
+// The relay server drops the unpaired connection after 30 seconds.
+const FIXED_RELAY_WAIT_TIMEOUT: Duration = Duration::from_secs(25);
 
 lazy_static::lazy_static! {
     static ref SOLVING_PK_MISMATCH: Arc<Mutex<String>> = Default::default();
@@ -76,6 +78,9 @@ impl RendezvousMediator {
         tokio::spawn(async move {
             direct_server(server_cloned).await;
         });
+        tokio::spawn(async move {
+            crate::server::metrics::start().await;
+        });
         #[cfg(not(any(target_os = "android", target_os = "ios")))]
         if crate::platform::is_installed() {
             std::thread::spawn(move || {
@@ -96,21 +101,30 @@ impl RendezvousMediator {
                     crate::test_nat_type();
                     nat_tested = true;
                 }
-                let mut futs = Vec::new();
                 let servers = Config::get_rendezvous_servers();
                 SHOULD_EXIT.store(false, Ordering::SeqCst);
                 MANUAL_RESTARTED.store(false, Ordering::SeqCst);
-                for host in servers.clone() {
-                    let server = server.clone();
-                    futs.push(tokio::spawn(async move {
-                        if let Err(err) = Self::start(server, host).await {
-                            log::error!("rendezvous mediator error: {err}");
-                        }
-                        // SHOULD_EXIT here is to ensure once one exits, the others also exit.
-                        SHOULD_EXIT.store(true, Ordering::SeqCst);
-                    }));
+                if let Some((relay_server, token)) = crate::common::get_fixed_relay() {
+                    // No registration to the rendezvous servers in the fixed relay mode.
+                    if let Err(err) =
+                        Self::start_fixed_relay(server.clone(), relay_server, token).await
+                    {
+                        log::error!("fixed relay error: {err}");
+                    }
+                } else {
+                    let mut futs = Vec::new();
+                    for host in servers.clone() {
+                        let server = server.clone();
+                        futs.push(tokio::spawn(async move {
+                            if let Err(err) = Self::start(server, host).await {
+                                log::error!("rendezvous mediator error: {err}");
+                            }
+                            // SHOULD_EXIT here is to ensure once one exits, the others also exit.
+                            SHOULD_EXIT.store(true, Ordering::SeqCst);
+                        }));
+                    }
+                    join_all(futs).await;
                 }
-                join_all(futs).await;
             } else {
                 server.write().unwrap().close_connections();
             }
@@ -389,6 +403,75 @@ impl RendezvousMediator {
         }
     }
 
+    /// Wait for incoming connections at the fixed relay server, without any rendezvous server.
+    ///
+    /// One standing connection is kept at the relay server, identified by the uuid derived from
+    /// the pairing token and our id. Once the controlling side joins it and sends the first frame,
+    /// it is handed over to the server, and a new standing connection is created.
+    async fn start_fixed_relay(
+        server: ServerPtr,
+        relay_server: String,
+        token: String,
+    ) -> ResultType<()> {
+        log::info!("start fixed relay of {}", relay_server);
+        let relay_server = check_port(&relay_server, RELAY_PORT);
+        let (pk, sk) = crate::common::get_fixed_relay_key_pair(&token);
+        let key = crate::get_key(true).await;
+        let mut timer = crate::rustdesk_interval(interval(TIMER_OUT));
+        while !SHOULD_EXIT.load(Ordering::SeqCst) {
+            let mut stream = connect_tcp(relay_server.clone(), CONNECT_TIMEOUT).await?;
+            let mut msg_out = Message::new();
+            msg_out.set_request_relay(RequestRelay {
+                licence_key: key.clone(),
+                uuid: crate::common::get_fixed_relay_uuid(&token, &Config::get_id()),
+                ..Default::default()
+            });
+            stream.send(&msg_out).await?;
+            let wait_start = Instant::now();
+            let paired = loop {
+                select! {
+                    res = stream.next() => {
+                        match res {
+                            Some(Ok(_)) => break true,
+                            Some(Err(err)) => bail!("Failed to receive from fixed relay: {}", err),
+                            None => {
+                                // closed by the relay server, e.g. on licence key mismatch
+                                sleep(1.).await;
+                                break false;
+                            }
+                        }
+                    }
+                    _ = timer.tick() => {
+                        if SHOULD_EXIT.load(Ordering::SeqCst)
+                            || wait_start.elapsed() >= FIXED_RELAY_WAIT_TIMEOUT
+                        {
+                            break false;
+                        }
+                    }
+                }
+            };
+            if paired {
+                log::info!("fixed relay paired on {}", relay_server);
+                let server = server.clone();
+                let (sk, pk) = (sk.0.to_vec(), pk.0.to_vec());
+                tokio::spawn(async move {
+                    allow_err!(
+                        crate::server::create_tcp_connection_with_key_pair(
+                            server,
+                            stream,
+                            Config::get_any_listen_addr(true),
+                            true,
+                            sk,
+                            pk,
+                        )
+                        .await
+                    );
+                });
+            }
+        }
+        Ok(())
+    }
+
     async fn handle_request_relay(&self, rr: RequestRelay, server: ServerPtr) -> ResultType<()> {
         self.create_relay(
             rr.socket_addr.into(),
@@ -661,6 +744,7 @@ async fn direct_server(server: ServerPtr) {
                             hbb_common::Stream::from(stream, local_addr),
                             addr,
                             false,
+                            false,
                         )
                         .await
                     );
@@ -42,6 +42,8 @@ pub mod uinput;
 pub mod rdp_input;
 #[cfg(target_os = "linux")]
 pub mod dbus;
+#[cfg(all(target_os = "linux", feature = "test_display"))]
+pub mod test_display;
 pub mod input_service;
 } else {
 mod clipboard_service {
@@ -56,12 +58,26 @@ pub const NAME_POS: &'static str = "";
 
 mod connection;
 pub mod display_service;
+pub mod metrics;
 #[cfg(windows)]
 pub mod portable_service;
 mod service;
+pub mod shared_area;
 mod video_qos;
 pub mod video_service;
 
+/// Whether the virtual display of the end-to-end tests is used instead of the real devices.
+/// Always false without the feature `test_display`.
+#[cfg(target_os = "linux")]
+#[inline]
+pub fn is_test_display() -> bool {
+    #[cfg(feature = "test_display")]
+    if test_display::is_enabled() {
+        return true;
+    }
+    false
+}
+
 pub type Childs = Arc<Mutex<Vec<std::process::Child>>>;
 type ConnMap = HashMap<i32, ConnInner>;
 
@@ -121,7 +137,14 @@ async fn accept_connection_(server: ServerPtr, socket: Stream, secure: bool) ->
     if let Ok((stream, addr)) = timeout(CONNECT_TIMEOUT, listener.accept()).await? {
         stream.set_nodelay(true).ok();
         let stream_addr = stream.local_addr()?;
-        create_tcp_connection(server, Stream::from(stream, stream_addr), addr, secure).await?;
+        create_tcp_connection(
+            server,
+            Stream::from(stream, stream_addr),
+            addr,
+            secure,
+            false,
+        )
+        .await?;
     }
     Ok(())
 }
@@ -131,11 +154,30 @@ pub async fn create_tcp_connection(
     stream: Stream,
     addr: SocketAddr,
     secure: bool,
+    relay: bool,
+) -> ResultType<()> {
+    let (sk, pk) = if secure {
+        Config::get_key_pair()
+    } else {
+        Default::default()
+    };
+    create_tcp_connection_with_key_pair(server, stream, addr, relay, sk, pk).await
+}
+
+/// Same as [`create_tcp_connection`], but signs our id with the given key pair
+/// instead of the one registered to the rendezvous server.
+/// An empty key pair means a non-secure connection.
+pub async fn create_tcp_connection_with_key_pair(
+    server: ServerPtr,
+    stream: Stream,
+    addr: SocketAddr,
+    relay: bool,
+    sk: Vec<u8>,
+    pk: Vec<u8>,
 ) -> ResultType<()> {
     let mut stream = stream;
     let id = server.write().unwrap().get_new_id();
-    let (sk, pk) = Config::get_key_pair();
-    if secure && pk.len() == sign::PUBLICKEYBYTES && sk.len() == sign::SECRETKEYBYTES {
+    if pk.len() == sign::PUBLICKEYBYTES && sk.len() == sign::SECRETKEYBYTES {
         let mut sk_ = [0u8; sign::SECRETKEYBYTES];
         sk_[..].copy_from_slice(&sk);
         let sk = sign::SecretKey(sk_);
@@ -196,7 +238,7 @@ pub async fn create_tcp_connection(
             .ok();
         log::info!("wake up macos");
     }
-    Connection::start(addr, stream, id, Arc::downgrade(&server)).await;
+    Connection::start(addr, stream, id, Arc::downgrade(&server), relay).await;
     Ok(())
 }
 
@@ -252,7 +294,7 @@ async fn create_relay_connection_(
         ..Default::default()
     });
     stream.send(&msg_out).await?;
-    create_tcp_connection(server, stream, peer_addr, secure).await?;
+    create_tcp_connection(server, stream, peer_addr, secure, true).await?;
     Ok(())
 }
 
@@ -21,6 +21,14 @@ use std::sync::atomic::{AtomicBool, Ordering};
 pub const NAME: &'static str = "audio";
 pub const AUDIO_DATA_SIZE_U8: usize = 960 * 4; // 10ms in 48000 stereo
 static RESTARTING: AtomicBool = AtomicBool::new(false);
+// kbps, the range of opus
+const MIN_BITRATE: u32 = 6;
+const MAX_BITRATE: u32 = 510;
+
+lazy_static::lazy_static! {
+    // the audio quality set by the connections, merged as they share the audio service
+    static ref USER_QUALITY: Mutex<HashMap<i32, AudioQuality>> = Default::default();
+}
 
 #[cfg(not(any(target_os = "linux", target_os = "android")))]
 pub fn new() -> GenericService {
@@ -32,6 +40,11 @@ pub fn new() -> GenericService {
 #[cfg(any(target_os = "linux", target_os = "android"))]
 pub fn new() -> GenericService {
     let svc = EmptyExtraFieldService::new(NAME.to_owned(), true);
+    #[cfg(all(target_os = "linux", feature = "test_display"))]
+    if super::test_display::is_enabled() {
+        GenericService::run(&svc.clone(), test_impl::run);
+        return svc.sp;
+    }
     GenericService::run(&svc.clone(), pa_impl::run);
     svc.sp
 }
@@ -44,6 +57,84 @@ pub fn restart() {
     RESTARTING.store(true, Ordering::SeqCst);
 }
 
+/// Set the audio quality of a connection, the service is restarted if the quality in use changes.
+pub fn user_audio_quality(id: i32, quality: AudioQuality) {
+    let mut lock = USER_QUALITY.lock().unwrap();
+    let old = merge_quality(lock.values());
+    lock.insert(id, quality);
+    if old != merge_quality(lock.values()) {
+        restart();
+    }
+}
+
+pub fn on_connection_close(id: i32) {
+    let mut lock = USER_QUALITY.lock().unwrap();
+    let old = merge_quality(lock.values());
+    lock.remove(&id);
+    if old != merge_quality(lock.values()) {
+        restart();
+    }
+}
+
+fn get_quality() -> AudioQuality {
+    merge_quality(USER_QUALITY.lock().unwrap().values())
+}
+
+// The highest bitrate and channels asked by the connections,
+// and the application only if all of them ask for the same one, otherwise all the audio.
+fn merge_quality<'a>(qualities: impl Iterator<Item = &'a AudioQuality>) -> AudioQuality {
+    // mono < the channels of the device < stereo
+    let channels_rank = |c: u32| match c {
+        1 => 0,
+        2 => 2,
+        _ => 1,
+    };
+    let mut merged: Option<AudioQuality> = None;
+    for q in qualities {
+        let Some(m) = merged.as_mut() else {
+            merged = Some(q.clone());
+            continue;
+        };
+        m.bitrate = m.bitrate.max(q.bitrate);
+        if channels_rank(q.channels) > channels_rank(m.channels) {
+            m.channels = q.channels;
+        }
+        if m.application != q.application {
+            m.application.clear();
+        }
+    }
+    merged.unwrap_or_default()
+}
+
+// the channels to encode, the channels of the device if not set
+fn encode_channels(quality: &AudioQuality, device_channels: u16) -> magnum_opus::Channels {
+    match quality.channels {
+        1 => Mono,
+        2 => Stereo,
+        _ if device_channels > 1 => Stereo,
+        _ => Mono,
+    }
+}
+
+fn new_encoder(
+    sample_rate: u32,
+    channels: magnum_opus::Channels,
+    quality: &AudioQuality,
+) -> ResultType<Encoder> {
+    let mut encoder = Encoder::new(sample_rate, channels, LowDelay)?;
+    if quality.bitrate > 0 {
+        let bitrate = quality.bitrate.clamp(MIN_BITRATE, MAX_BITRATE) * 1000;
+        encoder.set_bitrate(magnum_opus::Bitrate::Bits(bitrate as _))?;
+    }
+    log::info!(
+        "Audio encoder: {} Hz, {} channels, {} kbps",
+        sample_rate,
+        channels as u16,
+        quality.bitrate
+    );
+    Ok(encoder)
+}
+
 #[cfg(any(target_os = "linux", target_os = "android"))]
 mod pa_impl {
     use super::*;
@@ -56,27 +147,50 @@ mod pa_impl {
         unsafe {
             AUDIO_ZERO_COUNT = 0;
         }
-        let mut encoder = Encoder::new(crate::platform::PA_SAMPLE_RATE, Stereo, LowDelay)?;
+        let sample_rate = crate::platform::PA_SAMPLE_RATE;
+        let quality = get_quality();
+        let channels = encode_channels(&quality, 2);
+        let mut encoder = new_encoder(sample_rate, channels, &quality)?;
         #[cfg(target_os = "linux")]
-        allow_err!(
-            stream
-                .send(&crate::ipc::Data::Config((
-                    "audio-input".to_owned(),
-                    Some(Config::get_option("audio-input"))
-                )))
-                .await
-        );
+        {
+            allow_err!(
+                stream
+                    .send(&crate::ipc::Data::Config((
+                        "audio-input".to_owned(),
+                        Some(Config::get_option("audio-input"))
+                    )))
+                    .await
+            );
+            allow_err!(
+                stream
+                    .send(&crate::ipc::Data::Config((
+                        "audio-application".to_owned(),
+                        Some(quality.application.clone())
+                    )))
+                    .await
+            );
+        }
+        // the format may be changed by the restart
+        let format_msg = create_format_msg(sample_rate, channels as _);
+        super::super::video_service::record_audio_format(&format_msg);
+        sp.send(format_msg);
         #[cfg(target_os = "linux")]
         let zero_audio_frame: Vec<f32> = vec![0.; AUDIO_DATA_SIZE_U8 / 4];
         while sp.ok() && !RESTARTING.load(Ordering::SeqCst) {
             sp.snapshot(|sps| {
-                sps.send(create_format_msg(crate::platform::PA_SAMPLE_RATE, 2));
+                sps.send(create_format_msg(sample_rate, channels as _));
                 Ok(())
             })?;
             #[cfg(target_os = "linux")]
-            if let Ok(data) = stream.next_raw().await {
+            {
+                // An application not playing is captured as silence. If its capture fails, i.e. pactl
+                // or loading the modules fails, the _pa ipc sends nothing until the config is changed.
+                let data = match stream.next_raw().await {
+                    Ok(data) => data,
+                    Err(err) => bail!("Failed to read the audio of _pa ipc: {}", err),
+                };
                 if data.len() == 0 {
-                    send_f32(&zero_audio_frame, &mut encoder, &sp);
+                    send_stereo_f32(&zero_audio_frame, channels, &mut encoder, &sp);
                     continue;
                 }
                 if data.len() != AUDIO_DATA_SIZE_U8 {
@@ -85,20 +199,68 @@ mod pa_impl {
                 let data = unsafe {
                     std::slice::from_raw_parts::<f32>(data.as_ptr() as _, data.len() / 4)
                 };
-                send_f32(data, &mut encoder, &sp);
+                send_stereo_f32(data, channels, &mut encoder, &sp);
             }
             #[cfg(target_os = "android")]
             if let Some(data) = scrap::android::ffi::get_audio_raw() {
                 let data = unsafe {
                     std::slice::from_raw_parts::<f32>(data.as_ptr() as _, data.len() / 4)
                 };
-                send_f32(data, &mut encoder, &sp);
+                send_stereo_f32(data, channels, &mut encoder, &sp);
             } else {
                 hbb_common::sleep(0.1).await;
             }
         }
         Ok(())
     }
+
+    // the captured audio is stereo
+    fn send_stereo_f32(
+        data: &[f32],
+        channels: magnum_opus::Channels,
+        encoder: &mut Encoder,
+        sp: &GenericService,
+    ) {
+        if matches!(channels, Mono) {
+            let rate = crate::platform::PA_SAMPLE_RATE;
+            let data = crate::common::audio_rechannel(data.to_vec(), rate, rate, 2, 1);
+            send_f32(&data, encoder, sp);
+        } else {
+            send_f32(data, encoder, sp);
+        }
+    }
+}
+
+// The audio of the virtual test display, no device is needed.
+#[cfg(all(target_os = "linux", feature = "test_display"))]
+mod test_impl {
+    use super::*;
+
+    pub fn run(sp: EmptyExtraFieldService) -> ResultType<()> {
+        RESTARTING.store(false, Ordering::SeqCst);
+        unsafe {
+            AUDIO_ZERO_COUNT = 0;
+        }
+        let sample_rate = crate::platform::PA_SAMPLE_RATE;
+        let quality = get_quality();
+        let channels = encode_channels(&quality, 2);
+        let mut encoder = new_encoder(sample_rate, channels, &quality)?;
+        let format_msg = create_format_msg(sample_rate, channels as _);
+        super::super::video_service::record_audio_format(&format_msg);
+        sp.send(format_msg);
+        let mut index = 0;
+        while sp.ok() && !RESTARTING.load(Ordering::SeqCst) {
+            sp.snapshot(|sps| {
+                sps.send(create_format_msg(sample_rate, channels as _));
+                Ok(())
+            })?;
+            let data = super::super::test_display::audio_frame(index, sample_rate, channels as _);
+            send_f32(&data, &mut encoder, &sp);
+            index += 1;
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        Ok(())
+    }
 }
 
 #[cfg(not(any(target_os = "linux", target_os = "android")))]
@@ -126,6 +288,12 @@ mod cpal_impl {
     }
 
     pub fn run(sp: EmptyExtraFieldService, state: &mut State) -> ResultType<()> {
+        if RESTARTING.swap(false, Ordering::SeqCst) && state.stream.take().is_some() {
+            // the format may be changed, so it's sent to all
+            let (stream, format) = play(&sp)?;
+            sp.send_shared(format.clone());
+            state.stream = Some((stream, format));
+        }
         sp.snapshot(|sps| {
             match &state.stream {
                 None => {
@@ -236,7 +404,7 @@ mod cpal_impl {
         } else {
             48000
         };
-        let ch = if config.channels() > 1 { Stereo } else { Mono };
+        let ch = encode_channels(&get_quality(), config.channels());
         let stream = match config.sample_format() {
             I8 => build_input_stream::<i8>(device, &config, sp, sample_rate, ch)?,
             I16 => build_input_stream::<i16>(device, &config, sp, sample_rate, ch)?,
@@ -251,10 +419,9 @@ mod cpal_impl {
             f => bail!("unsupported audio format: {:?}", f),
         };
         stream.play()?;
-        Ok((
-            Box::new(stream),
-            Arc::new(create_format_msg(sample_rate, ch as _)),
-        ))
+        let format_msg = create_format_msg(sample_rate, ch as _);
+        super::super::video_service::record_audio_format(&format_msg);
+        Ok((Box::new(stream), Arc::new(format_msg)))
     }
 
     fn build_input_stream<T>(
@@ -277,7 +444,7 @@ mod cpal_impl {
             AUDIO_ZERO_COUNT = 0;
         }
         let device_channel = config.channels();
-        let mut encoder = Encoder::new(sample_rate, encode_channel, LowDelay)?;
+        let mut encoder = new_encoder(sample_rate, encode_channel, &get_quality())?;
         // https://www.opus-codec.org/docs/html_api/group__opusencoder.html#gace941e4ef26ed844879fde342ffbe546
         // https://chromium.googlesource.com/chromium/deps/opus/+/1.1.1/include/opus.h
         let frame_size = sample_rate as usize / 100; // 10 ms
@@ -371,6 +538,7 @@ fn send_f32(data: &[f32], encoder: &mut Encoder, sp: &GenericService) {
                             data: data.into(),
                             ..Default::default()
                         });
+                        super::video_service::record_audio(&msg_out);
                         sp.send(msg_out);
                     }
                     Err(_) => {}
@@ -390,8 +558,62 @@ fn send_f32(data: &[f32], encoder: &mut Encoder, sp: &GenericService) {
                 data: data.into(),
                 ..Default::default()
             });
+            super::video_service::record_audio(&msg_out);
             sp.send(msg_out);
         }
         Err(_) => {}
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quality(bitrate: u32, channels: u32, application: &str) -> AudioQuality {
+        AudioQuality {
+            bitrate,
+            channels,
+            application: application.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_encode_channels() {
+        assert!(matches!(encode_channels(&quality(0, 1, ""), 2), Mono));
+        assert!(matches!(encode_channels(&quality(0, 2, ""), 1), Stereo));
+        assert!(matches!(encode_channels(&quality(0, 0, ""), 2), Stereo));
+        assert!(matches!(encode_channels(&quality(0, 0, ""), 1), Mono));
+    }
+
+    #[test]
+    fn test_merge_quality() {
+        assert_eq!(merge_quality(std::iter::empty()), AudioQuality::default());
+        let qualities = [quality(32, 1, "firefox"), quality(64, 0, "firefox")];
+        assert_eq!(merge_quality(qualities.iter()), quality(64, 0, "firefox"));
+        let qualities = [quality(128, 2, "firefox"), quality(64, 0, "vlc")];
+        assert_eq!(merge_quality(qualities.iter()), quality(128, 2, ""));
+    }
+
+    #[test]
+    fn test_user_audio_quality() {
+        let restarted = || RESTARTING.swap(false, Ordering::SeqCst);
+        RESTARTING.store(false, Ordering::SeqCst);
+        user_audio_quality(-1, quality(64, 1, ""));
+        assert!(restarted());
+        assert_eq!(get_quality(), quality(64, 1, ""));
+        // unchanged
+        user_audio_quality(-1, quality(64, 1, ""));
+        assert!(!restarted());
+        // a lower quality of another connection is not used
+        user_audio_quality(-2, quality(32, 1, ""));
+        assert!(!restarted());
+        assert_eq!(get_quality(), quality(64, 1, ""));
+        on_connection_close(-1);
+        assert!(restarted());
+        assert_eq!(get_quality(), quality(32, 1, ""));
+        on_connection_close(-2);
+        assert!(restarted());
+        assert_eq!(get_quality(), AudioQuality::default());
+    }
+}
@@ -29,6 +29,11 @@ impl super::service::Reset for State {
 
 pub fn new() -> GenericService {
     let svc = EmptyExtraFieldService::new(NAME.to_owned(), true);
+    #[cfg(target_os = "linux")]
+    if !super::is_test_display() {
+        GenericService::run(&svc.clone(), x11_impl::run);
+        return svc.sp;
+    }
     GenericService::repeat::<State, _, _>(&svc.clone(), INTERVAL, run);
     svc.sp
 }
@@ -37,13 +42,44 @@ fn run(sp: EmptyExtraFieldService, state: &mut State) -> ResultType<()> {
     if let Some(msg) = check_clipboard(&mut state.ctx, None) {
         sp.send(msg);
     }
+    send_snapshot(&sp)
+}
+
+fn send_snapshot(sp: &EmptyExtraFieldService) -> ResultType<()> {
     sp.snapshot(|sps| {
-        let txt = crate::CONTENT.lock().unwrap().clone();
-        if !txt.is_empty() {
-            let msg_out = crate::create_clipboard_msg(txt);
+        let content = crate::CONTENT.lock().unwrap().clone();
+        if !content.is_empty() {
+            let msg_out = crate::create_clipboard_content_msg(&content);
             sps.send_shared(Arc::new(msg_out));
         }
         Ok(())
-    })?;
-    Ok(())
+    })
+}
+
+#[cfg(target_os = "linux")]
+mod x11_impl {
+    use super::*;
+    use crate::{platform::linux_clipboard::ClipboardWatcher, server::service::Reset};
+
+    // the clipboard is read only after its owner changes
+    pub fn run(sp: EmptyExtraFieldService) -> ResultType<()> {
+        let mut state = State::default();
+        let mut watcher = ClipboardWatcher::new();
+        while sp.ok() {
+            if watcher.clipboard_changed() {
+                if let Some(msg) = check_clipboard(&mut state.ctx, None) {
+                    sp.send(msg);
+                }
+            }
+            if watcher.primary_changed() && crate::is_primary_selection_enabled() {
+                if let Some(msg) = crate::check_primary_selection(&mut state.ctx, true) {
+                    sp.send(msg);
+                }
+            }
+            send_snapshot(&sp)?;
+            watcher.wait(std::time::Duration::from_millis(INTERVAL));
+        }
+        state.reset();
+        Ok(())
+    }
 }
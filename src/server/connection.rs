@@ -18,6 +18,7 @@ use crate::{
     client::{
         new_voice_call_request, new_voice_call_response, start_audio_thread, MediaData, MediaSender,
     },
+    clipboard_policy::{self, Decision},
     common::{get_default_sound_input, set_sound_input},
     display_service, ipc, privacy_mode, video_service, VERSION,
 };
@@ -190,6 +191,9 @@ pub struct Connection {
     restart: bool,
     recording: bool,
     block_input: bool,
+    gamepad: bool,
+    #[cfg(target_os = "linux")]
+    uinput_gamepad: Option<super::uinput::client::UInputGamepad>,
     last_test_delay: Option<Instant>,
     network_delay: u32,
     lock_after_session_end: bool,
@@ -239,6 +243,7 @@ pub struct Connection {
     supported_encoding_flag: (bool, Option<bool>),
     services_subed: bool,
     delayed_read_dir: Option<(String, bool)>,
+    stats: crate::session_stats::SessionStats,
 }
 
 impl ConnInner {
@@ -289,6 +294,7 @@ impl Connection {
         stream: super::Stream,
         id: i32,
         server: super::ServerPtrWeak,
+        relay: bool,
     ) {
         let _raii_id = raii::ConnectionID::new(id);
         let hash = Hash {
@@ -342,6 +348,9 @@ impl Connection {
             restart: Connection::permission("enable-remote-restart"),
             recording: Connection::permission("enable-record-session"),
             block_input: Connection::permission("enable-block-input"),
+            gamepad: Connection::permission("enable-gamepad"),
+            #[cfg(target_os = "linux")]
+            uinput_gamepad: None,
             last_test_delay: None,
             network_delay: 0,
             lock_after_session_end: false,
@@ -388,7 +397,14 @@ impl Connection {
             supported_encoding_flag: (false, None),
             services_subed: false,
             delayed_read_dir: None,
+            stats: crate::session_stats::SessionStats::new(
+                crate::session_stats::controlled_key(id),
+                crate::session_stats::SIDE_CONTROLLED,
+                id,
+                "",
+            ),
         };
+        conn.stats.direct = Some(!relay);
         let addr = hbb_common::try_into_v4(addr);
         if !conn.on_open(addr).await {
             conn.closed = true;
@@ -419,6 +435,9 @@ impl Connection {
         if !conn.block_input {
             conn.send_permission(Permission::BlockInput, false).await;
         }
+        if !conn.gamepad {
+            conn.send_permission(Permission::Gamepad, false).await;
+        }
         let mut test_delay_timer = crate::rustdesk_interval(time::interval(TEST_DELAY_TIMEOUT));
         let mut last_recv_time = Instant::now();
 
@@ -474,8 +493,13 @@ impl Connection {
                         }
                         ipc::Data::SwitchPermission{name, enabled} => {
                             log::info!("Change permission {} -> {}", name, enabled);
+                            conn.local_audit("permission", json!({"name": name, "enabled": enabled}));
                             if &name == "keyboard" {
                                 conn.keyboard = enabled;
+                                #[cfg(not(any(target_os = "android", target_os = "ios")))]
+                                if !enabled {
+                                    cancel_type_text(Some(conn.inner.id));
+                                }
                                 conn.send_permission(Permission::Keyboard, enabled).await;
                                 if let Some(s) = conn.server.upgrade() {
                                     s.write().unwrap().subscribe(
@@ -510,6 +534,14 @@ impl Connection {
                             } else if &name == "block_input" {
                                 conn.block_input = enabled;
                                 conn.send_permission(Permission::BlockInput, enabled).await;
+                            } else if &name == "gamepad" {
+                                conn.gamepad = enabled;
+                                conn.send_permission(Permission::Gamepad, enabled).await;
+                                // unplug the gamepads
+                                #[cfg(target_os = "linux")]
+                                if !enabled {
+                                    conn.uinput_gamepad = None;
+                                }
                             }
                         }
                         ipc::Data::RawMessage(bytes) => {
@@ -517,7 +549,9 @@ impl Connection {
                         }
                         #[cfg(any(target_os="windows", target_os="linux", target_os = "macos"))]
                         ipc::Data::ClipboardFile(clip) => {
-                            allow_err!(conn.stream.send(&clip_2_msg(clip)).await);
+                            if conn.check_file_clipboard_policy(&clip, true) {
+                                allow_err!(conn.stream.send(&clip_2_msg(clip)).await);
+                            }
                         }
                         ipc::Data::PrivacyModeState((_, state, impl_key)) => {
                             let msg_out = match state {
@@ -579,6 +613,7 @@ impl Connection {
                                 last_recv_time = Instant::now();
                                 *conn.last_recv_time.lock().unwrap() = Instant::now();
                                 if let Ok(msg_in) = Message::parse_from_bytes(&bytes) {
+                                    conn.stats.on_recv(&msg_in, bytes.len());
                                     if !conn.on_message(msg_in).await {
                                         break;
                                     }
@@ -596,8 +631,12 @@ impl Connection {
                 _ = conn.file_timer.tick() => {
                     if !conn.read_jobs.is_empty() {
                         conn.send_to_cm(ipc::Data::FileTransferLog(("transfer".to_string(), fs::serialize_transfer_jobs(&conn.read_jobs))));
+                        let transferred: HashMap<i32, u64> = conn.read_jobs.iter().map(|j| (j.id(), j.transferred())).collect();
                         match fs::handle_read_jobs(&mut conn.read_jobs, &mut conn.stream).await {
                             Ok(log) => {
+                                // finished jobs are removed, they send no block in their last round
+                                let sent: u64 = conn.read_jobs.iter().map(|j| j.transferred() - transferred.get(&j.id()).cloned().unwrap_or_default()).sum();
+                                super::metrics::on_file_bytes(true, sent);
                                 if !log.is_empty() {
                                     conn.send_to_cm(ipc::Data::FileTransferLog(("transfer".to_string(), log)));
                                 }
@@ -622,6 +661,7 @@ impl Connection {
                     if !conn.video_ack_required {
                         video_service::notify_video_frame_fetched(id, Some(instant.into()));
                     }
+                    conn.stats.on_send(&value);
                     if let Err(err) = conn.stream.send(&value as &Message).await {
                         conn.on_close(&err.to_string(), false).await;
                         break;
@@ -630,6 +670,7 @@ impl Connection {
                 Some((instant, value)) = rx.recv() => {
                     let latency = instant.elapsed().as_millis() as i64;
                     let msg: &Message = &value;
+                    let mut redacted = None;
 
                     if latency > 1000 {
                         match &msg.union {
@@ -654,8 +695,21 @@ impl Connection {
                         Some(message::Union::PeerInfo(..)) => {
                             conn.refresh_video_display(None);
                         }
+                        Some(message::Union::Clipboard(cb)) => {
+                            match conn.check_clipboard_policy(cb, true) {
+                                Decision::Allow => {}
+                                Decision::Redact(cb, _) => {
+                                    let mut msg = Message::new();
+                                    msg.set_clipboard(cb);
+                                    redacted = Some(msg);
+                                }
+                                Decision::Block(_) => continue,
+                            }
+                        }
                         _ => {}
                     }
+                    let msg = redacted.as_ref().unwrap_or(msg);
+                    conn.stats.on_send(msg);
                     if let Err(err) = conn.stream.send(msg).await {
                         conn.on_close(&err.to_string(), false).await;
                         break;
@@ -674,6 +728,7 @@ impl Connection {
                     conn.file_remove_log_control.on_timer().drain(..).map(|x| conn.send_to_cm(x)).count();
                     #[cfg(feature = "gpucodec")]
                     conn.update_supported_encoding();
+                    conn.publish_stats();
                 }
                 _ = test_delay_timer.tick() => {
                     if last_recv_time.elapsed() >= SEC30 {
@@ -725,6 +780,7 @@ impl Connection {
             try_stop_record_cursor_pos();
         }
         conn.on_close("End", true).await;
+        crate::session_stats::remove(&conn.stats.key);
         log::info!("#{} connection loop exited", id);
     }
 
@@ -946,6 +1002,7 @@ impl Connection {
             "ip": addr.ip(),
             "action": "new",
         }));
+        self.local_audit("connect", json!({}));
         true
     }
 
@@ -972,9 +1029,7 @@ impl Connection {
         v["uuid"] = json!(crate::encode64(hbb_common::get_uuid()));
         v["conn_id"] = json!(self.inner.id);
         v["session_id"] = json!(self.lr.session_id);
-        tokio::spawn(async move {
-            allow_err!(Self::post_audit_async(url, v).await);
-        });
+        crate::audit::post(url, v);
     }
 
     fn post_file_audit(
@@ -984,6 +1039,14 @@ impl Connection {
         files: Vec<(String, i64)>,
         info: Value,
     ) {
+        self.local_audit(
+            "file",
+            json!({
+                "type": r#type as i8,
+                "path": path,
+                "num": files.len(),
+            }),
+        );
         if self.server_audit_file.is_empty() {
             return;
         }
@@ -1007,12 +1070,11 @@ impl Connection {
             "is_file":is_file,
             "info":json!(info).to_string(),
         });
-        tokio::spawn(async move {
-            allow_err!(Self::post_audit_async(url, v).await);
-        });
+        crate::audit::post(url, v);
     }
 
     pub fn post_alarm_audit(typ: AlarmAuditType, info: Value) {
+        crate::audit::log("alarm", json!({"type": typ as i8, "info": info}));
         let url = crate::get_audit_server(
             Config::get_option("api-server"),
             Config::get_option("custom-rendezvous-server"),
@@ -1026,14 +1088,68 @@ impl Connection {
         v["uuid"] = json!(crate::encode64(hbb_common::get_uuid()));
         v["typ"] = json!(typ as i8);
         v["info"] = serde_json::Value::String(info.to_string());
-        tokio::spawn(async move {
-            allow_err!(Self::post_audit_async(url, v).await);
+        crate::audit::post(url, v);
+    }
+
+    // the transferred, redacted and blocked items are all audited
+    fn check_clipboard_policy(&self, cb: &Clipboard, outgoing: bool) -> Decision {
+        let decision = clipboard_policy::check(cb, outgoing);
+        let mut info = json!({
+            "direction": if outgoing { "out" } else { "in" },
+            "size": crate::common::get_clipboard_size(cb),
         });
+        match &decision {
+            Decision::Allow => {
+                info["action"] = json!("transfer");
+            }
+            Decision::Redact(_, rules) => {
+                info["action"] = json!("redact");
+                info["rules"] = json!(rules);
+            }
+            Decision::Block(reason) => {
+                log::info!("clipboard blocked: {}", reason);
+                info["action"] = json!("block");
+                info["reason"] = json!(reason);
+            }
+        }
+        self.local_audit("clipboard", info);
+        decision
     }
 
-    #[inline]
-    async fn post_audit_async(url: String, v: Value) -> ResultType<String> {
-        crate::post_request(url, v.to_string(), "").await
+    // The files are not inspected, only the direction is checked.
+    // The file lists are audited when blocked, the contents are dropped silently.
+    #[cfg(any(target_os = "windows", target_os = "linux", target_os = "macos"))]
+    fn check_file_clipboard_policy(&self, clip: &ipc::ClipboardFile, outgoing: bool) -> bool {
+        if !is_clip_data(clip) || clipboard_policy::is_direction_allowed(outgoing) {
+            return true;
+        }
+        if matches!(clip, ipc::ClipboardFile::FormatList { .. }) {
+            log::info!("file clipboard blocked: direction");
+            self.local_audit(
+                "clipboard",
+                json!({
+                    "direction": if outgoing { "out" } else { "in" },
+                    "files": true,
+                    "action": "block",
+                    "reason": "direction",
+                }),
+            );
+        }
+        false
+    }
+
+    fn local_audit(&self, event: &str, info: Value) {
+        crate::audit::log(
+            event,
+            json!({
+                "conn_id": self.inner.id,
+                "session_id": self.lr.session_id,
+                "peer_id": self.lr.my_id,
+                "peer_name": self.lr.my_name,
+                "ip": self.ip,
+                "info": info,
+            }),
+        );
     }
 
     async fn send_logon_response(&mut self) {
@@ -1045,6 +1161,7 @@ impl Connection {
             return;
         }
         self.authorized = true;
+        super::metrics::on_session_start(self.stats.direct == Some(false));
         let (conn_type, auth_conn_type) = if self.file_transfer.is_some() {
             (1, AuthConnType::FileTransfer)
         } else if self.port_forward_socket.is_some() {
@@ -1059,6 +1176,13 @@ impl Connection {
         self.post_conn_audit(
             json!({"peer": ((&self.lr.my_id, &self.lr.my_name)), "type": conn_type}),
         );
+        self.local_audit("login", json!({"type": conn_type}));
+        if self.port_forward_socket.is_some() {
+            self.local_audit(
+                "port_forward",
+                json!({"address": self.port_forward_address}),
+            );
+        }
         #[allow(unused_mut)]
         let mut username = crate::platform::get_active_username();
         let mut res = LoginResponse::new();
@@ -1091,6 +1215,15 @@ impl Connection {
             if crate::platform::current_is_wayland() {
                 platform_additions.insert("is_wayland".into(), json!(true));
             }
+            if super::uinput::client::is_touch_supported().await {
+                platform_additions.insert("multi_touch".into(), json!(true));
+            }
+            if super::uinput::client::is_pen_supported().await {
+                platform_additions.insert("pen".into(), json!(true));
+            }
+            if super::uinput::client::is_gamepad_supported().await {
+                platform_additions.insert("gamepad".into(), json!(true));
+            }
             #[cfg(feature = "linux_headless")]
             #[cfg(not(any(feature = "flatpak", feature = "appimage")))]
             if crate::platform::is_headless_allowed() {
@@ -1237,7 +1370,7 @@ impl Connection {
                     #[cfg(target_os = "linux")]
                     {
                         // use rdp_input when uinput is not available in wayland. Ex: flatpak
-                        if !is_x11() && !crate::is_server() {
+                        if !is_x11() && !crate::is_server() && !super::is_test_display() {
                             let _ = setup_rdp_input().await;
                         }
                     }
@@ -1351,6 +1484,12 @@ impl Connection {
         self.clipboard && !self.disable_clipboard
     }
 
+    // view only also disables the gamepads
+    #[cfg(target_os = "linux")]
+    fn gamepad_enabled(&self) -> bool {
+        self.gamepad && !self.disable_keyboard
+    }
+
     fn audio_enabled(&self) -> bool {
         self.audio && !self.disable_audio
     }
@@ -1376,6 +1515,7 @@ impl Connection {
             restart: self.restart,
             recording: self.recording,
             block_input: self.block_input,
+            gamepad: self.gamepad,
             from_switch: self.from_switch,
         });
     }
@@ -1827,6 +1967,13 @@ impl Connection {
                     }
                     self.update_auto_disconnect_timer();
                 }
+                Some(message::Union::GamepadEvent(_ge)) => {
+                    #[cfg(target_os = "linux")]
+                    if self.gamepad_enabled() {
+                        self.handle_gamepad(_ge).await;
+                    }
+                    self.update_auto_disconnect_timer();
+                }
                 Some(message::Union::PointerDeviceEvent(pde)) => {
                     #[cfg(any(target_os = "android", target_os = "ios"))]
                     if let Err(e) = match pde.union {
@@ -1974,7 +2121,11 @@ impl Connection {
                 {
                     #[cfg(not(any(target_os = "android", target_os = "ios")))]
                     if self.clipboard {
-                        update_clipboard(_cb, None);
+                        match self.check_clipboard_policy(&_cb, false) {
+                            Decision::Allow => update_clipboard(_cb, None),
+                            Decision::Redact(cb, _) => update_clipboard(cb, None),
+                            Decision::Block(_) => {}
+                        }
                     }
                 }
                 Some(message::Union::Cliprdr(_clip)) =>
@@ -1982,7 +2133,9 @@ impl Connection {
                     #[cfg(any(target_os = "windows", target_os = "linux", target_os = "macos"))]
                     if let Some(clip) = msg_2_clip(_clip) {
                         log::debug!("got clipfile from client peer");
-                        self.send_to_cm(ipc::Data::ClipboardFile(clip))
+                        if self.check_file_clipboard_policy(&clip, false) {
+                            self.send_to_cm(ipc::Data::ClipboardFile(clip))
+                        }
                     }
                 }
                 Some(message::Union::FileAction(fa)) => {
@@ -2082,6 +2235,10 @@ impl Connection {
                                 self.file_transferred = true;
                             }
                             Some(file_action::Union::RemoveDir(d)) => {
+                                self.local_audit(
+                                    "file",
+                                    json!({"action": "remove_dir", "path": d.path, "recursive": d.recursive}),
+                                );
                                 self.send_fs(ipc::FS::RemoveDir {
                                     path: d.path.clone(),
                                     id: d.id,
@@ -2090,6 +2247,10 @@ impl Connection {
                                 self.file_remove_log_control.on_remove_dir(d);
                             }
                             Some(file_action::Union::RemoveFile(f)) => {
+                                self.local_audit(
+                                    "file",
+                                    json!({"action": "remove_file", "path": f.path}),
+                                );
                                 self.send_fs(ipc::FS::RemoveFile {
                                     path: f.path.clone(),
                                     id: f.id,
@@ -2098,6 +2259,10 @@ impl Connection {
                                 self.file_remove_log_control.on_remove_file(f);
                             }
                             Some(file_action::Union::Create(c)) => {
+                                self.local_audit(
+                                    "file",
+                                    json!({"action": "create_dir", "path": c.path}),
+                                );
                                 self.send_fs(ipc::FS::CreateDir {
                                     path: c.path.clone(),
                                     id: c.id,
@@ -2134,6 +2299,7 @@ impl Connection {
                 }
                 Some(message::Union::FileResponse(fr)) => match fr.union {
                     Some(file_response::Union::Block(block)) => {
+                        super::metrics::on_file_bytes(false, block.data.len() as _);
                         self.send_fs(ipc::FS::WriteBlock {
                             id: block.id,
                             file_num: block.file_num,
@@ -2180,6 +2346,11 @@ impl Connection {
                     Some(misc::Union::TogglePrivacyMode(t)) => {
                         self.toggle_privacy_mode(t).await;
                     }
+                    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+                    Some(misc::Union::TypeText(t)) => {
+                        self.handle_type_text(t).await;
+                        self.update_auto_disconnect_timer();
+                    }
                     Some(misc::Union::ChatMessage(c)) => {
                         self.send_to_cm(ipc::Data::ChatMessage { text: c.text });
                         self.chat_unanswered = true;
@@ -2351,6 +2522,11 @@ impl Connection {
             }
             return;
         }
+        super::metrics::on_login_failure();
+        self.local_audit(
+            "login_failed",
+            json!({"method": if i == 0 { "password" } else { "2fa" }}),
+        );
         if failure.0 == time {
             failure.1 += 1;
             failure.2 += 1;
@@ -2539,6 +2715,98 @@ impl Connection {
         }
     }
 
+    #[cfg(target_os = "linux")]
+    async fn handle_gamepad(&mut self, ge: GamepadEvent) {
+        use crate::ipc::DataGamepad;
+        if self.uinput_gamepad.is_none() {
+            let tx = self.inner.tx.clone();
+            let on_rumble = move |index, strong: u16, weak: u16, duration| {
+                let mut msg_out = Message::new();
+                msg_out.set_gamepad_rumble(GamepadRumble {
+                    index,
+                    strong: strong as _,
+                    weak: weak as _,
+                    duration,
+                    ..Default::default()
+                });
+                if let Some(tx) = tx.as_ref() {
+                    tx.send((Instant::now(), Arc::new(msg_out))).ok();
+                }
+            };
+            match super::uinput::client::UInputGamepad::new(on_rumble).await {
+                Ok(gamepad) => self.uinput_gamepad = Some(gamepad),
+                Err(e) => {
+                    log::error!("Failed to connect uinput gamepad: {}", e);
+                    return;
+                }
+            }
+        }
+        let data = match ge.union {
+            Some(gamepad_event::Union::Connected(c)) => {
+                self.local_audit("gamepad", json!({"index": ge.index, "name": c.name}));
+                DataGamepad::Connected(ge.index, c.name)
+            }
+            Some(gamepad_event::Union::Disconnected(_)) => DataGamepad::Disconnected(ge.index),
+            Some(gamepad_event::Union::State(state)) => {
+                DataGamepad::State(ge.index, state.buttons, state.axes)
+            }
+            None => return,
+        };
+        if let Some(gamepad) = self.uinput_gamepad.as_ref() {
+            if let Err(e) = gamepad.send(data) {
+                log::error!("Failed to send gamepad data: {}", e);
+                self.uinput_gamepad = None;
+            }
+        }
+    }
+
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    async fn handle_type_text(&mut self, t: TypeText) {
+        if t.cancel {
+            cancel_type_text(Some(self.inner.id));
+            return;
+        }
+        if !self.peer_keyboard_enabled() {
+            return;
+        }
+        let make_msg = |text: String| {
+            let mut msg_out = Message::new();
+            msg_out.set_message_box(MessageBox {
+                msgtype: "nook-nocancel-hasclose".to_owned(),
+                title: "Type text".to_owned(),
+                text,
+                link: "".to_owned(),
+                ..Default::default()
+            });
+            msg_out
+        };
+        // The typed text is pasted in effect, it must not bypass the clipboard policy.
+        let cb = crate::common::create_clipboard(
+            &crate::common::ClipboardContent {
+                text: t.text.clone(),
+                ..Default::default()
+            },
+            false,
+        );
+        let text = match self.check_clipboard_policy(&cb, false) {
+            Decision::Allow => t.text,
+            Decision::Redact(cb, _) => crate::common::parse_clipboard(cb).text,
+            Decision::Block(reason) => {
+                let text = format!("Blocked by the clipboard policy: {}", reason);
+                self.send(make_msg(text)).await;
+                return;
+            }
+        };
+        let len = text.chars().count();
+        if let Err(e) = type_text(&text, t.interval, self.inner.id) {
+            log::error!("Failed to type text: {}", e);
+            self.local_audit("type_text", json!({"length": len, "error": e.to_string()}));
+            self.send(make_msg(e.to_string())).await;
+        } else {
+            self.local_audit("type_text", json!({"length": len}));
+        }
+    }
+
     #[cfg(all(windows, feature = "virtual_display_driver"))]
     async fn toggle_virtual_display(&mut self, t: ToggleVirtualDisplay) {
         let make_msg = |text: String| {
@@ -2597,6 +2865,10 @@ impl Connection {
 
     #[cfg(not(any(target_os = "android", target_os = "ios")))]
     fn change_resolution(&mut self, r: &Resolution) {
+        if super::shared_area::get().is_some() {
+            log::info!("The resolution can't be changed when only a part of the desktop is shared");
+            return;
+        }
         if self.keyboard {
             if let Ok(displays) = display_service::try_get_displays() {
                 if let Some(display) = displays.get(self.display_idx) {
@@ -2692,6 +2964,17 @@ impl Connection {
                 .unwrap()
                 .user_custom_fps(self.inner.id(), o.custom_fps as _);
         }
+        if o.video_scale > 0 {
+            let auto = o.auto_video_scale.enum_value() == Ok(BoolOption::Yes);
+            video_service::VIDEO_QOS.lock().unwrap().user_video_scale(
+                self.inner.id(),
+                o.video_scale as _,
+                auto,
+            );
+        }
+        if let Some(q) = o.audio_quality.clone().take() {
+            super::audio_service::user_audio_quality(self.inner.id(), q);
+        }
         if let Some(q) = o.supported_decoding.clone().take() {
             scrap::codec::Encoder::update(scrap::codec::EncodingUpdate::Update(self.inner.id(), q));
         }
@@ -2934,6 +3217,17 @@ impl Connection {
         }
         self.closed = true;
         log::info!("#{} Connection closed: {}", self.inner.id(), reason);
+        #[cfg(not(any(target_os = "android", target_os = "ios")))]
+        cancel_type_text(Some(self.inner.id));
+        #[cfg(target_os = "linux")]
+        {
+            let id = self.inner.id;
+            std::thread::spawn(move || {
+                release_multi_touch(id);
+                release_pen(id);
+            });
+        }
+        self.local_audit("disconnect", json!({"reason": reason}));
         if lock && self.lock_after_session_end && self.keyboard {
             #[cfg(not(any(target_os = "android", target_os = "ios")))]
             lock_screen().await;
@@ -2974,13 +3268,33 @@ impl Connection {
 
     #[inline]
     async fn send(&mut self, msg: Message) {
+        self.stats.on_send(&msg);
         allow_err!(self.stream.send(&msg).await);
     }
 
+    fn publish_stats(&mut self) {
+        self.stats.peer_id = self.lr.my_id.clone();
+        self.stats.conn_type = if self.file_transfer.is_some() {
+            "file_transfer"
+        } else if self.port_forward_socket.is_some() {
+            "port_forward"
+        } else {
+            "remote"
+        }
+        .to_owned();
+        self.stats.rtt = self.network_delay;
+        self.stats.bitrate = video_service::VIDEO_QOS.lock().unwrap().bitrate();
+        self.stats.publish();
+    }
+
     pub fn alive_conns() -> Vec<i32> {
         ALIVE_CONNS.lock().unwrap().clone()
     }
 
+    pub fn authed_conns() -> Vec<(i32, AuthConnType)> {
+        AUTHED_CONNS.lock().unwrap().clone()
+    }
+
     #[cfg(windows)]
     fn portable_check(&mut self) {
         if self.portable.is_installed
@@ -3242,12 +3556,14 @@ fn try_activate_screen() {
     });
 }
 
+#[derive(Clone, Copy)]
 pub enum AlarmAuditType {
     IpWhitelist = 0,
     ExceedThirtyAttempts = 1,
     SixAttemptsWithinOneMinute = 2,
 }
 
+#[derive(Clone, Copy)]
 pub enum FileAuditType {
     RemoteSend = 0,
     RemoteReceive = 1,
@@ -3511,6 +3827,7 @@ mod raii {
                 .lock()
                 .unwrap()
                 .on_connection_close(self.0);
+            audio_service::on_connection_close(self.0);
         }
     }
 
@@ -180,6 +180,9 @@ fn displays_to_msg(displays: Vec<DisplayInfo>) -> Message {
 fn check_get_displays_changed_msg() -> Option<Message> {
     #[cfg(target_os = "linux")]
     {
+        if check_update_test_display() {
+            return get_displays_msg();
+        }
         if !is_x11() {
             return get_displays_msg();
         }
@@ -189,6 +192,10 @@ fn check_get_displays_changed_msg() -> Option<Message> {
 }
 
 pub fn check_displays_changed() -> ResultType<()> {
+    #[cfg(target_os = "linux")]
+    if check_update_test_display() {
+        return Ok(());
+    }
     check_update_displays(&try_get_displays()?);
     Ok(())
 }
@@ -269,6 +276,14 @@ pub(super) fn get_display_info(idx: usize) -> Option<DisplayInfo> {
 // Display to DisplayInfo
 // The DisplayInfo is be sent to the peer.
 pub(super) fn check_update_displays(all: &Vec<Display>) {
+    if let Some(area) = super::shared_area::update(all) {
+        let name = all.get(area.display).map(|d| d.name()).unwrap_or_default();
+        SYNC_DISPLAYS
+            .lock()
+            .unwrap()
+            .check_changed(vec![area.to_display_info(name)]);
+        return;
+    }
     let displays = all
         .iter()
         .map(|d| {
@@ -290,9 +305,20 @@ pub(super) fn check_update_displays(all: &Vec<Display>) {
     SYNC_DISPLAYS.lock().unwrap().check_changed(displays);
 }
 
+// The virtual display of the end-to-end tests is the only display if it is enabled.
+#[cfg(target_os = "linux")]
+fn check_update_test_display() -> bool {
+    #[cfg(feature = "test_display")]
+    if let Some(display) = super::test_display::display_info() {
+        SYNC_DISPLAYS.lock().unwrap().check_changed(vec![display]);
+        return true;
+    }
+    false
+}
+
 pub fn is_inited_msg() -> Option<Message> {
     #[cfg(target_os = "linux")]
-    if !is_x11() {
+    if !is_x11() && !super::is_test_display() {
         return super::wayland::is_inited();
     }
     None
@@ -301,6 +327,9 @@ pub fn is_inited_msg() -> Option<Message> {
 pub async fn update_get_sync_displays() -> ResultType<Vec<DisplayInfo>> {
     #[cfg(target_os = "linux")]
     {
+        if check_update_test_display() {
+            return Ok(SYNC_DISPLAYS.lock().unwrap().displays.clone());
+        }
         if !is_x11() {
             return super::wayland::get_displays().await;
         }
@@ -313,6 +342,9 @@ pub async fn update_get_sync_displays() -> ResultType<Vec<DisplayInfo>> {
 pub fn get_primary() -> usize {
     #[cfg(target_os = "linux")]
     {
+        if super::is_test_display() {
+            return 0;
+        }
         if !is_x11() {
             return match super::wayland::get_primary() {
                 Ok(n) => n,
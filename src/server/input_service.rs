@@ -9,7 +9,10 @@ use enigo::{Enigo, Key, KeyboardControllable, MouseButton, MouseControllable};
 use super::rdp_input::client::{RdpInputKeyboard, RdpInputMouse};
 use hbb_common::{
     get_time,
-    message_proto::{pointer_device_event::Union::TouchEvent, touch_event::Union::ScaleUpdate},
+    message_proto::{
+        pointer_device_event::Union::{MultiTouchEvent, PenEvent, TouchEvent},
+        touch_event::Union::ScaleUpdate,
+    },
     protobuf::EnumOrUnknown,
 };
 use rdev::{self, EventType, Key as RdevKey, KeyCode, RawKey};
@@ -509,6 +512,201 @@ async fn set_uinput_resolution(minx: i32, maxx: i32, miny: i32, maxy: i32) -> Re
     super::uinput::client::set_resolution(minx, maxx, miny, maxy).await
 }
 
+#[cfg(target_os = "linux")]
+struct MultiTouch {
+    touch: super::uinput::client::UInputAbsolute,
+    conn: i32,
+    down: std::collections::HashSet<i32>,
+}
+
+#[cfg(target_os = "linux")]
+lazy_static::lazy_static! {
+    static ref MULTI_TOUCH: Mutex<Option<MultiTouch>> = Default::default();
+}
+
+// ((minx, maxx), (miny, maxy)) of all displays, the same coordinates as the mouse events.
+#[cfg(target_os = "linux")]
+fn get_desktop_resolution() -> Option<((i32, i32), (i32, i32))> {
+    let displays = super::display_service::get_sync_displays();
+    Some((
+        (
+            displays.iter().map(|d| d.x).min()?,
+            displays.iter().map(|d| d.x + d.width).max()?,
+        ),
+        (
+            displays.iter().map(|d| d.y).min()?,
+            displays.iter().map(|d| d.y + d.height).max()?,
+        ),
+    ))
+}
+
+#[cfg(target_os = "linux")]
+fn handle_multi_touch(evt: &hbb_common::message_proto::MultiTouchEvent, conn: i32) {
+    use crate::ipc::{Data, DataTouch};
+    let Some(resolution) = get_desktop_resolution() else {
+        return;
+    };
+    let mut lock = MULTI_TOUCH.lock().unwrap();
+    if lock.is_none() {
+        match super::uinput::client::UInputAbsolute::new_touch() {
+            Ok(touch) => {
+                log::info!("UInput touch created");
+                *lock = Some(MultiTouch {
+                    touch,
+                    conn,
+                    down: Default::default(),
+                });
+            }
+            Err(e) => {
+                log::error!("Failed to connect uinput touch: {}", e);
+                return;
+            }
+        }
+    }
+    let Some(multi_touch) = lock.as_mut() else {
+        return;
+    };
+    multi_touch.conn = conn;
+    for p in evt.points.iter() {
+        if p.down {
+            multi_touch.down.insert(p.id);
+        } else {
+            multi_touch.down.remove(&p.id);
+        }
+    }
+    let points = evt
+        .points
+        .iter()
+        .map(|p| (p.id, p.x, p.y, p.pressure, p.down))
+        .collect();
+    if let Err(e) = multi_touch.touch.set_resolution(resolution).and_then(|_| {
+        multi_touch
+            .touch
+            .send(Data::Touch(DataTouch::Frame(points)))
+    }) {
+        log::error!("Failed to send touch frame: {}", e);
+        *lock = None;
+    }
+}
+
+/// Lift the fingers left down by the closed connection.
+#[cfg(target_os = "linux")]
+pub fn release_multi_touch(conn: i32) {
+    use crate::ipc::{Data, DataTouch};
+    let mut lock = MULTI_TOUCH.lock().unwrap();
+    let Some(multi_touch) = lock.as_mut() else {
+        return;
+    };
+    if multi_touch.conn != conn || multi_touch.down.is_empty() {
+        return;
+    }
+    let points = multi_touch
+        .down
+        .drain()
+        .map(|id| (id, 0, 0, 0, false))
+        .collect();
+    if let Err(e) = multi_touch
+        .touch
+        .send(Data::Touch(DataTouch::Frame(points)))
+    {
+        log::error!("Failed to release touch: {}", e);
+        *lock = None;
+    }
+}
+
+#[cfg(target_os = "linux")]
+const HI_RES_SCROLL_RETRY_INTERVAL: Duration = Duration::from_secs(10);
+
+#[cfg(target_os = "linux")]
+lazy_static::lazy_static! {
+    // the client and the time of the last failure to connect
+    static ref HI_RES_SCROLL: Mutex<(Option<super::uinput::client::UInputScroll>, Option<Instant>)> = Default::default();
+}
+
+#[cfg(not(windows))]
+lazy_static::lazy_static! {
+    static ref HI_RES_SCROLL_REMAINDER: Mutex<(i32, i32)> = Default::default();
+}
+
+#[cfg(target_os = "linux")]
+struct Pen {
+    pen: super::uinput::client::UInputAbsolute,
+    conn: i32,
+    in_range: bool,
+}
+
+#[cfg(target_os = "linux")]
+lazy_static::lazy_static! {
+    static ref PEN: Mutex<Option<Pen>> = Default::default();
+}
+
+#[cfg(target_os = "linux")]
+fn handle_pen(evt: &hbb_common::message_proto::PenEvent, conn: i32) {
+    use crate::ipc::{Data, DataPen};
+    let Some(resolution) = get_desktop_resolution() else {
+        return;
+    };
+    let mut lock = PEN.lock().unwrap();
+    if lock.is_none() {
+        match super::uinput::client::UInputAbsolute::new_pen() {
+            Ok(pen) => {
+                log::info!("UInput pen created");
+                *lock = Some(Pen {
+                    pen,
+                    conn,
+                    in_range: false,
+                });
+            }
+            Err(e) => {
+                log::error!("Failed to connect uinput pen: {}", e);
+                return;
+            }
+        }
+    }
+    let Some(pen) = lock.as_mut() else {
+        return;
+    };
+    pen.conn = conn;
+    pen.in_range = evt.down || evt.hover;
+    let state = crate::ipc::DataPenState {
+        x: evt.x,
+        y: evt.y,
+        pressure: evt.pressure,
+        tilt_x: evt.tilt_x,
+        tilt_y: evt.tilt_y,
+        eraser: evt.eraser,
+        barrel: evt.barrel,
+        down: evt.down,
+        hover: evt.hover,
+    };
+    if let Err(e) = pen
+        .pen
+        .set_resolution(resolution)
+        .and_then(|_| pen.pen.send(Data::Pen(DataPen::State(state))))
+    {
+        log::error!("Failed to send pen state: {}", e);
+        *lock = None;
+    }
+}
+
+/// Take the pen out of range if the closed connection left it in.
+#[cfg(target_os = "linux")]
+pub fn release_pen(conn: i32) {
+    use crate::ipc::{Data, DataPen};
+    let mut lock = PEN.lock().unwrap();
+    let Some(pen) = lock.as_mut() else {
+        return;
+    };
+    if pen.conn != conn || !pen.in_range {
+        return;
+    }
+    pen.in_range = false;
+    if let Err(e) = pen.pen.send(Data::Pen(DataPen::State(Default::default()))) {
+        log::error!("Failed to release pen: {}", e);
+        *lock = None;
+    }
+}
+
 pub fn is_left_up(evt: &MouseEvent) -> bool {
     let buttons = evt.mask >> 3;
     let evt_type = evt.mask & 0x7;
@@ -559,7 +757,76 @@ fn get_modifier_state(key: Key, en: &mut Enigo) -> bool {
     }
 }
 
+// Clamp a mouse event into the shared area, `None` if it should be dropped.
+fn clamp_to_shared_area(evt: &MouseEvent) -> Option<MouseEvent> {
+    let mut evt = evt.clone();
+    let Some(area) = super::shared_area::get() else {
+        return Some(evt);
+    };
+    if !area.visible {
+        return None;
+    }
+    if evt.mask & 0x7 == MOUSE_TYPE_MOVE {
+        (evt.x, evt.y) = area.clamp(evt.x, evt.y);
+    } else {
+        // The buttons and the wheel act at the cursor, which may be moved out of the area locally.
+        let (x, y) = crate::get_cursor_pos()?;
+        if !area.contains(x, y) {
+            return None;
+        }
+    }
+    Some(evt)
+}
+
+// Clamp the points of a pointer device event into the shared area, `None` if it should be dropped.
+// The lifted fingers and the pen leaving the range are kept when the area is hidden, so nothing is left down.
+fn clamp_pointer_to_shared_area(evt: &PointerDeviceEvent) -> Option<PointerDeviceEvent> {
+    let mut evt = evt.clone();
+    let Some(area) = super::shared_area::get() else {
+        return Some(evt);
+    };
+    match &mut evt.union {
+        Some(MultiTouchEvent(touch)) => {
+            touch.points.retain(|p| area.visible || !p.down);
+            if touch.points.is_empty() {
+                return None;
+            }
+            for p in touch.points.iter_mut() {
+                (p.x, p.y) = area.clamp(p.x, p.y);
+            }
+        }
+        Some(PenEvent(pen)) => {
+            if !area.visible && (pen.down || pen.hover) {
+                return None;
+            }
+            (pen.x, pen.y) = area.clamp(pen.x, pen.y);
+        }
+        _ => {}
+    }
+    Some(evt)
+}
+
+// The key presses are dropped if the focus is out of the shared area, the releases are always passed.
+fn is_key_in_shared_area(evt: &KeyEvent) -> bool {
+    let Some(area) = super::shared_area::get() else {
+        return true;
+    };
+    if !evt.down && !evt.press {
+        return true;
+    }
+    super::shared_area::has_focus(&area)
+}
+
 pub fn handle_mouse(evt: &MouseEvent, conn: i32) {
+    #[cfg(all(target_os = "linux", feature = "test_display"))]
+    if super::test_display::is_enabled() {
+        super::test_display::push_input(super::test_display::TestInput::Mouse(evt.clone()));
+        return;
+    }
+    let Some(evt) = clamp_to_shared_area(evt) else {
+        return;
+    };
+    let evt = &evt;
     #[cfg(target_os = "macos")]
     if !is_server() {
         // having GUI, run main GUI thread, otherwise crash
@@ -575,6 +842,10 @@ pub fn handle_mouse(evt: &MouseEvent, conn: i32) {
 
 // to-do: merge handle_mouse and handle_pointer
 pub fn handle_pointer(evt: &PointerDeviceEvent, conn: i32) {
+    let Some(evt) = clamp_pointer_to_shared_area(evt) else {
+        return;
+    };
+    let evt = &evt;
     #[cfg(target_os = "macos")]
     if !is_server() {
         // having GUI, run main GUI thread, otherwise crash
@@ -829,6 +1100,14 @@ pub fn handle_pointer_(evt: &PointerDeviceEvent, conn: i32) {
             }
             _ => {}
         },
+        Some(MultiTouchEvent(_evt)) => {
+            #[cfg(target_os = "linux")]
+            handle_multi_touch(_evt, conn);
+        }
+        Some(PenEvent(_evt)) => {
+            #[cfg(target_os = "linux")]
+            handle_pen(_evt, conn);
+        }
         _ => {}
     }
 }
@@ -915,64 +1194,128 @@ pub fn handle_mouse_(evt: &MouseEvent, conn: i32) {
             }
             _ => {}
         },
-        MOUSE_TYPE_WHEEL | MOUSE_TYPE_TRACKPAD => {
-            #[allow(unused_mut)]
-            let mut x = evt.x;
-            #[allow(unused_mut)]
-            let mut y = evt.y;
-            #[cfg(not(windows))]
-            {
-                x = -x;
-                y = -y;
+        MOUSE_TYPE_WHEEL | MOUSE_TYPE_TRACKPAD => handle_scroll(&mut en, evt, evt_type),
+        _ => {}
+    }
+    #[cfg(not(target_os = "macos"))]
+    for key in to_release {
+        en.key_up(key.clone());
+    }
+}
+
+fn handle_scroll(en: &mut Enigo, evt: &MouseEvent, evt_type: i32) {
+    #[allow(unused_mut)]
+    let mut x = evt.x;
+    #[allow(unused_mut)]
+    let mut y = evt.y;
+    // the peers sending the high resolution deltas also send the notches for the older versions
+    if evt_type == MOUSE_TYPE_WHEEL && (evt.hi_res_x != 0 || evt.hi_res_y != 0) {
+        #[cfg(windows)]
+        {
+            if evt.hi_res_y != 0 {
+                en.mouse_scroll_y(evt.hi_res_y);
             }
+            if evt.hi_res_x != 0 {
+                en.mouse_scroll_x(evt.hi_res_x);
+            }
+            return;
+        }
+        #[cfg(target_os = "linux")]
+        if scroll_hi_res(evt.hi_res_x, evt.hi_res_y) {
+            return;
+        }
+        #[cfg(not(windows))]
+        {
+            (x, y) = hi_res_to_notches(evt.hi_res_x, evt.hi_res_y);
+        }
+    }
+    #[cfg(not(windows))]
+    {
+        x = -x;
+        y = -y;
+    }
 
-            #[cfg(any(target_os = "macos", target_os = "windows"))]
-            let is_track_pad = evt_type == MOUSE_TYPE_TRACKPAD;
+    #[cfg(any(target_os = "macos", target_os = "windows"))]
+    let is_track_pad = evt_type == MOUSE_TYPE_TRACKPAD;
 
-            #[cfg(target_os = "macos")]
-            {
-                // TODO: support track pad on win.
-
-                // fix shift + scroll(down/up)
-                if !is_track_pad
-                    && evt
-                        .modifiers
-                        .contains(&EnumOrUnknown::new(ControlKey::Shift))
-                {
-                    x = y;
-                    y = 0;
-                }
+    #[cfg(target_os = "macos")]
+    {
+        // TODO: support track pad on win.
+
+        // fix shift + scroll(down/up)
+        if !is_track_pad
+            && evt
+                .modifiers
+                .contains(&EnumOrUnknown::new(ControlKey::Shift))
+        {
+            x = y;
+            y = 0;
+        }
 
-                if x != 0 {
-                    en.mouse_scroll_x(x, is_track_pad);
-                }
-                if y != 0 {
-                    en.mouse_scroll_y(y, is_track_pad);
-                }
-            }
+        if x != 0 {
+            en.mouse_scroll_x(x, is_track_pad);
+        }
+        if y != 0 {
+            en.mouse_scroll_y(y, is_track_pad);
+        }
+    }
 
-            #[cfg(windows)]
-            if !is_track_pad {
-                x *= WHEEL_DELTA as i32;
-                y *= WHEEL_DELTA as i32;
-            }
+    #[cfg(windows)]
+    if !is_track_pad {
+        x *= WHEEL_DELTA as i32;
+        y *= WHEEL_DELTA as i32;
+    }
 
-            #[cfg(not(target_os = "macos"))]
-            {
-                if y != 0 {
-                    en.mouse_scroll_y(y);
-                }
-                if x != 0 {
-                    en.mouse_scroll_x(x);
-                }
+    #[cfg(not(target_os = "macos"))]
+    {
+        if y != 0 {
+            en.mouse_scroll_y(y);
+        }
+        if x != 0 {
+            en.mouse_scroll_x(x);
+        }
+    }
+}
+
+// the deltas less than a notch are kept for the next events
+#[cfg(not(windows))]
+fn hi_res_to_notches(x: i32, y: i32) -> (i32, i32) {
+    take_wheel_notches(&mut HI_RES_SCROLL_REMAINDER.lock().unwrap(), x, y)
+}
+
+// Scroll with the uinput high resolution wheels, false if the uinput service is not available.
+#[cfg(target_os = "linux")]
+fn scroll_hi_res(x: i32, y: i32) -> bool {
+    let mut lock = HI_RES_SCROLL.lock().unwrap();
+    if lock.0.is_none() {
+        // don't retry connecting on every event
+        if lock
+            .1
+            .map_or(false, |t| t.elapsed() < HI_RES_SCROLL_RETRY_INTERVAL)
+        {
+            return false;
+        }
+        match super::uinput::client::UInputScroll::new() {
+            Ok(scroll) => {
+                log::info!("UInput scroll created");
+                lock.0 = Some(scroll);
+            }
+            Err(e) => {
+                log::info!("Failed to connect uinput scroll: {}", e);
+                lock.1 = Some(Instant::now());
+                return false;
             }
         }
-        _ => {}
     }
-    #[cfg(not(target_os = "macos"))]
-    for key in to_release {
-        en.key_up(key.clone());
+    let Some(scroll) = lock.0.as_mut() else {
+        return false;
+    };
+    if let Err(e) = scroll.scroll(x, y) {
+        log::error!("Failed to send scroll: {}", e);
+        *lock = (None, Some(Instant::now()));
+        return false;
     }
+    true
 }
 
 #[cfg(target_os = "windows")]
@@ -1037,6 +1380,14 @@ pub async fn lock_screen() {
 }
 
 pub fn handle_key(evt: &KeyEvent) {
+    #[cfg(all(target_os = "linux", feature = "test_display"))]
+    if super::test_display::is_enabled() {
+        super::test_display::push_input(super::test_display::TestInput::Key(evt.clone()));
+        return;
+    }
+    if !is_key_in_shared_area(evt) {
+        return;
+    }
     #[cfg(target_os = "macos")]
     if !is_server() {
         // having GUI, run main GUI thread, otherwise crash
@@ -1571,6 +1922,122 @@ async fn send_sas() -> ResultType<()> {
     Ok(())
 }
 
+pub const OPTION_TYPE_TEXT_MAX_LENGTH: &str = "type-text-max-length";
+const DEFAULT_TYPE_TEXT_MAX_LENGTH: usize = 10_000;
+const DEFAULT_TYPE_TEXT_INTERVAL: u32 = 20;
+const MAX_TYPE_TEXT_INTERVAL: u32 = 1_000;
+
+lazy_static::lazy_static! {
+    // the id of the typing in progress and the connection which started it, the id is increased to cancel it
+    static ref TYPE_TEXT: Mutex<(usize, i32)> = Default::default();
+}
+
+/// The max chars typed in a request, 0 if typing is not allowed.
+pub fn type_text_max_length() -> usize {
+    parse_type_text_max_length(&Config::get_option(OPTION_TYPE_TEXT_MAX_LENGTH))
+}
+
+fn parse_type_text_max_length(v: &str) -> usize {
+    if v.is_empty() {
+        DEFAULT_TYPE_TEXT_MAX_LENGTH
+    } else {
+        v.parse().unwrap_or(0)
+    }
+}
+
+/// Type the text as keystrokes in a new thread, the typing in progress is cancelled.
+pub fn type_text(text: &str, interval: u32, conn: i32) -> ResultType<()> {
+    let max = type_text_max_length();
+    let len = text.chars().count();
+    if len > max {
+        bail!("The text is too long to type, {} over {} chars", len, max);
+    }
+    let interval = match interval {
+        0 => DEFAULT_TYPE_TEXT_INTERVAL,
+        n => n.min(MAX_TYPE_TEXT_INTERVAL),
+    };
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    let id = {
+        let mut lock = TYPE_TEXT.lock().unwrap();
+        *lock = (lock.0 + 1, conn);
+        lock.0
+    };
+    log::info!("Start typing {} chars for connection {}", len, conn);
+    std::thread::spawn(move || {
+        for chr in text.chars() {
+            if TYPE_TEXT.lock().unwrap().0 != id || EXITING.load(Ordering::SeqCst) {
+                log::info!("Typing cancelled");
+                return;
+            }
+            #[cfg(windows)]
+            crate::platform::windows::try_change_desktop();
+            for evt in type_char_events(chr) {
+                handle_key(&evt);
+            }
+            std::thread::sleep(Duration::from_millis(interval as _));
+        }
+        log::info!("Typing finished");
+    });
+    Ok(())
+}
+
+/// Cancel the typing started by the connection, or any if `conn` is None.
+pub fn cancel_type_text(conn: Option<i32>) {
+    let mut lock = TYPE_TEXT.lock().unwrap();
+    if conn.is_none() || conn == Some(lock.1) {
+        lock.0 += 1;
+    }
+}
+
+fn type_char_events(chr: char) -> Vec<KeyEvent> {
+    let control_key = match chr {
+        '\n' => Some(ControlKey::Return),
+        '\t' => Some(ControlKey::Tab),
+        _ => None,
+    };
+    if let Some(ck) = control_key {
+        return [true, false]
+            .iter()
+            .map(|down| {
+                let mut evt = KeyEvent::new();
+                evt.set_control_key(ck);
+                evt.down = *down;
+                evt.mode = KeyboardMode::Legacy.into();
+                evt
+            })
+            .collect();
+    }
+    // The virtual keys of the layout are typed if possible, they work even if unicode input doesn't, e.g. in VMs.
+    #[cfg(windows)]
+    if let Some((vk, shift)) = crate::platform::windows::get_vk_from_char(chr) {
+        let key = |vk: u32, down: bool| {
+            let mut evt = KeyEvent::new();
+            evt.set_chr(vk << 16);
+            evt.down = down;
+            evt.mode = KeyboardMode::Translate.into();
+            evt
+        };
+        let shift_vk = winapi::um::winuser::VK_SHIFT as u32;
+        let mut evts = vec![];
+        if shift {
+            evts.push(key(shift_vk, true));
+        }
+        evts.push(key(vk, true));
+        evts.push(key(vk, false));
+        if shift {
+            evts.push(key(shift_vk, false));
+        }
+        return evts;
+    }
+    // linux: the keysym of the layout, or a spare keycode remapped; others: unicode input
+    let mut evt = KeyEvent::new();
+    evt.set_seq(chr.to_string());
+    evt.down = true;
+    evt.press = true;
+    evt.mode = KeyboardMode::Translate.into();
+    vec![evt]
+}
+
 lazy_static::lazy_static! {
     static ref MODIFIER_MAP: HashMap<i32, Key> = [
         (ControlKey::Alt, Key::Alt),
@@ -1674,3 +2141,38 @@ lazy_static::lazy_static! {
         (ControlKey::Delete, true),
     ].iter().map(|(a, b)| (a.value(), b.clone())).collect();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_type_text_max_length() {
+        assert_eq!(parse_type_text_max_length(""), DEFAULT_TYPE_TEXT_MAX_LENGTH);
+        assert_eq!(parse_type_text_max_length("100"), 100);
+        assert_eq!(parse_type_text_max_length("0"), 0);
+        assert_eq!(parse_type_text_max_length("-1"), 0);
+        assert_eq!(parse_type_text_max_length("abc"), 0);
+    }
+
+    #[test]
+    fn test_type_char_events() {
+        for (chr, key) in [('\n', ControlKey::Return), ('\t', ControlKey::Tab)] {
+            let evts = type_char_events(chr);
+            assert_eq!(evts.len(), 2);
+            assert!(evts[0].down && !evts[1].down);
+            for evt in evts.iter() {
+                assert_eq!(evt.control_key(), key);
+                assert_eq!(evt.mode.enum_value(), Ok(KeyboardMode::Legacy));
+            }
+        }
+        #[cfg(not(windows))]
+        {
+            let evts = type_char_events('你');
+            assert_eq!(evts.len(), 1);
+            assert_eq!(evts[0].seq(), "你");
+            assert!(evts[0].down && evts[0].press);
+            assert_eq!(evts[0].mode.enum_value(), Ok(KeyboardMode::Translate));
+        }
+    }
+}
@@ -0,0 +1,322 @@
+//! Prometheus metrics of the server process.
+//!
+//! Disabled by default. If the option `metrics-port` is set, the metrics are served in the text
+//! exposition format at `http://127.0.0.1:<port>/metrics`. Only the loopback interface is bound,
+//! a node exporter or a reverse proxy on the same machine is expected to expose them further.
+
+use super::*;
+use hbb_common::{
+    config::{self, Config},
+    sleep,
+    tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    },
+};
+use std::{
+    fmt::Write as _,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Instant,
+};
+
+const OPTION_PORT: &str = "metrics-port";
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+// The fps of a display is reported as 0 if no frame is encoded in this time.
+const FPS_STALE_MILLIS: u128 = 2_000;
+
+static LOGIN_FAILURES: AtomicU64 = AtomicU64::new(0);
+static SESSIONS_DIRECT: AtomicU64 = AtomicU64::new(0);
+static SESSIONS_RELAY: AtomicU64 = AtomicU64::new(0);
+static FILE_BYTES_SENT: AtomicU64 = AtomicU64::new(0);
+static FILE_BYTES_RECEIVED: AtomicU64 = AtomicU64::new(0);
+
+lazy_static::lazy_static! {
+    static ref ENCODE_FPS: Arc<Mutex<HashMap<usize, FpsCounter>>> = Default::default();
+}
+
+struct FpsCounter {
+    since: Instant,
+    frames: u32,
+    fps: u32,
+}
+
+#[inline]
+pub fn on_login_failure() {
+    LOGIN_FAILURES.fetch_add(1, Ordering::Relaxed);
+}
+
+#[inline]
+pub fn on_session_start(relay: bool) {
+    if relay {
+        SESSIONS_RELAY.fetch_add(1, Ordering::Relaxed);
+    } else {
+        SESSIONS_DIRECT.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[inline]
+pub fn on_file_bytes(sent: bool, len: u64) {
+    if sent {
+        FILE_BYTES_SENT.fetch_add(len, Ordering::Relaxed);
+    } else {
+        FILE_BYTES_RECEIVED.fetch_add(len, Ordering::Relaxed);
+    }
+}
+
+/// Called by the video service for every encoded frame.
+pub fn on_frame_encoded(display: usize) {
+    let mut lock = ENCODE_FPS.lock().unwrap();
+    let counter = lock.entry(display).or_insert_with(|| FpsCounter {
+        since: Instant::now(),
+        frames: 0,
+        fps: 0,
+    });
+    counter.frames += 1;
+    let elapsed = counter.since.elapsed().as_millis();
+    if elapsed >= 1000 {
+        counter.fps = (counter.frames as u128 * 1000 / elapsed) as _;
+        counter.frames = 0;
+        counter.since = Instant::now();
+    }
+}
+
+/// Called when the video service of a display exits.
+pub fn on_video_service_exit(display: usize) {
+    ENCODE_FPS.lock().unwrap().remove(&display);
+}
+
+fn get_port() -> u16 {
+    Config::get_option(OPTION_PORT).parse().unwrap_or(0)
+}
+
+pub async fn start() {
+    let mut listener = None;
+    let mut port = 0;
+    loop {
+        if listener.is_none() {
+            port = get_port();
+            if port == 0 {
+                sleep(1.).await;
+                continue;
+            }
+            match TcpListener::bind(("127.0.0.1", port)).await {
+                Ok(l) => {
+                    log::info!("Metrics listening on: {:?}", l.local_addr());
+                    listener = Some(l);
+                }
+                Err(err) => {
+                    log::error!("Failed to listen metrics on port {}: {}", port, err);
+                    while port == get_port() {
+                        sleep(1.).await;
+                    }
+                    continue;
+                }
+            }
+        }
+        if let Some(l) = listener.as_mut() {
+            if port != get_port() {
+                log::info!("Exit metrics listen");
+                listener = None;
+                continue;
+            }
+            if let Ok(Ok((stream, _))) = hbb_common::timeout(1000, l.accept()).await {
+                tokio::spawn(async move {
+                    allow_err!(handle(stream).await);
+                });
+            }
+        }
+    }
+}
+
+async fn handle(mut stream: TcpStream) -> ResultType<()> {
+    let mut buf = Vec::new();
+    let mut tmp = [0u8; 1024];
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = hbb_common::timeout(3000, stream.read(&mut tmp)).await??;
+        if n == 0 || buf.len() > 8192 {
+            return Ok(());
+        }
+        buf.extend_from_slice(&tmp[..n]);
+    }
+    let request = String::from_utf8_lossy(&buf);
+    let path = request.split_whitespace().nth(1).unwrap_or_default();
+    let (status, body) = if request.starts_with("GET ") && (path == "/metrics" || path == "/") {
+        ("200 OK", render())
+    } else {
+        ("404 Not Found", "".to_owned())
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        CONTENT_TYPE,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await.ok();
+    Ok(())
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP {} {}", name, help).ok();
+    writeln!(out, "# TYPE {} {}", name, kind).ok();
+}
+
+fn render() -> String {
+    let mut out = String::new();
+
+    header(
+        &mut out,
+        "rustdesk_connections",
+        "gauge",
+        "Authorized connections by type.",
+    );
+    let conns = Connection::authed_conns();
+    for (label, conn_type) in [
+        ("remote", AuthConnType::Remote),
+        ("file_transfer", AuthConnType::FileTransfer),
+        ("port_forward", AuthConnType::PortForward),
+    ] {
+        let n = conns.iter().filter(|c| c.1 == conn_type).count();
+        writeln!(out, "rustdesk_connections{{type=\"{}\"}} {}", label, n).ok();
+    }
+
+    header(
+        &mut out,
+        "rustdesk_login_failures_total",
+        "counter",
+        "Failed login attempts.",
+    );
+    writeln!(
+        out,
+        "rustdesk_login_failures_total {}",
+        LOGIN_FAILURES.load(Ordering::Relaxed)
+    )
+    .ok();
+
+    header(
+        &mut out,
+        "rustdesk_sessions_total",
+        "counter",
+        "Accepted sessions by path.",
+    );
+    writeln!(
+        out,
+        "rustdesk_sessions_total{{path=\"direct\"}} {}",
+        SESSIONS_DIRECT.load(Ordering::Relaxed)
+    )
+    .ok();
+    writeln!(
+        out,
+        "rustdesk_sessions_total{{path=\"relay\"}} {}",
+        SESSIONS_RELAY.load(Ordering::Relaxed)
+    )
+    .ok();
+
+    header(
+        &mut out,
+        "rustdesk_sessions",
+        "gauge",
+        "Active sessions by path.",
+    );
+    let stats: Vec<_> = crate::session_stats::all()
+        .into_iter()
+        .filter(|s| s.side == crate::session_stats::SIDE_CONTROLLED)
+        .collect();
+    let direct = stats.iter().filter(|s| s.direct == Some(true)).count();
+    let relay = stats.iter().filter(|s| s.direct == Some(false)).count();
+    writeln!(out, "rustdesk_sessions{{path=\"direct\"}} {}", direct).ok();
+    writeln!(out, "rustdesk_sessions{{path=\"relay\"}} {}", relay).ok();
+
+    header(
+        &mut out,
+        "rustdesk_file_transfer_bytes_total",
+        "counter",
+        "File transfer bytes by direction.",
+    );
+    writeln!(
+        out,
+        "rustdesk_file_transfer_bytes_total{{direction=\"sent\"}} {}",
+        FILE_BYTES_SENT.load(Ordering::Relaxed)
+    )
+    .ok();
+    writeln!(
+        out,
+        "rustdesk_file_transfer_bytes_total{{direction=\"received\"}} {}",
+        FILE_BYTES_RECEIVED.load(Ordering::Relaxed)
+    )
+    .ok();
+
+    let online = config::get_online_states();
+    header(
+        &mut out,
+        "rustdesk_rendezvous_registered",
+        "gauge",
+        "Whether registered to the rendezvous server.",
+    );
+    for (host, latency) in online.iter() {
+        writeln!(
+            out,
+            "rustdesk_rendezvous_registered{{host=\"{}\"}} {}",
+            escape(host),
+            (*latency > 0) as u8
+        )
+        .ok();
+    }
+    header(
+        &mut out,
+        "rustdesk_rendezvous_latency_milliseconds",
+        "gauge",
+        "Registration latency to the rendezvous server.",
+    );
+    for (host, latency) in online.iter().filter(|(_, l)| **l > 0) {
+        writeln!(
+            out,
+            "rustdesk_rendezvous_latency_milliseconds{{host=\"{}\"}} {}",
+            escape(host),
+            latency
+        )
+        .ok();
+    }
+
+    header(
+        &mut out,
+        "rustdesk_nat_type",
+        "gauge",
+        "Detected NAT type, 0: unknown, 1: asymmetric, 2: symmetric.",
+    );
+    writeln!(out, "rustdesk_nat_type {}", Config::get_nat_type()).ok();
+
+    header(
+        &mut out,
+        "rustdesk_video_encode_fps",
+        "gauge",
+        "Encoded frames per second by display.",
+    );
+    let mut fps: Vec<_> = ENCODE_FPS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(display, c)| {
+            let stale = c.since.elapsed().as_millis() > FPS_STALE_MILLIS;
+            (*display, if stale { 0 } else { c.fps })
+        })
+        .collect();
+    fps.sort();
+    for (display, fps) in fps {
+        writeln!(
+            out,
+            "rustdesk_video_encode_fps{{display=\"{}\"}} {}",
+            display, fps
+        )
+        .ok();
+    }
+
+    out
+}
+
+fn escape(v: &str) -> String {
+    v.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -240,6 +240,23 @@ impl<T: Subscriber + From<ConnInner>> ServiceTmpl<T> {
         conn_ids
     }
 
+    pub fn send_video_frame_filter(
+        &self,
+        msg: Message,
+        filter: impl Fn(i32) -> bool,
+    ) -> HashSet<i32> {
+        let msg = Arc::new(msg);
+        let mut conn_ids = HashSet::new();
+        let mut lock = self.0.write().unwrap();
+        for s in lock.subscribes.values_mut() {
+            if filter(s.id()) {
+                s.send(msg.clone());
+                conn_ids.insert(s.id());
+            }
+        }
+        conn_ids
+    }
+
     pub fn send_without(&self, msg: Message, sub: i32) {
         let mut lock = self.0.write().unwrap();
         let msg = Arc::new(msg);
@@ -0,0 +1,285 @@
+//! Share a window or a rectangle of the desktop instead of the whole displays.
+//!
+//! If the option `share-window` is set, the first window whose title contains it is shared, X11 only for now.
+//! Otherwise, if the option `share-rect` is set to `x,y,width,height` in the desktop coordinates,
+//! the rectangle is shared. Wayland is not supported, the capturer can't crop the frames,
+//! so nothing is shared as an area there and the whole displays are shared as usual.
+//!
+//! The area is reported to the peer as the only display, with [`SharedArea`] set.
+//! The frames of the display which contains the area are cropped to it, and the mouse events are clamped into it.
+//! The windows above the shared window are visible in the area too.
+//! The key presses are dropped if the focus is not in the area, see [`has_focus`].
+
+use super::*;
+use scrap::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub const OPTION_SHARE_WINDOW: &str = "share-window";
+pub const OPTION_SHARE_RECT: &str = "share-rect";
+
+lazy_static::lazy_static! {
+    static ref AREA: RwLock<Option<Area>> = Default::default();
+}
+// Logged once, the displays are checked every second.
+static UNSUPPORTED_LOGGED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Area {
+    // the index of the display which contains the area
+    pub display: usize,
+    // in the desktop coordinates
+    pub x: i32,
+    pub y: i32,
+    pub width: usize,
+    pub height: usize,
+    pub window: String,
+    // false if the shared window is not found, nothing is captured then
+    pub visible: bool,
+}
+
+impl Area {
+    /// The area relative to the origin of its display, (x, y, width, height).
+    pub fn crop(&self, origin: (i32, i32)) -> (usize, usize, usize, usize) {
+        (
+            (self.x - origin.0) as _,
+            (self.y - origin.1) as _,
+            self.width,
+            self.height,
+        )
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x
+            && y >= self.y
+            && x < self.x + self.width as i32
+            && y < self.y + self.height as i32
+    }
+
+    /// Clamp a point in the desktop coordinates into the area.
+    pub fn clamp(&self, x: i32, y: i32) -> (i32, i32) {
+        (
+            x.clamp(self.x, self.x + self.width as i32 - 1),
+            y.clamp(self.y, self.y + self.height as i32 - 1),
+        )
+    }
+
+    pub fn to_display_info(&self, name: String) -> DisplayInfo {
+        DisplayInfo {
+            x: self.x,
+            y: self.y,
+            width: self.width as _,
+            height: self.height as _,
+            name,
+            online: true,
+            cursor_embedded: false,
+            original_resolution: Some(Resolution {
+                width: self.width as _,
+                height: self.height as _,
+                ..Default::default()
+            })
+            .into(),
+            shared_area: Some(SharedArea {
+                display: self.display as _,
+                window: self.window.clone(),
+                visible: self.visible,
+                ..Default::default()
+            })
+            .into(),
+            ..Default::default()
+        }
+    }
+}
+
+#[inline]
+pub fn is_enabled() -> bool {
+    !Config::get_option(OPTION_SHARE_WINDOW).is_empty()
+        || !Config::get_option(OPTION_SHARE_RECT).is_empty()
+}
+
+/// The shared area, `None` if the whole displays are shared.
+#[inline]
+pub fn get() -> Option<Area> {
+    AREA.read().unwrap().clone()
+}
+
+/// Whether the keyboard input goes to the area.
+///
+/// The focused window must be the shared window, or be centered in the shared rectangle.
+/// If the focused window is unknown, e.g. not X11, the cursor must be in the area.
+pub fn has_focus(area: &Area) -> bool {
+    if !area.visible {
+        return false;
+    }
+    match focused_window() {
+        Some((title, (x, y, width, height))) => {
+            if !area.window.is_empty() {
+                title.to_lowercase().contains(&area.window.to_lowercase())
+            } else {
+                area.contains(x + width as i32 / 2, y + height as i32 / 2)
+            }
+        }
+        None => crate::get_cursor_pos().map_or(false, |(x, y)| area.contains(x, y)),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn focused_window() -> Option<(String, (i32, i32, usize, usize))> {
+    if !crate::platform::linux::is_x11() {
+        return None;
+    }
+    scrap::x11::active_window().map(|(title, r)| (title, (r.x, r.y, r.width as _, r.height as _)))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn focused_window() -> Option<(String, (i32, i32, usize, usize))> {
+    None
+}
+
+/// Update the shared area with the current displays, called when the displays are checked.
+pub(super) fn update(displays: &[Display]) -> Option<Area> {
+    update_with(displays, can_crop())
+}
+
+fn update_with(displays: &[Display], can_crop: bool) -> Option<Area> {
+    if !can_crop {
+        // Never report an area which is not cropped, the whole display would be sent.
+        *AREA.write().unwrap() = None;
+        if is_enabled() && !UNSUPPORTED_LOGGED.swap(true, Ordering::SeqCst) {
+            log::error!(
+                "{} and {} are not supported by the capturer, ignored",
+                OPTION_SHARE_WINDOW,
+                OPTION_SHARE_RECT
+            );
+        }
+        return None;
+    }
+    let window = Config::get_option(OPTION_SHARE_WINDOW);
+    let rect = if !window.is_empty() {
+        window_rect(&window)
+    } else {
+        let rect = Config::get_option(OPTION_SHARE_RECT);
+        if rect.is_empty() || displays.is_empty() {
+            *AREA.write().unwrap() = None;
+            return None;
+        }
+        parse_rect(&rect)
+    };
+    let mut lock = AREA.write().unwrap();
+    let area = match rect.and_then(|r| locate(displays, r)) {
+        Some((display, (x, y, width, height))) => Area {
+            display,
+            x,
+            y,
+            width,
+            height,
+            window,
+            visible: true,
+        },
+        // Keep the last geometry if the window is hidden, so that nothing else is shared in the meantime.
+        None => match lock.as_ref().filter(|a| a.window == window) {
+            Some(last) => Area {
+                visible: false,
+                ..last.clone()
+            },
+            None => {
+                let d = displays.first()?;
+                Area {
+                    display: 0,
+                    x: d.origin().0,
+                    y: d.origin().1,
+                    width: d.width(),
+                    height: d.height(),
+                    window,
+                    visible: false,
+                }
+            }
+        },
+    };
+    if lock.as_ref() != Some(&area) {
+        log::info!("shared area: {:?}", area);
+    }
+    *lock = Some(area.clone());
+    Some(area)
+}
+
+// Whether the capturer crops the frames to the area, see `video_service::get_capturer`.
+#[cfg(target_os = "linux")]
+fn can_crop() -> bool {
+    #[cfg(feature = "test_display")]
+    if super::test_display::is_enabled() {
+        return false;
+    }
+    crate::platform::linux::is_x11()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn can_crop() -> bool {
+    true
+}
+
+fn parse_rect(s: &str) -> Option<(i32, i32, usize, usize)> {
+    let v: Vec<i32> = s
+        .split(',')
+        .map(|x| x.trim().parse())
+        .collect::<Result<_, _>>()
+        .ok()?;
+    if v.len() != 4 || v[2] <= 0 || v[3] <= 0 {
+        log::error!("invalid {}: {}", OPTION_SHARE_RECT, s);
+        return None;
+    }
+    Some((v[0], v[1], v[2] as _, v[3] as _))
+}
+
+#[cfg(target_os = "linux")]
+fn window_rect(title: &str) -> Option<(i32, i32, usize, usize)> {
+    if !crate::platform::linux::is_x11() {
+        return None;
+    }
+    scrap::x11::find_window_rect(title).map(|r| (r.x, r.y, r.width as _, r.height as _))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn window_rect(_title: &str) -> Option<(i32, i32, usize, usize)> {
+    None
+}
+
+// The display which contains the center of the rectangle, and the rectangle clipped to it.
+// The size is rounded down to even, which is required by some encoders.
+fn locate(
+    displays: &[Display],
+    (x, y, width, height): (i32, i32, usize, usize),
+) -> Option<(usize, (i32, i32, usize, usize))> {
+    let (cx, cy) = (x + width as i32 / 2, y + height as i32 / 2);
+    displays.iter().enumerate().find_map(|(i, d)| {
+        let (dx, dy) = d.origin();
+        let (dr, db) = (dx + d.width() as i32, dy + d.height() as i32);
+        if cx < dx || cy < dy || cx >= dr || cy >= db {
+            return None;
+        }
+        let (left, top) = (std::cmp::max(x, dx), std::cmp::max(y, dy));
+        let right = std::cmp::min(x + width as i32, dr);
+        let bottom = std::cmp::min(y + height as i32, db);
+        let (w, h) = ((right - left) as usize & !1, (bottom - top) as usize & !1);
+        (w > 0 && h > 0).then_some((i, (left, top, w, h)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_area_without_crop() {
+        *AREA.write().unwrap() = Some(Area {
+            display: 0,
+            x: 0,
+            y: 0,
+            width: 100,
+            height: 100,
+            window: "".to_owned(),
+            visible: true,
+        });
+        assert_eq!(update_with(&[], false), None);
+        assert_eq!(get(), None);
+    }
+}
@@ -0,0 +1,166 @@
+//! A virtual display for the end-to-end tests, which needs no X11, Wayland or audio device.
+//!
+//! Built with the feature `test_display` only, and enabled by the env `RUSTDESK_TEST_DISPLAY=<width>x<height>`, e.g. `1280x720`.
+//! The frames are animated deterministically from the frame index, and the audio is a sine wave.
+//! The input events and the clipboard are kept in memory, so that the tests can check them.
+
+use super::{video_service::CapturerInfo, *};
+use scrap::{Frame, PixelBuffer, Pixfmt, TraitCapturer};
+
+pub const ENV_TEST_DISPLAY: &str = "RUSTDESK_TEST_DISPLAY";
+const DISPLAY_NAME: &str = "test-display";
+// The size of the moving square.
+const SQUARE_SIZE: usize = 64;
+// The frequency of the sine wave of the audio.
+const TONE_HZ: f32 = 440.;
+
+lazy_static::lazy_static! {
+    static ref INPUT: Mutex<Vec<TestInput>> = Default::default();
+    static ref CLIPBOARD: Mutex<String> = Default::default();
+}
+
+/// An input event received from the peer.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TestInput {
+    Mouse(MouseEvent),
+    Key(KeyEvent),
+}
+
+/// The size of the virtual display, `None` if it is not enabled.
+pub fn size() -> Option<(usize, usize)> {
+    let v = std::env::var(ENV_TEST_DISPLAY).ok()?;
+    let (w, h) = v.trim().split_once('x')?;
+    let (w, h) = (w.parse::<usize>().ok()?, h.parse::<usize>().ok()?);
+    // even, as required by some encoders
+    (w >= 2 && h >= 2).then_some((w & !1, h & !1))
+}
+
+#[inline]
+pub fn is_enabled() -> bool {
+    size().is_some()
+}
+
+pub(super) fn display_info() -> Option<DisplayInfo> {
+    let (width, height) = size()?;
+    Some(DisplayInfo {
+        width: width as _,
+        height: height as _,
+        name: DISPLAY_NAME.to_owned(),
+        online: true,
+        original_resolution: Some(Resolution {
+            width: width as _,
+            height: height as _,
+            ..Default::default()
+        })
+        .into(),
+        ..Default::default()
+    })
+}
+
+pub(super) fn get_capturer(current: usize) -> ResultType<CapturerInfo> {
+    let Some((width, height)) = size() else {
+        bail!("Test display is not enabled");
+    };
+    if current != 0 {
+        bail!("Failed to get display {}, displays len: 1", current);
+    }
+    Ok(CapturerInfo {
+        origin: (0, 0),
+        width,
+        height,
+        ndisplay: 1,
+        current,
+        privacy_mode_id: 0,
+        _capturer_privacy_mode_id: 0,
+        capturer: Box::new(TestCapturer {
+            width,
+            height,
+            index: 0,
+            data: Vec::new(),
+        }),
+        crop: None,
+    })
+}
+
+struct TestCapturer {
+    width: usize,
+    height: usize,
+    index: u64,
+    data: Vec<u8>,
+}
+
+impl TraitCapturer for TestCapturer {
+    fn frame<'a>(&'a mut self, _timeout: std::time::Duration) -> std::io::Result<Frame<'a>> {
+        draw(self.index, self.width, self.height, &mut self.data);
+        self.index += 1;
+        Ok(Frame::PixelBuffer(PixelBuffer::new(
+            &self.data,
+            Pixfmt::BGRA,
+            self.width,
+            self.height,
+        )))
+    }
+
+    #[cfg(feature = "gpucodec")]
+    fn device(&self) -> scrap::AdapterDevice {
+        Default::default()
+    }
+
+    #[cfg(feature = "gpucodec")]
+    fn set_output_texture(&mut self, _texture: bool) {}
+}
+
+/// Draw the frame `index` in BGRA: a gradient background, with a white square moving to the right.
+pub fn draw(index: u64, width: usize, height: usize, data: &mut Vec<u8>) {
+    data.resize(width * height * 4, 0);
+    let square_x = (index as usize * 8) % std::cmp::max(width.saturating_sub(SQUARE_SIZE), 1);
+    let square_y = height.saturating_sub(SQUARE_SIZE) / 2;
+    for (y, row) in data.chunks_exact_mut(width * 4).enumerate() {
+        for (x, px) in row.chunks_exact_mut(4).enumerate() {
+            let in_square = x >= square_x
+                && x < square_x + SQUARE_SIZE
+                && y >= square_y
+                && y < square_y + SQUARE_SIZE;
+            if in_square {
+                px.copy_from_slice(&[255, 255, 255, 255]);
+            } else {
+                px.copy_from_slice(&[
+                    (x * 255 / width) as u8,
+                    (y * 255 / height) as u8,
+                    (index % 256) as u8,
+                    255,
+                ]);
+            }
+        }
+    }
+}
+
+/// The audio frame `index` of 10ms, interleaved f32 samples.
+pub fn audio_frame(index: u64, sample_rate: u32, channels: u16) -> Vec<f32> {
+    let n = sample_rate as usize / 100;
+    let start = index * n as u64;
+    (0..n)
+        .flat_map(|i| {
+            let t = (start + i as u64) as f32 / sample_rate as f32;
+            let v = (2. * std::f32::consts::PI * TONE_HZ * t).sin() * 0.5;
+            std::iter::repeat(v).take(channels as _)
+        })
+        .collect()
+}
+
+pub(super) fn push_input(input: TestInput) {
+    INPUT.lock().unwrap().push(input);
+}
+
+/// Take the input events received since the last call.
+pub fn take_input() -> Vec<TestInput> {
+    std::mem::take(&mut *INPUT.lock().unwrap())
+}
+
+pub fn get_clipboard() -> String {
+    CLIPBOARD.lock().unwrap().clone()
+}
+
+pub fn set_clipboard(content: String) {
+    *CLIPBOARD.lock().unwrap() = content;
+}
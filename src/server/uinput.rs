@@ -1,8 +1,12 @@
-use crate::ipc::{self, new_listener, Connection, Data, DataKeyboard, DataMouse};
+use crate::ipc::{
+    self, new_listener, Connection, Data, DataGamepad, DataKeyboard, DataMouse, DataPen,
+    DataPenState, DataTouch,
+};
 use enigo::{Key, KeyboardControllable, MouseButton, MouseControllable};
 use evdev::{
     uinput::{VirtualDevice, VirtualDeviceBuilder},
-    AttributeSet, EventType, InputEvent,
+    AbsInfo, AbsoluteAxisType, AttributeSet, EventType, FFEffectKind, InputEvent, InputEventKind,
+    RelativeAxisType, UInputEvent, UInputEventType, UinputAbsSetup,
 };
 use hbb_common::{allow_err, bail, log, tokio::{self, runtime::Runtime}, ResultType};
 
@@ -11,9 +15,14 @@ static IPC_REQUEST_TIMEOUT: u64 = 1000;
 static IPC_POSTFIX_KEYBOARD: &str = "_uinput_keyboard";
 static IPC_POSTFIX_MOUSE: &str = "_uinput_mouse";
 static IPC_POSTFIX_CONTROL: &str = "_uinput_control";
+static IPC_POSTFIX_TOUCH: &str = "_uinput_touch";
+static IPC_POSTFIX_PEN: &str = "_uinput_pen";
+static IPC_POSTFIX_GAMEPAD: &str = "_uinput_gamepad";
+static IPC_POSTFIX_SCROLL: &str = "_uinput_scroll";
 
 pub mod client {
     use super::*;
+    use std::{collections::HashMap, sync::Mutex};
 
     pub struct UInputKeyboard {
         conn: Connection,
@@ -154,6 +163,151 @@ pub mod client {
         }
     }
 
+    /// The client of a device of the absolute positions, created by the service with the resolution.
+    pub struct UInputAbsolute {
+        conn: Connection,
+        rt: Runtime,
+        // ((minx, maxx), (miny, maxy))
+        resolution: ((i32, i32), (i32, i32)),
+        resolution_data: fn((i32, i32), (i32, i32)) -> Data,
+    }
+
+    impl UInputAbsolute {
+        // Created in the input thread, so the runtime is owned to drive the ipc connection.
+        fn new(
+            postfix: &str,
+            resolution_data: fn((i32, i32), (i32, i32)) -> Data,
+        ) -> ResultType<Self> {
+            let rt = Runtime::new()?;
+            let conn = rt.block_on(ipc::connect(IPC_CONN_TIMEOUT, postfix))?;
+            Ok(Self {
+                conn,
+                rt,
+                resolution: Default::default(),
+                resolution_data,
+            })
+        }
+
+        pub fn new_touch() -> ResultType<Self> {
+            Self::new(IPC_POSTFIX_TOUCH, |x, y| {
+                Data::Touch(DataTouch::Resolution(x, y))
+            })
+        }
+
+        pub fn new_pen() -> ResultType<Self> {
+            Self::new(IPC_POSTFIX_PEN, |x, y| Data::Pen(DataPen::Resolution(x, y)))
+        }
+
+        pub fn send(&mut self, data: Data) -> ResultType<()> {
+            self.rt.block_on(self.conn.send(&data))
+        }
+
+        // The device is recreated by the service if the resolution changes.
+        pub fn set_resolution(&mut self, resolution: ((i32, i32), (i32, i32))) -> ResultType<()> {
+            if resolution != self.resolution {
+                self.send((self.resolution_data)(resolution.0, resolution.1))?;
+                self.resolution = resolution;
+            }
+            Ok(())
+        }
+    }
+
+    lazy_static::lazy_static! {
+        // postfix -> if the service is running, it runs as long as the root service starting the server
+        static ref SERVICE_RUNNING: Mutex<HashMap<&'static str, bool>> = Default::default();
+    }
+
+    // Checked once, not to open an ipc connection for each login.
+    async fn is_service_running(postfix: &'static str) -> bool {
+        if let Some(running) = SERVICE_RUNNING.lock().unwrap().get(postfix) {
+            return *running;
+        }
+        let running = ipc::connect(IPC_CONN_TIMEOUT, postfix).await.is_ok();
+        SERVICE_RUNNING.lock().unwrap().insert(postfix, running);
+        running
+    }
+
+    /// The touch service is only running with the root service.
+    pub async fn is_touch_supported() -> bool {
+        is_service_running(IPC_POSTFIX_TOUCH).await
+    }
+
+    /// The pen service is only running with the root service.
+    pub async fn is_pen_supported() -> bool {
+        is_service_running(IPC_POSTFIX_PEN).await
+    }
+
+    pub struct UInputScroll {
+        conn: Connection,
+        rt: Runtime,
+    }
+
+    impl UInputScroll {
+        // Created in the input thread, so the runtime is owned to drive the ipc connection.
+        pub fn new() -> ResultType<Self> {
+            let rt = Runtime::new()?;
+            let conn = rt.block_on(ipc::connect(IPC_CONN_TIMEOUT, IPC_POSTFIX_SCROLL))?;
+            Ok(Self { conn, rt })
+        }
+
+        /// Scroll by the high resolution deltas, 120 per notch, positive for left and up.
+        pub fn scroll(&mut self, x: i32, y: i32) -> ResultType<()> {
+            self.rt.block_on(self.conn.send(&Data::HiResScroll(x, y)))
+        }
+    }
+
+    // The gamepads of a connection, unplugged when it's dropped.
+    pub struct UInputGamepad {
+        tx: tokio::sync::mpsc::UnboundedSender<DataGamepad>,
+    }
+
+    impl UInputGamepad {
+        // `on_rumble(index, strong, weak, duration)`
+        pub async fn new<F>(on_rumble: F) -> ResultType<Self>
+        where
+            F: Fn(u32, u16, u16, u32) + Send + 'static,
+        {
+            let mut conn = ipc::connect(IPC_CONN_TIMEOUT, IPC_POSTFIX_GAMEPAD).await?;
+            let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<DataGamepad>();
+            tokio::spawn(async move {
+                loop {
+                    tokio::select! {
+                        data = rx.recv() => match data {
+                            Some(data) => {
+                                if let Err(e) = conn.send(&Data::Gamepad(data)).await {
+                                    log::error!("Failed to send gamepad data: {}", e);
+                                    break;
+                                }
+                            }
+                            None => break,
+                        },
+                        res = conn.next() => match res {
+                            Ok(Some(Data::Gamepad(DataGamepad::Rumble(index, strong, weak, duration)))) => {
+                                on_rumble(index, strong, weak, duration);
+                            }
+                            Err(e) => {
+                                log::info!("UInput gamepad ipc connection closed: {}", e);
+                                break;
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            });
+            Ok(Self { tx })
+        }
+
+        pub fn send(&self, data: DataGamepad) -> ResultType<()> {
+            self.tx.send(data)?;
+            Ok(())
+        }
+    }
+
+    /// The gamepad service is only running with the root service.
+    pub async fn is_gamepad_supported() -> bool {
+        is_service_running(IPC_POSTFIX_GAMEPAD).await
+    }
+
     pub async fn set_resolution(minx: i32, maxx: i32, miny: i32, maxy: i32) -> ResultType<()> {
         let mut conn = ipc::connect(IPC_CONN_TIMEOUT, IPC_POSTFIX_CONTROL).await?;
         conn.send(&Data::Control(ipc::DataControl::Resolution {
@@ -174,6 +328,42 @@ pub mod service {
     use mouce::MouseActions;
     use std::{collections::HashMap, sync::Mutex};
 
+    const TOUCH_SLOTS: i32 = 10;
+    const TOUCH_MAX_PRESSURE: i32 = 1000;
+    const TOUCH_DEFAULT_PRESSURE: i32 = 500;
+    const PEN_MAX_PRESSURE: i32 = 1000;
+    const PEN_DEFAULT_PRESSURE: i32 = 500;
+    const GAMEPAD_MAX: u32 = 4;
+    const GAMEPAD_EFFECTS_MAX: u32 = 16;
+    const GAMEPAD_FF_INTERVAL: u64 = 20;
+    const GAMEPAD_DPAD_UP: usize = 11;
+    // in the bit order of `GamepadState.buttons`, x and y as xpad, the d-pad is a hat
+    const GAMEPAD_BUTTONS: [Option<evdev::Key>; 15] = [
+        Some(evdev::Key::BTN_SOUTH),
+        Some(evdev::Key::BTN_EAST),
+        Some(evdev::Key::BTN_NORTH),
+        Some(evdev::Key::BTN_WEST),
+        Some(evdev::Key::BTN_TL),
+        Some(evdev::Key::BTN_TR),
+        Some(evdev::Key::BTN_SELECT),
+        Some(evdev::Key::BTN_START),
+        Some(evdev::Key::BTN_MODE),
+        Some(evdev::Key::BTN_THUMBL),
+        Some(evdev::Key::BTN_THUMBR),
+        None,
+        None,
+        None,
+        None,
+    ];
+    const GAMEPAD_AXES: [AbsoluteAxisType; 6] = [
+        AbsoluteAxisType::ABS_X,
+        AbsoluteAxisType::ABS_Y,
+        AbsoluteAxisType::ABS_RX,
+        AbsoluteAxisType::ABS_RY,
+        AbsoluteAxisType::ABS_Z,
+        AbsoluteAxisType::ABS_RZ,
+    ];
+
     lazy_static::lazy_static! {
     static ref KEY_MAP: HashMap<enigo::Key, evdev::Key> = HashMap::from(
         [
@@ -673,6 +863,625 @@ pub mod service {
         });
     }
 
+    enum AbsoluteData<E> {
+        // ((minx, maxx), (miny, maxy))
+        Resolution((i32, i32), (i32, i32)),
+        Event(E),
+    }
+
+    // A device of the absolute positions in the resolution of the displays.
+    trait AbsoluteDevice: Sized + Send + 'static {
+        type Event: Send;
+        const NAME: &'static str;
+
+        fn parse(data: Data) -> Option<AbsoluteData<Self::Event>>;
+        fn create(rng_x: (i32, i32), rng_y: (i32, i32)) -> ResultType<Self>;
+        fn handle(&mut self, event: &Self::Event);
+    }
+
+    fn spawn_absolute_handler<D: AbsoluteDevice>(mut stream: ipc::Connection) {
+        tokio::spawn(async move {
+            // ((minx, maxx), (miny, maxy))
+            let mut resolution = None;
+            // created at the first event, so that checking the service doesn't add a device
+            let mut device: Option<D> = None;
+            loop {
+                let data = match stream.next().await {
+                    Err(err) => {
+                        log::info!("UInput {} ipc connection closed: {}", D::NAME, err);
+                        break;
+                    }
+                    Ok(Some(data)) => data,
+                    Ok(None) => continue,
+                };
+                match D::parse(data) {
+                    Some(AbsoluteData::Resolution(rng_x, rng_y)) => {
+                        if resolution != Some((rng_x, rng_y)) {
+                            resolution = Some((rng_x, rng_y));
+                            device = None;
+                        }
+                    }
+                    Some(AbsoluteData::Event(event)) => {
+                        if device.is_none() {
+                            let Some((rng_x, rng_y)) = resolution else {
+                                continue;
+                            };
+                            log::info!(
+                                "Create uinput {} with rng_x: ({}, {}), rng_y: ({}, {})",
+                                D::NAME,
+                                rng_x.0,
+                                rng_x.1,
+                                rng_y.0,
+                                rng_y.1
+                            );
+                            match D::create(rng_x, rng_y) {
+                                Ok(d) => device = Some(d),
+                                Err(e) => {
+                                    log::error!("Failed to create {}, {}", D::NAME, e);
+                                    return;
+                                }
+                            }
+                        }
+                        if let Some(device) = device.as_mut() {
+                            device.handle(&event);
+                        }
+                    }
+                    None => {}
+                }
+            }
+        });
+    }
+
+    struct Touch {
+        device: VirtualDevice,
+        // id -> (slot, x, y, pressure)
+        points: HashMap<i32, (i32, i32, i32, i32)>,
+        tracking_id: i32,
+    }
+
+    fn create_uinput_touch(rng_x: (i32, i32), rng_y: (i32, i32)) -> ResultType<VirtualDevice> {
+        let mut keys = AttributeSet::<evdev::Key>::new();
+        keys.insert(evdev::Key::BTN_TOUCH);
+        let mut props = AttributeSet::<evdev::PropType>::new();
+        props.insert(evdev::PropType::DIRECT);
+        let abs = |axis, min, max| UinputAbsSetup::new(axis, AbsInfo::new(min, min, max, 0, 0, 0));
+        let touch = VirtualDeviceBuilder::new()?
+            .name("RustDesk UInput Touchscreen")
+            .with_keys(&keys)?
+            .with_properties(&props)?
+            .with_absolute_axis(&abs(AbsoluteAxisType::ABS_X, rng_x.0, rng_x.1))?
+            .with_absolute_axis(&abs(AbsoluteAxisType::ABS_Y, rng_y.0, rng_y.1))?
+            .with_absolute_axis(&abs(AbsoluteAxisType::ABS_PRESSURE, 0, TOUCH_MAX_PRESSURE))?
+            .with_absolute_axis(&abs(AbsoluteAxisType::ABS_MT_SLOT, 0, TOUCH_SLOTS - 1))?
+            .with_absolute_axis(&abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, 0, u16::MAX as _))?
+            .with_absolute_axis(&abs(AbsoluteAxisType::ABS_MT_POSITION_X, rng_x.0, rng_x.1))?
+            .with_absolute_axis(&abs(AbsoluteAxisType::ABS_MT_POSITION_Y, rng_y.0, rng_y.1))?
+            .with_absolute_axis(&abs(
+                AbsoluteAxisType::ABS_MT_PRESSURE,
+                0,
+                TOUCH_MAX_PRESSURE,
+            ))?
+            .build()?;
+        Ok(touch)
+    }
+
+    // Multi-touch protocol B, a slot per finger, and the single touch axes follow the first slot.
+    fn handle_touch(touch: &mut Touch, points: &[(i32, i32, i32, u32, bool)]) {
+        log::trace!("handle_touch {:?}", points);
+        let abs = |axis: AbsoluteAxisType, value: i32| {
+            InputEvent::new(EventType::ABSOLUTE, axis.0, value)
+        };
+        let was_down = !touch.points.is_empty();
+        let mut events = vec![];
+        for &(id, x, y, pressure, down) in points {
+            let slot = match touch.points.get(&id) {
+                Some(p) => p.0,
+                None if down => {
+                    match (0..TOUCH_SLOTS).find(|s| !touch.points.values().any(|p| p.0 == *s)) {
+                        Some(slot) => slot,
+                        None => continue,
+                    }
+                }
+                None => continue,
+            };
+            events.push(abs(AbsoluteAxisType::ABS_MT_SLOT, slot));
+            if down {
+                if !touch.points.contains_key(&id) {
+                    touch.tracking_id = (touch.tracking_id + 1) % (u16::MAX as i32);
+                    events.push(abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, touch.tracking_id));
+                }
+                let pressure = if pressure == 0 {
+                    TOUCH_DEFAULT_PRESSURE
+                } else {
+                    (pressure as i32).min(TOUCH_MAX_PRESSURE)
+                };
+                events.push(abs(AbsoluteAxisType::ABS_MT_POSITION_X, x));
+                events.push(abs(AbsoluteAxisType::ABS_MT_POSITION_Y, y));
+                events.push(abs(AbsoluteAxisType::ABS_MT_PRESSURE, pressure));
+                touch.points.insert(id, (slot, x, y, pressure));
+            } else {
+                events.push(abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, -1));
+                touch.points.remove(&id);
+            }
+        }
+        let is_down = !touch.points.is_empty();
+        if is_down != was_down {
+            events.push(InputEvent::new(
+                EventType::KEY,
+                evdev::Key::BTN_TOUCH.code(),
+                is_down as _,
+            ));
+        }
+        if let Some((_, x, y, pressure)) = touch.points.values().min_by_key(|p| p.0) {
+            events.push(abs(AbsoluteAxisType::ABS_X, *x));
+            events.push(abs(AbsoluteAxisType::ABS_Y, *y));
+            events.push(abs(AbsoluteAxisType::ABS_PRESSURE, *pressure));
+        } else {
+            events.push(abs(AbsoluteAxisType::ABS_PRESSURE, 0));
+        }
+        allow_err!(touch.device.emit(&events));
+    }
+
+    impl AbsoluteDevice for Touch {
+        type Event = Vec<(i32, i32, i32, u32, bool)>;
+        const NAME: &'static str = "touch";
+
+        fn parse(data: Data) -> Option<AbsoluteData<Self::Event>> {
+            match data {
+                Data::Touch(DataTouch::Resolution(rng_x, rng_y)) => {
+                    Some(AbsoluteData::Resolution(rng_x, rng_y))
+                }
+                Data::Touch(DataTouch::Frame(points)) => Some(AbsoluteData::Event(points)),
+                _ => None,
+            }
+        }
+
+        fn create(rng_x: (i32, i32), rng_y: (i32, i32)) -> ResultType<Self> {
+            Ok(Touch {
+                device: create_uinput_touch(rng_x, rng_y)?,
+                points: Default::default(),
+                tracking_id: 0,
+            })
+        }
+
+        fn handle(&mut self, points: &Self::Event) {
+            handle_touch(self, points);
+        }
+    }
+
+    struct Pen {
+        device: VirtualDevice,
+        // the last state in range
+        state: Option<DataPenState>,
+    }
+
+    fn create_uinput_pen(rng_x: (i32, i32), rng_y: (i32, i32)) -> ResultType<VirtualDevice> {
+        let mut keys = AttributeSet::<evdev::Key>::new();
+        keys.insert(evdev::Key::BTN_TOOL_PEN);
+        keys.insert(evdev::Key::BTN_TOOL_RUBBER);
+        keys.insert(evdev::Key::BTN_TOUCH);
+        keys.insert(evdev::Key::BTN_STYLUS);
+        let mut props = AttributeSet::<evdev::PropType>::new();
+        props.insert(evdev::PropType::DIRECT);
+        let abs = |axis, min, max, resolution| {
+            UinputAbsSetup::new(axis, AbsInfo::new(min, min, max, 0, 0, resolution))
+        };
+        let pen = VirtualDeviceBuilder::new()?
+            .name("RustDesk UInput Pen")
+            .with_keys(&keys)?
+            .with_properties(&props)?
+            .with_absolute_axis(&abs(AbsoluteAxisType::ABS_X, rng_x.0, rng_x.1, 0))?
+            .with_absolute_axis(&abs(AbsoluteAxisType::ABS_Y, rng_y.0, rng_y.1, 0))?
+            .with_absolute_axis(&abs(AbsoluteAxisType::ABS_PRESSURE, 0, PEN_MAX_PRESSURE, 0))?
+            // the resolution of the tilt is units per radian
+            .with_absolute_axis(&abs(AbsoluteAxisType::ABS_TILT_X, -90, 90, 57))?
+            .with_absolute_axis(&abs(AbsoluteAxisType::ABS_TILT_Y, -90, 90, 57))?
+            .build()?;
+        Ok(pen)
+    }
+
+    fn handle_pen(pen: &mut Pen, state: &DataPenState) {
+        log::trace!("handle_pen {:?}", state);
+        let key =
+            |key: evdev::Key, down: bool| InputEvent::new(EventType::KEY, key.code(), down as _);
+        let tool = |eraser: bool| {
+            if eraser {
+                evdev::Key::BTN_TOOL_RUBBER
+            } else {
+                evdev::Key::BTN_TOOL_PEN
+            }
+        };
+        let mut events = vec![];
+        let in_range = state.down || state.hover;
+        if let Some(last) = pen.state.as_ref() {
+            // lift the tip and the button before leaving the range or switching the tool
+            if !in_range || last.eraser != state.eraser {
+                if last.down {
+                    events.push(key(evdev::Key::BTN_TOUCH, false));
+                }
+                if last.barrel {
+                    events.push(key(evdev::Key::BTN_STYLUS, false));
+                }
+                events.push(InputEvent::new(
+                    EventType::ABSOLUTE,
+                    AbsoluteAxisType::ABS_PRESSURE.0,
+                    0,
+                ));
+                events.push(key(tool(last.eraser), false));
+                allow_err!(pen.device.emit(&events));
+                events.clear();
+                pen.state = None;
+            }
+        }
+        if !in_range {
+            return;
+        }
+        let last = pen.state.take().unwrap_or_default();
+        if !last.down && !last.hover {
+            events.push(key(tool(state.eraser), true));
+        }
+        let pressure = if !state.down {
+            0
+        } else if state.pressure == 0 {
+            PEN_DEFAULT_PRESSURE
+        } else {
+            (state.pressure as i32).min(PEN_MAX_PRESSURE)
+        };
+        for (axis, value) in [
+            (AbsoluteAxisType::ABS_X, state.x),
+            (AbsoluteAxisType::ABS_Y, state.y),
+            (AbsoluteAxisType::ABS_PRESSURE, pressure),
+            (AbsoluteAxisType::ABS_TILT_X, state.tilt_x.clamp(-90, 90)),
+            (AbsoluteAxisType::ABS_TILT_Y, state.tilt_y.clamp(-90, 90)),
+        ] {
+            events.push(InputEvent::new(EventType::ABSOLUTE, axis.0, value));
+        }
+        if last.down != state.down {
+            events.push(key(evdev::Key::BTN_TOUCH, state.down));
+        }
+        if last.barrel != state.barrel {
+            events.push(key(evdev::Key::BTN_STYLUS, state.barrel));
+        }
+        allow_err!(pen.device.emit(&events));
+        pen.state = Some(state.clone());
+    }
+
+    impl AbsoluteDevice for Pen {
+        type Event = DataPenState;
+        const NAME: &'static str = "pen";
+
+        fn parse(data: Data) -> Option<AbsoluteData<Self::Event>> {
+            match data {
+                Data::Pen(DataPen::Resolution(rng_x, rng_y)) => {
+                    Some(AbsoluteData::Resolution(rng_x, rng_y))
+                }
+                Data::Pen(DataPen::State(state)) => Some(AbsoluteData::Event(state)),
+                _ => None,
+            }
+        }
+
+        fn create(rng_x: (i32, i32), rng_y: (i32, i32)) -> ResultType<Self> {
+            Ok(Pen {
+                device: create_uinput_pen(rng_x, rng_y)?,
+                state: None,
+            })
+        }
+
+        fn handle(&mut self, state: &Self::Event) {
+            handle_pen(self, state);
+        }
+    }
+
+    struct Scroll {
+        device: VirtualDevice,
+        // the high resolution deltas not sent as notches yet
+        remainder: (i32, i32),
+    }
+
+    // The wheels of a mouse, the pointer axes and buttons are never used,
+    // but without them the device is not taken as a mouse.
+    fn create_uinput_scroll() -> ResultType<VirtualDevice> {
+        let mut keys = AttributeSet::<evdev::Key>::new();
+        keys.insert(evdev::Key::BTN_LEFT);
+        keys.insert(evdev::Key::BTN_RIGHT);
+        keys.insert(evdev::Key::BTN_MIDDLE);
+        let mut axes = AttributeSet::<RelativeAxisType>::new();
+        axes.insert(RelativeAxisType::REL_X);
+        axes.insert(RelativeAxisType::REL_Y);
+        axes.insert(RelativeAxisType::REL_WHEEL);
+        axes.insert(RelativeAxisType::REL_HWHEEL);
+        axes.insert(RelativeAxisType::REL_WHEEL_HI_RES);
+        axes.insert(RelativeAxisType::REL_HWHEEL_HI_RES);
+        let scroll = VirtualDeviceBuilder::new()?
+            .name("RustDesk UInput Scroll")
+            .with_keys(&keys)?
+            .with_relative_axes(&axes)?
+            .build()?;
+        Ok(scroll)
+    }
+
+    fn handle_scroll(scroll: &mut Scroll, x: i32, y: i32) {
+        log::trace!("handle_scroll {} {}", x, y);
+        // REL_HWHEEL is positive for right
+        let x = -x;
+        let notches = crate::input::take_wheel_notches(&mut scroll.remainder, x, y);
+        let rel =
+            |axis: RelativeAxisType, value| InputEvent::new(EventType::RELATIVE, axis.0, value);
+        let mut events = vec![];
+        // the notches are for the applications not reading the high resolution axes
+        for (axis, value) in [
+            (RelativeAxisType::REL_WHEEL_HI_RES, y),
+            (RelativeAxisType::REL_WHEEL, notches.1),
+            (RelativeAxisType::REL_HWHEEL_HI_RES, x),
+            (RelativeAxisType::REL_HWHEEL, notches.0),
+        ] {
+            if value != 0 {
+                events.push(rel(axis, value));
+            }
+        }
+        if !events.is_empty() {
+            allow_err!(scroll.device.emit(&events));
+        }
+    }
+
+    fn spawn_scroll_handler(mut stream: ipc::Connection) {
+        tokio::spawn(async move {
+            // created at the first delta, so that checking the service doesn't add a device
+            let mut scroll: Option<Scroll> = None;
+            loop {
+                tokio::select! {
+                    res = stream.next() => {
+                        match res {
+                            Err(err) => {
+                                log::info!("UInput scroll ipc connection closed: {}", err);
+                                break;
+                            }
+                            Ok(Some(Data::HiResScroll(x, y))) => {
+                                if scroll.is_none() {
+                                    log::info!("Create uinput scroll");
+                                    match create_uinput_scroll() {
+                                        Ok(device) => {
+                                            scroll = Some(Scroll {
+                                                device,
+                                                remainder: (0, 0),
+                                            })
+                                        }
+                                        Err(e) => {
+                                            log::error!("Failed to create scroll, {}", e);
+                                            return;
+                                        }
+                                    }
+                                }
+                                if let Some(scroll) = scroll.as_mut() {
+                                    handle_scroll(scroll, x, y);
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    struct Gamepad {
+        device: VirtualDevice,
+        buttons: u32,
+        // effect id -> (strong, weak, duration)
+        effects: HashMap<i16, (u16, u16, u32)>,
+    }
+
+    // UINPUT_MAX_NAME_SIZE, including the terminating NUL.
+    const UINPUT_MAX_NAME_SIZE: usize = 80;
+
+    // The name comes from the peer, drop the control characters and keep it within the uinput limit.
+    fn gamepad_device_name(name: &str) -> String {
+        let mut device_name = "RustDesk Gamepad (".to_owned();
+        let max = UINPUT_MAX_NAME_SIZE - 1 - ")".len();
+        for c in name.chars().filter(|c| !c.is_control()) {
+            if device_name.len() + c.len_utf8() > max {
+                break;
+            }
+            device_name.push(c);
+        }
+        device_name.push(')');
+        device_name
+    }
+
+    // The same layout as an xbox 360 controller, so that the applications map it without configuration.
+    fn create_uinput_gamepad(name: &str) -> ResultType<VirtualDevice> {
+        let mut keys = AttributeSet::<evdev::Key>::new();
+        for key in GAMEPAD_BUTTONS.iter().flatten() {
+            keys.insert(*key);
+        }
+        let mut ff = AttributeSet::<evdev::FFEffectType>::new();
+        ff.insert(evdev::FFEffectType::FF_RUMBLE);
+        let abs = |axis, min, max, fuzz, flat| {
+            UinputAbsSetup::new(axis, AbsInfo::new(0, min, max, fuzz, flat, 0))
+        };
+        let mut builder = VirtualDeviceBuilder::new()?
+            .name(&gamepad_device_name(name))
+            .input_id(evdev::InputId::new(
+                evdev::BusType::BUS_USB,
+                0x045e,
+                0x028e,
+                0x110,
+            ))
+            .with_keys(&keys)?
+            .with_ff(&ff)?
+            .with_ff_effects_max(GAMEPAD_EFFECTS_MAX);
+        for axis in &GAMEPAD_AXES[..4] {
+            builder =
+                builder.with_absolute_axis(&abs(*axis, i16::MIN as _, i16::MAX as _, 16, 128))?;
+        }
+        for axis in &GAMEPAD_AXES[4..] {
+            builder = builder.with_absolute_axis(&abs(*axis, 0, u8::MAX as _, 0, 0))?;
+        }
+        for axis in [AbsoluteAxisType::ABS_HAT0X, AbsoluteAxisType::ABS_HAT0Y] {
+            builder = builder.with_absolute_axis(&abs(axis, -1, 1, 0, 0))?;
+        }
+        Ok(builder.build()?)
+    }
+
+    fn handle_gamepad_state(pad: &mut Gamepad, buttons: u32, axes: &[i32]) {
+        log::trace!("handle_gamepad_state {:b} {:?}", buttons, axes);
+        let events = gamepad_state_events(pad.buttons, buttons, axes);
+        pad.buttons = buttons;
+        allow_err!(pad.device.emit(&events));
+    }
+
+    // The events of the changed buttons and all the axes.
+    fn gamepad_state_events(last_buttons: u32, buttons: u32, axes: &[i32]) -> Vec<InputEvent> {
+        let mut events = vec![];
+        let changed = last_buttons ^ buttons;
+        for (i, key) in GAMEPAD_BUTTONS.iter().enumerate() {
+            if let Some(key) = key {
+                if changed & (1 << i) != 0 {
+                    events.push(InputEvent::new(
+                        EventType::KEY,
+                        key.code(),
+                        (buttons & (1 << i) != 0) as _,
+                    ));
+                }
+            }
+        }
+        // the d-pad is a hat
+        let pressed = |i: usize| (buttons & (1 << i) != 0) as i32;
+        if changed & (0b1111 << GAMEPAD_DPAD_UP) != 0 {
+            let x = pressed(GAMEPAD_DPAD_UP + 3) - pressed(GAMEPAD_DPAD_UP + 2);
+            let y = pressed(GAMEPAD_DPAD_UP + 1) - pressed(GAMEPAD_DPAD_UP);
+            events.push(InputEvent::new(
+                EventType::ABSOLUTE,
+                AbsoluteAxisType::ABS_HAT0X.0,
+                x,
+            ));
+            events.push(InputEvent::new(
+                EventType::ABSOLUTE,
+                AbsoluteAxisType::ABS_HAT0Y.0,
+                y,
+            ));
+        }
+        for (i, value) in axes.iter().enumerate().take(GAMEPAD_AXES.len()) {
+            let value = if i < 4 {
+                (*value).clamp(i16::MIN as _, i16::MAX as _)
+            } else {
+                (*value).clamp(0, u8::MAX as _)
+            };
+            events.push(InputEvent::new(
+                EventType::ABSOLUTE,
+                GAMEPAD_AXES[i].0,
+                value,
+            ));
+        }
+        events
+    }
+
+    // Read the force feedback uploads and plays of the applications.
+    fn poll_gamepad_ff(pad: &mut Gamepad) -> Vec<(u16, u16, u32)> {
+        let mut rumbles = vec![];
+        let events: Vec<InputEvent> = match pad.device.fetch_events() {
+            Ok(events) => events.collect(),
+            Err(_) => return rumbles,
+        };
+        for event in events {
+            match event.kind() {
+                InputEventKind::UInput(code) if code == UInputEventType::UI_FF_UPLOAD.0 => {
+                    if let Ok(mut upload) = pad.device.process_ff_upload(UInputEvent(event)) {
+                        let effect = upload.effect();
+                        if let FFEffectKind::Rumble {
+                            strong_magnitude,
+                            weak_magnitude,
+                        } = effect.kind
+                        {
+                            pad.effects.insert(
+                                upload.effect_id(),
+                                (strong_magnitude, weak_magnitude, effect.replay.length as _),
+                            );
+                            upload.set_retval(0);
+                        } else {
+                            upload.set_retval(-hbb_common::libc::EINVAL);
+                        }
+                    }
+                }
+                InputEventKind::UInput(code) if code == UInputEventType::UI_FF_ERASE.0 => {
+                    if let Ok(erase) = pad.device.process_ff_erase(UInputEvent(event)) {
+                        pad.effects.remove(&(erase.effect_id() as i16));
+                    }
+                }
+                InputEventKind::ForceFeedback(id) => {
+                    if event.value() > 0 {
+                        if let Some(effect) = pad.effects.get(&(id as i16)) {
+                            rumbles.push(*effect);
+                        }
+                    } else {
+                        rumbles.push((0, 0, 0));
+                    }
+                }
+                _ => {}
+            }
+        }
+        rumbles
+    }
+
+    fn spawn_gamepad_handler(mut stream: ipc::Connection) {
+        tokio::spawn(async move {
+            // removed with the connection
+            let mut pads: HashMap<u32, Gamepad> = HashMap::new();
+            let mut timer = crate::rustdesk_interval(tokio::time::interval(
+                std::time::Duration::from_millis(GAMEPAD_FF_INTERVAL),
+            ));
+            loop {
+                tokio::select! {
+                    res = stream.next() => {
+                        match res {
+                            Err(err) => {
+                                log::info!("UInput gamepad ipc connection closed: {}", err);
+                                break;
+                            }
+                            Ok(Some(Data::Gamepad(data))) => match data {
+                                DataGamepad::Connected(index, name) => {
+                                    if index >= GAMEPAD_MAX || pads.contains_key(&index) {
+                                        continue;
+                                    }
+                                    log::info!("Create uinput gamepad {}: {}", index, name);
+                                    match create_uinput_gamepad(&name) {
+                                        Ok(device) => {
+                                            pads.insert(index, Gamepad {
+                                                device,
+                                                buttons: 0,
+                                                effects: Default::default(),
+                                            });
+                                        }
+                                        Err(e) => {
+                                            log::error!("Failed to create gamepad, {}", e);
+                                        }
+                                    }
+                                }
+                                DataGamepad::Disconnected(index) => {
+                                    pads.remove(&index);
+                                }
+                                DataGamepad::State(index, buttons, axes) => {
+                                    if let Some(pad) = pads.get_mut(&index) {
+                                        handle_gamepad_state(pad, buttons, &axes);
+                                    }
+                                }
+                                DataGamepad::Rumble(..) => {}
+                            },
+                            _ => {}
+                        }
+                    }
+                    _ = timer.tick() => {
+                        for (index, pad) in pads.iter_mut() {
+                            for (strong, weak, duration) in poll_gamepad_ff(pad) {
+                                allow_err!(stream.send(&Data::Gamepad(DataGamepad::Rumble(*index, strong, weak, duration))).await);
+                            }
+                        }
+                    }
+                }
+            }
+        });
+    }
+
     fn spawn_controller_handler(mut stream: ipc::Connection) {
         tokio::spawn(async move {
             loop {
@@ -751,6 +1560,34 @@ pub mod service {
         start_service(IPC_POSTFIX_CONTROL, spawn_controller_handler).await;
     }
 
+    /// Start uinput touch service.
+    #[tokio::main(flavor = "current_thread")]
+    pub async fn start_service_touch() {
+        log::info!("start uinput touch service");
+        start_service(IPC_POSTFIX_TOUCH, spawn_absolute_handler::<Touch>).await;
+    }
+
+    /// Start uinput pen service.
+    #[tokio::main(flavor = "current_thread")]
+    pub async fn start_service_pen() {
+        log::info!("start uinput pen service");
+        start_service(IPC_POSTFIX_PEN, spawn_absolute_handler::<Pen>).await;
+    }
+
+    /// Start uinput gamepad service.
+    #[tokio::main(flavor = "current_thread")]
+    pub async fn start_service_gamepad() {
+        log::info!("start uinput gamepad service");
+        start_service(IPC_POSTFIX_GAMEPAD, spawn_gamepad_handler).await;
+    }
+
+    /// Start uinput scroll service.
+    #[tokio::main(flavor = "current_thread")]
+    pub async fn start_service_scroll() {
+        log::info!("start uinput scroll service");
+        start_service(IPC_POSTFIX_SCROLL, spawn_scroll_handler).await;
+    }
+
     pub fn stop_service_keyboard() {
         log::info!("stop uinput keyboard service");
     }
@@ -760,4 +1597,83 @@ pub mod service {
     pub fn stop_service_control() {
         log::info!("stop uinput control service");
     }
+    pub fn stop_service_touch() {
+        log::info!("stop uinput touch service");
+    }
+    pub fn stop_service_pen() {
+        log::info!("stop uinput pen service");
+    }
+    pub fn stop_service_gamepad() {
+        log::info!("stop uinput gamepad service");
+    }
+    pub fn stop_service_scroll() {
+        log::info!("stop uinput scroll service");
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn events(last_buttons: u32, buttons: u32, axes: &[i32]) -> Vec<(EventType, u16, i32)> {
+            gamepad_state_events(last_buttons, buttons, axes)
+                .iter()
+                .map(|e| (e.event_type(), e.code(), e.value()))
+                .collect()
+        }
+
+        #[test]
+        fn test_gamepad_buttons() {
+            let south = evdev::Key::BTN_SOUTH.code();
+            let start = evdev::Key::BTN_START.code();
+            assert_eq!(events(0, 1, &[]), vec![(EventType::KEY, south, 1)]);
+            assert_eq!(
+                events(1, 1 << 7, &[]),
+                vec![(EventType::KEY, south, 0), (EventType::KEY, start, 1)]
+            );
+            // unchanged
+            assert!(events(1 << 7, 1 << 7, &[]).is_empty());
+        }
+
+        #[test]
+        fn test_gamepad_dpad() {
+            let hat_x = AbsoluteAxisType::ABS_HAT0X.0;
+            let hat_y = AbsoluteAxisType::ABS_HAT0Y.0;
+            let up = 1 << GAMEPAD_DPAD_UP;
+            let right = 1 << (GAMEPAD_DPAD_UP + 3);
+            assert_eq!(
+                events(0, up | right, &[]),
+                vec![
+                    (EventType::ABSOLUTE, hat_x, 1),
+                    (EventType::ABSOLUTE, hat_y, -1)
+                ]
+            );
+            assert_eq!(
+                events(up | right, 0, &[]),
+                vec![
+                    (EventType::ABSOLUTE, hat_x, 0),
+                    (EventType::ABSOLUTE, hat_y, 0)
+                ]
+            );
+        }
+
+        #[test]
+        fn test_gamepad_axes() {
+            let evts = events(0, 0, &[40_000, -40_000, 100, -100, 300, -5, 1]);
+            let expected: Vec<_> = GAMEPAD_AXES
+                .iter()
+                .zip([32767, -32768, 100, -100, 255, 0])
+                .map(|(axis, v)| (EventType::ABSOLUTE, axis.0, v))
+                .collect();
+            assert_eq!(evts, expected);
+        }
+
+        #[test]
+        fn test_gamepad_device_name() {
+            assert_eq!(gamepad_device_name("Pad\n\t1"), "RustDesk Gamepad (Pad1)");
+            let name = gamepad_device_name(&"手".repeat(40));
+            assert!(name.len() < UINPUT_MAX_NAME_SIZE);
+            assert!(name.starts_with("RustDesk Gamepad (手"));
+            assert!(name.ends_with("手)"));
+        }
+    }
 }
@@ -1,9 +1,27 @@
 use super::*;
 use scrap::codec::Quality;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 pub const FPS: u32 = 30;
 pub const MIN_FPS: u32 = 1;
 pub const MAX_FPS: u32 = 120;
+// Encode the displays in several tiers, so that a viewer with a bad network doesn't drag down the others.
+pub const OPTION_SIMULCAST: &str = "enable-simulcast";
+// The number of the simulcast tiers. Tier 0 is the full quality one, the lower tiers have lower bitrate and fps.
+pub const TIERS: usize = 3;
+// The scale of the video, in percent of the display size.
+pub const MIN_VIDEO_SCALE: u32 = 10;
+pub const MAX_VIDEO_SCALE: u32 = 100;
+// The scale used if the network delay is high and the user allows the automatic downscaling.
+const AUTO_VIDEO_SCALE: u32 = 50;
+// Send the lossless frames instead of the lossy ones while the screen is static, so that the text is crisp.
+// Off by default, the lossless frames are much larger.
+pub const OPTION_LOSSLESS: &str = "enable-lossless";
+// The screen is static if the ratio of the changed area is not more than this.
+const STATIC_RATIO: f32 = 0.02;
+// The lossy frames are used again if the ratio of the changed area is more than this.
+const MOTION_RATIO: f32 = 0.1;
+// How long the screen must be static before switching to the lossless frames.
+const STATIC_DURATION: Duration = Duration::from_secs(1);
 trait Percent {
     fn as_percent(&self) -> u32;
 }
@@ -36,6 +54,23 @@ struct UserData {
     delay: Option<Delay>,
     response_delayed: bool,
     record: bool,
+    // None if the peer can't restore a downscaled video
+    video_scale: Option<u32>,
+    auto_video_scale: bool,
+}
+
+impl UserData {
+    // The simulcast tier that fits the network of the user.
+    fn tier(&self) -> usize {
+        if self.response_delayed {
+            return TIERS - 1;
+        }
+        match self.delay.map(|d| d.state) {
+            Some(DelayState::HighDelay) => 1,
+            Some(DelayState::Broken) => 2,
+            _ => 0,
+        }
+    }
 }
 
 pub struct VideoQoS {
@@ -44,6 +79,15 @@ pub struct VideoQoS {
     users: HashMap<i32, UserData>,
     bitrate_store: u32,
     support_abr: HashMap<usize, bool>,
+    simulcast: bool,
+    tier_fps: [u32; TIERS],
+    motion: HashMap<usize, Motion>,
+}
+
+#[derive(Default, Debug, Copy, Clone)]
+struct Motion {
+    static_since: Option<Instant>,
+    lossless: bool,
 }
 
 #[derive(PartialEq, Debug, Clone, Copy)]
@@ -82,6 +126,9 @@ impl Default for VideoQoS {
             users: Default::default(),
             bitrate_store: 0,
             support_abr: Default::default(),
+            simulcast: false,
+            tier_fps: [FPS; TIERS],
+            motion: Default::default(),
         }
     }
 }
@@ -124,7 +171,112 @@ impl VideoQoS {
         self.support_abr.insert(display_idx, support);
     }
 
+    /// The users routed to the lower simulcast tiers, the others are in tier 0.
+    pub fn tiers(&self) -> HashMap<i32, usize> {
+        if !self.simulcast {
+            return Default::default();
+        }
+        self.users
+            .iter()
+            .map(|(id, u)| (*id, u.tier()))
+            .filter(|(_, tier)| *tier > 0)
+            .collect()
+    }
+
+    pub fn tier_spf(&self, tier: usize) -> Duration {
+        if tier == 0 {
+            return self.spf();
+        }
+        let fps = self.tier_fps.get(tier).copied().unwrap_or(FPS);
+        Duration::from_secs_f32(1. / (std::cmp::max(fps, MIN_FPS) as f32))
+    }
+
+    pub fn tier_quality(&self, tier: usize) -> Quality {
+        match tier {
+            0 => self.quality,
+            1 => reduce_quality(self.quality, DelayState::HighDelay),
+            _ => reduce_quality(self.quality, DelayState::Broken),
+        }
+    }
+
+    /// The scale of the video of a tier, in percent of the display size.
+    ///
+    /// The video is downscaled only if all users of the tier support it.
+    pub fn tier_scale(&self, tier: usize) -> u32 {
+        let simulcast = self.simulcast;
+        let users: Vec<_> = self
+            .users
+            .values()
+            .filter(|u| !simulcast || u.tier() == tier)
+            .collect();
+        if users.is_empty() || users.iter().any(|u| u.video_scale.is_none()) {
+            return MAX_VIDEO_SCALE;
+        }
+        let mut scale = users
+            .iter()
+            .filter_map(|u| u.video_scale)
+            .max()
+            .unwrap_or(MAX_VIDEO_SCALE);
+        let high_delay = users.iter().any(|u| {
+            u.response_delayed
+                || matches!(
+                    u.delay.map(|d| d.state),
+                    Some(DelayState::HighDelay | DelayState::Broken)
+                )
+        });
+        if high_delay && users.iter().all(|u| u.auto_video_scale) {
+            scale = std::cmp::min(scale, AUTO_VIDEO_SCALE);
+        }
+        scale.clamp(MIN_VIDEO_SCALE, MAX_VIDEO_SCALE)
+    }
+
+    /// Whether the lossless frames can be used when the screen is static.
+    ///
+    /// Not if a user is recording, the video is downscaled, the network is slow,
+    /// the low quality is chosen, or a peer can't decode them.
+    pub fn lossless_allowed(&self) -> bool {
+        Config::get_option(OPTION_LOSSLESS) == "Y"
+            && !self.users.is_empty()
+            && !self.record()
+            && self.quality != Quality::Low
+            && self.tier_scale(0) == MAX_VIDEO_SCALE
+            && self.users.values().all(|u| {
+                !u.response_delayed
+                    && matches!(
+                        u.delay.map(|d| d.state),
+                        None | Some(DelayState::Normal | DelayState::LowDelay)
+                    )
+            })
+            && scrap::codec::Encoder::lossless_supported()
+    }
+
+    /// Update the motion of a display with the ratio of the changed area of a frame,
+    /// and return whether to use the lossless frames.
+    pub fn update_motion(&mut self, display_idx: usize, changed: f32) -> bool {
+        let m = self.motion.entry(display_idx).or_default();
+        if m.lossless {
+            if changed > MOTION_RATIO {
+                *m = Default::default();
+            }
+        } else if changed <= STATIC_RATIO {
+            let since = *m.static_since.get_or_insert_with(Instant::now);
+            m.lossless = since.elapsed() >= STATIC_DURATION;
+        } else {
+            m.static_since = None;
+        }
+        m.lossless
+    }
+
+    pub fn clear_motion(&mut self, display_idx: usize) {
+        self.motion.remove(&display_idx);
+    }
+
     pub fn refresh(&mut self, typ: Option<RefreshType>) {
+        self.simulcast = Config::get_option(OPTION_SIMULCAST) == "Y";
+        let simulcast = self.simulcast;
+        // With simulcast, the fps and the quality are merged from the users of tier 0 only.
+        let in_tier0 = |u: &UserData| !simulcast || u.tier() == 0;
+
         // fps
         let user_fps = |u: &UserData| {
             // custom_fps
@@ -155,6 +307,7 @@ impl VideoQoS {
         let mut fps = self
             .users
             .iter()
+            .filter(|(_, u)| in_tier0(u))
             .map(|(_, u)| user_fps(u))
             .filter(|u| *u >= MIN_FPS)
             .min()
@@ -163,6 +316,16 @@ impl VideoQoS {
             fps = MAX_FPS;
         }
         self.fps = fps;
+        for tier in 1..TIERS {
+            self.tier_fps[tier] = self
+                .users
+                .iter()
+                .filter(|(_, u)| u.tier() == tier)
+                .map(|(_, u)| std::cmp::min(user_fps(u), MAX_FPS))
+                .filter(|u| *u >= MIN_FPS)
+                .min()
+                .unwrap_or(std::cmp::max(fps >> tier, MIN_FPS));
+        }
 
         // quality
         // latest image quality
@@ -185,6 +348,7 @@ impl VideoQoS {
             let delay = self
                 .users
                 .iter()
+                .filter(|(_, u)| in_tier0(u))
                 .map(|u| u.1.delay)
                 .filter(|d| d.is_some())
                 .max_by(|a, b| {
@@ -192,36 +356,7 @@ impl VideoQoS {
                 });
             let delay = delay.unwrap_or_default().unwrap_or_default().state;
             if delay != DelayState::Normal {
-                match self.quality {
-                    Quality::Best => {
-                        quality = if delay == DelayState::Broken {
-                            Quality::Low
-                        } else {
-                            Quality::Balanced
-                        };
-                    }
-                    Quality::Balanced => {
-                        quality = Quality::Low;
-                    }
-                    Quality::Low => {
-                        quality = Quality::Low;
-                    }
-                    Quality::Custom(b) => match delay {
-                        DelayState::LowDelay => {
-                            quality =
-                                Quality::Custom(if b >= 150 { 100 } else { std::cmp::min(50, b) });
-                        }
-                        DelayState::HighDelay => {
-                            quality =
-                                Quality::Custom(if b >= 100 { 50 } else { std::cmp::min(25, b) });
-                        }
-                        DelayState::Broken => {
-                            quality =
-                                Quality::Custom(if b >= 50 { 25 } else { std::cmp::min(10, b) });
-                        }
-                        DelayState::Normal => {}
-                    },
-                }
+                quality = reduce_quality(self.quality, delay);
             } else {
                 match self.quality {
                     Quality::Low => {
@@ -308,6 +443,24 @@ impl VideoQoS {
         self.refresh(Some(RefreshType::SetImageQuality));
     }
 
+    pub fn user_video_scale(&mut self, id: i32, scale: u32, auto: bool) {
+        let scale = Some(scale.clamp(MIN_VIDEO_SCALE, MAX_VIDEO_SCALE));
+        if let Some(user) = self.users.get_mut(&id) {
+            user.video_scale = scale;
+            user.auto_video_scale = auto;
+        } else {
+            self.users.insert(
+                id,
+                UserData {
+                    video_scale: scale,
+                    auto_video_scale: auto,
+                    ..Default::default()
+                },
+            );
+        }
+        self.refresh(None);
+    }
+
     pub fn user_network_delay(&mut self, id: i32, delay: u32) {
         let state = DelayState::from_delay(delay);
         let debounce = 3;
@@ -386,3 +539,111 @@ impl VideoQoS {
         self.refresh(None);
     }
 }
+
+// The quality of the encoder under a network delay.
+fn reduce_quality(quality: Quality, delay: DelayState) -> Quality {
+    match quality {
+        Quality::Best => {
+            if delay == DelayState::Broken {
+                Quality::Low
+            } else {
+                Quality::Balanced
+            }
+        }
+        Quality::Balanced => Quality::Low,
+        Quality::Low => Quality::Low,
+        Quality::Custom(b) => match delay {
+            DelayState::LowDelay => {
+                Quality::Custom(if b >= 150 { 100 } else { std::cmp::min(50, b) })
+            }
+            DelayState::HighDelay => {
+                Quality::Custom(if b >= 100 { 50 } else { std::cmp::min(25, b) })
+            }
+            DelayState::Broken => Quality::Custom(if b >= 50 { 25 } else { std::cmp::min(10, b) }),
+            DelayState::Normal => quality,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(state: Option<DelayState>, response_delayed: bool) -> UserData {
+        UserData {
+            delay: state.map(|state| Delay {
+                state,
+                ..Default::default()
+            }),
+            response_delayed,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_tiers() {
+        let mut qos = VideoQoS::default();
+        qos.users.insert(1, user(None, false));
+        qos.users.insert(2, user(Some(DelayState::LowDelay), false));
+        qos.users
+            .insert(3, user(Some(DelayState::HighDelay), false));
+        qos.users.insert(4, user(Some(DelayState::Broken), false));
+        qos.users.insert(5, user(Some(DelayState::Normal), true));
+        assert!(qos.tiers().is_empty());
+        qos.simulcast = true;
+        let tiers = qos.tiers();
+        assert_eq!(tiers.len(), 3);
+        assert_eq!(tiers.get(&1), None);
+        assert_eq!(tiers.get(&2), None);
+        assert_eq!(tiers.get(&3), Some(&1));
+        assert_eq!(tiers.get(&4), Some(&2));
+        assert_eq!(tiers.get(&5), Some(&(TIERS - 1)));
+    }
+
+    #[test]
+    fn test_reduce_quality() {
+        use DelayState::*;
+        assert_eq!(reduce_quality(Quality::Best, HighDelay), Quality::Balanced);
+        assert_eq!(reduce_quality(Quality::Best, Broken), Quality::Low);
+        assert_eq!(reduce_quality(Quality::Balanced, LowDelay), Quality::Low);
+        assert_eq!(reduce_quality(Quality::Low, Broken), Quality::Low);
+        assert_eq!(
+            reduce_quality(Quality::Custom(200), LowDelay),
+            Quality::Custom(100)
+        );
+        assert_eq!(
+            reduce_quality(Quality::Custom(120), LowDelay),
+            Quality::Custom(50)
+        );
+        assert_eq!(
+            reduce_quality(Quality::Custom(30), LowDelay),
+            Quality::Custom(30)
+        );
+        assert_eq!(
+            reduce_quality(Quality::Custom(100), HighDelay),
+            Quality::Custom(50)
+        );
+        assert_eq!(
+            reduce_quality(Quality::Custom(80), HighDelay),
+            Quality::Custom(25)
+        );
+        assert_eq!(
+            reduce_quality(Quality::Custom(50), Broken),
+            Quality::Custom(25)
+        );
+        assert_eq!(
+            reduce_quality(Quality::Custom(8), Broken),
+            Quality::Custom(8)
+        );
+        assert_eq!(
+            reduce_quality(Quality::Custom(300), Normal),
+            Quality::Custom(300)
+        );
+        // the lower tiers never have a better quality than tier 0
+        let mut qos = VideoQoS::default();
+        qos.quality = Quality::Custom(1000);
+        assert_eq!(qos.tier_quality(0), Quality::Custom(1000));
+        assert_eq!(qos.tier_quality(1), Quality::Custom(50));
+        assert_eq!(qos.tier_quality(2), Quality::Custom(25));
+    }
+}
@@ -21,7 +21,7 @@
 use super::{
     display_service::{check_display_changed, get_display_info},
     service::ServiceTmpl,
-    video_qos::VideoQoS,
+    video_qos::{VideoQoS, MAX_VIDEO_SCALE},
     *,
 };
 #[cfg(target_os = "linux")]
@@ -46,21 +46,25 @@ use hbb_common::{
 use scrap::gpucodec::{GpuEncoder, GpuEncoderConfig};
 #[cfg(feature = "hwcodec")]
 use scrap::hwcodec::{HwEncoder, HwEncoderConfig};
+#[cfg(feature = "openh264")]
+use scrap::openh264::OpenH264EncoderConfig;
 #[cfg(not(windows))]
 use scrap::Capturer;
 use scrap::{
     aom::AomEncoderConfig,
     codec::{Encoder, EncoderCfg, Quality},
+    lossless::LosslessEncoder,
     record::{Recorder, RecorderContext},
     vpxcodec::{VpxEncoderConfig, VpxVideoCodecId},
-    CodecName, Display, Frame, TraitCapturer,
+    CodecName, Display, Frame, Pixfmt, TraitCapturer, TraitPixelBuffer,
 };
 #[cfg(windows)]
 use std::sync::Once;
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     io::ErrorKind::WouldBlock,
     ops::{Deref, DerefMut},
+    sync::atomic::{AtomicU8, Ordering},
     time::{self, Duration, Instant},
 };
 
@@ -75,7 +79,14 @@ lazy_static::lazy_static! {
     pub static ref VIDEO_QOS: Arc<Mutex<VideoQoS>> = Default::default();
     pub static ref IS_UAC_RUNNING: Arc<Mutex<bool>> = Default::default();
     pub static ref IS_FOREGROUND_WINDOW_ELEVATED: Arc<Mutex<bool>> = Default::default();
+    // The recorders of the displays, the audio frames are written to all of them.
+    static ref RECORDERS: Arc<Mutex<HashMap<usize, Arc<Mutex<Option<Recorder>>>>>> = Default::default();
+    // The displays whose capturer doesn't give the frames that can be scaled, they are sent in full size.
+    // The origin and the size of the display, it's checked again if the display changes.
+    static ref UNSCALABLE_DISPLAYS: Mutex<HashMap<usize, ((i32, i32), usize, usize)>> = Default::default();
 }
+// The channels of the audio being encoded, for the audio track of the new recorders.
+static RECORD_AUDIO_CHANNELS: AtomicU8 = AtomicU8::new(scrap::record::DEFAULT_AUDIO_CHANNELS);
 
 #[inline]
 pub fn notify_video_frame_fetched(conn_id: i32, frame_tm: Option<Instant>) {
@@ -106,9 +117,14 @@ impl VideoFrameController {
         }
     }
 
+    // The viewers of the lower simulcast tiers are also added to `fetched_conn_ids`, but not waited for.
     #[tokio::main(flavor = "current_thread")]
     async fn try_wait_next(&mut self, fetched_conn_ids: &mut HashSet<i32>, timeout_millis: u64) {
         if self.send_conn_ids.is_empty() {
+            let mut rx = FRAME_FETCHED_NOTIFIER.1.lock().await;
+            while let Ok((id, _)) = rx.try_recv() {
+                fetched_conn_ids.insert(id);
+            }
             return;
         }
 
@@ -132,6 +148,107 @@ impl VideoFrameController {
     }
 }
 
+// The frame of a lower simulcast tier is sent again after this, even if not fetched by all its viewers.
+const TIER_FETCH_TIMEOUT: Duration = Duration::from_secs(3);
+
+// The encoder of a lower simulcast tier, it encodes the frames captured for tier 0.
+struct TierEncoder {
+    encoder: Encoder,
+    quality: Quality,
+    size: (usize, usize),
+    spf: Duration,
+    last: Option<Instant>,
+    // the viewers which haven't fetched the last frame, no frame is sent to the tier until they do
+    pending: HashSet<i32>,
+}
+
+// Encodes the frames without loss for the users of tier 0, while the screen is static.
+struct Lossless {
+    encoder: LosslessEncoder,
+    on: bool,
+    failed: bool,
+}
+
+impl Lossless {
+    fn new(display_idx: usize) -> Self {
+        VIDEO_QOS.lock().unwrap().clear_motion(display_idx);
+        Self {
+            encoder: LosslessEncoder::new(),
+            on: false,
+            failed: false,
+        }
+    }
+
+    // `None` if the lossy frames are used, otherwise the lossless frame, which is `None` if nothing changed.
+    fn handle(
+        &mut self,
+        display_idx: usize,
+        frame: &Frame,
+        crop: Option<(usize, usize, usize, usize)>,
+    ) -> Option<Option<VideoFrame>> {
+        if self.failed {
+            return None;
+        }
+        let Frame::PixelBuffer(captured) = frame else {
+            return None;
+        };
+        let res = self
+            .encoder
+            .update(captured, crop, frame.dirty_rects())
+            .and_then(|changed| {
+                let on = VIDEO_QOS
+                    .lock()
+                    .unwrap()
+                    .update_motion(display_idx, changed);
+                // The peers need all the tiles when switched from the lossy frames.
+                let key = on && !self.on;
+                if on != self.on {
+                    log::info!("display {display_idx} lossless: {on}, changed: {changed:.3}");
+                    self.on = on;
+                }
+                if on {
+                    self.encoder.encode(key)
+                } else {
+                    Ok(None)
+                }
+            });
+        match res {
+            Ok(vf) => self.on.then_some(vf),
+            Err(e) => {
+                log::error!("lossless failed: {e}");
+                self.failed = true;
+                self.on = false;
+                None
+            }
+        }
+    }
+
+    // Called if no frame is captured because nothing changed, e.g. `WouldBlock`,
+    // so that a static screen is switched to the lossless frames too.
+    // Returns the key frame of the last captured frame when switched.
+    fn idle(&mut self, display_idx: usize) -> Option<VideoFrame> {
+        if self.failed || self.on {
+            return None;
+        }
+        if !VIDEO_QOS.lock().unwrap().update_motion(display_idx, 0.0) {
+            return None;
+        }
+        match self.encoder.encode(true) {
+            Ok(Some(vf)) => {
+                log::info!("display {display_idx} lossless: true, idle");
+                self.on = true;
+                Some(vf)
+            }
+            Ok(None) => None,
+            Err(e) => {
+                log::error!("lossless failed: {e}");
+                self.failed = true;
+                None
+            }
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct VideoService {
     sp: GenericService,
@@ -275,6 +392,8 @@ pub(super) struct CapturerInfo {
     pub privacy_mode_id: i32,
     pub _capturer_privacy_mode_id: i32,
     pub capturer: Box<dyn TraitCapturer>,
+    // the shared area in the captured frames, (x, y, width, height)
+    pub crop: Option<(usize, usize, usize, usize)>,
 }
 
 impl Deref for CapturerInfo {
@@ -294,21 +413,32 @@ impl DerefMut for CapturerInfo {
 fn get_capturer(current: usize, portable_service_running: bool) -> ResultType<CapturerInfo> {
     #[cfg(target_os = "linux")]
     {
+        #[cfg(feature = "test_display")]
+        if super::test_display::is_enabled() {
+            return super::test_display::get_capturer(current);
+        }
         if !is_x11() {
             return super::wayland::get_capturer();
         }
     }
 
     let mut displays = Display::all()?;
-    let ndisplay = displays.len();
-    if ndisplay <= current {
+    // Only the display which contains the shared area is captured, as display 0.
+    let area = super::shared_area::get();
+    let physical = match &area {
+        Some(area) if current == 0 => area.display,
+        Some(_) => bail!("Display {} is not shared", current),
+        None => current,
+    };
+    if displays.len() <= physical {
         bail!(
             "Failed to get display {}, displays len: {}",
-            current,
-            ndisplay
+            physical,
+            displays.len()
         );
     }
-    let display = displays.remove(current);
+    let ndisplay = if area.is_some() { 1 } else { displays.len() };
+    let display = displays.remove(physical);
 
     #[cfg(target_os = "linux")]
     if let Display::X11(inner) = &display {
@@ -320,7 +450,15 @@ fn get_capturer(current: usize, portable_service_running: bool) -> ResultType<Ca
         }
     }
 
-    let (origin, width, height) = (display.origin(), display.width(), display.height());
+    let (origin, width, height, crop) = match &area {
+        Some(area) => (
+            (area.x, area.y),
+            area.width,
+            area.height,
+            Some(area.crop(display.origin())),
+        ),
+        None => (display.origin(), display.width(), display.height(), None),
+    };
     let name = display.name();
     log::debug!(
         "#displays={}, current={}, origin: {:?}, width={}, height={}, cpus={}/{}, name:{}",
@@ -378,6 +516,7 @@ fn get_capturer(current: usize, portable_service_running: bool) -> ResultType<Ca
         privacy_mode_id,
         _capturer_privacy_mode_id: capturer_privacy_mode_id,
         capturer,
+        crop,
     })
 }
 
@@ -389,7 +528,9 @@ fn run(vs: VideoService) -> ResultType<()> {
     // to-do: wayland ensure_inited should pass current display index.
     // But for now, we do not support multi-screen capture on wayland.
     #[cfg(target_os = "linux")]
-    super::wayland::ensure_inited()?;
+    if !super::is_test_display() {
+        super::wayland::ensure_inited()?;
+    }
     #[cfg(target_os = "linux")]
     let _wayland_call_on_ret = SimpleCallOnReturn {
         b: true,
@@ -413,17 +554,52 @@ fn run(vs: VideoService) -> ResultType<()> {
     let mut quality = video_qos.quality();
     let record_incoming = !Config::get_option("allow-auto-record-incoming").is_empty();
     let client_record = video_qos.record();
+    let mut scale = video_qos.tier_scale(0);
     drop(video_qos);
-    let encoder_cfg = get_encoder_config(
+    let record = client_record || record_incoming;
+    let mut size = scaled_size(c.width, c.height, scale);
+    let mut encoder_cfg = get_encoder_config(
         &c,
+        size,
         display_idx,
         quality,
-        client_record || record_incoming,
+        record,
         last_portable_service_running,
     );
+    // The texture is encoded without the conversion to yuv, so it can't be resized.
+    #[cfg(feature = "gpucodec")]
+    let scale_fixed = matches!(encoder_cfg, EncoderCfg::GPU(_));
+    #[cfg(not(feature = "gpucodec"))]
+    let scale_fixed = false;
+    let scale_fixed = scale_fixed
+        || UNSCALABLE_DISPLAYS.lock().unwrap().get(&display_idx)
+            == Some(&(c.origin, c.width, c.height));
+    if scale_fixed && scale != MAX_VIDEO_SCALE {
+        scale = MAX_VIDEO_SCALE;
+        size = (c.width, c.height);
+        encoder_cfg = get_encoder_config(
+            &c,
+            size,
+            display_idx,
+            quality,
+            record,
+            last_portable_service_running,
+        );
+    }
+    if scale != MAX_VIDEO_SCALE {
+        log::info!(
+            "video scale: {scale}%, {:?} -> {:?}",
+            (c.width, c.height),
+            size
+        );
+    }
     Encoder::set_fallback(&encoder_cfg);
     let codec_name = Encoder::negotiated_codec();
-    let recorder = get_recorder(c.width, c.height, &codec_name, record_incoming);
+    let recorder = get_recorder(size.0, size.1, &codec_name, record_incoming);
+    RECORDERS
+        .lock()
+        .unwrap()
+        .insert(display_idx, recorder.clone());
     let mut encoder;
     let use_i444 = Encoder::use_i444(&encoder_cfg);
     match Encoder::new(encoder_cfg.clone(), use_i444) {
@@ -443,6 +619,11 @@ fn run(vs: VideoService) -> ResultType<()> {
         sp.set_option_bool(OPTION_REFRESH, false);
     }
 
+    // simulcast, conn id -> tier of the users not in tier 0
+    let mut tiers: HashMap<i32, usize> = HashMap::new();
+    let mut tier_encoders: HashMap<usize, TierEncoder> = HashMap::new();
+    let mut lossless: Option<Lossless> = None;
+
     let mut frame_controller = VideoFrameController::new();
 
     let start = time::Instant::now();
@@ -458,6 +639,7 @@ fn run(vs: VideoService) -> ResultType<()> {
     let mut would_block_count = 0u32;
     let mut yuv = Vec::new();
     let mut mid_data = Vec::new();
+    let mut scaled = Vec::new();
 
     while sp.ok() {
         #[cfg(windows)]
@@ -474,8 +656,109 @@ fn run(vs: VideoService) -> ResultType<()> {
         if client_record != video_qos.record() {
             bail!("SWITCH");
         }
+        if !scale_fixed && scale != video_qos.tier_scale(0) {
+            log::info!(
+                "video scale changed: {scale} -> {}",
+                video_qos.tier_scale(0)
+            );
+            bail!("SWITCH");
+        }
+        let new_tiers = video_qos.tiers();
+        let wanted_tiers: Vec<_> = new_tiers
+            .values()
+            .copied()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .map(|t| {
+                let tier_scale = if scale_fixed {
+                    MAX_VIDEO_SCALE
+                } else {
+                    video_qos.tier_scale(t)
+                };
+                (
+                    t,
+                    video_qos.tier_quality(t),
+                    video_qos.tier_spf(t),
+                    scaled_size(c.width, c.height, tier_scale),
+                )
+            })
+            .collect();
+        let lossless_allowed = !record_incoming && video_qos.lossless_allowed();
         drop(video_qos);
 
+        if lossless_allowed != lossless.is_some() {
+            if lossless.take().map_or(false, |l| l.on) {
+                // The peers need a key frame to go back to the lossy stream.
+                encoder = Encoder::new(encoder_cfg.clone(), use_i444)
+                    .map_err(|e| anyhow!("Failed to create encoder: {}", e))?;
+                allow_err!(encoder.set_quality(quality));
+            }
+            if lossless_allowed {
+                lossless = Some(Lossless::new(display_idx));
+            }
+        }
+
+        if new_tiers != tiers {
+            // The users switched to another tier need a key frame to decode the new stream.
+            let switched: HashSet<usize> = tiers
+                .keys()
+                .chain(new_tiers.keys())
+                .filter_map(|id| {
+                    let old = tiers.get(id).copied().unwrap_or(0);
+                    let new = new_tiers.get(id).copied().unwrap_or(0);
+                    (old != new).then_some(new)
+                })
+                .collect();
+            for tier in switched {
+                if tier == 0 {
+                    encoder = Encoder::new(encoder_cfg.clone(), use_i444)
+                        .map_err(|e| anyhow!("Failed to create encoder: {}", e))?;
+                    allow_err!(encoder.set_quality(quality));
+                } else {
+                    tier_encoders.remove(&tier);
+                }
+            }
+            tiers = new_tiers;
+        }
+        // The stream of a tier is restarted with a new encoder if its size is changed.
+        tier_encoders.retain(|t, te| wanted_tiers.iter().any(|w| w.0 == *t && w.3 == te.size));
+        for (tier, tier_quality, tier_spf, tier_size) in wanted_tiers {
+            if let Some(te) = tier_encoders.get_mut(&tier) {
+                if te.quality != tier_quality {
+                    allow_err!(te.encoder.set_quality(tier_quality));
+                    te.quality = tier_quality;
+                }
+                te.spf = tier_spf;
+            } else {
+                let cfg = if tier_size == size {
+                    encoder_cfg.clone()
+                } else {
+                    get_encoder_config(
+                        &c,
+                        tier_size,
+                        display_idx,
+                        tier_quality,
+                        record,
+                        last_portable_service_running,
+                    )
+                };
+                let mut te = TierEncoder {
+                    encoder: Encoder::new(cfg, use_i444)
+                        .map_err(|e| anyhow!("Failed to create tier {} encoder: {}", tier, e))?,
+                    quality: tier_quality,
+                    size: tier_size,
+                    spf: tier_spf,
+                    last: None,
+                    pending: HashSet::new(),
+                };
+                allow_err!(te.encoder.set_quality(tier_quality));
+                log::info!(
+                    "simulcast tier {tier} started, quality: {tier_quality:?}, size: {tier_size:?}"
+                );
+                tier_encoders.insert(tier, te);
+            }
+        }
+
         if sp.is_option_true(OPTION_REFRESH) {
             let _ = try_broadcast_display_changed(&sp, display_idx, &c);
             bail!("SWITCH");
@@ -512,6 +795,12 @@ fn run(vs: VideoService) -> ResultType<()> {
             try_broadcast_display_changed(&sp, display_idx, &c)?;
         }
 
+        if c.crop.is_some() && !super::shared_area::get().map_or(false, |a| a.visible) {
+            // The shared window is hidden, nothing is sent until it is visible again.
+            std::thread::sleep(spf);
+            continue;
+        }
+
         frame_controller.reset();
 
         let res = match c.frame(spf) {
@@ -519,15 +808,44 @@ fn run(vs: VideoService) -> ResultType<()> {
                 let time = now - start;
                 let ms = (time.as_secs() * 1000 + time.subsec_millis() as u64) as i64;
                 if frame.valid() {
+                    let scaling = scale != MAX_VIDEO_SCALE
+                        || tier_encoders.values().any(|te| te.size != size);
+                    if !scale_fixed && scaling && !is_scalable(&frame) {
+                        log::warn!(
+                            "display {display_idx} can't be scaled, fall back to no scaling"
+                        );
+                        UNSCALABLE_DISPLAYS
+                            .lock()
+                            .unwrap()
+                            .insert(display_idx, (c.origin, c.width, c.height));
+                        bail!("SWITCH");
+                    }
+                    let mut lossless_frame = None;
+                    if let Some(l) = lossless.as_mut() {
+                        let was_on = l.on;
+                        lossless_frame = l.handle(display_idx, &frame, c.crop);
+                        if was_on && !l.on {
+                            // The peers need a key frame to go back to the lossy stream.
+                            encoder = Encoder::new(encoder_cfg.clone(), use_i444)
+                                .map_err(|e| anyhow!("Failed to create encoder: {}", e))?;
+                            allow_err!(encoder.set_quality(quality));
+                        }
+                    }
                     let send_conn_ids = handle_one_frame(
                         display_idx,
                         &sp,
                         frame,
+                        c.crop,
+                        size,
                         &mut yuv,
                         &mut mid_data,
+                        &mut scaled,
                         ms,
                         &mut encoder,
                         recorder.clone(),
+                        &tiers,
+                        &mut tier_encoders,
+                        lossless_frame,
                     )?;
                     frame_controller.set_send(now, send_conn_ids);
                 }
@@ -542,6 +860,10 @@ fn run(vs: VideoService) -> ResultType<()> {
 
         match res {
             Err(ref e) if e.kind() == WouldBlock => {
+                if let Some(vf) = lossless.as_mut().and_then(|l| l.idle(display_idx)) {
+                    let send_conn_ids = send_lossless(display_idx, &sp, &tiers, vf);
+                    frame_controller.set_send(now, send_conn_ids);
+                }
                 #[cfg(windows)]
                 if try_gdi > 0 && !c.is_gdi() {
                     if try_gdi > 3 {
@@ -598,10 +920,17 @@ fn run(vs: VideoService) -> ResultType<()> {
             check_privacy_mode_changed(&sp, c.privacy_mode_id)?;
             frame_controller.try_wait_next(&mut fetched_conn_ids, 300);
             // break if all connections have received current frame
-            if fetched_conn_ids.len() >= frame_controller.send_conn_ids.len() {
+            if frame_controller
+                .send_conn_ids
+                .iter()
+                .all(|id| fetched_conn_ids.contains(id))
+            {
                 break;
             }
         }
+        for te in tier_encoders.values_mut() {
+            te.pending.retain(|id| !fetched_conn_ids.contains(id));
+        }
 
         let elapsed = now.elapsed();
         // may need to enable frame(timeout)
@@ -611,6 +940,8 @@ fn run(vs: VideoService) -> ResultType<()> {
         }
     }
 
+    // The service is stopped, e.g. no connection, not restarted by the errors like "SWITCH".
+    UNSCALABLE_DISPLAYS.lock().unwrap().remove(&display_idx);
     Ok(())
 }
 
@@ -627,11 +958,15 @@ impl Drop for Raii {
         #[cfg(feature = "gpucodec")]
         GpuEncoder::set_not_use(self.0, false);
         VIDEO_QOS.lock().unwrap().set_support_abr(self.0, true);
+        VIDEO_QOS.lock().unwrap().clear_motion(self.0);
+        super::metrics::on_video_service_exit(self.0);
+        RECORDERS.lock().unwrap().remove(&self.0);
     }
 }
 
 fn get_encoder_config(
     c: &CapturerInfo,
+    (width, height): (usize, usize),
     _display_idx: usize,
     quality: Quality,
     record: bool,
@@ -642,6 +977,11 @@ fn get_encoder_config(
         log::info!("gdi:{}, portable:{}", c.is_gdi(), _portable_service);
         GpuEncoder::set_not_use(_display_idx, true);
     }
+    // The texture can't be cropped to the shared area.
+    #[cfg(feature = "gpucodec")]
+    if c.crop.is_some() {
+        GpuEncoder::set_not_use(_display_idx, true);
+    }
     #[cfg(feature = "gpucodec")]
     Encoder::update(scrap::codec::EncodingUpdate::Check);
     // https://www.wowza.com/community/t/the-correct-keyframe-interval-in-obs-studio/95162
@@ -653,8 +993,8 @@ fn get_encoder_config(
             if let Some(feature) = GpuEncoder::try_get(&c.device(), negotiated_codec.clone()) {
                 EncoderCfg::GPU(GpuEncoderConfig {
                     device: c.device(),
-                    width: c.width,
-                    height: c.height,
+                    width: width,
+                    height: height,
                     quality,
                     feature,
                     keyframe_interval,
@@ -662,8 +1002,8 @@ fn get_encoder_config(
             } else {
                 handle_hw_encoder(
                     negotiated_codec.clone(),
-                    c.width,
-                    c.height,
+                    width,
+                    height,
                     quality as _,
                     keyframe_interval,
                 )
@@ -671,22 +1011,22 @@ fn get_encoder_config(
             #[cfg(not(feature = "gpucodec"))]
             handle_hw_encoder(
                 negotiated_codec.clone(),
-                c.width,
-                c.height,
+                width,
+                height,
                 quality as _,
                 keyframe_interval,
             )
         }
         CodecName::H264HW(_name) | CodecName::H265HW(_name) => handle_hw_encoder(
             negotiated_codec.clone(),
-            c.width,
-            c.height,
+            width,
+            height,
             quality as _,
             keyframe_interval,
         ),
         name @ (CodecName::VP8 | CodecName::VP9) => EncoderCfg::VPX(VpxEncoderConfig {
-            width: c.width as _,
-            height: c.height as _,
+            width: width as _,
+            height: height as _,
             quality,
             codec: if name == CodecName::VP8 {
                 VpxVideoCodecId::VP8
@@ -696,14 +1036,56 @@ fn get_encoder_config(
             keyframe_interval,
         }),
         CodecName::AV1 => EncoderCfg::AOM(AomEncoderConfig {
-            width: c.width as _,
-            height: c.height as _,
+            width: width as _,
+            height: height as _,
+            quality,
+            keyframe_interval,
+        }),
+        #[cfg(feature = "openh264")]
+        CodecName::H264SW => EncoderCfg::OpenH264(OpenH264EncoderConfig {
+            width: width,
+            height: height,
             quality,
             keyframe_interval,
         }),
     }
 }
 
+// The encoder config used when the negotiated codec is not available.
+fn fallback_vp9_config(
+    width: usize,
+    height: usize,
+    quality: Quality,
+    keyframe_interval: Option<usize>,
+) -> EncoderCfg {
+    EncoderCfg::VPX(VpxEncoderConfig {
+        width: width as _,
+        height: height as _,
+        quality,
+        codec: VpxVideoCodecId::VP9,
+        keyframe_interval,
+    })
+}
+
+// Only the rgb frames are scaled before the conversion to yuv.
+fn is_scalable(frame: &Frame) -> bool {
+    match frame {
+        Frame::PixelBuffer(pixelbuffer) => {
+            matches!(pixelbuffer.pixfmt(), Pixfmt::BGRA | Pixfmt::RGBA)
+        }
+        Frame::Texture(_) => false,
+    }
+}
+
+// The size of the downscaled video, the encoders require even dimensions.
+fn scaled_size(width: usize, height: usize, scale: u32) -> (usize, usize) {
+    if scale >= MAX_VIDEO_SCALE {
+        return (width, height);
+    }
+    let f = |v: usize| std::cmp::max((v * scale as usize / 100) & !1, 2);
+    (f(width), f(height))
+}
+
 fn handle_hw_encoder(
     _name: CodecName,
     width: usize,
@@ -711,6 +1093,7 @@ fn handle_hw_encoder(
     quality: Quality,
     keyframe_interval: Option<usize>,
 ) -> EncoderCfg {
+    let codec = format!("{:?}", _name);
     let f = || {
         #[cfg(feature = "hwcodec")]
         match _name {
@@ -762,13 +1145,10 @@ fn handle_hw_encoder(
 
     match f() {
         Ok(cfg) => cfg,
-        _ => EncoderCfg::VPX(VpxEncoderConfig {
-            width: width as _,
-            height: height as _,
-            quality,
-            codec: VpxVideoCodecId::VP9,
-            keyframe_interval,
-        }),
+        _ => {
+            log::warn!("No hardware encoder for {}, fall back to vp9", codec);
+            fallback_vp9_config(width, height, quality, keyframe_interval)
+        }
     }
 }
 
@@ -796,9 +1176,16 @@ fn get_recorder(
             width,
             height,
             format: codec_name.into(),
+            audio_channels: RECORD_AUDIO_CHANNELS.load(Ordering::SeqCst),
             tx,
         })
-        .map_or(Default::default(), |r| Arc::new(Mutex::new(Some(r))))
+        .map_or_else(
+            |e| {
+                log::error!("Failed to record the incoming session: {e}");
+                Default::default()
+            },
+            |r| Arc::new(Mutex::new(Some(r))),
+        )
     } else {
         Default::default()
     };
@@ -806,6 +1193,25 @@ fn get_recorder(
     recorder
 }
 
+/// Set the audio channels of the recorders when the audio encoder starts.
+pub fn record_audio_format(format_msg: &Message) {
+    if let Some(message::Union::Misc(misc)) = &format_msg.union {
+        if let Some(misc::Union::AudioFormat(f)) = &misc.union {
+            RECORD_AUDIO_CHANNELS.store(f.channels as _, Ordering::SeqCst);
+        }
+    }
+    record_audio(format_msg);
+}
+
+/// Write an audio frame to the recorders of all displays.
+pub fn record_audio(msg: &Message) {
+    for recorder in RECORDERS.lock().unwrap().values() {
+        if let Some(r) = recorder.lock().unwrap().as_mut() {
+            r.write_message(msg);
+        }
+    }
+}
+
 fn check_privacy_mode_changed(sp: &GenericService, privacy_mode_id: i32) -> ResultType<()> {
     let privacy_mode_id_2 = get_privacy_mode_conn_id().unwrap_or(INVALID_PRIVACY_MODE_CONN_ID);
     if privacy_mode_id != privacy_mode_id_2 {
@@ -821,16 +1227,45 @@ fn check_privacy_mode_changed(sp: &GenericService, privacy_mode_id: i32) -> Resu
     Ok(())
 }
 
+// The users of tier 0, i.e. not in the `tiers`.
+fn send_tier0(sp: &GenericService, tiers: &HashMap<i32, usize>, msg: Message) -> HashSet<i32> {
+    if tiers.is_empty() {
+        sp.send_video_frame(msg)
+    } else {
+        sp.send_video_frame_filter(msg, |id| !tiers.contains_key(&id))
+    }
+}
+
+fn send_lossless(
+    display: usize,
+    sp: &GenericService,
+    tiers: &HashMap<i32, usize>,
+    mut vf: VideoFrame,
+) -> HashSet<i32> {
+    vf.display = display as _;
+    super::metrics::on_frame_encoded(display);
+    let mut msg = Message::new();
+    msg.set_video_frame(vf);
+    send_tier0(sp, tiers, msg)
+}
+
 #[inline]
 fn handle_one_frame(
     display: usize,
     sp: &GenericService,
     frame: Frame,
+    crop: Option<(usize, usize, usize, usize)>,
+    size: (usize, usize),
     yuv: &mut Vec<u8>,
     mid_data: &mut Vec<u8>,
+    scaled: &mut Vec<u8>,
     ms: i64,
     encoder: &mut Encoder,
     recorder: Arc<Mutex<Option<Recorder>>>,
+    tiers: &HashMap<i32, usize>,
+    tier_encoders: &mut HashMap<usize, TierEncoder>,
+    // replaces the lossy frame of tier 0 if `Some`
+    lossless: Option<Option<VideoFrame>>,
 ) -> ResultType<HashSet<i32>> {
     sp.snapshot(|sps| {
         // so that new sub and old sub share the same encoder after switch
@@ -840,33 +1275,66 @@ fn handle_one_frame(
         Ok(())
     })?;
 
-    let frame = frame.to(encoder.yuvfmt(), yuv, mid_data)?;
     let mut send_conn_ids: HashSet<i32> = Default::default();
-    match encoder.encode_to_message(frame, ms) {
-        Ok(mut vf) => {
-            vf.display = display as _;
-            let mut msg = Message::new();
-            msg.set_video_frame(vf);
-            recorder
-                .lock()
-                .unwrap()
-                .as_mut()
-                .map(|r| r.write_message(&msg));
-            send_conn_ids = sp.send_video_frame(msg);
-        }
-        Err(e) => match e.to_string().as_str() {
-            scrap::codec::ENCODE_NEED_SWITCH => {
-                bail!("SWITCH");
+    if let Some(lossless) = lossless {
+        if let Some(vf) = lossless {
+            send_conn_ids = send_lossless(display, sp, tiers, vf);
+        }
+    } else {
+        let input = frame.to_scaled(encoder.yuvfmt(), crop, size, yuv, mid_data, scaled)?;
+        match encoder.encode_to_message(input, ms) {
+            Ok(mut vf) => {
+                vf.display = display as _;
+                super::metrics::on_frame_encoded(display);
+                let mut msg = Message::new();
+                msg.set_video_frame(vf);
+                recorder
+                    .lock()
+                    .unwrap()
+                    .as_mut()
+                    .map(|r| r.write_message(&msg));
+                send_conn_ids = send_tier0(sp, tiers, msg);
             }
-            _ => {}
-        },
+            Err(e) => match e.to_string().as_str() {
+                scrap::codec::ENCODE_NEED_SWITCH => {
+                    bail!("SWITCH");
+                }
+                _ => {}
+            },
+        }
+    }
+    for (&tier, te) in tier_encoders.iter_mut() {
+        // a little earlier, so that the fps of the tier isn't reduced by the jitter of the capture
+        if te.last.map_or(false, |t| t.elapsed() < te.spf * 9 / 10) {
+            continue;
+        }
+        // The same backpressure as tier 0, without stalling the capture of the others.
+        te.pending.retain(|id| tiers.get(id) == Some(&tier));
+        if !te.pending.is_empty() && te.last.map_or(false, |t| t.elapsed() < TIER_FETCH_TIMEOUT) {
+            continue;
+        }
+        let input = frame.to_scaled(te.encoder.yuvfmt(), crop, te.size, yuv, mid_data, scaled)?;
+        match te.encoder.encode_to_message(input, ms) {
+            Ok(mut vf) => {
+                te.last = Some(Instant::now());
+                vf.display = display as _;
+                let mut msg = Message::new();
+                msg.set_video_frame(vf);
+                te.pending = sp.send_video_frame_filter(msg, |id| tiers.get(&id) == Some(&tier));
+            }
+            Err(e) => {
+                if e.to_string() == scrap::codec::ENCODE_NEED_SWITCH {
+                    bail!("SWITCH");
+                }
+            }
+        }
     }
     Ok(send_conn_ids)
 }
 
 pub fn is_inited_msg() -> Option<Message> {
     #[cfg(target_os = "linux")]
-    if !is_x11() {
+    if !is_x11() && !super::is_test_display() {
         return super::wayland::is_inited();
     }
     None
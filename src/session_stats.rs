@@ -0,0 +1,334 @@
+//! Per-session network statistics of both the controlled side and the controlling side.
+//!
+//! Every process collects the statistics of its own sessions. The controlling side reports them to
+//! the local server process, so all of them can be queried over ipc with [`crate::ipc::Data::SessionStats`],
+//! e.g. `rustdesk --session-stats`.
+//! If the option `session-stats-interval` is set to a number of seconds, the statistics are also
+//! appended periodically as json lines to `session_stats.jsonl` in the log directory.
+//! The file is renamed to `session_stats.jsonl.1` when it exceeds [`MAX_FILE_SIZE`], replacing the older one.
+
+use std::{
+    collections::HashMap,
+    io::Write,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use hbb_common::tokio;
+use hbb_common::{config::Config, get_time, log, message_proto::*, protobuf::Message as _};
+use serde_derive::{Deserialize, Serialize};
+
+pub const SIDE_CONTROLLED: &str = "controlled";
+pub const SIDE_CONTROLLING: &str = "controlling";
+
+const OPTION_INTERVAL: &str = "session-stats-interval";
+const FILE_NAME: &str = "session_stats.jsonl";
+const MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+// Reported statistics of the other processes are removed if not updated in time.
+const STALE_MILLIS: i64 = 15_000;
+
+lazy_static::lazy_static! {
+    static ref STATS: Arc<Mutex<HashMap<String, SessionStats>>> = Default::default();
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct SessionStats {
+    pub key: String,
+    pub side: String,
+    pub pid: u32,
+    pub conn_id: i32,
+    pub peer_id: String,
+    pub conn_type: String,
+    pub direct: Option<bool>,
+    pub codec: String,
+    // ms since epoch
+    pub start_time: i64,
+    pub update_time: i64,
+    // round trip time, ms
+    pub rtt: u32,
+    // target bitrate of the encoder, kbps
+    pub bitrate: u32,
+    // encoded or decoded frames per second, by display
+    pub fps: HashMap<usize, u32>,
+    pub frames_decoded: u64,
+    pub frames_dropped: u64,
+    // average decode time, ms
+    pub decode_time: f32,
+    // bytes by message type
+    pub bytes_in: HashMap<String, u64>,
+    pub bytes_out: HashMap<String, u64>,
+    #[serde(skip)]
+    frame_counter: HashMap<usize, u32>,
+    #[serde(skip)]
+    last_publish: Option<Instant>,
+}
+
+impl SessionStats {
+    pub fn new(key: String, side: &str, conn_id: i32, peer_id: &str) -> Self {
+        let now = get_time();
+        Self {
+            key,
+            side: side.to_owned(),
+            pid: std::process::id(),
+            conn_id,
+            peer_id: peer_id.to_owned(),
+            start_time: now,
+            update_time: now,
+            ..Default::default()
+        }
+    }
+
+    #[inline]
+    pub fn on_recv(&mut self, msg: &Message, len: usize) {
+        self.on_video_frame(msg);
+        *self
+            .bytes_in
+            .entry(message_type(msg).to_owned())
+            .or_default() += len as u64;
+    }
+
+    #[inline]
+    pub fn on_send(&mut self, msg: &Message) {
+        self.on_video_frame(msg);
+        *self
+            .bytes_out
+            .entry(message_type(msg).to_owned())
+            .or_default() += msg.compute_size();
+    }
+
+    #[inline]
+    fn on_video_frame(&mut self, msg: &Message) {
+        if let Some(message::Union::VideoFrame(vf)) = &msg.union {
+            *self.frame_counter.entry(vf.display as usize).or_default() += 1;
+            let format = scrap::CodecFormat::from(vf);
+            if format != scrap::CodecFormat::Unknown {
+                self.codec = format.to_string();
+            }
+        }
+    }
+
+    /// Called by the decoder thread for every decoded frame.
+    #[inline]
+    pub fn on_frame_decoded(&mut self, decode_time: Duration) {
+        let ms = decode_time.as_secs_f32() * 1000.;
+        self.decode_time = if self.frames_decoded == 0 {
+            ms
+        } else {
+            self.decode_time * 0.9 + ms * 0.1
+        };
+        self.frames_decoded += 1;
+    }
+
+    /// Publish a snapshot of the statistics, so that it can be queried.
+    ///
+    /// It should be called about every second, the fps is calculated from the frames since the last call.
+    /// The decode statistics are kept, they are updated in place by the decoder thread.
+    pub fn publish(&mut self) {
+        let now = Instant::now();
+        if let Some(last) = self.last_publish {
+            let elapsed = std::cmp::max(now.duration_since(last).as_millis() as u32, 1);
+            self.fps = self
+                .frame_counter
+                .drain()
+                .map(|(k, v)| (k, v * 1000 / elapsed))
+                .collect();
+        }
+        self.last_publish = Some(now);
+        self.update_time = get_time();
+        start_writer();
+        let mut lock = STATS.lock().unwrap();
+        let mut stats = self.clone();
+        if let Some(old) = lock.get(&self.key) {
+            stats.frames_decoded = old.frames_decoded;
+            stats.decode_time = old.decode_time;
+        }
+        lock.insert(stats.key.clone(), stats);
+    }
+}
+
+pub fn controlled_key(conn_id: i32) -> String {
+    format!("{}-{}-{}", SIDE_CONTROLLED, std::process::id(), conn_id)
+}
+
+pub fn controlling_key(session_id: u64) -> String {
+    format!("{}-{}-{}", SIDE_CONTROLLING, std::process::id(), session_id)
+}
+
+/// The name of the message type, used as the key of the byte counters.
+pub fn message_type(msg: &Message) -> &'static str {
+    match &msg.union {
+        Some(message::Union::SignedId(_)) => "signed_id",
+        Some(message::Union::PublicKey(_)) => "public_key",
+        Some(message::Union::TestDelay(_)) => "test_delay",
+        Some(message::Union::VideoFrame(_)) => "video_frame",
+        Some(message::Union::LoginRequest(_)) => "login_request",
+        Some(message::Union::LoginResponse(_)) => "login_response",
+        Some(message::Union::Hash(_)) => "hash",
+        Some(message::Union::MouseEvent(_)) => "mouse_event",
+        Some(message::Union::AudioFrame(_)) => "audio_frame",
+        Some(message::Union::CursorData(_)) => "cursor_data",
+        Some(message::Union::CursorPosition(_)) => "cursor_position",
+        Some(message::Union::CursorId(_)) => "cursor_id",
+        Some(message::Union::KeyEvent(_)) => "key_event",
+        Some(message::Union::Clipboard(_)) => "clipboard",
+        Some(message::Union::FileAction(_)) => "file_action",
+        Some(message::Union::FileResponse(_)) => "file_response",
+        Some(message::Union::Misc(_)) => "misc",
+        Some(message::Union::Cliprdr(_)) => "cliprdr",
+        Some(message::Union::MessageBox(_)) => "message_box",
+        Some(message::Union::SwitchSidesResponse(_)) => "switch_sides_response",
+        Some(message::Union::VoiceCallRequest(_)) => "voice_call_request",
+        Some(message::Union::VoiceCallResponse(_)) => "voice_call_response",
+        Some(message::Union::PeerInfo(_)) => "peer_info",
+        Some(message::Union::PointerDeviceEvent(_)) => "pointer_device_event",
+        Some(message::Union::Auth2fa(_)) => "auth_2fa",
+        _ => "other",
+    }
+}
+
+/// Update the statistics of a session in place, if it exists.
+pub fn update_with(key: &str, f: impl FnOnce(&mut SessionStats)) {
+    if let Some(stats) = STATS.lock().unwrap().get_mut(key) {
+        f(stats);
+    }
+}
+
+pub fn remove(key: &str) {
+    STATS.lock().unwrap().remove(key);
+}
+
+/// The statistics of all sessions known by this process, including the reported ones.
+pub fn all() -> Vec<SessionStats> {
+    let now = get_time();
+    let pid = std::process::id();
+    let mut lock = STATS.lock().unwrap();
+    lock.retain(|_, s| s.pid == pid || now - s.update_time < STALE_MILLIS);
+    let mut v: Vec<_> = lock.values().cloned().collect();
+    v.sort_by(|a, b| a.start_time.cmp(&b.start_time));
+    v
+}
+
+/// Merge the statistics reported by the other processes.
+pub fn merge(stats: Vec<SessionStats>) {
+    let pid = std::process::id();
+    let mut lock = STATS.lock().unwrap();
+    for s in stats.into_iter().filter(|s| s.pid != pid) {
+        lock.insert(s.key.clone(), s);
+    }
+}
+
+fn local() -> Vec<SessionStats> {
+    let pid = std::process::id();
+    STATS
+        .lock()
+        .unwrap()
+        .values()
+        .filter(|s| s.pid == pid)
+        .cloned()
+        .collect()
+}
+
+/// Report the statistics of this process to the server process.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub async fn report() -> hbb_common::ResultType<()> {
+    let stats = local();
+    if stats.is_empty() {
+        return Ok(());
+    }
+    let mut c = crate::ipc::connect(1000, "").await?;
+    c.send(&crate::ipc::Data::SessionStats(Some(stats))).await?;
+    Ok(())
+}
+
+/// Query the statistics of all sessions from the server process.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+#[tokio::main(flavor = "current_thread")]
+pub async fn query() -> hbb_common::ResultType<Vec<SessionStats>> {
+    let mut c = crate::ipc::connect(1000, "").await?;
+    c.send(&crate::ipc::Data::SessionStats(None)).await?;
+    if let Some(crate::ipc::Data::SessionStats(Some(stats))) = c.next_timeout(1000).await? {
+        return Ok(stats);
+    }
+    Ok(vec![])
+}
+
+fn get_interval() -> u64 {
+    Config::get_option(OPTION_INTERVAL).parse().unwrap_or(0)
+}
+
+fn start_writer() {
+    use std::sync::Once;
+    static ONCE: Once = Once::new();
+    ONCE.call_once(|| {
+        std::thread::spawn(|| {
+            let mut last = std::time::Instant::now();
+            loop {
+                std::thread::sleep(std::time::Duration::from_secs(1));
+                let interval = get_interval();
+                if interval == 0 || last.elapsed().as_secs() < interval {
+                    continue;
+                }
+                last = std::time::Instant::now();
+                if let Err(e) = write_lines(&local()) {
+                    log::error!("Failed to write session stats: {}", e);
+                }
+            }
+        });
+    });
+}
+
+fn write_lines(stats: &[SessionStats]) -> hbb_common::ResultType<()> {
+    if stats.is_empty() {
+        return Ok(());
+    }
+    let mut lines = String::new();
+    for s in stats {
+        lines.push_str(&serde_json::to_string(s)?);
+        lines.push('\n');
+    }
+    let path = Config::log_path().join(FILE_NAME);
+    rotate(&path, MAX_FILE_SIZE)?;
+    let mut f = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    // one write for all lines, so lines from different processes do not interleave
+    f.write_all(lines.as_bytes())?;
+    Ok(())
+}
+
+fn rotate(path: &std::path::Path, max_size: u64) -> hbb_common::ResultType<()> {
+    match std::fs::metadata(path) {
+        Ok(m) if m.len() >= max_size => {}
+        _ => return Ok(()),
+    }
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(".1");
+    std::fs::rename(path, rotated)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotate() {
+        let dir = std::env::temp_dir().join(format!("session_stats_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(FILE_NAME);
+        let rotated = dir.join(format!("{}.1", FILE_NAME));
+        std::fs::write(&path, "1234").unwrap();
+        rotate(&path, 5).unwrap();
+        assert!(path.exists());
+        std::fs::write(&path, "12345").unwrap();
+        std::fs::write(&rotated, "old").unwrap();
+        rotate(&path, 5).unwrap();
+        assert!(!path.exists());
+        assert_eq!(std::fs::read_to_string(&rotated).unwrap(), "12345");
+        // no file yet
+        rotate(&path, 5).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -305,6 +305,9 @@ impl InvokeUiSession for SciterHandler {
         self.call("updateBlockInputState", &make_args!(on));
     }
 
+    // unused in sciter
+    fn on_gamepad_rumble(&self, _index: u32, _strong: u32, _weak: u32, _duration: u32) {}
+
     fn switch_back(&self, _id: &str) {}
 
     fn portable_service_running(&self, _running: bool) {}
@@ -438,6 +441,17 @@ impl sciter::EventHandler for SciterSession {
         fn transfer_file();
         fn tunnel();
         fn lock_screen();
+        fn type_clipboard();
+        fn is_typing_text();
+        fn cancel_type_text();
+        fn is_macro_recording();
+        fn is_macro_playing();
+        fn start_macro_recording();
+        fn stop_macro_recording(String);
+        fn play_macro(String, f64);
+        fn stop_macro();
+        fn set_video_scale(i32, bool);
+        fn set_audio_quality(i32, i32, String);
         fn reconnect(bool);
         fn get_chatbox();
         fn get_icon();
@@ -30,11 +30,12 @@ use hbb_common::{
     Stream,
 };
 
+use crate::client::input_macro::{self, InputMacro, Macro, Rect};
 use crate::client::io_loop::Remote;
 use crate::client::{
     check_if_retry, handle_hash, handle_login_error, handle_login_from_ui, handle_test_delay,
-    input_os_password, send_mouse, send_pointer_device_event, start_video_audio_threads,
-    FileManager, Key, LoginConfigHandler, QualityStatus, KEY_MAP,
+    input_os_password, send_hi_res_scroll, send_mouse, send_pointer_device_event,
+    start_video_audio_threads, FileManager, Key, LoginConfigHandler, QualityStatus, KEY_MAP,
 };
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
 use crate::common::GrabState;
@@ -56,6 +57,11 @@ pub struct Session<T: InvokeUiSession> {
     pub server_clipboard_enabled: Arc<RwLock<bool>>,
     pub last_change_display: Arc<Mutex<ChangeDisplayRecord>>,
     pub connection_round_state: Arc<Mutex<ConnectionRoundState>>,
+    pub input_macro: Arc<Mutex<InputMacro>>,
+    // the high resolution wheel deltas not sent as notches yet
+    pub hi_res_scroll_remainder: Arc<Mutex<(i32, i32)>>,
+    // the estimated end of the typing requested, the peer doesn't report it
+    pub type_text_until: Arc<Mutex<Option<Instant>>>,
 }
 
 #[derive(Clone)]
@@ -363,6 +369,107 @@ impl<T: InvokeUiSession> Session<T> {
         self.send(Data::Message(msg_out));
     }
 
+    pub fn type_text(&self, text: String) {
+        if text.is_empty() {
+            return;
+        }
+        // 20ms per char by default on the peer
+        let duration = TokioDuration::from_millis(text.chars().count() as u64 * 20 + 1_000);
+        *self.type_text_until.lock().unwrap() = Some(Instant::now() + duration);
+        let mut misc = Misc::new();
+        misc.set_type_text(TypeText {
+            text,
+            ..Default::default()
+        });
+        let mut msg_out = Message::new();
+        msg_out.set_misc(misc);
+        self.send(Data::Message(msg_out));
+    }
+
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    pub fn type_clipboard(&self) {
+        let text = crate::common::ClipboardContext::new()
+            .ok()
+            .and_then(|mut ctx| ctx.get_text().ok())
+            .unwrap_or_default();
+        self.type_text(text);
+    }
+
+    pub fn is_typing_text(&self) -> bool {
+        self.type_text_until
+            .lock()
+            .unwrap()
+            .map_or(false, |t| t > Instant::now())
+    }
+
+    pub fn cancel_type_text(&self) {
+        *self.type_text_until.lock().unwrap() = None;
+        let mut misc = Misc::new();
+        misc.set_type_text(TypeText {
+            cancel: true,
+            ..Default::default()
+        });
+        let mut msg_out = Message::new();
+        msg_out.set_misc(misc);
+        self.send(Data::Message(msg_out));
+    }
+
+    pub fn is_macro_recording(&self) -> bool {
+        self.input_macro.lock().unwrap().is_recording()
+    }
+
+    pub fn is_macro_playing(&self) -> bool {
+        self.input_macro.lock().unwrap().is_playing()
+    }
+
+    pub fn start_macro_recording(&self) -> bool {
+        self.input_macro.lock().unwrap().start_recording()
+    }
+
+    /// Save the recorded macro to `path`, discard it if `path` is empty.
+    pub fn stop_macro_recording(&self, path: String) -> bool {
+        let Some(m) = self.input_macro.lock().unwrap().stop_recording() else {
+            return false;
+        };
+        if path.is_empty() {
+            return true;
+        }
+        if let Err(err) = m.save(&path) {
+            log::error!("Failed to save macro {}: {}", path, err);
+            self.msgbox("custom-nocancel-error", "Error", &err.to_string(), "");
+            return false;
+        }
+        true
+    }
+
+    pub fn play_macro(&self, path: String, speed: f64) {
+        let m = match Macro::load(&path) {
+            Ok(m) => m,
+            Err(err) => {
+                log::error!("Failed to load macro {}: {}", path, err);
+                self.msgbox("custom-nocancel-error", "Error", &err.to_string(), "");
+                return;
+            }
+        };
+        let mut lock = self.input_macro.lock().unwrap();
+        let Some(stop) = lock.start_playing() else {
+            return;
+        };
+        let messages = m.messages(lock.display());
+        drop(lock);
+        let session = self.clone();
+        std::thread::spawn(move || {
+            input_macro::play(messages, speed, &stop, |msg| {
+                session.send(Data::Message(msg))
+            });
+            session.input_macro.lock().unwrap().finish_playing(&stop);
+        });
+    }
+
+    pub fn stop_macro(&self) {
+        self.input_macro.lock().unwrap().stop_playing();
+    }
+
     #[cfg(not(feature = "flutter"))]
     pub fn refresh_video(&self, _display: i32) {
         self.send(Data::Message(LoginConfigHandler::refresh()));
@@ -412,6 +519,20 @@ impl<T: InvokeUiSession> Session<T> {
         self.send(Data::Message(msg));
     }
 
+    pub fn set_video_scale(&self, scale: i32, auto: bool) {
+        let msg = self.lc.write().unwrap().set_video_scale(scale, auto);
+        self.send(Data::Message(msg));
+    }
+
+    pub fn set_audio_quality(&self, bitrate: i32, channels: i32, application: String) {
+        let msg = self
+            .lc
+            .write()
+            .unwrap()
+            .set_audio_quality(bitrate, channels, application);
+        self.send(Data::Message(msg));
+    }
+
     pub fn get_remember(&self) -> bool {
         self.lc.read().unwrap().remember
     }
@@ -920,6 +1041,40 @@ impl<T: InvokeUiSession> Session<T> {
         send_pointer_device_event(evt, alt, ctrl, shift, command, self);
     }
 
+    // Only if the peer has "multi_touch" in the platform additions.
+    pub fn send_multi_touch(
+        &self,
+        points: Vec<TouchPoint>,
+        alt: bool,
+        ctrl: bool,
+        shift: bool,
+        command: bool,
+    ) {
+        if points.is_empty() {
+            return;
+        }
+        let mut evt = PointerDeviceEvent::new();
+        evt.set_multi_touch_event(MultiTouchEvent {
+            points,
+            ..Default::default()
+        });
+        send_pointer_device_event(evt, alt, ctrl, shift, command, self);
+    }
+
+    // Only if the peer has "gamepad" in the platform additions.
+    pub fn send_gamepad_event(&self, evt: GamepadEvent) {
+        let mut msg_out = Message::new();
+        msg_out.set_gamepad_event(evt);
+        self.send(Data::Message(msg_out));
+    }
+
+    // Only if the peer has "pen" in the platform additions.
+    pub fn send_pen(&self, pen: PenEvent, alt: bool, ctrl: bool, shift: bool, command: bool) {
+        let mut evt = PointerDeviceEvent::new();
+        evt.set_pen_event(pen);
+        send_pointer_device_event(evt, alt, ctrl, shift, command, self);
+    }
+
     #[inline]
     #[cfg(not(any(target_os = "android", target_os = "ios")))]
     fn is_scroll_reverse_mode(&self) -> bool {
@@ -1003,6 +1158,27 @@ impl<T: InvokeUiSession> Session<T> {
         }
     }
 
+    /// Scroll by the high resolution deltas, 120 per notch, positive for left and up.
+    /// They're from the trackpad, so not reversed by `reverse_mouse_wheel`.
+    pub fn send_hi_res_scroll(
+        &self,
+        x: i32,
+        y: i32,
+        alt: bool,
+        ctrl: bool,
+        shift: bool,
+        command: bool,
+    ) {
+        let notches = crate::input::take_wheel_notches(
+            &mut self.hi_res_scroll_remainder.lock().unwrap(),
+            x,
+            y,
+        );
+        let (alt, ctrl, shift, command) =
+            keyboard::client::get_modifiers_state(alt, ctrl, shift, command);
+        send_hi_res_scroll((x, y), notches, alt, ctrl, shift, command, self);
+    }
+
     pub fn reconnect(&self, force_relay: bool) {
         // 1. If current session is connecting, do not reconnect.
         // 2. If the connection is established, send `Data::Close`.
@@ -1107,6 +1283,7 @@ impl<T: InvokeUiSession> Session<T> {
     }
 
     pub fn close(&self) {
+        self.input_macro.lock().unwrap().stop_playing();
         self.send(Data::Close);
     }
 
@@ -1213,6 +1390,19 @@ impl<T: InvokeUiSession> Session<T> {
 
     #[inline]
     pub fn handle_peer_switch_display(&self, display: &SwitchDisplay) {
+        // The display size is used to restore the downscaled video.
+        if let Some(pi) = self.lc.write().unwrap().peer_info.as_mut() {
+            if let Some(d) = pi.displays.get_mut(display.display as usize) {
+                d.width = display.width;
+                d.height = display.height;
+            }
+        }
+        self.input_macro.lock().unwrap().set_display(Rect::new(
+            display.x,
+            display.y,
+            display.width,
+            display.height,
+        ));
         self.ui_handler.switch_display(display);
         self.set_custom_resolution(display);
     }
@@ -1334,6 +1524,7 @@ pub trait InvokeUiSession: Send + Sync + Clone + 'static + Sized + Default {
         is_identical: bool,
     );
     fn update_block_input_state(&self, on: bool);
+    fn on_gamepad_rumble(&self, index: u32, strong: u32, weak: u32, duration: u32);
     fn job_progress(&self, id: i32, file_num: i32, speed: f64, finished_size: f64);
     fn adapt_size(&self);
     fn on_rgba(&self, display: usize, rgba: &mut scrap::ImageRgb);
@@ -1376,6 +1567,9 @@ impl<T: InvokeUiSession> Interface for Session<T> {
     }
 
     fn send(&self, data: Data) {
+        if let Data::Message(msg) = &data {
+            self.input_macro.lock().unwrap().record(msg);
+        }
         if let Some(sender) = self.sender.read().unwrap().as_ref() {
             sender.send(data).ok();
         }
@@ -1424,6 +1618,12 @@ impl<T: InvokeUiSession> Interface for Session<T> {
                 input_os_password(p, true, self.clone());
             }
             let current = &pi.displays[pi.current_display as usize];
+            self.input_macro.lock().unwrap().set_display(Rect::new(
+                current.x,
+                current.y,
+                current.width,
+                current.height,
+            ));
             self.set_display(
                 current.x,
                 current.y,